@@ -1,44 +1,165 @@
 use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
 
 declare_id!("ArpHisvZS1EECpwisEM32EXMz2hoqmdhaQwY2TxWLiD7");
 
+// The seller index lists the active auctions of a seller in an account of
+// fixed size, paid by the seller at its first auction. 16 ids keep its rent
+// low (149 bytes) and bound the scan of end and cancel, and a seller with as
+// many auctions running has to end or cancel one before starting another
+const MAX_ACTIVE_AUCTIONS: usize = 16;
+
+// Once the bidding is over, the seller has this many slots (about a day) to
+// end the auction, revealing its hidden reserve price if any. Afterwards
+// anyone can end it, and a hidden reserve price counts as not met, so that
+// the deposits of the highest bidder are never locked forever
+const END_DEADLINE_SLOTS: u64 = 216_000;
+
+// `max_len` can not name a constant, so the size of the active ids of the
+// seller index is checked against MAX_ACTIVE_AUCTIONS at compile time
+const _: () = assert!(SellerIndex::INIT_SPACE == 8 + 4 + 8 * MAX_ACTIVE_AUCTIONS + 1);
+
 #[program]
 pub mod auction {
     use super::*;
 
     pub fn start(
         ctx: Context<StartCtx>,
+        auction_id: u64,
         auctioned_object: String,
         duration_slots: u64,
         starting_bid: u64,
+        amount: u64,
+        extension_window: u64,
+        extension_amount: u64,
+        max_extension: u64,
+        mode: AuctionMode,
+        reserve: ReservePrice,
+        buy_now_price: Option<u64>,
     ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+        if let Some(buy_now_price) = buy_now_price {
+            require!(
+                mode == AuctionMode::English && buy_now_price > starting_bid,
+                CustomError::InvalidBuyNowPrice
+            );
+        }
+        match mode {
+            AuctionMode::English => {}
+            AuctionMode::Dutch { floor_price } => {
+                require!(floor_price <= starting_bid, CustomError::InvalidFloorPrice);
+                // The first bid closes a Dutch auction, so a reserve price
+                // above the floor price, or one that can not be checked
+                // before the end, would let it close on a void sale
+                match reserve {
+                    ReservePrice::None => {}
+                    ReservePrice::Visible { price } => {
+                        require!(price <= floor_price, CustomError::InvalidDutchReserve)
+                    }
+                    ReservePrice::Hidden { .. } => return err!(CustomError::InvalidDutchReserve),
+                }
+            }
+            AuctionMode::Sealed { penalty_bps, .. } => {
+                require!(penalty_bps <= 10000, CustomError::InvalidPenalty);
+            }
+        }
+
+        // The auctioned tokens (e.g. an NFT, with amount 1) are held by the
+        // token account of the auction until the end
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sellers_token_account.to_account_info(),
+                    to: ctx.accounts.escrow_token_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
         let auction_info = &mut ctx.accounts.auction_info;
         auction_info.seller = *ctx.accounts.seller.key;
         auction_info.highest_bidder = *ctx.accounts.seller.key; // The seller is the first bidder at the beginning
-        auction_info.end_time = Clock::get()?.slot + duration_slots;
+        auction_info.start_time = Clock::get()?.slot;
+        auction_info.end_time = auction_info
+            .start_time
+            .checked_add(duration_slots)
+            .ok_or(CustomError::InvalidEndTime)?;
+        auction_info.max_end_time = auction_info
+            .end_time
+            .checked_add(max_extension)
+            .ok_or(CustomError::InvalidEndTime)?;
+        auction_info.extension_window = extension_window;
+        auction_info.extension_amount = extension_amount;
         auction_info.highest_bid = starting_bid;
+        auction_info.second_bid = starting_bid;
         auction_info.object = auctioned_object;
+        auction_info.mint = ctx.accounts.mint.key();
+        auction_info.amount = amount;
+        auction_info.mode = mode;
+        auction_info.reserve = reserve;
+        auction_info.buy_now_price = buy_now_price;
+        auction_info.id = auction_id;
+        auction_info.bump = ctx.bumps.auction_info;
+
+        // The index of the seller lists its active auctions, and gives the id
+        // of the next one
+        let seller_index = &mut ctx.accounts.seller_index;
+        require!(
+            seller_index.active.len() < MAX_ACTIVE_AUCTIONS,
+            CustomError::TooManyActiveAuctions
+        );
+        seller_index.active.push(auction_id);
+        seller_index.next_id += 1;
+        seller_index.bump = ctx.bumps.seller_index;
+
         emit!(Start {});
         Ok(())
     }
 
-    pub fn bid(
-        ctx: Context<BidCtx>,
-        auctioned_object: String,
-        amount_to_deposit: u64,
-    ) -> Result<()> {
+    pub fn bid(ctx: Context<BidCtx>, amount_to_deposit: u64) -> Result<()> {
         let auction_info = &mut ctx.accounts.auction_info;
         let bidder = &ctx.accounts.bidder;
-        let current_highest_bidder = &ctx.accounts.current_highest_bidder;
+        let pending_return = &mut ctx.accounts.pending_return;
 
-        if Clock::get()?.slot > auction_info.end_time {
+        let slot = Clock::get()?.slot;
+        if slot > auction_info.end_time || auction_info.closed {
             return err!(CustomError::AuctionEnded);
         }
 
-        if amount_to_deposit <= auction_info.highest_bid {
-            return err!(CustomError::InvalidBidAmount);
+        if auction_info.seller == *bidder.key {
+            return err!(CustomError::SellerCannotBid);
         }
 
+        // Whether the bid wins right away, closing the auction
+        let (amount_to_deposit, closes) = match auction_info.mode {
+            // A bid at or above the buy-now price wins, paying the buy-now
+            // price
+            AuctionMode::English => {
+                if amount_to_deposit <= auction_info.highest_bid {
+                    return err!(CustomError::InvalidBidAmount);
+                }
+                match auction_info.buy_now_price {
+                    Some(buy_now_price) if amount_to_deposit >= buy_now_price => {
+                        (buy_now_price, true)
+                    }
+                    _ => (amount_to_deposit, false),
+                }
+            }
+            // The first bid at or above the current price wins, paying the
+            // current price: the bid amount is the most the bidder pays
+            AuctionMode::Dutch { floor_price } => {
+                let price = dutch_price(auction_info, floor_price, slot);
+                if amount_to_deposit < price {
+                    return err!(CustomError::BidBelowPrice);
+                }
+                (price, true)
+            }
+            AuctionMode::Sealed { .. } => return err!(CustomError::WrongAuctionMode),
+        };
+
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
             &bidder.key(),
             &auction_info.key(),
@@ -51,105 +172,608 @@ pub mod auction {
         )
         .unwrap();
 
-        // Return founds to the previous bidder if it's not the first attempt (the first bidder is the seller)
-        if auction_info.highest_bidder != auction_info.seller {
-            **current_highest_bidder
-                .to_account_info()
-                .try_borrow_mut_lamports()? += auction_info.highest_bid;
-            **auction_info.to_account_info().try_borrow_mut_lamports()? -= auction_info.highest_bid;
-        }
+        // The previous bid is not pushed back to its bidder: every deposit
+        // stays in the auction and is recorded in the pending return of its
+        // bidder, who withdraws it with withdraw_refund once outbid
+        pending_return.bump = ctx.bumps.pending_return;
+        pending_return.amount += amount_to_deposit;
 
         auction_info.highest_bid = amount_to_deposit;
         auction_info.highest_bidder = *bidder.key;
+        auction_info.bids += 1;
+
+        if closes {
+            // The winning bid ends the auction, which can be settled by end
+            // right away
+            auction_info.closed = true;
+            auction_info.end_time = slot;
+        } else if slot + auction_info.extension_window > auction_info.end_time {
+            // Anti-sniping: a bid in the last slots pushes the deadline out,
+            // so that the others can counter-bid, up to the maximum end time
+            auction_info.end_time = auction_info
+                .max_end_time
+                .min(auction_info.end_time + auction_info.extension_amount);
+        }
 
         emit!(Bid {
             sender: *bidder.key,
-            amount: amount_to_deposit
+            amount: amount_to_deposit,
+            end_time: auction_info.end_time
         });
 
         Ok(())
     }
 
-    pub fn end(ctx: Context<EndCtx>, auctioned_object: String) -> Result<()> {
-        msg!("Auction name: {}", auctioned_object);
+    pub fn end(ctx: Context<EndCtx>, reserve_price: u64, reserve_salt: String) -> Result<()> {
         let auction_info = &mut ctx.accounts.auction_info;
+        msg!("Auction name: {}", auction_info.object);
         let seller = &ctx.accounts.seller;
+        let by_seller = ctx.accounts.caller.key() == seller.key();
 
-        if Clock::get()?.slot <= auction_info.end_time {
+        let slot = Clock::get()?.slot;
+        if slot <= auction_info.bidding_end_time() && !auction_info.closed {
             return err!(CustomError::AuctionNotEnded);
         }
 
-        // send the auction_info.object to the highest bidder
+        // A Dutch or buy-now auction is closed at the slot of its winning
+        // bid, which becomes its end time
+        require!(
+            by_seller || slot > auction_info.bidding_end_time() + END_DEADLINE_SLOTS,
+            CustomError::InvalidSeller
+        );
+
+        require!(!auction_info.ended, CustomError::AuctionAlreadyEnded);
+        auction_info.ended = true;
+        ctx.accounts
+            .seller_index
+            .active
+            .retain(|&id| id != auction_info.id);
+
+        // A hidden reserve price is revealed by the seller, as a sealed bid.
+        // When someone else ends the auction, it is not revealed
+        let reserve = match auction_info.reserve {
+            ReservePrice::None => Some(0),
+            ReservePrice::Visible { price } => Some(price),
+            ReservePrice::Hidden { .. } if !by_seller => None,
+            ReservePrice::Hidden { commitment } => {
+                let hash = anchor_lang::solana_program::keccak::hashv(&[
+                    &reserve_price.to_le_bytes(),
+                    reserve_salt.as_bytes(),
+                ])
+                .to_bytes();
+                require!(hash == commitment, CustomError::InvalidReserve);
+                Some(reserve_price)
+            }
+        };
+
+        // If the reserve price is not met, or not revealed, the auction is
+        // void: the deposits of the highest bidder are all refundable, and
+        // the auctioned tokens go back to the seller. Otherwise a sealed
+        // winner pays at least the reserve price
+        match reserve {
+            Some(reserve) if auction_info.highest_bid >= reserve => {
+                auction_info.second_bid = auction_info.second_bid.max(reserve);
+            }
+            _ => auction_info.highest_bidder = auction_info.seller,
+        }
+
+        require_keys_eq!(
+            ctx.accounts.winner.key(),
+            auction_info.highest_bidder,
+            CustomError::InvalidWinner
+        );
 
-        **seller.to_account_info().try_borrow_mut_lamports()? +=
-            **auction_info.to_account_info().try_borrow_mut_lamports()?;
-        **auction_info.to_account_info().try_borrow_mut_lamports()? = 0;
+        // The auctioned tokens go to the highest bidder, who is the seller
+        // itself if nobody bid. The emptied token account of the auction is
+        // closed, and its rent goes to the seller
+        let id = auction_info.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"auction",
+            auction_info.seller.as_ref(),
+            &id,
+            &[auction_info.bump],
+        ]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.winners_token_account.to_account_info(),
+                    authority: auction_info.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            auction_info.amount,
+        )?;
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.escrow_token_account.to_account_info(),
+                destination: seller.to_account_info(),
+                authority: auction_info.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        // Only the price goes to the seller: the auction keeps the outbid
+        // deposits until their bidders withdraw them
+        let price = if auction_info.highest_bidder != auction_info.seller {
+            auction_info.price()
+        } else {
+            0
+        };
+        **seller.to_account_info().try_borrow_mut_lamports()? += price;
+        **auction_info.to_account_info().try_borrow_mut_lamports()? -= price;
 
         emit!(End {
             winner: auction_info.highest_bidder,
-            amount: auction_info.highest_bid
+            amount: price
         });
 
         Ok(())
     }
+
+    pub fn cancel(ctx: Context<CancelCtx>) -> Result<()> {
+        let auction_info = &ctx.accounts.auction_info;
+
+        require!(!auction_info.ended, CustomError::AuctionAlreadyEnded);
+        require!(auction_info.bids == 0, CustomError::AuctionHasBids);
+        ctx.accounts
+            .seller_index
+            .active
+            .retain(|&id| id != auction_info.id);
+
+        // With no bids there are no deposits: the auctioned tokens go back to
+        // the seller, and the token account and the auction are closed
+        let id = auction_info.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"auction",
+            auction_info.seller.as_ref(),
+            &id,
+            &[auction_info.bump],
+        ]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.sellers_token_account.to_account_info(),
+                    authority: auction_info.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            auction_info.amount,
+        )?;
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.escrow_token_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: auction_info.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_refund(ctx: Context<WithdrawRefundCtx>) -> Result<()> {
+        let auction_info = &ctx.accounts.auction_info;
+        msg!("Auction name: {}", auction_info.object);
+        let bidder = &ctx.accounts.bidder;
+        let pending_return = &mut ctx.accounts.pending_return;
+        let seller = &ctx.accounts.seller;
+
+        // The deposits of the highest bidder cover the price, which is
+        // locked for the seller
+        let locked = if auction_info.highest_bidder == *bidder.key {
+            auction_info.price()
+        } else {
+            0
+        };
+        let refund = pending_return.amount - locked;
+        require!(refund > 0, CustomError::NothingToWithdraw);
+
+        // Sealed deposits are locked until all bids are revealed, and those
+        // of the bidders who did not reveal are penalized in favour of the
+        // seller
+        let mut penalty = 0;
+        if let AuctionMode::Sealed { penalty_bps, .. } = auction_info.mode {
+            require!(
+                Clock::get()?.slot > auction_info.bidding_end_time(),
+                CustomError::RevealNotOver
+            );
+            if !pending_return.revealed {
+                penalty = (refund as u128 * penalty_bps as u128 / 10000) as u64;
+            }
+        }
+
+        pending_return.amount -= refund;
+        **bidder.to_account_info().try_borrow_mut_lamports()? += refund - penalty;
+        **seller.to_account_info().try_borrow_mut_lamports()? += penalty;
+        **auction_info.to_account_info().try_borrow_mut_lamports()? -= refund;
+
+        Ok(())
+    }
+
+    pub fn commit_bid(ctx: Context<BidCtx>, commitment: [u8; 32], deposit: u64) -> Result<()> {
+        let auction_info = &mut ctx.accounts.auction_info;
+        let bidder = &ctx.accounts.bidder;
+        let pending_return = &mut ctx.accounts.pending_return;
+
+        require!(
+            matches!(auction_info.mode, AuctionMode::Sealed { .. }),
+            CustomError::WrongAuctionMode
+        );
+
+        if Clock::get()?.slot > auction_info.end_time {
+            return err!(CustomError::AuctionEnded);
+        }
+
+        if auction_info.seller == *bidder.key {
+            return err!(CustomError::SellerCannotBid);
+        }
+
+        require!(
+            pending_return.commitment == [0; 32],
+            CustomError::AlreadyCommitted
+        );
+
+        // The deposit hides the bid, which it must cover when revealed
+        let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
+            &bidder.key(),
+            &auction_info.key(),
+            deposit,
+        );
+
+        anchor_lang::solana_program::program::invoke(
+            &transfer_instruction,
+            &[bidder.to_account_info(), auction_info.to_account_info()],
+        )
+        .unwrap();
+
+        pending_return.bump = ctx.bumps.pending_return;
+        pending_return.amount += deposit;
+        pending_return.commitment = commitment;
+        auction_info.bids += 1;
+
+        Ok(())
+    }
+
+    pub fn reveal_bid(ctx: Context<RevealBidCtx>, amount: u64, salt: String) -> Result<()> {
+        let auction_info = &mut ctx.accounts.auction_info;
+        msg!("Auction name: {}", auction_info.object);
+        let bidder = &ctx.accounts.bidder;
+        let pending_return = &mut ctx.accounts.pending_return;
+
+        require!(
+            matches!(auction_info.mode, AuctionMode::Sealed { .. }),
+            CustomError::WrongAuctionMode
+        );
+
+        let slot = Clock::get()?.slot;
+        require!(
+            slot > auction_info.end_time && slot <= auction_info.bidding_end_time(),
+            CustomError::NotRevealPhase
+        );
+
+        require!(!pending_return.revealed, CustomError::AlreadyRevealed);
+
+        let hash = anchor_lang::solana_program::keccak::hashv(&[
+            bidder.key.as_ref(),
+            auction_info.key().as_ref(),
+            &amount.to_le_bytes(),
+            salt.as_bytes(),
+        ])
+        .to_bytes();
+        require!(
+            hash == pending_return.commitment,
+            CustomError::InvalidCommitment
+        );
+
+        require!(
+            amount <= pending_return.amount,
+            CustomError::DepositBelowBid
+        );
+
+        pending_return.revealed = true;
+
+        // Vickrey: the highest bidder pays the second highest bid, which is
+        // at least the starting bid
+        if amount > auction_info.highest_bid {
+            auction_info.second_bid = auction_info.highest_bid;
+            auction_info.highest_bid = amount;
+            auction_info.highest_bidder = *bidder.key;
+        } else if amount > auction_info.second_bid {
+            auction_info.second_bid = amount;
+        }
+
+        Ok(())
+    }
+}
+
+impl AuctionInfo {
+    // What the highest bidder pays: the second highest bid in a sealed
+    // auction, the highest bid otherwise. Until the end, the whole highest
+    // sealed bid is locked, since a hidden reserve price may raise the price
+    // above the second highest bid
+    fn price(&self) -> u64 {
+        match self.mode {
+            AuctionMode::Sealed { .. } if self.ended => self.second_bid,
+            _ => self.highest_bid,
+        }
+    }
+
+    // The end of the bidding, including the reveal phase of a sealed auction
+    fn bidding_end_time(&self) -> u64 {
+        match self.mode {
+            AuctionMode::Sealed { reveal_slots, .. } => self.end_time + reveal_slots,
+            _ => self.end_time,
+        }
+    }
+}
+
+// The price of a Dutch auction decays linearly from the starting bid, at the
+// start, to the floor price, at the end
+fn dutch_price(auction_info: &AuctionInfo, floor_price: u64, slot: u64) -> u64 {
+    let elapsed = (slot - auction_info.start_time) as u128;
+    let duration = (auction_info.end_time - auction_info.start_time) as u128;
+    if elapsed >= duration {
+        return floor_price;
+    }
+    let decay = (auction_info.highest_bid - floor_price) as u128 * elapsed / duration;
+    auction_info.highest_bid - decay as u64
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct AuctionInfo {
-    pub seller: Pubkey,         // 32 bytes
-    pub highest_bidder: Pubkey, // 32 bytes
-    pub end_time: u64,          // 8 bytes
-    pub highest_bid: u64,       // 8 bytes
+    pub seller: Pubkey,             // 32 bytes
+    pub highest_bidder: Pubkey,     // 32 bytes
+    pub start_time: u64,            // 8 bytes
+    pub end_time: u64,              // 8 bytes
+    pub max_end_time: u64,          // 8 bytes
+    pub extension_window: u64,      // 8 bytes
+    pub extension_amount: u64,      // 8 bytes
+    pub highest_bid: u64,           // 8 bytes
+    pub second_bid: u64,            // 8 bytes
+    pub mint: Pubkey,               // 32 bytes
+    pub amount: u64,                // 8 bytes
+    pub mode: AuctionMode,          // 11 bytes
+    pub reserve: ReservePrice,      // 33 bytes
+    pub buy_now_price: Option<u64>, // 9 bytes
+    pub bids: u32,                  // 4 bytes
+    pub id: u64,                    // 8 bytes
     #[max_len(30)]
     pub object: String,
+    pub closed: bool,               // 1 byte
+    pub ended: bool,                // 1 byte
+    pub bump: u8,                   // 1 byte
+}
+
+// The auctions of a seller are numbered from 0. Its active auctions, the
+// ones not ended yet, are listed in its index
+#[account]
+#[derive(InitSpace)]
+pub struct SellerIndex {
+    pub next_id: u64, // 8 bytes
+    #[max_len(16)] // MAX_ACTIVE_AUCTIONS, checked at compile time
+    pub active: Vec<u64>,
+    pub bump: u8, // 1 byte
+}
+
+// The lamports deposited by a bidder and not yet withdrawn, including the
+// highest bid if the bidder is the highest bidder. The commitment to a sealed
+// bid is keccak(bidder || auction || amount || salt): the keys bind it to its
+// bidder and its auction, so that another bidder can not copy it and reveal
+// the same bid
+#[account]
+#[derive(InitSpace)]
+pub struct PendingReturn {
+    pub amount: u64,          // 8 bytes
+    pub commitment: [u8; 32], // 32 bytes
+    pub revealed: bool,       // 1 byte
+    pub bump: u8,             // 1 byte
+}
+
+// In a Dutch auction, highest_bid is the starting price until the winning
+// bid. In a sealed auction, bids are committed until end_time, then revealed
+// for reveal_slots slots
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum AuctionMode {
+    English,
+    Dutch { floor_price: u64 },
+    Sealed { reveal_slots: u64, penalty_bps: u16 },
+}
+
+// A hidden reserve price is committed to as keccak(price || salt), and
+// revealed by the seller at the end
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ReservePrice {
+    None,
+    Visible { price: u64 },
+    Hidden { commitment: [u8; 32] },
 }
 
 #[derive(Accounts)]
-#[instruction(auctioned_object: String)]
+#[instruction(auction_id: u64)]
 pub struct StartCtx<'info> {
     #[account(mut)]
     pub seller: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump,
+        space = 8 + SellerIndex::INIT_SPACE,
+        constraint = seller_index.next_id == auction_id @ CustomError::InvalidAuctionId
+    )]
+    pub seller_index: Account<'info, SellerIndex>,
     #[account(
         init, 
         payer = seller, 
-        seeds = [auctioned_object.as_ref()],
+        seeds = [b"auction", seller.key().as_ref(), &auction_id.to_le_bytes()],
         bump,
         space = 8 + AuctionInfo::INIT_SPACE
     )]
     pub auction_info: Account<'info, AuctionInfo>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller
+    )]
+    pub sellers_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = auction_info
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-#[instruction(auctioned_object: String)]
 pub struct BidCtx<'info> {
     #[account(mut)]
     pub bidder: Signer<'info>,
     #[account(
         mut,
-        seeds = [auctioned_object.as_ref()],
+        seeds = [b"auction", auction_info.seller.as_ref(), &auction_info.id.to_le_bytes()],
+        bump = auction_info.bump
+    )]
+    pub auction_info: Account<'info, AuctionInfo>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        seeds = [b"pending_return", auction_info.key().as_ref(), bidder.key().as_ref()],
         bump,
-        constraint = auction_info.highest_bidder == *current_highest_bidder.key
+        space = 8 + PendingReturn::INIT_SPACE
+    )]
+    pub pending_return: Account<'info, PendingReturn>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawRefundCtx<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"auction", auction_info.seller.as_ref(), &auction_info.id.to_le_bytes()],
+        bump = auction_info.bump
     )]
     pub auction_info: Account<'info, AuctionInfo>,
+    #[account(
+        mut,
+        seeds = [b"pending_return", auction_info.key().as_ref(), bidder.key().as_ref()],
+        bump = pending_return.bump
+    )]
+    pub pending_return: Account<'info, PendingReturn>,
+    #[account(mut, address = auction_info.seller @ CustomError::InvalidSeller)]
+    pub seller: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBidCtx<'info> {
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"auction", auction_info.seller.as_ref(), &auction_info.id.to_le_bytes()],
+        bump = auction_info.bump
+    )]
+    pub auction_info: Account<'info, AuctionInfo>,
+    #[account(
+        mut,
+        seeds = [b"pending_return", auction_info.key().as_ref(), bidder.key().as_ref()],
+        bump = pending_return.bump
+    )]
+    pub pending_return: Account<'info, PendingReturn>,
+}
+
+#[derive(Accounts)]
+pub struct EndCtx<'info> {
+    // The seller, or anyone after the end deadline
+    #[account(mut)]
+    pub caller: Signer<'info>,
     #[account(mut)]
-    pub current_highest_bidder: SystemAccount<'info>,
+    pub seller: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"auction", auction_info.seller.as_ref(), &auction_info.id.to_le_bytes()],
+        bump = auction_info.bump,
+        constraint = auction_info.seller == *seller.key @ CustomError::InvalidSeller
+    )]
+    pub auction_info: Account<'info, AuctionInfo>,
+    #[account(
+        mut,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump
+    )]
+    pub seller_index: Account<'info, SellerIndex>,
+    #[account(address = auction_info.mint @ CustomError::InvalidMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = auction_info
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    // The highest bidder, or the seller if nobody bid or the reserve price
+    // is not met
+    pub winner: SystemAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = mint,
+        associated_token::authority = winner
+    )]
+    pub winners_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-#[instruction(auctioned_object: String)]
-pub struct EndCtx<'info> {
+pub struct CancelCtx<'info> {
     #[account(mut)]
     pub seller: Signer<'info>,
     #[account(
         mut,
-        seeds = [auctioned_object.as_ref()],
-        bump,
-        constraint = auction_info.seller == *seller.key @ CustomError::InvalidSeller
+        seeds = [b"auction", auction_info.seller.as_ref(), &auction_info.id.to_le_bytes()],
+        bump = auction_info.bump,
+        constraint = auction_info.seller == *seller.key @ CustomError::InvalidSeller,
+        close = seller
     )]
     pub auction_info: Account<'info, AuctionInfo>,
+    #[account(
+        mut,
+        seeds = [b"seller_index", seller.key().as_ref()],
+        bump = seller_index.bump
+    )]
+    pub seller_index: Account<'info, SellerIndex>,
+    #[account(address = auction_info.mint @ CustomError::InvalidMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = auction_info
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller
+    )]
+    pub sellers_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[error_code]
@@ -165,6 +789,75 @@ pub enum CustomError {
 
     #[msg("Invalid seller for the auction provided")]
     InvalidSeller,
+
+    #[msg("The auctioned amount should be positive")]
+    InvalidAmount,
+
+    #[msg("Invalid mint for the auction provided")]
+    InvalidMint,
+
+    #[msg("Invalid highest bidder for the auction provided")]
+    InvalidWinner,
+
+    #[msg("The auction has already been ended")]
+    AuctionAlreadyEnded,
+
+    #[msg("The seller can not bid")]
+    SellerCannotBid,
+
+    #[msg("Nothing to withdraw")]
+    NothingToWithdraw,
+
+    #[msg("The floor price can not be higher than the starting bid")]
+    InvalidFloorPrice,
+
+    #[msg("The bid is lower than the current price")]
+    BidBelowPrice,
+
+    #[msg("The instruction is not available in the mode of the auction")]
+    WrongAuctionMode,
+
+    #[msg("The penalty can be at most 10000 basis points")]
+    InvalidPenalty,
+
+    #[msg("The bidder has already committed a bid")]
+    AlreadyCommitted,
+
+    #[msg("The bids can not be revealed now")]
+    NotRevealPhase,
+
+    #[msg("The bid has already been revealed")]
+    AlreadyRevealed,
+
+    #[msg("The revealed bid does not match the commitment")]
+    InvalidCommitment,
+
+    #[msg("The deposit does not cover the revealed bid")]
+    DepositBelowBid,
+
+    #[msg("The reveal phase is not over")]
+    RevealNotOver,
+
+    #[msg("The buy-now price should be higher than the starting bid, in an English auction")]
+    InvalidBuyNowPrice,
+
+    #[msg("The revealed reserve price does not match the commitment")]
+    InvalidReserve,
+
+    #[msg("The auction can not be canceled once bid")]
+    AuctionHasBids,
+
+    #[msg("The auction id should be the next one of the seller")]
+    InvalidAuctionId,
+
+    #[msg("The seller has too many active auctions")]
+    TooManyActiveAuctions,
+
+    #[msg("The reserve price of a Dutch auction can not be hidden or above the floor price")]
+    InvalidDutchReserve,
+
+    #[msg("The end time of the auction is past the last slot")]
+    InvalidEndTime,
 }
 
 #[event]
@@ -174,6 +867,7 @@ pub struct Start {}
 pub struct Bid {
     sender: Pubkey,
     amount: u64,
+    end_time: u64, // The deadline, possibly extended by the bid
 }
 
 #[event]
@@ -181,3 +875,361 @@ pub struct End {
     winner: Pubkey,
     amount: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::{
+        instruction::Instruction, program_pack::Pack, system_instruction, system_program,
+    };
+    use anchor_lang::{InstructionData, ToAccountMetas};
+    use anchor_spl::associated_token::{
+        get_associated_token_address, spl_associated_token_account,
+    };
+    use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+    use solana_sdk::{
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+
+    // The instructions borrow the accounts for as long as the accounts borrow
+    // their data, which the test processor can not express, so the accounts
+    // are leaked for the duration of the test
+    fn process(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        data: &[u8],
+    ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+        entry(program_id, Box::leak(Box::new(accounts.to_vec())), data)
+    }
+
+    // The terms of an auction besides its duration, by default an English
+    // auction with a starting bid of 1_000 lamports and no extension
+    struct Terms {
+        starting_bid: u64,
+        extension: (u64, u64, u64),
+        mode: AuctionMode,
+        reserve: ReservePrice,
+        buy_now_price: Option<u64>,
+    }
+
+    impl Default for Terms {
+        fn default() -> Self {
+            Self {
+                starting_bid: 1_000,
+                extension: (0, 0, 0),
+                mode: AuctionMode::English,
+                reserve: ReservePrice::None,
+                buy_now_price: None,
+            }
+        }
+    }
+
+    // The payer is the seller of every auction
+    struct Test {
+        context: ProgramTestContext,
+        payer: Keypair,
+    }
+
+    impl Test {
+        async fn start() -> Self {
+            let program_test = ProgramTest::new("auction", ID, processor!(process));
+            let context = program_test.start_with_context().await;
+            Self {
+                payer: context.payer.insecure_clone(),
+                context,
+            }
+        }
+
+        async fn send(
+            &mut self,
+            instructions: &[Instruction],
+            signers: &[&Keypair],
+        ) -> std::result::Result<(), BanksClientError> {
+            let blockhash = self.context.get_new_latest_blockhash().await?;
+            let mut all_signers = vec![&self.payer];
+            all_signers.extend_from_slice(signers);
+
+            let transaction = Transaction::new_signed_with_payer(
+                instructions,
+                Some(&self.payer.pubkey()),
+                &all_signers,
+                blockhash,
+            );
+            self.context
+                .banks_client
+                .process_transaction(transaction)
+                .await
+        }
+
+        // A mint of a single token, held by the associated token account of
+        // the payer
+        async fn create_mint(&mut self) -> Pubkey {
+            let mint = Keypair::new();
+            let payer = self.payer.pubkey();
+            let rent = self.context.banks_client.get_rent().await.unwrap();
+            let instructions = [
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    rent.minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &payer,
+                    None,
+                    0,
+                )
+                .unwrap(),
+                spl_associated_token_account::instruction::create_associated_token_account(
+                    &payer,
+                    &payer,
+                    &mint.pubkey(),
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &get_associated_token_address(&payer, &mint.pubkey()),
+                    &payer,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ];
+            self.send(&instructions, &[&mint]).await.unwrap();
+
+            mint.pubkey()
+        }
+
+        // Starts an auction of a new token by the payer, as its first auction
+        async fn start_auction(
+            &mut self,
+            duration_slots: u64,
+            terms: Terms,
+        ) -> std::result::Result<Pubkey, BanksClientError> {
+            let mint = self.create_mint().await;
+            let seller = self.payer.pubkey();
+            let auction_id = 0u64;
+            let (auction_info, _) = Pubkey::find_program_address(
+                &[b"auction", seller.as_ref(), &auction_id.to_le_bytes()],
+                &ID,
+            );
+            let (seller_index, _) =
+                Pubkey::find_program_address(&[b"seller_index", seller.as_ref()], &ID);
+            let (extension_window, extension_amount, max_extension) = terms.extension;
+
+            let start = Instruction {
+                program_id: ID,
+                accounts: accounts::StartCtx {
+                    seller,
+                    seller_index,
+                    auction_info,
+                    mint,
+                    sellers_token_account: get_associated_token_address(&seller, &mint),
+                    escrow_token_account: get_associated_token_address(&auction_info, &mint),
+                    token_program: spl_token::id(),
+                    system_program: system_program::id(),
+                    associated_token_program: spl_associated_token_account::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::Start {
+                    auction_id,
+                    auctioned_object: "lot".to_string(),
+                    duration_slots,
+                    starting_bid: terms.starting_bid,
+                    amount: 1,
+                    extension_window,
+                    extension_amount,
+                    max_extension,
+                    mode: terms.mode,
+                    reserve: terms.reserve,
+                    buy_now_price: terms.buy_now_price,
+                }
+                .data(),
+            };
+            self.send(&[start], &[]).await?;
+
+            Ok(auction_info)
+        }
+
+        async fn funded_bidder(&mut self) -> Keypair {
+            let bidder = Keypair::new();
+            let transfer =
+                system_instruction::transfer(&self.payer.pubkey(), &bidder.pubkey(), 1_000_000_000);
+            self.send(&[transfer], &[]).await.unwrap();
+            bidder
+        }
+
+        async fn commit_bid(
+            &mut self,
+            auction_info: &Pubkey,
+            bidder: &Keypair,
+            commitment: [u8; 32],
+            deposit: u64,
+        ) -> std::result::Result<(), BanksClientError> {
+            let (pending_return, _) = Pubkey::find_program_address(
+                &[
+                    b"pending_return",
+                    auction_info.as_ref(),
+                    bidder.pubkey().as_ref(),
+                ],
+                &ID,
+            );
+            let commit_bid = Instruction {
+                program_id: ID,
+                accounts: accounts::BidCtx {
+                    bidder: bidder.pubkey(),
+                    auction_info: *auction_info,
+                    pending_return,
+                    system_program: system_program::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::CommitBid {
+                    commitment,
+                    deposit,
+                }
+                .data(),
+            };
+            self.send(&[commit_bid], &[bidder]).await
+        }
+
+        async fn reveal_bid(
+            &mut self,
+            auction_info: &Pubkey,
+            bidder: &Keypair,
+            amount: u64,
+            salt: &str,
+        ) -> std::result::Result<(), BanksClientError> {
+            let (pending_return, _) = Pubkey::find_program_address(
+                &[
+                    b"pending_return",
+                    auction_info.as_ref(),
+                    bidder.pubkey().as_ref(),
+                ],
+                &ID,
+            );
+            let reveal_bid = Instruction {
+                program_id: ID,
+                accounts: accounts::RevealBidCtx {
+                    bidder: bidder.pubkey(),
+                    auction_info: *auction_info,
+                    pending_return,
+                }
+                .to_account_metas(None),
+                data: instruction::RevealBid {
+                    amount,
+                    salt: salt.to_string(),
+                }
+                .data(),
+            };
+            self.send(&[reveal_bid], &[bidder]).await
+        }
+
+        async fn auction_info(&mut self, auction_info: &Pubkey) -> AuctionInfo {
+            let account = self
+                .context
+                .banks_client
+                .get_account(*auction_info)
+                .await
+                .unwrap()
+                .unwrap();
+            AuctionInfo::try_deserialize(&mut &account.data[..]).unwrap()
+        }
+    }
+
+    // The end of an auction, extended by the maximum extension, must be a
+    // slot number
+    #[tokio::test]
+    async fn an_end_past_the_last_slot_is_rejected() {
+        let mut test = Test::start().await;
+
+        assert!(test
+            .start_auction(u64::MAX, Terms::default())
+            .await
+            .is_err());
+        let terms = Terms {
+            extension: (10, 20, u64::MAX),
+            ..Terms::default()
+        };
+        assert!(test.start_auction(1_000, terms).await.is_err());
+
+        let terms = Terms {
+            extension: (10, 20, u64::MAX / 2),
+            ..Terms::default()
+        };
+        let auction_info = test.start_auction(1_000, terms).await.unwrap();
+        let info = test.auction_info(&auction_info).await;
+        assert_eq!(info.max_end_time, info.end_time + u64::MAX / 2);
+    }
+
+    fn bid_commitment(
+        bidder: &Keypair,
+        auction_info: &Pubkey,
+        amount: u64,
+        salt: &str,
+    ) -> [u8; 32] {
+        anchor_lang::solana_program::keccak::hashv(&[
+            bidder.pubkey().as_ref(),
+            auction_info.as_ref(),
+            &amount.to_le_bytes(),
+            salt.as_bytes(),
+        ])
+        .to_bytes()
+    }
+
+    // A sealed bid opens for its own bidder only, and the highest revealed
+    // bidder pays the second highest bid
+    #[tokio::test]
+    async fn sealed_bids_are_bound_to_their_bidder() {
+        let mut test = Test::start().await;
+        let terms = Terms {
+            mode: AuctionMode::Sealed {
+                reveal_slots: 100,
+                penalty_bps: 1_000,
+            },
+            ..Terms::default()
+        };
+        let auction_info = test.start_auction(1_000, terms).await.unwrap();
+        let (alice, bob, carol) = (
+            test.funded_bidder().await,
+            test.funded_bidder().await,
+            test.funded_bidder().await,
+        );
+
+        // Carol copies the commitment of Alice
+        let alices_commitment = bid_commitment(&alice, &auction_info, 5_000, "alice");
+        let bobs_commitment = bid_commitment(&bob, &auction_info, 3_000, "bob");
+        for (bidder, commitment) in [
+            (&alice, alices_commitment),
+            (&bob, bobs_commitment),
+            (&carol, alices_commitment),
+        ] {
+            test.commit_bid(&auction_info, bidder, commitment, 6_000)
+                .await
+                .unwrap();
+        }
+
+        let end_time = test.auction_info(&auction_info).await.end_time;
+        test.context.warp_to_slot(end_time + 1).unwrap();
+        assert!(test
+            .reveal_bid(&auction_info, &carol, 5_000, "alice")
+            .await
+            .is_err());
+        test.reveal_bid(&auction_info, &alice, 5_000, "alice")
+            .await
+            .unwrap();
+        test.reveal_bid(&auction_info, &bob, 3_000, "bob")
+            .await
+            .unwrap();
+
+        let info = test.auction_info(&auction_info).await;
+        assert_eq!(
+            (info.highest_bidder, info.highest_bid, info.second_bid),
+            (alice.pubkey(), 5_000, 3_000)
+        );
+    }
+}
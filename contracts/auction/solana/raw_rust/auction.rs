@@ -1,12 +1,13 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
-    msg,
-    program::invoke_signed,
+    keccak, msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction, system_program,
@@ -15,13 +16,116 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+// The seller index lists the active auctions of a seller in an account of
+// fixed size, paid by the seller at its first auction. 16 ids keep its rent
+// low and bound the scan of end and cancel, and a seller with as many
+// auctions running has to end or cancel one before starting another
+const MAX_ACTIVE_AUCTIONS: usize = 16;
+
+// Once the bidding is over, the seller has this many slots (about a day) to
+// end the auction, revealing its hidden reserve price if any. Afterwards
+// anyone can end it, and a hidden reserve price counts as not met, so that
+// the deposits of the highest bidder are never locked forever
+const END_DEADLINE_SLOTS: u64 = 216_000;
+
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct AuctionState {
     pub auctioned_object: String,
     pub seller: Pubkey,
+    // The auction PDA is seeded by the seller and the id, so that the name
+    // of the auctioned object can not be taken by other sellers
+    pub id: u64,
     pub highest_bidder: Pubkey,
+    pub start_time: u64,
     pub end_time: u64,
     pub highest_bid: u64,
+    // The second highest revealed bid of a sealed auction, which is what the
+    // highest bidder pays
+    pub second_bid: u64,
+    // A bid less than extension_window slots before the end pushes it out
+    // by extension_amount slots, up to max_end_time
+    pub max_end_time: u64,
+    pub extension_window: u64,
+    pub extension_amount: u64,
+    // The token account holding the auctioned tokens, owned by the auction
+    pub escrow_token_account: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub mode: AuctionMode,
+    pub reserve: ReservePrice,
+    pub buy_now_price: Option<u64>,
+    // The number of bids, or of sealed commitments: the seller can cancel the
+    // auction only with no bids
+    pub bids: u32,
+    // Bidding is closed early by a winning Dutch or buy-now bid
+    pub closed: bool,
+    pub ended: bool,
+    pub bump: u8,
+}
+
+impl AuctionState {
+    // What the highest bidder pays: the second highest bid in a sealed
+    // auction, the highest bid otherwise. Until the end, the whole highest
+    // sealed bid is locked, since a hidden reserve price may raise the price
+    // above the second highest bid
+    fn price(&self) -> u64 {
+        match self.mode {
+            AuctionMode::Sealed { .. } if self.ended => self.second_bid,
+            _ => self.highest_bid,
+        }
+    }
+
+    // The end of the bidding, including the reveal phase of a sealed auction
+    fn bidding_end_time(&self) -> u64 {
+        match self.mode {
+            AuctionMode::Sealed { reveal_slots, .. } => self.end_time + reveal_slots,
+            _ => self.end_time,
+        }
+    }
+}
+
+// In a Dutch auction, highest_bid is the starting price until the winning
+// bid. In a sealed auction, bids are committed until end_time, then revealed
+// for reveal_slots slots
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, Clone, Copy, PartialEq, Eq)]
+enum AuctionMode {
+    English,
+    Dutch { floor_price: u64 },
+    Sealed { reveal_slots: u64, penalty_bps: u16 },
+}
+
+// A hidden reserve price is committed to as keccak(price || salt), and
+// revealed by the seller at the end
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, Clone, Copy, PartialEq, Eq)]
+enum ReservePrice {
+    None,
+    Visible { price: u64 },
+    Hidden { commitment: [u8; 32] },
+}
+
+// The lamports deposited by a bidder and not yet withdrawn, including the
+// highest bid if the bidder is the highest bidder. The lamports are held by
+// the auction account
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
+struct PendingReturn {
+    pub amount: u64,
+    // The commitment to a sealed bid, keccak(bidder || auction || amount ||
+    // salt): the keys bind it to its bidder and its auction, so that another
+    // bidder can not copy it and reveal the same bid
+    pub commitment: [u8; 32],
+    pub revealed: bool,
+    pub bump: u8,
+}
+
+// The auctions of a seller are numbered from 0, and the ones not yet ended
+// are the first active_count ids of active, in its seller index. The array
+// has a fixed size, so the account is exactly as large as the index
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
+struct SellerIndex {
+    pub next_id: u64,
+    pub active_count: u8,
+    pub active: [u64; MAX_ACTIVE_AUCTIONS],
+    pub bump: u8,
 }
 
 pub fn process_instruction(
@@ -43,7 +147,23 @@ pub fn process_instruction(
             accounts,
             &instruction_data[1..instruction_data.len()],
         ),
-        2 => end(program_id, accounts),
+        2 => end(
+            program_id,
+            accounts,
+            &instruction_data[1..instruction_data.len()],
+        ),
+        3 => withdraw_refund(program_id, accounts),
+        4 => commit_bid(
+            program_id,
+            accounts,
+            &instruction_data[1..instruction_data.len()],
+        ),
+        5 => reveal_bid(
+            program_id,
+            accounts,
+            &instruction_data[1..instruction_data.len()],
+        ),
+        6 => cancel(program_id, accounts),
         _ => {
             msg!("Didn't found the entrypoint required");
             Err(ProgramError::InvalidInstructionData)
@@ -56,11 +176,20 @@ fn start(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8])
     let seller_account: &AccountInfo = next_account_info(accounts_iter)?;
     let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
     let system_program_account = next_account_info(accounts_iter)?;
+    // A token account of the seller holding the auctioned tokens, which is
+    // handed over to the auction
+    let escrow_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let seller_index_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
 
     if system_program_account.key != &system_program::id() {
         return Err(ProgramError::InvalidAccountData);
     }
 
+    if token_program_account.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
     if !seller_account.is_signer {
         msg!("The seller should be signer");
         return Err(ProgramError::MissingRequiredSignature);
@@ -68,14 +197,52 @@ fn start(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8])
 
     let end_time = u64::from_le_bytes(instruction_data[..8].try_into().unwrap());
     let initial_bid = u64::from_le_bytes(instruction_data[8..16].try_into().unwrap());
-    let auctioned_object = String::from_utf8(instruction_data[16..].to_vec()).unwrap();
+    let extension_window = u64::from_le_bytes(instruction_data[16..24].try_into().unwrap());
+    let extension_amount = u64::from_le_bytes(instruction_data[24..32].try_into().unwrap());
+    let max_extension = u64::from_le_bytes(instruction_data[32..40].try_into().unwrap());
+    let auction_id = u64::from_le_bytes(instruction_data[40..48].try_into().unwrap());
+    let mut rest = &instruction_data[48..];
+    let mode = AuctionMode::deserialize(&mut rest)?;
+    let reserve = ReservePrice::deserialize(&mut rest)?;
+    let buy_now_price = Option::<u64>::deserialize(&mut rest)?;
+    let auctioned_object = String::from_utf8(rest.to_vec()).unwrap();
+
+    let (auction_pda, auction_bump) = Pubkey::find_program_address(
+        &[
+            b"auction",
+            seller_account.key.as_ref(),
+            &auction_id.to_le_bytes(),
+        ],
+        program_id,
+    );
+
+    let escrow_token_account_data =
+        spl_token::state::Account::unpack(&escrow_token_account.data.borrow())?;
 
     let auction_state = AuctionState {
         auctioned_object,
         seller: *seller_account.key,
+        id: auction_id,
         highest_bidder: *seller_account.key, // The seller is the highest bidder at the beginning
+        start_time: Clock::get()?.slot,
         end_time,
         highest_bid: initial_bid,
+        second_bid: initial_bid,
+        max_end_time: end_time
+            .checked_add(max_extension)
+            .ok_or(ProgramError::InvalidInstructionData)?,
+        extension_window,
+        extension_amount,
+        escrow_token_account: *escrow_token_account.key,
+        mint: escrow_token_account_data.mint,
+        amount: escrow_token_account_data.amount,
+        mode,
+        reserve,
+        buy_now_price,
+        bids: 0,
+        closed: false,
+        ended: false,
+        bump: auction_bump,
     }; 
 
     if auction_state.end_time <= Clock::get()?.slot {
@@ -88,14 +255,74 @@ fn start(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8])
         return Err(ProgramError::InvalidInstructionData);
     }
 
-    let (auction_pda, auction_bump) =
-        Pubkey::find_program_address(&[auction_state.auctioned_object.as_bytes()], program_id);
+    if auction_state.amount == 0 {
+        msg!("The auctioned amount should be positive");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    match auction_state.mode {
+        AuctionMode::English => {}
+        AuctionMode::Dutch { floor_price } => {
+            if floor_price > auction_state.highest_bid {
+                msg!("The floor price can not be higher than the starting bid");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            // The first bid closes a Dutch auction, so a reserve price above
+            // the floor price, or one that can not be checked before the
+            // end, would let it close on a void sale
+            let valid_reserve = match auction_state.reserve {
+                ReservePrice::None => true,
+                ReservePrice::Visible { price } => price <= floor_price,
+                ReservePrice::Hidden { .. } => false,
+            };
+            if !valid_reserve {
+                msg!("The reserve price of a Dutch auction can not be hidden or above the floor price");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+        }
+        AuctionMode::Sealed { penalty_bps, .. } => {
+            if penalty_bps > 10000 {
+                msg!("The penalty can be at most 10000 basis points");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+        }
+    }
+
+    if let Some(buy_now_price) = auction_state.buy_now_price {
+        if auction_state.mode != AuctionMode::English || buy_now_price <= auction_state.highest_bid
+        {
+            msg!("The buy-now price should be higher than the starting bid, in an English auction");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    }
 
     if auction_pda != *auction_account_pda.key {
         msg!("Not the right PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
+    let mut seller_index = get_or_create_seller_index(
+        program_id,
+        seller_account,
+        seller_index_account_pda,
+        system_program_account,
+    )?;
+
+    if seller_index.next_id != auction_id {
+        msg!("The auction id should be the next one of the seller");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if seller_index.active_count as usize >= MAX_ACTIVE_AUCTIONS {
+        msg!("The seller has too many active auctions");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    seller_index.active[seller_index.active_count as usize] = auction_id;
+    seller_index.active_count += 1;
+    seller_index.next_id += 1;
+    seller_index.serialize(&mut &mut seller_index_account_pda.try_borrow_mut_data()?[..])?;
+
     let size = auction_state.try_to_vec()?.len();
     let rent_lamports = Rent::get()?.minimum_balance(size);
     invoke_signed(
@@ -111,19 +338,42 @@ fn start(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8])
             auction_account_pda.clone(),
             system_program_account.clone(),
         ],
-        &[&[auction_state.auctioned_object.as_bytes(), &[auction_bump]]],
+        &[&[
+            b"auction",
+            seller_account.key.as_ref(),
+            &auction_id.to_le_bytes(),
+            &[auction_bump],
+        ]],
     )?;
 
     auction_state.serialize(&mut &mut auction_account_pda.try_borrow_mut_data()?[..])?;
 
+    // The auction becomes the owner of the token account, until the end. The
+    // token program checks that the seller is its current owner
+    invoke(
+        &spl_token::instruction::set_authority(
+            token_program_account.key,
+            escrow_token_account.key,
+            Some(auction_account_pda.key),
+            spl_token::instruction::AuthorityType::AccountOwner,
+            seller_account.key,
+            &[&seller_account.key],
+        )?,
+        &[
+            escrow_token_account.clone(),
+            seller_account.clone(),
+            token_program_account.clone(),
+        ],
+    )?;
+
     Ok(())
 }
 
 fn bid(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
     let bidder_account: &AccountInfo = next_account_info(accounts_iter)?;
-    let current_highest_bidder_account: &AccountInfo = next_account_info(accounts_iter)?;
     let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let pending_return_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
     let system_program_account: &AccountInfo = next_account_info(accounts_iter)?;
 
     if !bidder_account.is_signer {
@@ -137,8 +387,15 @@ fn bid(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -
 
     let mut auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
 
-    let (auction_pda, _auction_bump) =
-        Pubkey::find_program_address(&[auction_state.auctioned_object.as_bytes()], program_id);
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
 
     if auction_pda != *auction_account_pda.key {
         msg!("Not the right PDA");
@@ -150,19 +407,54 @@ fn bid(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -
         .rev()
         .fold(0, |acc, &x| (acc << 8) + x as u64);
 
-    let (_auction_pda, auction_bump) =
-        Pubkey::find_program_address(&[auction_state.auctioned_object.as_bytes()], program_id);
-
-    if Clock::get()?.slot > auction_state.end_time {
+    let slot = Clock::get()?.slot;
+    if slot > auction_state.end_time || auction_state.closed {
         msg!("The auction is over");
         return Err(ProgramError::InvalidInstructionData);
     }
 
-    if amount_to_deposit <= auction_state.highest_bid {
-        msg!("The new amount should be higher than the previous");
-        return Err(ProgramError::InvalidInstructionData);
+    if auction_state.seller == *bidder_account.key {
+        msg!("The seller can not bid");
+        return Err(ProgramError::InvalidAccountData);
     }
 
+    // Whether the bid wins right away, closing the auction
+    let (amount_to_deposit, closes) = match auction_state.mode {
+        // A bid at or above the buy-now price wins, paying the buy-now price
+        AuctionMode::English => {
+            if amount_to_deposit <= auction_state.highest_bid {
+                msg!("The new amount should be higher than the previous");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            match auction_state.buy_now_price {
+                Some(buy_now_price) if amount_to_deposit >= buy_now_price => (buy_now_price, true),
+                _ => (amount_to_deposit, false),
+            }
+        }
+        // The first bid at or above the current price wins, paying the current
+        // price: the bid amount is the most the bidder pays
+        AuctionMode::Dutch { floor_price } => {
+            let price = dutch_price(&auction_state, floor_price, slot);
+            if amount_to_deposit < price {
+                msg!("The bid is lower than the current price");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            (price, true)
+        }
+        AuctionMode::Sealed { .. } => {
+            msg!("The instruction is not available in the mode of the auction");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    };
+
+    let mut pending_return = get_or_create_pending_return(
+        program_id,
+        bidder_account,
+        auction_account_pda,
+        pending_return_account_pda,
+        system_program_account,
+    )?;
+
     // Transfer founds from the new bidder to auction_account_pda
     invoke_signed(
         &system_instruction::transfer(
@@ -175,52 +467,1853 @@ fn bid(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -
             auction_account_pda.clone(),
             system_program_account.clone(),
         ],
-        &[&[auction_state.auctioned_object.as_bytes(), &[auction_bump]]],
+        &[&[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ]],
     )?;
 
-    // Return founds to the previous bidder if it's not the seller (there was at least one real bid)
-    if auction_state.highest_bidder != auction_state.seller {
-        **current_highest_bidder_account.try_borrow_mut_lamports()? += auction_state.highest_bid;
-        **auction_account_pda.try_borrow_mut_lamports()? -= auction_state.highest_bid;
-    }
+    // The previous bid is not pushed back to its bidder: every deposit stays
+    // in the auction and is recorded in the pending return of its bidder, who
+    // withdraws it with withdraw_refund once outbid
+    pending_return.amount += amount_to_deposit;
+    pending_return.serialize(&mut &mut pending_return_account_pda.try_borrow_mut_data()?[..])?;
 
     auction_state.highest_bid = amount_to_deposit;
     auction_state.highest_bidder = *bidder_account.key;
+    auction_state.bids += 1;
+
+    if closes {
+        // The winning bid ends the auction, which can be settled by end right
+        // away
+        auction_state.closed = true;
+        auction_state.end_time = slot;
+    } else if slot + auction_state.extension_window > auction_state.end_time {
+        // Anti-sniping: a bid in the last slots pushes the deadline out, so
+        // that the others can counter-bid, up to the maximum end time
+        auction_state.end_time = auction_state
+            .max_end_time
+            .min(auction_state.end_time + auction_state.extension_amount);
+    }
+
+    auction_state.serialize(&mut &mut auction_account_pda.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+fn end(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    // The seller, or anyone after the end deadline
+    let caller_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let seller_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let escrow_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let winners_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let seller_index_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if token_program_account.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if !caller_account.is_signer {
+        msg!("The caller should be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
+
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
+
+    if auction_pda != *auction_account_pda.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if auction_state.seller != *seller_account.key {
+        msg!("Not the seller of the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let by_seller = caller_account.key == seller_account.key;
+
+    let slot = Clock::get()?.slot;
+    if slot <= auction_state.bidding_end_time() && !auction_state.closed {
+        msg!("The auction is not over");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // A Dutch or buy-now auction is closed at the slot of its winning bid,
+    // which becomes its end time
+    if !by_seller && slot <= auction_state.bidding_end_time() + END_DEADLINE_SLOTS {
+        msg!("Only the seller can end the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if auction_state.ended {
+        msg!("The auction has already been ended");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    auction_state.ended = true;
+
+    // A hidden reserve price is revealed by the seller, as a sealed bid.
+    // When someone else ends the auction, it is not revealed
+    let reserve = match auction_state.reserve {
+        ReservePrice::None => Some(0),
+        ReservePrice::Visible { price } => Some(price),
+        ReservePrice::Hidden { .. } if !by_seller => None,
+        ReservePrice::Hidden { commitment } => {
+            if instruction_data.len() < 8 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            let reserve_price = u64::from_le_bytes(instruction_data[..8].try_into().unwrap());
+            let hash = keccak::hashv(&[&instruction_data[..8], &instruction_data[8..]]).to_bytes();
+            if hash != commitment {
+                msg!("The revealed reserve price does not match the commitment");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            Some(reserve_price)
+        }
+    };
+
+    // If the reserve price is not met, or not revealed, the auction is void:
+    // the deposits of the highest bidder are all refundable, and the
+    // auctioned tokens go back to the seller. Otherwise a sealed winner pays
+    // at least the reserve price
+    match reserve {
+        Some(reserve) if auction_state.highest_bid >= reserve => {
+            auction_state.second_bid = auction_state.second_bid.max(reserve);
+        }
+        _ => auction_state.highest_bidder = auction_state.seller,
+    }
+
+    if *escrow_token_account.key != auction_state.escrow_token_account {
+        msg!("Not the token account of the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The auctioned tokens go to the highest bidder, who is the seller itself
+    // if nobody bid or the reserve price is not met
+    let winners_token_account_data =
+        spl_token::state::Account::unpack(&winners_token_account.data.borrow())?;
+
+    if winners_token_account_data.owner != auction_state.highest_bidder
+        || winners_token_account_data.mint != auction_state.mint
+    {
+        msg!("Not a token account of the highest bidder for the auctioned mint");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let id = auction_state.id.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"auction",
+        auction_state.seller.as_ref(),
+        &id,
+        &[auction_state.bump],
+    ]];
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program_account.key,
+            escrow_token_account.key,
+            winners_token_account.key,
+            auction_account_pda.key,
+            &[],
+            auction_state.amount,
+        )?,
+        &[
+            escrow_token_account.clone(),
+            winners_token_account.clone(),
+            auction_account_pda.clone(),
+            token_program_account.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    // The emptied token account is closed, and its rent goes to the seller
+    invoke_signed(
+        &spl_token::instruction::close_account(
+            token_program_account.key,
+            escrow_token_account.key,
+            seller_account.key,
+            auction_account_pda.key,
+            &[],
+        )?,
+        &[
+            escrow_token_account.clone(),
+            seller_account.clone(),
+            auction_account_pda.clone(),
+            token_program_account.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    // Only the price goes to the seller: the auction keeps the outbid
+    // deposits until their bidders withdraw them
+    if auction_state.highest_bidder != auction_state.seller {
+        **seller_account.try_borrow_mut_lamports()? += auction_state.price();
+        **auction_account_pda.try_borrow_mut_lamports()? -= auction_state.price();
+    }
+
+    remove_active_auction(
+        program_id,
+        seller_account,
+        seller_index_account_pda,
+        auction_state.id,
+    )?;
 
     auction_state.serialize(&mut &mut auction_account_pda.try_borrow_mut_data()?[..])?;
 
     Ok(())
 }
 
-fn end(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+fn cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
     let seller_account: &AccountInfo = next_account_info(accounts_iter)?;
     let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let escrow_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let sellers_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let seller_index_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if token_program_account.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
     if !seller_account.is_signer {
         msg!("The seller should be signer");
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    if auction_account_pda.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
     let auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
 
-    let (auction_pda, _auction_bump) =
-        Pubkey::find_program_address(&[auction_state.auctioned_object.as_bytes()], program_id);
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
 
     if auction_pda != *auction_account_pda.key {
         msg!("Not the right PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
+    if auction_state.seller != *seller_account.key {
+        msg!("Only the seller can cancel the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
 
-    if Clock::get()?.slot <= auction_state.end_time {
-        msg!("The auction is not over");
+    if auction_state.ended {
+        msg!("The auction has already been ended");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if auction_state.bids > 0 {
+        msg!("The auction can not be canceled once bid");
         return Err(ProgramError::InvalidInstructionData);
     }
 
+    if *escrow_token_account.key != auction_state.escrow_token_account {
+        msg!("Not the token account of the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // With no bids there are no deposits: the auctioned tokens go back to the
+    // seller, and the token account and the auction are closed
+    let id = auction_state.id.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"auction",
+        auction_state.seller.as_ref(),
+        &id,
+        &[auction_state.bump],
+    ]];
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program_account.key,
+            escrow_token_account.key,
+            sellers_token_account.key,
+            auction_account_pda.key,
+            &[],
+            auction_state.amount,
+        )?,
+        &[
+            escrow_token_account.clone(),
+            sellers_token_account.clone(),
+            auction_account_pda.clone(),
+            token_program_account.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::close_account(
+            token_program_account.key,
+            escrow_token_account.key,
+            seller_account.key,
+            auction_account_pda.key,
+            &[],
+        )?,
+        &[
+            escrow_token_account.clone(),
+            seller_account.clone(),
+            auction_account_pda.clone(),
+            token_program_account.clone(),
+        ],
+        signer_seeds,
+    )?;
+
     **seller_account.try_borrow_mut_lamports()? += **auction_account_pda.try_borrow_lamports()?;
     **auction_account_pda.try_borrow_mut_lamports()? = 0;
 
+    remove_active_auction(
+        program_id,
+        seller_account,
+        seller_index_account_pda,
+        auction_state.id,
+    )?;
+
+    Ok(())
+}
+
+fn withdraw_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    let bidder_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let pending_return_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let seller_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !bidder_account.is_signer {
+        msg!("The bidder should be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if auction_account_pda.owner != program_id || pending_return_account_pda.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
+
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
+
+    if auction_pda != *auction_account_pda.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut pending_return =
+        PendingReturn::try_from_slice(*pending_return_account_pda.data.borrow())?;
+
+    let pending_return_pda = Pubkey::create_program_address(
+        &[
+            b"pending_return",
+            auction_account_pda.key.as_ref(),
+            bidder_account.key.as_ref(),
+            &[pending_return.bump],
+        ],
+        program_id,
+    )?;
+
+    if pending_return_pda != *pending_return_account_pda.key {
+        msg!("Not the right pending return PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if auction_state.seller != *seller_account.key {
+        msg!("Not the seller of the auction");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The deposits of the highest bidder cover the price, which is locked for
+    // the seller
+    let locked = if auction_state.highest_bidder == *bidder_account.key {
+        auction_state.price()
+    } else {
+        0
+    };
+    let refund = pending_return.amount - locked;
+
+    if refund == 0 {
+        msg!("Nothing to withdraw");
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    // Sealed deposits are locked until all bids are revealed, and those of the
+    // bidders who did not reveal are penalized in favour of the seller
+    let mut penalty = 0;
+    if let AuctionMode::Sealed { penalty_bps, .. } = auction_state.mode {
+        if Clock::get()?.slot <= auction_state.bidding_end_time() {
+            msg!("The reveal phase is not over");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if !pending_return.revealed {
+            penalty = (refund as u128 * penalty_bps as u128 / 10000) as u64;
+        }
+    }
+
+    pending_return.amount -= refund;
+    pending_return.serialize(&mut &mut pending_return_account_pda.try_borrow_mut_data()?[..])?;
+
+    **bidder_account.try_borrow_mut_lamports()? += refund - penalty;
+    **seller_account.try_borrow_mut_lamports()? += penalty;
+    **auction_account_pda.try_borrow_mut_lamports()? -= refund;
+
     Ok(())
 }
+
+fn commit_bid(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    let bidder_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let pending_return_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let system_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !bidder_account.is_signer {
+        msg!("The bidder should be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if system_program_account.key != &system_program::id() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if auction_account_pda.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
+
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
+
+    if auction_pda != *auction_account_pda.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if instruction_data.len() != 40 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let deposit = u64::from_le_bytes(instruction_data[..8].try_into().unwrap());
+    let commitment: [u8; 32] = instruction_data[8..40].try_into().unwrap();
+
+    if !matches!(auction_state.mode, AuctionMode::Sealed { .. }) {
+        msg!("The instruction is not available in the mode of the auction");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if Clock::get()?.slot > auction_state.end_time {
+        msg!("The auction is over");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if auction_state.seller == *bidder_account.key {
+        msg!("The seller can not bid");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut pending_return = get_or_create_pending_return(
+        program_id,
+        bidder_account,
+        auction_account_pda,
+        pending_return_account_pda,
+        system_program_account,
+    )?;
+
+    if pending_return.commitment != [0; 32] {
+        msg!("The bidder has already committed a bid");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The deposit hides the bid, which it must cover when revealed
+    invoke(
+        &system_instruction::transfer(bidder_account.key, auction_account_pda.key, deposit),
+        &[
+            bidder_account.clone(),
+            auction_account_pda.clone(),
+            system_program_account.clone(),
+        ],
+    )?;
+
+    pending_return.amount += deposit;
+    pending_return.commitment = commitment;
+    pending_return.serialize(&mut &mut pending_return_account_pda.try_borrow_mut_data()?[..])?;
+
+    auction_state.bids += 1;
+    auction_state.serialize(&mut &mut auction_account_pda.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+fn reveal_bid(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    let bidder_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let auction_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+    let pending_return_account_pda: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !bidder_account.is_signer {
+        msg!("The bidder should be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if auction_account_pda.owner != program_id || pending_return_account_pda.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut auction_state = AuctionState::try_from_slice(*auction_account_pda.data.borrow())?;
+
+    let auction_pda = Pubkey::create_program_address(
+        &[
+            b"auction",
+            auction_state.seller.as_ref(),
+            &auction_state.id.to_le_bytes(),
+            &[auction_state.bump],
+        ],
+        program_id,
+    )?;
+
+    if auction_pda != *auction_account_pda.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut pending_return =
+        PendingReturn::try_from_slice(*pending_return_account_pda.data.borrow())?;
+
+    let pending_return_pda = Pubkey::create_program_address(
+        &[
+            b"pending_return",
+            auction_account_pda.key.as_ref(),
+            bidder_account.key.as_ref(),
+            &[pending_return.bump],
+        ],
+        program_id,
+    )?;
+
+    if pending_return_pda != *pending_return_account_pda.key {
+        msg!("Not the right pending return PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if instruction_data.len() < 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = u64::from_le_bytes(instruction_data[..8].try_into().unwrap());
+    let salt = &instruction_data[8..];
+
+    if !matches!(auction_state.mode, AuctionMode::Sealed { .. }) {
+        msg!("The instruction is not available in the mode of the auction");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let slot = Clock::get()?.slot;
+    if slot <= auction_state.end_time || slot > auction_state.bidding_end_time() {
+        msg!("The bids can not be revealed now");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if pending_return.revealed {
+        msg!("The bid has already been revealed");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let hash = keccak::hashv(&[
+        bidder_account.key.as_ref(),
+        auction_account_pda.key.as_ref(),
+        &amount.to_le_bytes(),
+        salt,
+    ])
+    .to_bytes();
+
+    if hash != pending_return.commitment {
+        msg!("The revealed bid does not match the commitment");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if amount > pending_return.amount {
+        msg!("The deposit does not cover the revealed bid");
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    pending_return.revealed = true;
+    pending_return.serialize(&mut &mut pending_return_account_pda.try_borrow_mut_data()?[..])?;
+
+    // Vickrey: the highest bidder pays the second highest bid, which is at
+    // least the starting bid
+    if amount > auction_state.highest_bid {
+        auction_state.second_bid = auction_state.highest_bid;
+        auction_state.highest_bid = amount;
+        auction_state.highest_bidder = *bidder_account.key;
+    } else if amount > auction_state.second_bid {
+        auction_state.second_bid = amount;
+    }
+
+    auction_state.serialize(&mut &mut auction_account_pda.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+// The pending return of a bidder is created at its first bid
+fn get_or_create_pending_return<'a>(
+    program_id: &Pubkey,
+    bidder_account: &AccountInfo<'a>,
+    auction_account_pda: &AccountInfo<'a>,
+    pending_return_account_pda: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+) -> Result<PendingReturn, ProgramError> {
+    let (pending_return_pda, pending_return_bump) = Pubkey::find_program_address(
+        &[
+            b"pending_return",
+            auction_account_pda.key.as_ref(),
+            bidder_account.key.as_ref(),
+        ],
+        program_id,
+    );
+
+    if pending_return_pda != *pending_return_account_pda.key {
+        msg!("Not the right pending return PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if !pending_return_account_pda.data_is_empty() {
+        return Ok(PendingReturn::try_from_slice(
+            *pending_return_account_pda.data.borrow(),
+        )?);
+    }
+
+    let pending_return = PendingReturn {
+        amount: 0,
+        commitment: [0; 32],
+        revealed: false,
+        bump: pending_return_bump,
+    };
+    let size = pending_return.try_to_vec()?.len();
+    let rent_lamports = Rent::get()?.minimum_balance(size);
+    invoke_signed(
+        &system_instruction::create_account(
+            bidder_account.key,
+            pending_return_account_pda.key,
+            rent_lamports,
+            size.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            bidder_account.clone(),
+            pending_return_account_pda.clone(),
+            system_program_account.clone(),
+        ],
+        &[&[
+            b"pending_return",
+            auction_account_pda.key.as_ref(),
+            bidder_account.key.as_ref(),
+            &[pending_return_bump],
+        ]],
+    )?;
+    Ok(pending_return)
+}
+
+// The seller index of a seller is created at its first auction. Afterwards
+// its address is checked with the stored bump, without searching for it
+fn get_or_create_seller_index<'a>(
+    program_id: &Pubkey,
+    seller_account: &AccountInfo<'a>,
+    seller_index_account_pda: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+) -> Result<SellerIndex, ProgramError> {
+    if !seller_index_account_pda.data_is_empty() {
+        if seller_index_account_pda.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        let seller_index = SellerIndex::try_from_slice(*seller_index_account_pda.data.borrow())?;
+
+        let seller_index_pda = Pubkey::create_program_address(
+            &[
+                b"seller_index",
+                seller_account.key.as_ref(),
+                &[seller_index.bump],
+            ],
+            program_id,
+        )?;
+
+        if seller_index_pda != *seller_index_account_pda.key {
+            msg!("Not the right seller index PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        return Ok(seller_index);
+    }
+
+    let (seller_index_pda, seller_index_bump) =
+        Pubkey::find_program_address(&[b"seller_index", seller_account.key.as_ref()], program_id);
+
+    if seller_index_pda != *seller_index_account_pda.key {
+        msg!("Not the right seller index PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let seller_index = SellerIndex {
+        next_id: 0,
+        active_count: 0,
+        active: [0; MAX_ACTIVE_AUCTIONS],
+        bump: seller_index_bump,
+    };
+    let size = seller_index.try_to_vec()?.len();
+    let rent_lamports = Rent::get()?.minimum_balance(size);
+    invoke_signed(
+        &system_instruction::create_account(
+            seller_account.key,
+            seller_index_account_pda.key,
+            rent_lamports,
+            size.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            seller_account.clone(),
+            seller_index_account_pda.clone(),
+            system_program_account.clone(),
+        ],
+        &[&[
+            b"seller_index",
+            seller_account.key.as_ref(),
+            &[seller_index_bump],
+        ]],
+    )?;
+    Ok(seller_index)
+}
+
+// An ended or canceled auction is no longer active
+fn remove_active_auction(
+    program_id: &Pubkey,
+    seller_account: &AccountInfo,
+    seller_index_account_pda: &AccountInfo,
+    auction_id: u64,
+) -> ProgramResult {
+    if seller_index_account_pda.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut seller_index = SellerIndex::try_from_slice(*seller_index_account_pda.data.borrow())?;
+
+    let seller_index_pda = Pubkey::create_program_address(
+        &[
+            b"seller_index",
+            seller_account.key.as_ref(),
+            &[seller_index.bump],
+        ],
+        program_id,
+    )?;
+
+    if seller_index_pda != *seller_index_account_pda.key {
+        msg!("Not the right seller index PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The last active id takes the place of the removed one
+    let count = seller_index.active_count as usize;
+    if let Some(i) = seller_index.active[..count]
+        .iter()
+        .position(|&id| id == auction_id)
+    {
+        seller_index.active[i] = seller_index.active[count - 1];
+        seller_index.active[count - 1] = 0;
+        seller_index.active_count -= 1;
+    }
+    seller_index.serialize(&mut &mut seller_index_account_pda.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+// The price of a Dutch auction decays linearly from the starting bid, at the
+// start, to the floor price, at the end
+fn dutch_price(auction_state: &AuctionState, floor_price: u64, slot: u64) -> u64 {
+    let elapsed = (slot - auction_state.start_time) as u128;
+    let duration = (auction_state.end_time - auction_state.start_time) as u128;
+    if elapsed >= duration {
+        return floor_price;
+    }
+    let decay = (auction_state.highest_bid - floor_price) as u128 * elapsed / duration;
+    auction_state.highest_bid - decay as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+
+    async fn send(
+        context: &mut ProgramTestContext,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let blockhash = context.get_new_latest_blockhash().await?;
+        let mut all_signers = vec![&context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        context.banks_client.process_transaction(transaction).await
+    }
+
+    async fn auction_state(context: &mut ProgramTestContext, auction: &Pubkey) -> AuctionState {
+        let account = context
+            .banks_client
+            .get_account(*auction)
+            .await
+            .unwrap()
+            .unwrap();
+        AuctionState::try_from_slice(&account.data).unwrap()
+    }
+
+    // The terms of an auction besides its end, by default an English auction
+    // with a starting bid of 1_000 lamports and no extension
+    struct Terms {
+        initial_bid: u64,
+        extension: (u64, u64, u64),
+        mode: AuctionMode,
+        reserve: ReservePrice,
+        buy_now_price: Option<u64>,
+    }
+
+    impl Default for Terms {
+        fn default() -> Self {
+            Self {
+                initial_bid: 1_000,
+                extension: (0, 0, 0),
+                mode: AuctionMode::English,
+                reserve: ReservePrice::None,
+                buy_now_price: None,
+            }
+        }
+    }
+
+    struct Auction {
+        address: Pubkey,
+        mint: Pubkey,
+        escrow_token_account: Pubkey,
+    }
+
+    // Starts an auction of a token by the payer, with the next id of its
+    // seller index
+    async fn start_auction(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        end_time: u64,
+        terms: Terms,
+    ) -> Result<Auction, BanksClientError> {
+        let seller = context.payer.pubkey();
+        let (mint, escrow_token_account) = (Keypair::new(), Keypair::new());
+        let rent = context.banks_client.get_rent().await.unwrap();
+
+        let create_token_account = [
+            system_instruction::create_account(
+                &seller,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &seller,
+                None,
+                0,
+            )
+            .unwrap(),
+            system_instruction::create_account(
+                &seller,
+                &escrow_token_account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &escrow_token_account.pubkey(),
+                &mint.pubkey(),
+                &seller,
+            )
+            .unwrap(),
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &escrow_token_account.pubkey(),
+                &seller,
+                &[],
+                1,
+            )
+            .unwrap(),
+        ];
+        send(
+            context,
+            &create_token_account,
+            &[&mint, &escrow_token_account],
+        )
+        .await
+        .unwrap();
+
+        let auction_id = match context
+            .banks_client
+            .get_account(seller_index(program_id, &seller))
+            .await
+            .unwrap()
+        {
+            Some(account) => SellerIndex::try_from_slice(&account.data).unwrap().next_id,
+            None => 0,
+        };
+        let (auction, _) = Pubkey::find_program_address(
+            &[b"auction", seller.as_ref(), &auction_id.to_le_bytes()],
+            program_id,
+        );
+        let (extension_window, extension_amount, max_extension) = terms.extension;
+
+        let mut data = vec![0];
+        for value in [
+            end_time,
+            terms.initial_bid,
+            extension_window,
+            extension_amount,
+            max_extension,
+            auction_id,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&terms.mode.try_to_vec().unwrap());
+        data.extend_from_slice(&terms.reserve.try_to_vec().unwrap());
+        data.extend_from_slice(&terms.buy_now_price.try_to_vec().unwrap());
+        data.extend_from_slice(b"lot");
+
+        let start = Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new(seller, true),
+                AccountMeta::new(auction, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(escrow_token_account.pubkey(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(seller_index(program_id, &seller), false),
+            ],
+        );
+        send(context, &[start], &[]).await?;
+
+        Ok(Auction {
+            address: auction,
+            mint: mint.pubkey(),
+            escrow_token_account: escrow_token_account.pubkey(),
+        })
+    }
+
+    async fn start_english_auction(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        end_time: u64,
+        extension: (u64, u64, u64),
+    ) -> Pubkey {
+        let terms = Terms {
+            extension,
+            ..Terms::default()
+        };
+        start_auction(context, program_id, end_time, terms)
+            .await
+            .unwrap()
+            .address
+    }
+
+    fn seller_index(program_id: &Pubkey, seller: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"seller_index", seller.as_ref()], program_id).0
+    }
+
+    async fn bid(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        bidder: &Keypair,
+        amount: u64,
+    ) -> Result<(), BanksClientError> {
+        let (pending_return, _) = Pubkey::find_program_address(
+            &[
+                b"pending_return",
+                auction.as_ref(),
+                bidder.pubkey().as_ref(),
+            ],
+            program_id,
+        );
+
+        let mut data = vec![1];
+        data.extend_from_slice(&amount.to_le_bytes());
+        let bid = Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new(bidder.pubkey(), true),
+                AccountMeta::new(*auction, false),
+                AccountMeta::new(pending_return, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        send(context, &[bid], &[bidder]).await
+    }
+
+    fn bid_commitment(bidder: &Keypair, auction: &Pubkey, amount: u64, salt: &[u8]) -> [u8; 32] {
+        keccak::hashv(&[
+            bidder.pubkey().as_ref(),
+            auction.as_ref(),
+            &amount.to_le_bytes(),
+            salt,
+        ])
+        .to_bytes()
+    }
+
+    async fn commit_bid(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        bidder: &Keypair,
+        commitment: [u8; 32],
+        deposit: u64,
+    ) -> Result<(), BanksClientError> {
+        let (pending_return, _) = Pubkey::find_program_address(
+            &[
+                b"pending_return",
+                auction.as_ref(),
+                bidder.pubkey().as_ref(),
+            ],
+            program_id,
+        );
+
+        let mut data = vec![4];
+        data.extend_from_slice(&deposit.to_le_bytes());
+        data.extend_from_slice(&commitment);
+        let commit_bid = Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new(bidder.pubkey(), true),
+                AccountMeta::new(*auction, false),
+                AccountMeta::new(pending_return, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        send(context, &[commit_bid], &[bidder]).await
+    }
+
+    async fn reveal_bid(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        bidder: &Keypair,
+        amount: u64,
+        salt: &[u8],
+    ) -> Result<(), BanksClientError> {
+        let (pending_return, _) = Pubkey::find_program_address(
+            &[
+                b"pending_return",
+                auction.as_ref(),
+                bidder.pubkey().as_ref(),
+            ],
+            program_id,
+        );
+
+        let mut data = vec![5];
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.extend_from_slice(salt);
+        let reveal_bid = Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new_readonly(bidder.pubkey(), true),
+                AccountMeta::new(*auction, false),
+                AccountMeta::new(pending_return, false),
+            ],
+        );
+        send(context, &[reveal_bid], &[bidder]).await
+    }
+
+    async fn funded_bidder(context: &mut ProgramTestContext) -> Keypair {
+        let bidder = Keypair::new();
+        let transfer =
+            system_instruction::transfer(&context.payer.pubkey(), &bidder.pubkey(), 1_000_000_000);
+        send(context, &[transfer], &[]).await.unwrap();
+        bidder
+    }
+
+    // The fee of each signature of a transaction, paid by the payer, who is
+    // the seller
+    const FEE: u64 = 5_000;
+
+    async fn lamports(context: &mut ProgramTestContext, account: &Pubkey) -> u64 {
+        context.banks_client.get_balance(*account).await.unwrap()
+    }
+
+    // A token account of the auctioned mint, to receive the auctioned tokens
+    async fn create_token_account(
+        context: &mut ProgramTestContext,
+        auction: &Auction,
+        owner: &Pubkey,
+    ) -> Pubkey {
+        let token_account = Keypair::new();
+        let rent = context.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &context.payer.pubkey(),
+                &token_account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                &auction.mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+        send(context, &instructions, &[&token_account])
+            .await
+            .unwrap();
+        token_account.pubkey()
+    }
+
+    async fn token_balance(context: &mut ProgramTestContext, token_account: &Pubkey) -> u64 {
+        let account = context
+            .banks_client
+            .get_account(*token_account)
+            .await
+            .unwrap()
+            .unwrap();
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    // Ends the auction by the seller, revealing the hidden reserve price, if
+    // any, with the data of the instruction
+    async fn end(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Auction,
+        winners_token_account: &Pubkey,
+        reveal: &[u8],
+    ) -> Result<(), BanksClientError> {
+        let seller = context.payer.pubkey();
+        let mut data = vec![2];
+        data.extend_from_slice(reveal);
+        let end = Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new(seller, true),
+                AccountMeta::new(seller, false),
+                AccountMeta::new(auction.address, false),
+                AccountMeta::new(auction.escrow_token_account, false),
+                AccountMeta::new(*winners_token_account, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(seller_index(program_id, &seller), false),
+            ],
+        );
+        send(context, &[end], &[]).await
+    }
+
+    async fn cancel(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Auction,
+        sellers_token_account: &Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let seller = context.payer.pubkey();
+        let cancel = Instruction::new_with_bytes(
+            *program_id,
+            &[6],
+            vec![
+                AccountMeta::new(seller, true),
+                AccountMeta::new(auction.address, false),
+                AccountMeta::new(auction.escrow_token_account, false),
+                AccountMeta::new(*sellers_token_account, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(seller_index(program_id, &seller), false),
+            ],
+        );
+        send(context, &[cancel], &[]).await
+    }
+
+    async fn withdraw_refund(
+        context: &mut ProgramTestContext,
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        bidder: &Keypair,
+    ) -> Result<(), BanksClientError> {
+        let (pending_return, _) = Pubkey::find_program_address(
+            &[
+                b"pending_return",
+                auction.as_ref(),
+                bidder.pubkey().as_ref(),
+            ],
+            program_id,
+        );
+        let withdraw_refund = Instruction::new_with_bytes(
+            *program_id,
+            &[3],
+            vec![
+                AccountMeta::new(bidder.pubkey(), true),
+                AccountMeta::new(*auction, false),
+                AccountMeta::new(pending_return, false),
+                AccountMeta::new(context.payer.pubkey(), false),
+            ],
+        );
+        send(context, &[withdraw_refund], &[bidder]).await
+    }
+
+    // The bids in the last slots push the end out by the extension amount,
+    // up to the maximum extension, and the bidding closes at the extended end
+    #[tokio::test]
+    async fn late_bids_extend_the_end_up_to_the_max_extension() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        let end_time = 1_000;
+        let auction =
+            start_english_auction(&mut context, &program_id, end_time, (10, 20, 30)).await;
+        let (alice, bob) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+
+        // Outside of the extension window, the end does not move
+        context.warp_to_slot(end_time - 50).unwrap();
+        bid(&mut context, &program_id, &auction, &alice, 2_000)
+            .await
+            .unwrap();
+        assert_eq!(
+            auction_state(&mut context, &auction).await.end_time,
+            end_time
+        );
+
+        // Within it, the end moves by the extension amount
+        context.warp_to_slot(end_time - 5).unwrap();
+        bid(&mut context, &program_id, &auction, &bob, 3_000)
+            .await
+            .unwrap();
+        assert_eq!(
+            auction_state(&mut context, &auction).await.end_time,
+            end_time + 20
+        );
+
+        // A bid after the original end, within the extension, is still valid,
+        // and the end moves up to the maximum extension only
+        context.warp_to_slot(end_time + 15).unwrap();
+        bid(&mut context, &program_id, &auction, &alice, 4_000)
+            .await
+            .unwrap();
+        let state = auction_state(&mut context, &auction).await;
+        assert_eq!(state.end_time, end_time + 30);
+        assert_eq!(state.max_end_time, end_time + 30);
+
+        // Further bids can not push it further
+        context.warp_to_slot(end_time + 29).unwrap();
+        bid(&mut context, &program_id, &auction, &bob, 5_000)
+            .await
+            .unwrap();
+        let state = auction_state(&mut context, &auction).await;
+        assert_eq!(state.end_time, end_time + 30);
+        assert_eq!(
+            (state.highest_bid, state.highest_bidder),
+            (5_000, bob.pubkey())
+        );
+
+        // And the bidding closes at the maximum extension
+        context.warp_to_slot(end_time + 31).unwrap();
+        assert!(bid(&mut context, &program_id, &auction, &alice, 6_000)
+            .await
+            .is_err());
+    }
+
+    // The end of an auction, extended by the maximum extension, must be a
+    // slot number
+    #[tokio::test]
+    async fn an_end_past_the_last_slot_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        let terms = Terms {
+            extension: (10, 20, u64::MAX),
+            ..Terms::default()
+        };
+        assert!(start_auction(&mut context, &program_id, 1_000, terms)
+            .await
+            .is_err());
+
+        let terms = Terms {
+            extension: (10, 20, u64::MAX - 1_000),
+            ..Terms::default()
+        };
+        let auction = start_auction(&mut context, &program_id, 1_000, terms)
+            .await
+            .unwrap();
+        assert_eq!(
+            auction_state(&mut context, &auction.address)
+                .await
+                .max_end_time,
+            u64::MAX
+        );
+    }
+
+    // The seller index is created at the first auction of a seller, and
+    // lists the following ones
+    #[tokio::test]
+    async fn auctions_are_listed_in_the_seller_index() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        for _ in 0..3 {
+            start_auction(&mut context, &program_id, 1_000, Terms::default())
+                .await
+                .unwrap();
+        }
+
+        let account = context
+            .banks_client
+            .get_account(seller_index(&program_id, &context.payer.pubkey()))
+            .await
+            .unwrap()
+            .unwrap();
+        let index = SellerIndex::try_from_slice(&account.data).unwrap();
+        assert_eq!(index.next_id, 3);
+        assert_eq!(index.active[..index.active_count as usize], [0, 1, 2]);
+    }
+
+    // A hidden reserve price is revealed by the seller at the end: if the
+    // highest bid is below it, the auctioned token goes back to the seller,
+    // and the highest bidder withdraws the whole bid
+    #[tokio::test]
+    async fn a_hidden_reserve_price_not_met_refunds_the_highest_bid() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        let (reserve_price, salt) = (5_000u64, b"salt");
+        let commitment = keccak::hashv(&[&reserve_price.to_le_bytes(), salt]).to_bytes();
+        let terms = Terms {
+            reserve: ReservePrice::Hidden { commitment },
+            ..Terms::default()
+        };
+        let end_time = 1_000;
+        let auction = start_auction(&mut context, &program_id, end_time, terms)
+            .await
+            .unwrap();
+        let (alice, bob) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+        bid(&mut context, &program_id, &auction.address, &alice, 2_000)
+            .await
+            .unwrap();
+        bid(&mut context, &program_id, &auction.address, &bob, 3_000)
+            .await
+            .unwrap();
+
+        // The highest bid can not be withdrawn before the end
+        assert!(
+            withdraw_refund(&mut context, &program_id, &auction.address, &bob)
+                .await
+                .is_err()
+        );
+
+        let seller = context.payer.pubkey();
+        let sellers_token_account = create_token_account(&mut context, &auction, &seller).await;
+        let bobs_token_account = create_token_account(&mut context, &auction, &bob.pubkey()).await;
+        context.warp_to_slot(end_time + 1).unwrap();
+
+        // The revealed reserve price must match the commitment
+        let mut reveal = 4_000u64.to_le_bytes().to_vec();
+        reveal.extend_from_slice(salt);
+        assert!(end(
+            &mut context,
+            &program_id,
+            &auction,
+            &sellers_token_account,
+            &reveal
+        )
+        .await
+        .is_err());
+
+        // It is not met, so the winner is the seller itself
+        let mut reveal = reserve_price.to_le_bytes().to_vec();
+        reveal.extend_from_slice(salt);
+        assert!(end(
+            &mut context,
+            &program_id,
+            &auction,
+            &bobs_token_account,
+            &reveal
+        )
+        .await
+        .is_err());
+        let seller_lamports = lamports(&mut context, &seller).await;
+        end(
+            &mut context,
+            &program_id,
+            &auction,
+            &sellers_token_account,
+            &reveal,
+        )
+        .await
+        .unwrap();
+        assert_eq!(token_balance(&mut context, &sellers_token_account).await, 1);
+        let state = auction_state(&mut context, &auction.address).await;
+        assert_eq!(state.highest_bidder, seller);
+
+        // The seller gets the rent of the emptied token account, and no price
+        let rent = context.banks_client.get_rent().await.unwrap();
+        assert_eq!(
+            lamports(&mut context, &seller).await,
+            seller_lamports + rent.minimum_balance(spl_token::state::Account::LEN) - FEE
+        );
+
+        // Both bids are refunded, the highest one included
+        for (bidder, amount) in [(&bob, 3_000), (&alice, 2_000)] {
+            let before = lamports(&mut context, &bidder.pubkey()).await;
+            withdraw_refund(&mut context, &program_id, &auction.address, bidder)
+                .await
+                .unwrap();
+            assert_eq!(
+                lamports(&mut context, &bidder.pubkey()).await,
+                before + amount
+            );
+        }
+    }
+
+    // A bid at or above the buy-now price wins right away, paying the buy-now
+    // price, and the seller can end the auction before its end time
+    #[tokio::test]
+    async fn a_buy_now_bid_ends_the_auction() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        let terms = Terms {
+            buy_now_price: Some(5_000),
+            ..Terms::default()
+        };
+        let auction = start_auction(&mut context, &program_id, 1_000, terms)
+            .await
+            .unwrap();
+        let (alice, bob) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+        bid(&mut context, &program_id, &auction.address, &alice, 2_000)
+            .await
+            .unwrap();
+
+        context.warp_to_slot(100).unwrap();
+        bid(&mut context, &program_id, &auction.address, &bob, 6_000)
+            .await
+            .unwrap();
+        let state = auction_state(&mut context, &auction.address).await;
+        assert!(state.closed);
+        assert_eq!(state.end_time, 100);
+        assert_eq!(
+            (state.highest_bid, state.highest_bidder),
+            (5_000, bob.pubkey())
+        );
+
+        // The bidding is closed
+        assert!(
+            bid(&mut context, &program_id, &auction.address, &alice, 7_000)
+                .await
+                .is_err()
+        );
+
+        let seller = context.payer.pubkey();
+        let bobs_token_account = create_token_account(&mut context, &auction, &bob.pubkey()).await;
+        let seller_lamports = lamports(&mut context, &seller).await;
+        end(
+            &mut context,
+            &program_id,
+            &auction,
+            &bobs_token_account,
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(token_balance(&mut context, &bobs_token_account).await, 1);
+
+        // The seller gets the buy-now price, and the rent of the emptied token
+        // account
+        let rent = context.banks_client.get_rent().await.unwrap();
+        assert_eq!(
+            lamports(&mut context, &seller).await,
+            seller_lamports + rent.minimum_balance(spl_token::state::Account::LEN) + 5_000 - FEE
+        );
+    }
+
+    // The seller can cancel an auction with no bids only, getting the
+    // auctioned token back
+    #[tokio::test]
+    async fn an_auction_can_be_canceled_only_without_bids() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+        let seller = context.payer.pubkey();
+
+        let auction = start_auction(&mut context, &program_id, 1_000, Terms::default())
+            .await
+            .unwrap();
+        let sellers_token_account = create_token_account(&mut context, &auction, &seller).await;
+        let alice = funded_bidder(&mut context).await;
+        bid(&mut context, &program_id, &auction.address, &alice, 2_000)
+            .await
+            .unwrap();
+        assert!(
+            cancel(&mut context, &program_id, &auction, &sellers_token_account)
+                .await
+                .is_err()
+        );
+
+        let auction = start_auction(&mut context, &program_id, 1_000, Terms::default())
+            .await
+            .unwrap();
+        let sellers_token_account = create_token_account(&mut context, &auction, &seller).await;
+        cancel(&mut context, &program_id, &auction, &sellers_token_account)
+            .await
+            .unwrap();
+        assert_eq!(token_balance(&mut context, &sellers_token_account).await, 1);
+        assert!(context
+            .banks_client
+            .get_account(auction.address)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    // Every deposit stays in the auction: an outbid bidder withdraws all of
+    // its deposits, while those of the highest bidder cover its bid
+    #[tokio::test]
+    async fn only_outbid_deposits_can_be_withdrawn() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+
+        let auction = start_english_auction(&mut context, &program_id, 1_000, (0, 0, 0)).await;
+        let (alice, bob) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+
+        // Alice raises her own bid, depositing the amount of each bid
+        bid(&mut context, &program_id, &auction, &alice, 2_000)
+            .await
+            .unwrap();
+        bid(&mut context, &program_id, &auction, &alice, 3_000)
+            .await
+            .unwrap();
+        bid(&mut context, &program_id, &auction, &bob, 4_000)
+            .await
+            .unwrap();
+
+        // Bob is the highest bidder, with nothing to withdraw
+        assert!(withdraw_refund(&mut context, &program_id, &auction, &bob)
+            .await
+            .is_err());
+
+        let before = lamports(&mut context, &alice.pubkey()).await;
+        withdraw_refund(&mut context, &program_id, &auction, &alice)
+            .await
+            .unwrap();
+        assert_eq!(
+            lamports(&mut context, &alice.pubkey()).await,
+            before + 5_000
+        );
+
+        // And only once
+        assert!(withdraw_refund(&mut context, &program_id, &auction, &alice)
+            .await
+            .is_err());
+
+        // Once outbid in turn, Bob can withdraw his bid
+        bid(&mut context, &program_id, &auction, &alice, 5_000)
+            .await
+            .unwrap();
+        let before = lamports(&mut context, &bob.pubkey()).await;
+        withdraw_refund(&mut context, &program_id, &auction, &bob)
+            .await
+            .unwrap();
+        assert_eq!(lamports(&mut context, &bob.pubkey()).await, before + 4_000);
+    }
+
+    // The price of a Dutch auction decays linearly from the starting bid to
+    // the floor price at the end, and the first bid at or above it wins,
+    // paying the current price
+    #[tokio::test]
+    async fn the_dutch_price_decays_linearly_to_the_floor_price() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+        let (alice, bob) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+
+        let terms = Terms {
+            initial_bid: 10_000,
+            mode: AuctionMode::Dutch { floor_price: 2_000 },
+            ..Terms::default()
+        };
+        let end_time = 1_000;
+        let auction = start_auction(&mut context, &program_id, end_time, terms)
+            .await
+            .unwrap()
+            .address;
+        let start_time = auction_state(&mut context, &auction).await.start_time;
+
+        // A quarter of the way, the price has lost a quarter of the decay
+        let slot = start_time + (end_time - start_time) / 4;
+        let price = 10_000 - 8_000 * (slot - start_time) / (end_time - start_time);
+        context.warp_to_slot(slot).unwrap();
+        assert!(bid(&mut context, &program_id, &auction, &alice, price - 1)
+            .await
+            .is_err());
+
+        // A bid above the price pays the price only, and closes the auction
+        bid(&mut context, &program_id, &auction, &alice, 10_000)
+            .await
+            .unwrap();
+        let state = auction_state(&mut context, &auction).await;
+        assert!(state.closed);
+        assert_eq!(
+            (state.highest_bid, state.highest_bidder),
+            (price, alice.pubkey())
+        );
+        assert!(bid(&mut context, &program_id, &auction, &bob, 10_000)
+            .await
+            .is_err());
+
+        // At the end, the price is the floor price
+        let terms = Terms {
+            initial_bid: 10_000,
+            mode: AuctionMode::Dutch { floor_price: 2_000 },
+            ..Terms::default()
+        };
+        let end_time = 2_000;
+        let auction = start_auction(&mut context, &program_id, end_time, terms)
+            .await
+            .unwrap()
+            .address;
+        context.warp_to_slot(end_time).unwrap();
+        assert!(bid(&mut context, &program_id, &auction, &bob, 1_999)
+            .await
+            .is_err());
+        bid(&mut context, &program_id, &auction, &bob, 2_000)
+            .await
+            .unwrap();
+        assert_eq!(
+            auction_state(&mut context, &auction).await.highest_bid,
+            2_000
+        );
+    }
+
+    // The sealed bids are committed until the end, then revealed: the highest
+    // bidder wins and pays the second highest bid, and the bidders who did
+    // not reveal lose the penalty on their deposits to the seller
+    #[tokio::test]
+    async fn sealed_bids_are_revealed_and_settled_at_the_second_price() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new("auction", program_id, processor!(process_instruction));
+        let mut context = program_test.start_with_context().await;
+        let seller = context.payer.pubkey();
+
+        let terms = Terms {
+            mode: AuctionMode::Sealed {
+                reveal_slots: 100,
+                penalty_bps: 1_000,
+            },
+            ..Terms::default()
+        };
+        let end_time = 1_000;
+        let auction = start_auction(&mut context, &program_id, end_time, terms)
+            .await
+            .unwrap();
+        let address = auction.address;
+        let (alice, bob, carol, dave) = (
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+            funded_bidder(&mut context).await,
+        );
+
+        // The deposits hide the bids, and Carol copies the commitment of Alice
+        let alices_commitment = bid_commitment(&alice, &address, 5_000, b"alice");
+        let commitments = [
+            (&alice, alices_commitment, 6_000),
+            (&bob, bid_commitment(&bob, &address, 3_000, b"bob"), 3_000),
+            (&carol, alices_commitment, 6_000),
+            (
+                &dave,
+                bid_commitment(&dave, &address, 4_000, b"dave"),
+                4_000,
+            ),
+        ];
+        for (bidder, commitment, deposit) in commitments {
+            commit_bid(
+                &mut context,
+                &program_id,
+                &address,
+                bidder,
+                commitment,
+                deposit,
+            )
+            .await
+            .unwrap();
+        }
+        assert!(
+            commit_bid(&mut context, &program_id, &address, &bob, [1; 32], 1)
+                .await
+                .is_err()
+        );
+
+        // The bids are revealed after the end only
+        assert!(
+            reveal_bid(&mut context, &program_id, &address, &alice, 5_000, b"alice")
+                .await
+                .is_err()
+        );
+        context.warp_to_slot(end_time + 1).unwrap();
+        assert!(
+            commit_bid(&mut context, &program_id, &address, &carol, [1; 32], 1)
+                .await
+                .is_err()
+        );
+
+        // The copied commitment does not open for Carol
+        assert!(
+            reveal_bid(&mut context, &program_id, &address, &carol, 5_000, b"alice")
+                .await
+                .is_err()
+        );
+        reveal_bid(&mut context, &program_id, &address, &alice, 5_000, b"alice")
+            .await
+            .unwrap();
+        assert!(
+            reveal_bid(&mut context, &program_id, &address, &bob, 3_001, b"bob")
+                .await
+                .is_err()
+        );
+        reveal_bid(&mut context, &program_id, &address, &bob, 3_000, b"bob")
+            .await
+            .unwrap();
+        let state = auction_state(&mut context, &address).await;
+        assert_eq!(
+            (state.highest_bidder, state.highest_bid, state.second_bid),
+            (alice.pubkey(), 5_000, 3_000)
+        );
+
+        // The deposits are locked until the end of the reveal phase
+        assert!(withdraw_refund(&mut context, &program_id, &address, &bob)
+            .await
+            .is_err());
+        context.warp_to_slot(end_time + 101).unwrap();
+        assert!(
+            reveal_bid(&mut context, &program_id, &address, &dave, 4_000, b"dave")
+                .await
+                .is_err()
+        );
+
+        // Alice gets the token, and the seller the second highest bid
+        let alices_token_account =
+            create_token_account(&mut context, &auction, &alice.pubkey()).await;
+        let seller_lamports = lamports(&mut context, &seller).await;
+        end(
+            &mut context,
+            &program_id,
+            &auction,
+            &alices_token_account,
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(token_balance(&mut context, &alices_token_account).await, 1);
+        let rent = context.banks_client.get_rent().await.unwrap();
+        assert_eq!(
+            lamports(&mut context, &seller).await,
+            seller_lamports + rent.minimum_balance(spl_token::state::Account::LEN) + 3_000 - FEE
+        );
+
+        // Alice withdraws what her deposit exceeds the price by, Bob all his
+        // deposit, and Carol and Dave, who did not reveal, lose 10% of it
+        for (bidder, refund, penalty) in [
+            (&alice, 3_000, 0),
+            (&bob, 3_000, 0),
+            (&carol, 5_400, 600),
+            (&dave, 3_600, 400),
+        ] {
+            let bidder_lamports = lamports(&mut context, &bidder.pubkey()).await;
+            let seller_lamports = lamports(&mut context, &seller).await;
+            withdraw_refund(&mut context, &program_id, &address, bidder)
+                .await
+                .unwrap();
+            assert_eq!(
+                lamports(&mut context, &bidder.pubkey()).await,
+                bidder_lamports + refund
+            );
+            assert_eq!(
+                lamports(&mut context, &seller).await,
+                seller_lamports + penalty - 2 * FEE
+            );
+        }
+    }
+}
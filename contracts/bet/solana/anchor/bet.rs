@@ -18,6 +18,7 @@ pub mod bet {
         bet_info.participant2 = *participant2.key;
         bet_info.deadline = Clock::get()?.slot + delay;
         bet_info.wager = wager;
+        bet_info.bump = ctx.bumps.bet_info;
 
         system_program::transfer(
             CpiContext::new(
@@ -82,6 +83,7 @@ pub struct BetInfo {
     pub participant2: Pubkey,
     pub wager: u64,
     pub deadline: u64,
+    pub bump: u8,
 }
 
 #[derive(Accounts)]
@@ -121,7 +123,7 @@ pub struct WinCtx<'info> {
         mut, 
         has_one = oracle @ Error::InvalidOracle, // The provided oracle must match the oracle_bet_info.oracle
         seeds = [participant1.key().as_ref(), participant2.key().as_ref()], 
-        bump,
+        bump = bet_info.bump,
     )]
     pub bet_info: Account<'info, BetInfo>,
 
@@ -143,7 +145,7 @@ pub struct TimeoutCtx<'info> {
     #[account(
         mut,
         seeds = [participant1.key().as_ref(), participant2.key().as_ref()], 
-        bump,
+        bump = bet_info.bump,
     )]
     pub bet_info: Account<'info, BetInfo>,
 
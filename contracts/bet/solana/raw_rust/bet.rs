@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -16,17 +16,18 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 pub struct OracleBetInfo {
     pub oracle: Pubkey,       // 32 bytes
     pub participant1: Pubkey, // 32 bytes
     pub participant2: Pubkey, // 32 bytes
     pub wager: u64,           // 8 bytes
     pub deadline: u64,        // 8 bytes
+    pub bump: u8,             // 1 byte
 }
 
 impl OracleBetInfo {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8;
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1;
 }
 
 pub enum OracleBetInstruction {
@@ -134,6 +135,7 @@ fn join<'a>(
         participant2: *participant2_account.key,
         wager,
         deadline,
+        bump: pda_bump,
     };
 
     oracle_bet_info.serialize(&mut &mut oracle_bet_pda.try_borrow_mut_data()?[..])?;
@@ -181,22 +183,23 @@ fn win<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResul
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
+    let oracle_bet_info: OracleBetInfo =
+        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
         &[
             participant1_account.key.as_ref(),
             participant2_account.key.as_ref(),
+            &[oracle_bet_info.bump],
         ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *oracle_bet_pda.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let oracle_bet_info: OracleBetInfo =
-        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
-
     if oracle_bet_info.oracle != *oracle_account.key {
         msg!("The oracle isn't in the oracle_bet_info");
         return Err(ProgramError::InvalidInstructionData);
@@ -227,22 +230,23 @@ fn timeout<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramR
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
+    let oracle_bet_info: OracleBetInfo =
+        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
         &[
             participant1_account.key.as_ref(),
             participant2_account.key.as_ref(),
+            &[oracle_bet_info.bump],
         ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *oracle_bet_pda.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let oracle_bet_info: OracleBetInfo =
-        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
-
     if Clock::get()?.slot < oracle_bet_info.deadline {
         msg!("The timeout was not reached yet");
         return Err(ProgramError::InvalidInstructionData);
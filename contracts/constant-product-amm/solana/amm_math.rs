@@ -0,0 +1,1248 @@
+//! The math of the constant-product AMM, shared by the Anchor and the raw
+//! Rust programs and by the off-chain clients (see `tools/rosetta-sol`), so
+//! that the quotes are exactly the on-chain results, rounding included.
+//!
+//! It only depends on `core`, so it can be included with `#[path]` as a
+//! module of any crate, `no_std` ones included.
+
+// All the amounts are in base units of the respective mints, so the two mints
+// of a pool may have different decimals.
+// The swap fee is expressed in basis points of the input amount
+pub const FEE_DENOMINATOR: u64 = 10_000;
+
+// The maximum amplification coefficient of a StableSwap pool: the higher it
+// is, the closer the curve gets to the constant sum x + y
+pub const MAX_AMP: u64 = 10_000;
+
+// The Newton's method of the StableSwap math fails if it does not converge
+// within this number of iterations
+pub const MAX_ITERATIONS: usize = 255;
+
+// The liquidity locked forever by the first deposit, so that the price of a
+// liquidity token can not be inflated to round the later deposits down to 0
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteError {
+    // One of the deposited amounts is 0
+    ZeroAmount,
+    // The first deposit does not exceed the minimum liquidity
+    InsufficientInitialLiquidity,
+    // The deposit is too small to mint any liquidity
+    ZeroLiquidity,
+    // The redeemed liquidity is not less than the supply
+    ExceedsSupply,
+    // One of the reserves is 0
+    EmptyPool,
+    MathOverflow,
+}
+
+// The amounts taken by a deposit, and the liquidity minted for them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepositQuote {
+    pub amount0: u64,
+    pub amount1: u64,
+    // Minted to the sender
+    pub liquidity: u64,
+    // Added to the supply without being minted, on the first deposit
+    pub locked_liquidity: u64,
+}
+
+// A single-sided deposit: part of the input token is swapped through the
+// pool, and the rest is deposited together with the output of the swap.
+// The deposit amounts are in the order of the swap, i.e. amount0 is the one
+// of the input token and amount1 the one of the output token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZapInQuote {
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub deposit: DepositQuote,
+}
+
+// A single-sided redeem: the redeemed amount of the other token is swapped
+// through the pool into the output token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZapOutQuote {
+    // The redeemed amount of the output token
+    pub redeemed_out: u64,
+    // The redeemed amount of the other token, swapped into the output one
+    pub swap_in: u64,
+    pub swap_out: u64,
+    // The total amount of the output token
+    pub amount_out: u64,
+}
+
+// The output amount of a swap
+pub fn quote_swap(
+    curve: &impl Curve,
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+) -> Result<u64, QuoteError> {
+    curve
+        .amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+        .ok_or(QuoteError::MathOverflow)
+}
+
+// The price impact of a swap, in basis points: how much less the output is
+// than the input amount at the spot price before the swap, fee included
+pub fn price_impact_bps(
+    curve: &impl Curve,
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+) -> Result<u64, QuoteError> {
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(QuoteError::EmptyPool);
+    }
+
+    let amount_out = quote_swap(curve, amount_in, reserve_in, reserve_out, fee_bps)?;
+
+    // Scaled down to 64 bits, so that the amount at the spot price fits
+    let (mut numerator, mut denominator) = curve
+        .spot_price(reserve_in, reserve_out)
+        .ok_or(QuoteError::MathOverflow)?;
+    while numerator > u64::MAX as u128 || denominator > u64::MAX as u128 {
+        numerator >>= 1;
+        denominator >>= 1;
+    }
+
+    let spot_amount_out = amount_in as u128 * numerator / denominator.max(1);
+    if spot_amount_out == 0 {
+        return Ok(0);
+    }
+
+    Ok(
+        (spot_amount_out.saturating_sub(amount_out as u128) * FEE_DENOMINATOR as u128
+            / spot_amount_out) as u64,
+    )
+}
+
+// The amounts to deposit, at the current exchange rate: one of the desired
+// amounts is deposited in full, and the other one is reduced to keep the rate.
+// The excess is never transferred, so it stays with the sender.
+// The liquidity is proportional to the smaller share of the reserves, since
+// the deposit may not be exactly at the exchange rate because of the rounding.
+// The first deposit mints the geometric mean of the amounts, which does not
+// depend on the decimals or on the order of the mints, and MINIMUM_LIQUIDITY
+// of it is locked forever: it is counted in the supply, but never minted
+pub fn quote_deposit(
+    amount0_desired: u64,
+    amount1_desired: u64,
+    reserve0: u64,
+    reserve1: u64,
+    supply: u64,
+) -> Result<DepositQuote, QuoteError> {
+    if amount0_desired == 0 || amount1_desired == 0 {
+        return Err(QuoteError::ZeroAmount);
+    }
+
+    if supply == 0 {
+        let liquidity = sqrt(amount0_desired as u128 * amount1_desired as u128) as u64;
+        if liquidity <= MINIMUM_LIQUIDITY {
+            return Err(QuoteError::InsufficientInitialLiquidity);
+        }
+
+        return Ok(DepositQuote {
+            amount0: amount0_desired,
+            amount1: amount1_desired,
+            liquidity: liquidity - MINIMUM_LIQUIDITY,
+            locked_liquidity: MINIMUM_LIQUIDITY,
+        });
+    }
+
+    let amount1_optimal = quote(amount0_desired, reserve0, reserve1)?;
+    let (amount0, amount1) = if amount1_optimal <= amount1_desired {
+        (amount0_desired, amount1_optimal)
+    } else {
+        (quote(amount1_desired, reserve1, reserve0)?, amount1_desired)
+    };
+
+    let liquidity = core::cmp::min(
+        quote(amount0, reserve0, supply)?,
+        quote(amount1, reserve1, supply)?,
+    );
+    if liquidity == 0 {
+        return Err(QuoteError::ZeroLiquidity);
+    }
+
+    Ok(DepositQuote {
+        amount0,
+        amount1,
+        liquidity,
+        locked_liquidity: 0,
+    })
+}
+
+// The amounts returned for the redeemed liquidity, proportional to the
+// reserves and rounded down
+pub fn quote_redeem(
+    amount: u64,
+    reserve0: u64,
+    reserve1: u64,
+    supply: u64,
+) -> Result<(u64, u64), QuoteError> {
+    if amount >= supply {
+        return Err(QuoteError::ExceedsSupply);
+    }
+
+    Ok((
+        quote(amount, supply, reserve0)?,
+        quote(amount, supply, reserve1)?,
+    ))
+}
+
+// The split of a single-sided deposit which mints the most liquidity: the
+// swapped amount is the largest one for which the rest of the input amount is
+// still enough to deposit all the output of the swap, at the exchange rate of
+// the reserves after the swap (net of the protocol fee), found by bisection
+// with the swap math of the curve, or the next one. The excess of either
+// token is never transferred, so it stays with the sender
+pub fn quote_zap_in(
+    curve: &impl Curve,
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    supply: u64,
+    fee_bps: u16,
+    protocol_fee_share: u16,
+) -> Result<ZapInQuote, QuoteError> {
+    if amount_in == 0 {
+        return Err(QuoteError::ZeroAmount);
+    }
+
+    // The exchange rate is set by the first deposit, which takes both tokens
+    if supply == 0 || reserve_in == 0 || reserve_out == 0 {
+        return Err(QuoteError::EmptyPool);
+    }
+
+    // The reserves after swapping `swap_in`, and its output
+    let swap = |swap_in: u64| -> Result<(u64, u64, u64), QuoteError> {
+        let swap_out = quote_swap(curve, swap_in, reserve_in, reserve_out, fee_bps)?;
+        let reserve_in = reserve_in
+            .checked_add(swap_in - protocol_fee(swap_in, fee_bps, protocol_fee_share))
+            .ok_or(QuoteError::MathOverflow)?;
+        Ok((swap_out, reserve_in, reserve_out - swap_out))
+    };
+
+    let (mut low, mut high) = (0, amount_in);
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        let (swap_out, reserve_in, reserve_out) = swap(mid)?;
+
+        if (amount_in - mid) as u128 * reserve_out as u128 >= swap_out as u128 * reserve_in as u128
+        {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    [low, low + 1]
+        .into_iter()
+        .filter(|&swap_in| swap_in > 0 && swap_in < amount_in)
+        .filter_map(|swap_in| {
+            let (swap_out, reserve_in, reserve_out) = swap(swap_in).ok()?;
+            let deposit = quote_deposit(
+                amount_in - swap_in,
+                swap_out,
+                reserve_in,
+                reserve_out,
+                supply,
+            )
+            .ok()?;
+            Some(ZapInQuote {
+                swap_in,
+                swap_out,
+                deposit,
+            })
+        })
+        .reduce(|best, quote| {
+            if quote.deposit.liquidity > best.deposit.liquidity {
+                quote
+            } else {
+                best
+            }
+        })
+        .ok_or(QuoteError::ZeroLiquidity)
+}
+
+// The redeemed amounts, with the one of the other token swapped into the
+// output token at the reserves left by the redeem
+pub fn quote_zap_out(
+    curve: &impl Curve,
+    amount: u64,
+    reserve_out: u64,
+    reserve_other: u64,
+    supply: u64,
+    fee_bps: u16,
+) -> Result<ZapOutQuote, QuoteError> {
+    let (redeemed_out, swap_in) = quote_redeem(amount, reserve_out, reserve_other, supply)?;
+
+    let swap_out = if swap_in == 0 {
+        0
+    } else {
+        quote_swap(
+            curve,
+            swap_in,
+            reserve_other - swap_in,
+            reserve_out - redeemed_out,
+            fee_bps,
+        )?
+    };
+
+    Ok(ZapOutQuote {
+        redeemed_out,
+        swap_in,
+        swap_out,
+        amount_out: redeemed_out + swap_out,
+    })
+}
+
+// The amount of the other token worth `amount_a`, at the rate of the reserves
+pub fn quote(amount_a: u64, reserve_a: u64, reserve_b: u64) -> Result<u64, QuoteError> {
+    if reserve_a == 0 {
+        return Err(QuoteError::EmptyPool);
+    }
+
+    u64::try_from(amount_a as u128 * reserve_b as u128 / reserve_a as u128)
+        .map_err(|_| QuoteError::MathOverflow)
+}
+
+// The integer square root, rounded down, by the Babylonian method
+pub fn sqrt(y: u128) -> u128 {
+    if y < 4 {
+        return if y == 0 { 0 } else { 1 };
+    }
+
+    let mut z = y;
+    let mut x = y / 2 + 1;
+    while x < z {
+        z = x;
+        x = (y / x + x) / 2;
+    }
+
+    z
+}
+
+// The part of the swap fee on the input amount set aside for the protocol,
+// the share being in basis points of the fee. It is rounded down, so that
+// the rounding goes to the liquidity providers
+pub fn protocol_fee(amount_in: u64, fee_bps: u16, protocol_fee_share: u16) -> u64 {
+    (amount_in as u128 * fee_bps as u128 * protocol_fee_share as u128
+        / (FEE_DENOMINATOR as u128 * FEE_DENOMINATOR as u128)) as u64
+}
+
+// The fee on the given amount, rounded up
+pub fn fee_ceil(amount: u64, fee_bps: u16) -> u64 {
+    (amount as u128 * fee_bps as u128).div_ceil(FEE_DENOMINATOR as u128) as u64
+}
+
+// The math of a curve, used to price the swaps and to check the flash swaps.
+// The result is None when the calculation overflows
+pub trait Curve {
+    // The fee is taken from the input amount and left in the reserves, so
+    // that it accrues to the liquidity providers
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64>;
+
+    // The invariant of the reserves, which never decreases with a swap
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128>;
+
+    // The output amount per unit of input amount, for an infinitesimal swap
+    // without fee, as a fraction
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)>;
+}
+
+// x * y = k
+pub struct ConstantProduct;
+
+impl Curve for ConstantProduct {
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64> {
+        let amount_in_with_fee =
+            (amount_in as u128).checked_mul(FEE_DENOMINATOR.checked_sub(fee_bps as u64)? as u128)?;
+        let numerator = amount_in_with_fee.checked_mul(reserve_out as u128)?;
+        let denominator = (reserve_in as u128)
+            .checked_mul(FEE_DENOMINATOR as u128)?
+            .checked_add(amount_in_with_fee)?;
+
+        u64::try_from(numerator.checked_div(denominator)?).ok()
+    }
+
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128> {
+        Some(reserve0 as u128 * reserve1 as u128)
+    }
+
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)> {
+        Some((reserve_out as u128, reserve_in as u128))
+    }
+}
+
+// Curve's StableSwap invariant for two tokens, where Ann = amp * 2^2:
+//   Ann * (x + y) + D = Ann * D + D^3 / (4 * x * y)
+// It is close to the constant sum x + y = D when the reserves are balanced,
+// and to the constant product when they are not
+pub struct StableSwap {
+    pub amp: u64,
+}
+
+impl StableSwap {
+    // D, by Newton's method starting from x + y
+    pub fn compute_d(&self, x: u64, y: u64) -> Option<u128> {
+        if x == 0 || y == 0 {
+            return Some(0);
+        }
+
+        let (x, y) = (x as u128, y as u128);
+        let sum = x + y;
+        let ann = self.amp as u128 * 4;
+        let mut d = sum;
+
+        for _ in 0..MAX_ITERATIONS {
+            let d_p = Self::d_p(d, x, y)?;
+
+            let d_prev = d;
+            let numerator = ann
+                .checked_mul(sum)?
+                .checked_add(d_p.checked_mul(2)?)?
+                .checked_mul(d)?;
+            let denominator = (ann - 1).checked_mul(d)?.checked_add(d_p.checked_mul(3)?)?;
+            d = numerator / denominator;
+
+            if d.abs_diff(d_prev) <= 1 {
+                return Some(d);
+            }
+        }
+
+        None
+    }
+
+    // The reserve y that keeps D given the reserve x, by Newton's method on
+    //   y^2 + (x + D / Ann - D) * y = D^3 / (4 * x * Ann)
+    pub fn compute_y(&self, x: u64, d: u128) -> Option<u128> {
+        let x = x as u128;
+        let ann = self.amp as u128 * 4;
+
+        let c = Self::d_p(d, x, ann)?;
+        let b = x + d / ann;
+        let mut y = d;
+
+        for _ in 0..MAX_ITERATIONS {
+            let y_prev = y;
+            y = y.checked_mul(y)?.checked_add(c)? / (y * 2 + b).checked_sub(d)?;
+
+            if y.abs_diff(y_prev) <= 1 {
+                return Some(y);
+            }
+        }
+
+        None
+    }
+
+    // D^3 / (4 * x * y), dividing by the smaller of x and y first: with
+    // very unbalanced reserves, D^2 / 2x rounded down would keep too few
+    // digits for Newton's method to converge
+    fn d_p(d: u128, x: u128, y: u128) -> Option<u128> {
+        let (x, y) = (x.min(y), x.max(y));
+        let d_p = d.checked_mul(d)? / (x * 2);
+        Some(d_p.checked_mul(d)? / (y * 2))
+    }
+}
+
+impl Curve for StableSwap {
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64> {
+        if reserve_in == 0 || reserve_out == 0 {
+            return Some(0);
+        }
+
+        let amount_in_with_fee = (amount_in as u128
+            * FEE_DENOMINATOR.checked_sub(fee_bps as u64)? as u128
+            / FEE_DENOMINATOR as u128) as u64;
+
+        let d = self.compute_d(reserve_in, reserve_out)?;
+        let y = self.compute_y(reserve_in.checked_add(amount_in_with_fee)?, d)?;
+
+        // One more unit is kept by the pool, against the rounding of y
+        Some((reserve_out as u128).saturating_sub(y + 1) as u64)
+    }
+
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128> {
+        self.compute_d(reserve0, reserve1)
+    }
+
+    // The ratio of the partial derivatives of the invariant:
+    //   (Ann + D_P / x) / (Ann + D_P / y), with D_P = D^3 / (4 * x * y)
+    // multiplied by x * y on both sides
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)> {
+        if reserve_in == 0 || reserve_out == 0 {
+            return None;
+        }
+
+        let d = self.compute_d(reserve_in, reserve_out)?;
+        let (x, y) = (reserve_in as u128, reserve_out as u128);
+        let d_p = Self::d_p(d, x, y)?;
+        let ann_xy = (self.amp as u128 * 4).checked_mul(x)?.checked_mul(y)?;
+
+        Some((
+            ann_xy.checked_add(d_p.checked_mul(y)?)?,
+            ann_xy.checked_add(d_p.checked_mul(x)?)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESERVES: [u64; 5] = [1_001, 1_000_000, 123_456_789, 10u64.pow(15), u64::MAX / 2];
+    const AMOUNTS: [u64; 4] = [1, 999, 100_000, 10u64.pow(12)];
+    const FEES: [u16; 4] = [0, 1, 30, 1_000];
+
+    // The output of a swap as the instructions used to compute it, before the
+    // math was shared with the clients
+    fn constant_product_out(
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> u64 {
+        let amount_in_with_fee = amount_in as u128 * (10_000 - fee_bps as u128);
+        (amount_in_with_fee * reserve_out as u128
+            / (reserve_in as u128 * 10_000 + amount_in_with_fee)) as u64
+    }
+
+    #[test]
+    fn quote_swap_matches_the_instruction_math() {
+        for reserve_in in RESERVES {
+            for reserve_out in RESERVES {
+                for amount_in in AMOUNTS {
+                    for fee_bps in FEES {
+                        assert_eq!(
+                            quote_swap(
+                                &ConstantProduct,
+                                amount_in,
+                                reserve_in,
+                                reserve_out,
+                                fee_bps
+                            ),
+                            Ok(constant_product_out(
+                                amount_in,
+                                reserve_in,
+                                reserve_out,
+                                fee_bps
+                            )),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quote_swap_overflow_is_an_error() {
+        assert_eq!(
+            quote_swap(&ConstantProduct, u64::MAX, u64::MAX, u64::MAX, 0),
+            Err(QuoteError::MathOverflow),
+        );
+        assert_eq!(
+            ConstantProduct.amount_out(1, 1, 1, FEE_DENOMINATOR as u16 + 1),
+            None
+        );
+    }
+
+    #[test]
+    fn quote_rejects_an_empty_reserve() {
+        assert_eq!(quote(1, 0, 1), Err(QuoteError::EmptyPool));
+        assert_eq!(quote(u64::MAX, 1, 2), Err(QuoteError::MathOverflow));
+        assert_eq!(quote(7, 2, 3), Ok(10));
+        assert_eq!(
+            quote_deposit(1_000, 1_000, 0, 1_000, 1_000),
+            Err(QuoteError::EmptyPool)
+        );
+    }
+
+    #[test]
+    fn quote_deposit_matches_the_instruction_math() {
+        assert_eq!(
+            quote_deposit(4_000, 9_000, 0, 0, 0),
+            Ok(DepositQuote {
+                amount0: 4_000,
+                amount1: 9_000,
+                liquidity: 6_000 - MINIMUM_LIQUIDITY,
+                locked_liquidity: MINIMUM_LIQUIDITY,
+            })
+        );
+
+        for reserve0 in RESERVES {
+            for reserve1 in RESERVES {
+                let supply = sqrt(reserve0 as u128 * reserve1 as u128) as u64;
+                for amount0_desired in AMOUNTS {
+                    for amount1_desired in AMOUNTS {
+                        let quote = quote_deposit(
+                            amount0_desired,
+                            amount1_desired,
+                            reserve0,
+                            reserve1,
+                            supply,
+                        );
+
+                        // It used to be truncated to 64 bits, and is now an error
+                        let amount1_optimal =
+                            amount0_desired as u128 * reserve1 as u128 / reserve0 as u128;
+                        if amount1_optimal > u64::MAX as u128 {
+                            assert_eq!(quote, Err(QuoteError::MathOverflow));
+                            continue;
+                        }
+
+                        let (amount0, amount1) = if amount1_optimal as u64 <= amount1_desired {
+                            (amount0_desired, amount1_optimal as u64)
+                        } else {
+                            let amount0_optimal =
+                                amount1_desired as u128 * reserve0 as u128 / reserve1 as u128;
+                            (amount0_optimal as u64, amount1_desired)
+                        };
+                        let liquidity = core::cmp::min(
+                            amount0 as u128 * supply as u128 / reserve0 as u128,
+                            amount1 as u128 * supply as u128 / reserve1 as u128,
+                        ) as u64;
+
+                        if liquidity == 0 {
+                            assert_eq!(quote, Err(QuoteError::ZeroLiquidity));
+                        } else {
+                            assert_eq!(
+                                quote,
+                                Ok(DepositQuote {
+                                    amount0,
+                                    amount1,
+                                    liquidity,
+                                    locked_liquidity: 0,
+                                })
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quote_redeem_matches_the_instruction_math() {
+        for reserve0 in RESERVES {
+            for reserve1 in RESERVES {
+                let supply = sqrt(reserve0 as u128 * reserve1 as u128) as u64;
+                for amount in AMOUNTS.into_iter().filter(|&amount| amount < supply) {
+                    assert_eq!(
+                        quote_redeem(amount, reserve0, reserve1, supply),
+                        Ok((
+                            (amount as u128 * reserve0 as u128 / supply as u128) as u64,
+                            (amount as u128 * reserve1 as u128 / supply as u128) as u64,
+                        ))
+                    );
+                }
+                assert_eq!(
+                    quote_redeem(supply, reserve0, reserve1, supply),
+                    Err(QuoteError::ExceedsSupply)
+                );
+            }
+        }
+    }
+
+    // A zap in is a swap followed by a deposit at the reserves after the swap,
+    // which is how the instruction updates the pool
+    #[test]
+    fn quote_zap_in_is_a_swap_then_a_deposit() {
+        let curves = [
+            CurveKind::ConstantProduct,
+            CurveKind::StableSwap(1),
+            CurveKind::StableSwap(100),
+        ];
+        for curve in curves {
+            for (reserve_in, reserve_out) in [
+                (1_000_000, 1_000_000),
+                (1_000_000, 3_000_000),
+                (5_000_000, 70_000_000),
+            ] {
+                let supply = sqrt(reserve_in as u128 * reserve_out as u128) as u64;
+                for amount_in in [10_000, 500_000, 2_000_000] {
+                    for (fee_bps, protocol_fee_share) in [(0, 0), (30, 0), (30, 2_000)] {
+                        let quote = curve
+                            .zap_in(
+                                amount_in,
+                                reserve_in,
+                                reserve_out,
+                                supply,
+                                fee_bps,
+                                protocol_fee_share,
+                            )
+                            .unwrap();
+                        assert_eq!(
+                            curve.swap(quote.swap_in, reserve_in, reserve_out, fee_bps),
+                            Ok(quote.swap_out)
+                        );
+
+                        let reserve_in = reserve_in + quote.swap_in
+                            - protocol_fee(quote.swap_in, fee_bps, protocol_fee_share);
+                        let reserve_out = reserve_out - quote.swap_out;
+                        assert_eq!(
+                            quote_deposit(
+                                amount_in - quote.swap_in,
+                                quote.swap_out,
+                                reserve_in,
+                                reserve_out,
+                                supply,
+                            ),
+                            Ok(quote.deposit)
+                        );
+                        assert!(quote.swap_in + quote.deposit.amount0 <= amount_in);
+                        assert!(quote.deposit.amount1 <= quote.swap_out);
+                    }
+                }
+            }
+        }
+    }
+
+    // A zap out is a redeem followed by a swap at the reserves after the
+    // redeem, which is how the instruction updates the pool
+    #[test]
+    fn quote_zap_out_is_a_redeem_then_a_swap() {
+        for curve in [CurveKind::ConstantProduct, CurveKind::StableSwap(100)] {
+            for (reserve_out, reserve_other) in [(1_000_000, 1_000_000), (9_000, 4_000_000)] {
+                let supply = sqrt(reserve_out as u128 * reserve_other as u128) as u64;
+                for amount in [1, 1_000, supply / 2, supply - 1] {
+                    let quote = curve
+                        .zap_out(amount, reserve_out, reserve_other, supply, 30)
+                        .unwrap();
+                    let (redeemed_out, swap_in) =
+                        quote_redeem(amount, reserve_out, reserve_other, supply).unwrap();
+
+                    assert_eq!((quote.redeemed_out, quote.swap_in), (redeemed_out, swap_in));
+                    assert_eq!(
+                        curve.swap(
+                            swap_in,
+                            reserve_other - swap_in,
+                            reserve_out - redeemed_out,
+                            30
+                        ),
+                        Ok(quote.swap_out)
+                    );
+                    assert_eq!(quote.amount_out, redeemed_out + quote.swap_out);
+                    assert!(quote.amount_out < reserve_out);
+                }
+            }
+        }
+    }
+
+    // Swaps back and forth as the swap instruction does, returning the
+    // invariant of the reserves after each swap
+    fn swap_back_and_forth(
+        curve: &impl Curve,
+        (mut reserve0, mut reserve1): (u64, u64),
+        fee_bps: u16,
+        protocol_fee_share: u16,
+    ) -> Vec<u128> {
+        let mut invariants = vec![curve.invariant(reserve0, reserve1).unwrap()];
+        for (i, amount_in) in [1, 7, 1_000, 33_333, 250_000, 999_999, 5, 4_000_000]
+            .into_iter()
+            .enumerate()
+        {
+            let (reserve_in, reserve_out) = if i % 2 == 0 {
+                (&mut reserve0, &mut reserve1)
+            } else {
+                (&mut reserve1, &mut reserve0)
+            };
+            let amount_out =
+                quote_swap(curve, amount_in, *reserve_in, *reserve_out, fee_bps).unwrap();
+
+            *reserve_in += amount_in - protocol_fee(amount_in, fee_bps, protocol_fee_share);
+            *reserve_out -= amount_out;
+            invariants.push(curve.invariant(reserve0, reserve1).unwrap());
+        }
+
+        invariants
+    }
+
+    // The fee is left in the reserves, so k grows with every swap
+    #[test]
+    fn constant_product_k_increases_with_every_swap() {
+        for reserves in [
+            (1_000_000, 1_000_000),
+            (2_000, 3_000_000_000),
+            (10u64.pow(15), 10u64.pow(9)),
+        ] {
+            for fee_bps in [1, 30, 100, 9_999] {
+                let invariants = swap_back_and_forth(&ConstantProduct, reserves, fee_bps, 0);
+                assert!(
+                    invariants.windows(2).all(|k| k[0] < k[1]),
+                    "{reserves:?}, {fee_bps}: {invariants:?}"
+                );
+            }
+
+            // Without a fee, k still never decreases, because of the rounding
+            let invariants = swap_back_and_forth(&ConstantProduct, reserves, 0, 0);
+            assert!(invariants.windows(2).all(|k| k[0] <= k[1]));
+        }
+    }
+
+    // A pool of a token of 6 decimals and one of 9 decimals, worth 150 of the
+    // first: the amounts are in base units, so only the exchange rate of the
+    // reserves differs from the one of whole tokens
+    #[test]
+    fn mixed_decimals_pool() {
+        let (one0, one1) = (10u64.pow(6), 10u64.pow(9));
+        let (amount0, amount1) = (150_000 * one0, 1_000 * one1);
+
+        let first = quote_deposit(amount0, amount1, 0, 0, 0).unwrap();
+        assert_eq!(
+            first.liquidity + first.locked_liquidity,
+            sqrt(amount0 as u128 * amount1 as u128) as u64
+        );
+        // The geometric mean does not depend on the order of the mints
+        let swapped = quote_deposit(amount1, amount0, 0, 0, 0).unwrap();
+        assert_eq!(swapped.liquidity, first.liquidity);
+
+        let (reserve0, reserve1) = (amount0, amount1);
+        let supply = first.liquidity + first.locked_liquidity;
+
+        // A whole token of the second mint is worth 150 of the first one,
+        // less the fee and the price impact
+        let amount_out = quote_swap(&ConstantProduct, one1, reserve1, reserve0, 30).unwrap();
+        assert_eq!(amount_out, 149_401_047);
+        let amount_out = quote_swap(&ConstantProduct, 150 * one0, reserve0, reserve1, 30).unwrap();
+        assert_eq!(amount_out, 996_006_981);
+
+        // A later deposit takes the amounts at the exchange rate of the
+        // reserves, whatever the decimals
+        let deposit = quote_deposit(300 * one0, 10 * one1, reserve0, reserve1, supply).unwrap();
+        assert_eq!((deposit.amount0, deposit.amount1), (300 * one0, 2 * one1));
+
+        // And its redeem returns the same amounts, rounded down
+        let (redeemed0, redeemed1) = quote_redeem(
+            deposit.liquidity,
+            reserve0 + deposit.amount0,
+            reserve1 + deposit.amount1,
+            supply + deposit.liquidity,
+        )
+        .unwrap();
+        assert!(redeemed0 <= deposit.amount0 && deposit.amount0 - redeemed0 <= 1);
+        assert!(redeemed1 <= deposit.amount1 && deposit.amount1 - redeemed1 <= 1_000);
+    }
+
+    // At the extremes of the decimals, a whole token of 0 decimals against
+    // 12 decimals, the math neither overflows nor rounds the amounts to 0
+    #[test]
+    fn extreme_decimals_pool() {
+        let (reserve0, reserve1) = (1_000_000, 1_000_000 * 10u64.pow(12));
+
+        let first = quote_deposit(reserve0, reserve1, 0, 0, 0).unwrap();
+        let supply = first.liquidity + first.locked_liquidity;
+        assert_eq!(supply, 10u64.pow(12));
+
+        assert_eq!(
+            quote_swap(&ConstantProduct, 1, reserve0, reserve1, 0),
+            Ok(999_999_000_000)
+        );
+        assert_eq!(
+            quote_swap(&ConstantProduct, 10u64.pow(12), reserve1, reserve0, 0),
+            Ok(0)
+        );
+        assert_eq!(
+            quote_swap(&ConstantProduct, 2 * 10u64.pow(12), reserve1, reserve0, 0),
+            Ok(1)
+        );
+
+        let deposit = quote_deposit(1, 10u64.pow(12), reserve0, reserve1, supply).unwrap();
+        assert_eq!(
+            (deposit.amount0, deposit.amount1, deposit.liquidity),
+            (1, 10u64.pow(12), 10u64.pow(6))
+        );
+    }
+
+    // Off, the protocol fee is always 0, and on, it is at most the swap fee
+    #[test]
+    fn protocol_fee_is_part_of_the_swap_fee() {
+        for amount_in in AMOUNTS.into_iter().chain([u64::MAX]) {
+            for fee_bps in FEES {
+                assert_eq!(protocol_fee(amount_in, fee_bps, 0), 0);
+                for protocol_fee_share in [1, 2_000, 10_000] {
+                    let fee = amount_in as u128 * fee_bps as u128 / FEE_DENOMINATOR as u128;
+                    assert!(protocol_fee(amount_in, fee_bps, protocol_fee_share) as u128 <= fee);
+                }
+            }
+        }
+
+        // So that k still grows with every swap when it is on, if less than
+        // when it is off
+        let reserves = (1_000_000, 3_000_000);
+        let invariants_off = swap_back_and_forth(&ConstantProduct, reserves, 30, 0);
+        let invariants_on = swap_back_and_forth(&ConstantProduct, reserves, 30, 10_000);
+        assert!(invariants_on.windows(2).all(|k| k[0] <= k[1]));
+        assert!(invariants_on.last() < invariants_off.last());
+    }
+
+    // The donation attack on the first deposit: the attacker mints the
+    // smallest liquidity, then donates to the pool (e.g. paying back a flash
+    // swap) to inflate the price of a liquidity token, so that the deposit of
+    // a victim is rounded down to fewer liquidity tokens, and finally redeems
+    // its own at the inflated price. Returns the profit of the attacker, and
+    // the liquidity minted to the victim
+    fn donation_attack(
+        first_deposit: DepositQuote,
+        donation: u64,
+        victim_deposit: u64,
+    ) -> (i128, Result<u64, QuoteError>) {
+        let attacker_liquidity = first_deposit.liquidity;
+        let mut supply = attacker_liquidity + first_deposit.locked_liquidity;
+        let mut reserve = first_deposit.amount0 + donation;
+
+        let victim_liquidity =
+            quote_deposit(victim_deposit, victim_deposit, reserve, reserve, supply)
+                .map(|deposit| deposit.liquidity);
+        if let Ok(liquidity) = victim_liquidity {
+            supply += liquidity;
+            reserve += victim_deposit;
+        }
+
+        let (redeemed, _) = quote_redeem(attacker_liquidity, reserve, reserve, supply).unwrap();
+        let cost = first_deposit.amount0 + donation;
+        (redeemed as i128 - cost as i128, victim_liquidity)
+    }
+
+    // Without the locked liquidity, the attack steals a quarter of the
+    // deposit of the victim, as in the README
+    #[test]
+    fn donation_attack_without_the_locked_liquidity() {
+        let first_deposit = DepositQuote {
+            amount0: 1,
+            amount1: 1,
+            liquidity: 1,
+            locked_liquidity: 0,
+        };
+        let (profit, victim_liquidity) =
+            donation_attack(first_deposit, 10u64.pow(9), 1_500_000_000);
+
+        assert_eq!(victim_liquidity, Ok(1));
+        assert_eq!(profit, 249_999_999);
+    }
+
+    // With it, the first deposit can not mint a single liquidity token, and
+    // the attack always costs more than it steals: most of the donation goes
+    // to the locked liquidity
+    #[test]
+    fn donation_attack_is_unprofitable() {
+        assert_eq!(
+            quote_deposit(1, 1, 0, 0, 0),
+            Err(QuoteError::InsufficientInitialLiquidity)
+        );
+        assert_eq!(
+            quote_deposit(1_000, 1_000, 0, 0, 0),
+            Err(QuoteError::InsufficientInitialLiquidity)
+        );
+
+        let first_deposit = quote_deposit(1_001, 1_001, 0, 0, 0).unwrap();
+        assert_eq!(first_deposit.liquidity, 1);
+
+        for donation in [0, 1_000, 10u64.pow(6), 10u64.pow(9), 10u64.pow(12)] {
+            for victim_deposit in [1, 1_000, 10u64.pow(6), 1_500_000_000, 10u64.pow(13)] {
+                let (profit, victim_liquidity) =
+                    donation_attack(first_deposit, donation, victim_deposit);
+
+                assert!(profit <= 0, "{donation}, {victim_deposit}: {profit}");
+                if donation > 0 {
+                    assert!(
+                        profit < -(donation as i128) / 2,
+                        "{donation}, {victim_deposit}: {profit}"
+                    );
+                }
+                // The victim either gets liquidity, or the deposit fails
+                assert!(victim_liquidity.map_or(true, |liquidity| liquidity > 0));
+            }
+        }
+    }
+
+    const AMPS: [u64; 4] = [1, 10, 100, MAX_AMP];
+
+    #[test]
+    fn compute_d_of_balanced_reserves_is_their_sum() {
+        for amp in AMPS {
+            for reserve in [1, 1_000_000, 10u64.pow(12), 10u64.pow(16)] {
+                assert_eq!(
+                    StableSwap { amp }.compute_d(reserve, reserve),
+                    Some(2 * reserve as u128)
+                );
+            }
+            assert_eq!(StableSwap { amp }.compute_d(0, 1_000), Some(0));
+        }
+    }
+
+    // D lies between the constant product and the constant sum of the
+    // reserves, closer to the sum the higher the amplification
+    #[test]
+    fn compute_d_converges_between_the_product_and_the_sum() {
+        for amp in AMPS {
+            for (x, y) in [
+                (1_000_000, 1_000_001),
+                (1_000_000, 3_000_000),
+                (1, 10u64.pow(9)),
+                (10u64.pow(12), 1_000),
+                (10u64.pow(16), 10u64.pow(16) / 3),
+            ] {
+                let d = StableSwap { amp }.compute_d(x, y).unwrap();
+                let d_sum = x as u128 + y as u128;
+                let d_product = 2 * sqrt(x as u128 * y as u128);
+
+                assert!(d <= d_sum, "amp {amp}, ({x}, {y}): {d} > {d_sum}");
+                assert!(
+                    d + 1 >= d_product,
+                    "amp {amp}, ({x}, {y}): {d} < {d_product}"
+                );
+                assert_eq!(StableSwap { amp }.compute_d(y, x), Some(d));
+            }
+        }
+
+        // Beyond the range of the 128-bit math, D is an error rather than
+        // a wrong value
+        for (x, y) in [
+            (1_000, 10u64.pow(18)),
+            (u64::MAX / 2, 1),
+            (u64::MAX, u64::MAX),
+        ] {
+            assert_eq!(StableSwap { amp: 100 }.compute_d(x, y), None);
+        }
+
+        let (x, y) = (1_000_000, 3_000_000);
+        let d_low = StableSwap { amp: 1 }.compute_d(x, y).unwrap();
+        let d_high = StableSwap { amp: MAX_AMP }.compute_d(x, y).unwrap();
+        assert!(d_low < d_high);
+    }
+
+    // compute_y inverts compute_d, rounded down to the largest y which does
+    // not increase D: a unit less never pays out more than the reserve holds
+    #[test]
+    fn compute_y_keeps_d() {
+        for amp in AMPS {
+            for (x, y) in [
+                (1_000_000, 1_000_000),
+                (1_000_000, 3_000_000),
+                (10, 10u64.pow(9)),
+                (10u64.pow(9), 10),
+                (1_000, 10u64.pow(12)),
+            ] {
+                let curve = StableSwap { amp };
+                let d = curve.compute_d(x, y).unwrap();
+                let y_computed = curve.compute_y(x, d).unwrap() as u64;
+
+                assert!(y_computed <= y, "amp {amp}, ({x}, {y}): {y_computed}");
+                assert!(curve.compute_d(x, y_computed).unwrap() <= d);
+                assert!(curve.compute_d(x, y_computed + 1).unwrap() >= d);
+                if x == y {
+                    assert_eq!(y_computed, y);
+                }
+            }
+        }
+    }
+
+    // The example of the README
+    #[test]
+    fn stable_swap_amount_out_of_the_readme() {
+        assert_eq!(
+            quote_swap(&StableSwap { amp: 100 }, 100_000, 1_000_000, 1_000_000, 0),
+            Ok(99_949)
+        );
+        assert_eq!(
+            quote_swap(&ConstantProduct, 100_000, 1_000_000, 1_000_000, 0),
+            Ok(90_909)
+        );
+    }
+
+    // The output of a swap without fee, and D, as computed by get_y and get_D
+    // of Curve's StableSwap contract (SwapTemplateBase.vy) for two coins, at
+    // other amplifications and with imbalanced reserves
+    #[test]
+    fn stable_swap_matches_curve() {
+        for (amp, amount_in, reserve_in, reserve_out, amount_out, d) in [
+            (1, 100_000, 1_000_000, 1_000_000, 96_760, 2_000_000),
+            (10, 100_000, 1_000_000, 1_000_000, 99_521, 2_000_000),
+            (100, 100_000, 1_000_000, 1_000_000, 99_949, 2_000_000),
+            (1_000, 100_000, 1_000_000, 1_000_000, 99_994, 2_000_000),
+            (MAX_AMP, 100_000, 1_000_000, 1_000_000, 99_999, 2_000_000),
+            (100, 1_000_000, 10_000_000, 2_000_000, 959_847, 11_976_260),
+            (100, 1_000_000, 2_000_000, 10_000_000, 1_013_855, 11_976_260),
+            (
+                50,
+                5_000_000_000,
+                1_000_000_000_000,
+                10_000_000_000,
+                492_970_898,
+                917_914_126_855,
+            ),
+            (
+                200,
+                123_456_789,
+                987_654_321,
+                123_456_789_012,
+                598_134_975,
+                120_149_393_998,
+            ),
+        ] {
+            let curve = StableSwap { amp };
+            assert_eq!(curve.compute_d(reserve_in, reserve_out), Some(d));
+            assert_eq!(
+                quote_swap(&curve, amount_in, reserve_in, reserve_out, 0),
+                Ok(amount_out),
+                "amp {amp}, {amount_in} in ({reserve_in}, {reserve_out})"
+            );
+        }
+    }
+
+    // An invalid fee is an error rather than an underflow
+    #[test]
+    fn stable_swap_rejects_a_fee_above_the_denominator() {
+        let curve = StableSwap { amp: 100 };
+        assert_eq!(curve.amount_out(1_000, 1_000_000, 1_000_000, 10_001), None);
+    }
+
+    // At the bounds of the amplification, and with very unbalanced reserves,
+    // the output grows with the input and stays below the output reserve.
+    // From balanced reserves, it is between the ones of the constant product
+    // and of the constant sum
+    #[test]
+    fn stable_swap_amount_out_is_bounded() {
+        for amp in [1, MAX_AMP] {
+            for (reserve_in, reserve_out) in [
+                (1_000_000, 1_000_000),
+                (10u64.pow(12), 1_000),
+                (1_000, 10u64.pow(12)),
+            ] {
+                let mut last_amount_out = 0;
+                for amount_in in [1, 1_000, 1_000_000, 10u64.pow(12)] {
+                    let amount_out =
+                        quote_swap(&StableSwap { amp }, amount_in, reserve_in, reserve_out, 0)
+                            .unwrap();
+                    let constant_product_out =
+                        quote_swap(&ConstantProduct, amount_in, reserve_in, reserve_out, 0)
+                            .unwrap();
+
+                    assert!(amount_out < reserve_out);
+                    assert!(amount_out >= last_amount_out);
+                    if reserve_in == reserve_out {
+                        assert!(constant_product_out <= amount_out + 1);
+                        assert!(amount_out <= amount_in);
+                    }
+                    last_amount_out = amount_out;
+                }
+            }
+        }
+    }
+
+    // The curves of the pools, as the tests need to iterate over them
+    #[derive(Clone, Copy)]
+    enum CurveKind {
+        ConstantProduct,
+        StableSwap(u64),
+    }
+
+    impl CurveKind {
+        fn swap(
+            self,
+            amount_in: u64,
+            reserve_in: u64,
+            reserve_out: u64,
+            fee_bps: u16,
+        ) -> Result<u64, QuoteError> {
+            match self {
+                CurveKind::ConstantProduct => quote_swap(
+                    &ConstantProduct,
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    fee_bps,
+                ),
+                CurveKind::StableSwap(amp) => quote_swap(
+                    &StableSwap { amp },
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    fee_bps,
+                ),
+            }
+        }
+
+        fn zap_in(
+            self,
+            amount_in: u64,
+            reserve_in: u64,
+            reserve_out: u64,
+            supply: u64,
+            fee_bps: u16,
+            protocol_fee_share: u16,
+        ) -> Result<ZapInQuote, QuoteError> {
+            match self {
+                CurveKind::ConstantProduct => quote_zap_in(
+                    &ConstantProduct,
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    supply,
+                    fee_bps,
+                    protocol_fee_share,
+                ),
+                CurveKind::StableSwap(amp) => quote_zap_in(
+                    &StableSwap { amp },
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    supply,
+                    fee_bps,
+                    protocol_fee_share,
+                ),
+            }
+        }
+
+        fn zap_out(
+            self,
+            amount: u64,
+            reserve_out: u64,
+            reserve_other: u64,
+            supply: u64,
+            fee_bps: u16,
+        ) -> Result<ZapOutQuote, QuoteError> {
+            match self {
+                CurveKind::ConstantProduct => quote_zap_out(
+                    &ConstantProduct,
+                    amount,
+                    reserve_out,
+                    reserve_other,
+                    supply,
+                    fee_bps,
+                ),
+                CurveKind::StableSwap(amp) => quote_zap_out(
+                    &StableSwap { amp },
+                    amount,
+                    reserve_out,
+                    reserve_other,
+                    supply,
+                    fee_bps,
+                ),
+            }
+        }
+    }
+}
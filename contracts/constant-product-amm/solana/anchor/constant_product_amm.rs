@@ -1,23 +1,41 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, SetAuthority, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer};
 pub use spl_token::instruction::AuthorityType::AccountOwner;
 
 declare_id!("ADY3EuA5hCthUru7JDWrTB1CqqxZxthZGWmvYGUw96rn");
 
+// The math of the pools, shared with the raw program and the clients
+#[path = "../amm_math.rs"]
+pub mod amm_math;
+use amm_math::*;
+
 #[program]
 pub mod constant_product_amm {
     use super::*;
 
-    pub fn initialize(ctx: Context<InitializeCtx>) -> Result<()> {
-        let (amm_info_pda, _amm_bump) = Pubkey::find_program_address(
-            &[
-                b"amm",
-                ctx.accounts.mint0.to_account_info().key.as_ref(),
-                ctx.accounts.mint1.to_account_info().key.as_ref(),
-            ],
-            ctx.program_id,
-        );
+    pub fn initialize_registry(ctx: Context<InitializeRegistryCtx>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        registry.bump = ctx.bumps.registry;
+        registry.pool_count = 0;
+
+        Ok(())
+    }
+
+    pub fn initialize(ctx: Context<InitializeCtx>, fee_bps: u16, curve: CurveType) -> Result<()> {
+        require!((fee_bps as u64) < FEE_DENOMINATOR, CustomError::InvalidFee);
+
+        if let CurveType::StableSwap { amp } = curve {
+            require!((1..=MAX_AMP).contains(&amp), CustomError::InvalidAmp);
+            // The StableSwap invariant compares the reserves unit by unit
+            require!(
+                ctx.accounts.mint0.decimals == ctx.accounts.mint1.decimals,
+                CustomError::DecimalsMismatch
+            );
+        }
+
+        let amm_info_pda = ctx.accounts.amm_info.key();
 
         msg!("Transferring the token_account0 to the holder_PDA");
         let cpi_accounts = SetAuthority {
@@ -47,19 +65,70 @@ pub mod constant_product_amm {
         amm_info.mint1 = *ctx.accounts.mint1.to_account_info().key;
         amm_info.token_account0 = *ctx.accounts.token_account0.to_account_info().key;
         amm_info.token_account1 = *ctx.accounts.token_account1.to_account_info().key;
-        amm_info.ever_deposited = false;
+        amm_info.lp_mint = ctx.accounts.lp_mint.key();
         amm_info.reserve0 = 0;
         amm_info.reserve1 = 0;
         amm_info.supply = 0;
+        amm_info.fee_bps = fee_bps;
+        amm_info.curve = curve;
+        // The protocol fee is off until the admin turns it on, and the
+        // treasury is the admin itself
+        amm_info.admin = ctx.accounts.initializer.key();
+        amm_info.treasury = ctx.accounts.initializer.key();
+        amm_info.protocol_fee_share = 0;
+        amm_info.protocol_fees0 = 0;
+        amm_info.protocol_fees1 = 0;
+        amm_info.price0_cumulative = 0;
+        amm_info.price1_cumulative = 0;
+        amm_info.last_update_slot = Clock::get()?.slot;
+        amm_info.bump = ctx.bumps.amm_info;
+
+        // The pool is listed by a new entry, at the next index of the registry
+        let pool_entry = &mut ctx.accounts.pool_entry;
+        pool_entry.bump = ctx.bumps.pool_entry;
+        pool_entry.amm_info = amm_info_pda;
+        pool_entry.mint0 = amm_info.mint0;
+        pool_entry.mint1 = amm_info.mint1;
+        pool_entry.token_account0 = amm_info.token_account0;
+        pool_entry.token_account1 = amm_info.token_account1;
+        ctx.accounts.registry.pool_count += 1;
 
         Ok(())
     }
 
-    pub fn deposit(ctx: Context<DepositCtx>, amount0: u64, amount1: u64) -> Result<()> {
-        require!(amount0 > 0, CustomError::InvalidAmount);
-        require!(amount1 > 0, CustomError::InvalidAmount);
+    pub fn deposit(
+        ctx: Context<DepositCtx>,
+        amount0_desired: u64,
+        amount1_desired: u64,
+        amount0_min: u64,
+        amount1_min: u64,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if let Some(deadline) = deadline {
+            require!(slot <= deadline, CustomError::DeadlineExpired);
+        }
 
         let amm_info = &mut ctx.accounts.amm_info;
+        amm_info.update_cumulative_prices(slot);
+
+        let DepositQuote {
+            amount0,
+            amount1,
+            liquidity,
+            locked_liquidity,
+        } = quote_deposit(
+            amount0_desired,
+            amount1_desired,
+            amm_info.reserve0,
+            amm_info.reserve1,
+            amm_info.supply,
+        )?;
+
+        require!(
+            amount0 >= amount0_min && amount1 >= amount1_min,
+            CustomError::SlippageExceeded
+        );
 
         token::transfer(
             CpiContext::new(
@@ -74,7 +143,7 @@ pub mod constant_product_amm {
                     authority: ctx.accounts.sender.to_account_info().clone(),
                 },
             ),
-            amount0 * 10u64.pow(ctx.accounts.mint0.decimals as u32),
+            amount0,
         )?;
 
         token::transfer(
@@ -90,77 +159,103 @@ pub mod constant_product_amm {
                     authority: ctx.accounts.sender.to_account_info().clone(),
                 },
             ),
-            amount1 * 10u64.pow(ctx.accounts.mint1.decimals as u32),
+            amount1,
         )?;
 
-        // Calculate the amount of tokens to mint
-        let to_mint: u64;
-
-        if amm_info.ever_deposited {
-            if (amm_info.reserve0 * amount1) != (amm_info.reserve1 * amount0) {
-                return err!(CustomError::DepPreconditionFailed);
-            }
-            to_mint = (amount0 * amm_info.supply) / amm_info.reserve0;
-        } else {
-            amm_info.ever_deposited = true;
-            to_mint = amount0;
-        }
+        // Mint the liquidity tokens to the sender, the AMM PDA being the
+        // authority of the LP mint
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            "amm".as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
 
-        if to_mint <= 0 {
-            return err!(CustomError::DepPreconditionFailed);
-        }
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.senders_lp_token_account.to_account_info(),
+                    authority: amm_info.to_account_info(),
+                },
+                amm_pda_signer_seeds,
+            ),
+            liquidity,
+        )?;
 
-        amm_info.supply += to_mint;
+        // Only the program mints and burns LP tokens, so the supply is the
+        // one of the LP mint plus the locked minimum liquidity
+        amm_info.supply += liquidity + locked_liquidity;
         amm_info.reserve0 += amount0;
         amm_info.reserve1 += amount1;
 
-        let minted_pda = &mut ctx.accounts.minted_pda;
-        minted_pda.minted += to_mint;
-
         Ok(())
     }
 
-    pub fn redeem(ctx: Context<RedeemOrSwapCtx>, amount: u64) -> Result<()> {
+    pub fn redeem(
+        ctx: Context<RedeemCtx>,
+        amount: u64,
+        amount0_min: u64,
+        amount1_min: u64,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if let Some(deadline) = deadline {
+            require!(slot <= deadline, CustomError::DeadlineExpired);
+        }
+
         let amm_info = &mut ctx.accounts.amm_info;
-        let minted_pda = &mut ctx.accounts.minted_pda;
+        amm_info.update_cumulative_prices(slot);
 
         require!(
-            minted_pda.minted >= amount,
+            ctx.accounts.senders_lp_token_account.amount >= amount,
             CustomError::InvalidAmountForRedeem
         );
+
+        let (amount0, amount1) = quote_redeem(
+            amount,
+            amm_info.reserve0,
+            amm_info.reserve1,
+            amm_info.supply,
+        )?;
+
         require!(
-            amount <= amm_info.supply,
-            CustomError::InvalidAmountForRedeem
+            amount0 >= amount0_min && amount1 >= amount1_min,
+            CustomError::SlippageExceeded
         );
 
-        let amount0: u64 = (amount * amm_info.reserve0) / amm_info.supply;
-        let amount1: u64 = (amount * amm_info.reserve1) / amm_info.supply;
+        // Burn the liquidity tokens of the sender
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.senders_lp_token_account.to_account_info(),
+                    authority: ctx.accounts.sender.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
 
         // Transfer the tokens to the sender
-        let (amm_info_pda, amm_bump) = Pubkey::find_program_address(
-            &[
-                b"amm",
-                ctx.accounts.mint0.to_account_info().key.as_ref(),
-                ctx.accounts.mint1.to_account_info().key.as_ref(),
-            ],
-            ctx.program_id,
-        );
+        let amm_info_pda = amm_info.key();
 
         let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
             "amm".as_bytes(),
             amm_info.mint0.as_ref(),
             amm_info.mint1.as_ref(),
-            &[amm_bump],
+            &[amm_info.bump],
         ]];
 
         anchor_lang::solana_program::program::invoke_signed(
             &spl_token::instruction::transfer(
-                &anchor_spl::token::ID,
+                &ctx.accounts.token_program.key(),
                 &ctx.accounts.pdas_token_account0.key(),
                 &ctx.accounts.senders_token_account0.key(),
                 &amm_info_pda, //owner
                 &[&amm_info_pda],
-                amount0 * 10u64.pow(ctx.accounts.mint0.decimals as u32),
+                amount0,
             )?,
             &[
                 ctx.accounts.pdas_token_account0.to_account_info().clone(),
@@ -176,12 +271,12 @@ pub mod constant_product_amm {
 
         anchor_lang::solana_program::program::invoke_signed(
             &spl_token::instruction::transfer(
-                &anchor_spl::token::ID,
+                &ctx.accounts.token_program.key(),
                 &ctx.accounts.pdas_token_account1.key(),
                 &ctx.accounts.senders_token_account1.key(),
                 &amm_info_pda, //owner
                 &[&amm_info_pda],
-                amount1 * 10u64.pow(ctx.accounts.mint1.decimals as u32),
+                amount1,
             )?,
             &[
                 ctx.accounts.pdas_token_account1.to_account_info().clone(),
@@ -200,14 +295,252 @@ pub mod constant_product_amm {
         amm_info.reserve0 -= amount0;
         amm_info.reserve1 -= amount1;
 
-        // Update the minted_pda data
-        minted_pda.minted -= amount;
+        Ok(())
+    }
+
+    // Deposits a single token: the part of it which mints the most liquidity
+    // is swapped through the pool, and the rest is deposited together with
+    // the output of the swap
+    pub fn zap_in(
+        ctx: Context<DepositCtx>,
+        is_mint0: bool,
+        amount_in: u64,
+        min_liquidity: u64,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if let Some(deadline) = deadline {
+            require!(slot <= deadline, CustomError::DeadlineExpired);
+        }
+
+        let amm_info = &mut ctx.accounts.amm_info;
+        amm_info.update_cumulative_prices(slot);
+
+        let (reserve_in, reserve_out) = if is_mint0 {
+            (amm_info.reserve0, amm_info.reserve1)
+        } else {
+            (amm_info.reserve1, amm_info.reserve0)
+        };
+
+        let ZapInQuote {
+            swap_in,
+            swap_out,
+            deposit,
+        } = quote_zap_in(
+            &amm_info.curve,
+            amount_in,
+            reserve_in,
+            reserve_out,
+            amm_info.supply,
+            amm_info.fee_bps,
+            amm_info.protocol_fee_share,
+        )?;
+
+        require!(
+            deposit.liquidity >= min_liquidity,
+            CustomError::LiquidityLessThanMinLiquidity
+        );
+
+        let (senders_token_account_in, pdas_token_account_in) = if is_mint0 {
+            (
+                &ctx.accounts.senders_token_account0,
+                &ctx.accounts.pdas_token_account0,
+            )
+        } else {
+            (
+                &ctx.accounts.senders_token_account1,
+                &ctx.accounts.pdas_token_account1,
+            )
+        };
+        let (senders_token_account_out, pdas_token_account_out) = if is_mint0 {
+            (
+                &ctx.accounts.senders_token_account1,
+                &ctx.accounts.pdas_token_account1,
+            )
+        } else {
+            (
+                &ctx.accounts.senders_token_account0,
+                &ctx.accounts.pdas_token_account0,
+            )
+        };
+
+        // Only the swapped and the deposited amounts of the input token are
+        // transferred, the excess stays with the sender
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: senders_token_account_in.to_account_info(),
+                    to: pdas_token_account_in.to_account_info(),
+                    authority: ctx.accounts.sender.to_account_info(),
+                },
+            ),
+            swap_in + deposit.amount0,
+        )?;
+
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            "amm".as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
+
+        // The output of the swap which is not deposited goes to the sender
+        if swap_out > deposit.amount1 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: pdas_token_account_out.to_account_info(),
+                        to: senders_token_account_out.to_account_info(),
+                        authority: amm_info.to_account_info(),
+                    },
+                    amm_pda_signer_seeds,
+                ),
+                swap_out - deposit.amount1,
+            )?;
+        }
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.senders_lp_token_account.to_account_info(),
+                    authority: amm_info.to_account_info(),
+                },
+                amm_pda_signer_seeds,
+            ),
+            deposit.liquidity,
+        )?;
+
+        let protocol_fee_in = protocol_fee(swap_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+        let reserve_in = reserve_in + swap_in - protocol_fee_in + deposit.amount0;
+        let reserve_out = reserve_out - swap_out + deposit.amount1;
+
+        if is_mint0 {
+            (amm_info.reserve0, amm_info.reserve1) = (reserve_in, reserve_out);
+            amm_info.protocol_fees0 += protocol_fee_in;
+        } else {
+            (amm_info.reserve0, amm_info.reserve1) = (reserve_out, reserve_in);
+            amm_info.protocol_fees1 += protocol_fee_in;
+        }
+        amm_info.supply += deposit.liquidity;
+
+        Ok(())
+    }
+
+    // Redeems into a single token: the redeemed amount of the other token is
+    // swapped through the pool into the output one
+    pub fn zap_out(
+        ctx: Context<RedeemCtx>,
+        is_mint0: bool,
+        amount: u64,
+        min_out_amount: u64,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if let Some(deadline) = deadline {
+            require!(slot <= deadline, CustomError::DeadlineExpired);
+        }
+
+        let amm_info = &mut ctx.accounts.amm_info;
+        amm_info.update_cumulative_prices(slot);
+
+        require!(
+            ctx.accounts.senders_lp_token_account.amount >= amount,
+            CustomError::InvalidAmountForRedeem
+        );
+
+        // The output token is mint0 if is_mint0, mint1 otherwise
+        let (reserve_out, reserve_other) = if is_mint0 {
+            (amm_info.reserve0, amm_info.reserve1)
+        } else {
+            (amm_info.reserve1, amm_info.reserve0)
+        };
+
+        let ZapOutQuote {
+            redeemed_out,
+            swap_in,
+            swap_out,
+            amount_out,
+        } = quote_zap_out(
+            &amm_info.curve,
+            amount,
+            reserve_out,
+            reserve_other,
+            amm_info.supply,
+            amm_info.fee_bps,
+        )?;
+
+        require!(
+            amount_out >= min_out_amount,
+            CustomError::AmountLessThanMinOutAmount
+        );
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.senders_lp_token_account.to_account_info(),
+                    authority: ctx.accounts.sender.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let (pdas_token_account_out, senders_token_account_out) = if is_mint0 {
+            (
+                &ctx.accounts.pdas_token_account0,
+                &ctx.accounts.senders_token_account0,
+            )
+        } else {
+            (
+                &ctx.accounts.pdas_token_account1,
+                &ctx.accounts.senders_token_account1,
+            )
+        };
+
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            "amm".as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
+
+        // The redeemed amount of the other token never leaves the pool
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: pdas_token_account_out.to_account_info(),
+                    to: senders_token_account_out.to_account_info(),
+                    authority: amm_info.to_account_info(),
+                },
+                amm_pda_signer_seeds,
+            ),
+            amount_out,
+        )?;
+
+        let protocol_fee_in = protocol_fee(swap_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+        let reserve_out = reserve_out - redeemed_out - swap_out;
+        let reserve_other = reserve_other - protocol_fee_in;
+
+        if is_mint0 {
+            (amm_info.reserve0, amm_info.reserve1) = (reserve_out, reserve_other);
+            amm_info.protocol_fees1 += protocol_fee_in;
+        } else {
+            (amm_info.reserve0, amm_info.reserve1) = (reserve_other, reserve_out);
+            amm_info.protocol_fees0 += protocol_fee_in;
+        }
+        amm_info.supply -= amount;
 
         Ok(())
     }
 
     pub fn swap(
-        ctx: Context<RedeemOrSwapCtx>,
+        ctx: Context<SwapCtx>,
         is_mint0: bool,
         amount_in: u64,
         min_out_amount: u64,
@@ -215,6 +548,8 @@ pub mod constant_product_amm {
         require!(amount_in > 0, CustomError::InvalidAmount);
 
         let amm_info = &mut ctx.accounts.amm_info;
+        amm_info.update_cumulative_prices(Clock::get()?.slot);
+
         let senders_token_account0 = ctx
             .accounts
             .senders_token_account0
@@ -249,14 +584,20 @@ pub mod constant_product_amm {
                     authority: ctx.accounts.sender.to_account_info().clone(),
                 },
             ),
-            amount_in * 10u64.pow(ctx.accounts.mint1.decimals as u32),
+            amount_in,
         )?;
 
         msg!("amount_in: {}", amount_in);
         msg!("reserve_out: {}", reserve_out);
         msg!("reserve_in: {}", reserve_in);
         msg!("amount_in: {}", min_out_amount);
-        let amount_out = amount_in * reserve_out / (reserve_in + amount_in);
+        let amount_out = quote_swap(
+            &amm_info.curve,
+            amount_in,
+            reserve_in,
+            reserve_out,
+            amm_info.fee_bps,
+        )?;
 
         msg!("Amount out: {}", amount_out);
 
@@ -270,30 +611,23 @@ pub mod constant_product_amm {
             (pdas_token_account0, senders_token_account0)
         };
 
-        let (amm_info_pda, amm_bump) = Pubkey::find_program_address(
-            &[
-                b"amm",
-                ctx.accounts.mint0.to_account_info().key.as_ref(),
-                ctx.accounts.mint1.to_account_info().key.as_ref(),
-            ],
-            ctx.program_id,
-        );
+        let amm_info_pda = amm_info.key();
 
         let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
             "amm".as_bytes(),
             amm_info.mint0.as_ref(),
             amm_info.mint1.as_ref(),
-            &[amm_bump],
+            &[amm_info.bump],
         ]];
 
         anchor_lang::solana_program::program::invoke_signed(
             &spl_token::instruction::transfer(
-                &anchor_spl::token::ID,
+                &ctx.accounts.token_program.key(),
                 &source.key(),
                 &destination.key(),
                 &amm_info_pda, //owner
                 &[&amm_info_pda],
-                amount_out * 10u64.pow(ctx.accounts.mint1.decimals as u32),
+                amount_out,
             )?,
             &[
                 source.to_account_info().clone(),
@@ -304,128 +638,584 @@ pub mod constant_product_amm {
             &amm_pda_signer_seeds,
         )?;
 
+        // The protocol fee is part of the swap fee, and is held by the pool
+        // token account apart from the reserves
+        let protocol_fee_in =
+            protocol_fee(amount_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+
         if is_mint0 {
-            amm_info.reserve0 = amm_info.reserve0 + amount_in;
+            amm_info.reserve0 = amm_info.reserve0 + amount_in - protocol_fee_in;
             amm_info.reserve1 = amm_info.reserve1 - amount_out;
+            amm_info.protocol_fees0 += protocol_fee_in;
         } else {
             amm_info.reserve0 = amm_info.reserve0 - amount_out;
-            amm_info.reserve1 = amm_info.reserve1 + amount_in;
+            amm_info.reserve1 = amm_info.reserve1 + amount_in - protocol_fee_in;
+            amm_info.protocol_fees1 += protocol_fee_in;
         }
 
         Ok(())
     }
-}
 
-#[account]
-#[derive(InitSpace)]
-pub struct AmmInfo {
-    pub mint0: Pubkey,          // 32 bytes
-    pub mint1: Pubkey,          // 32 bytes
-    pub token_account0: Pubkey, // 32 bytes
-    pub token_account1: Pubkey, // 32 bytes
-    pub reserve0: u64,          // 8 bytes
-    pub reserve1: u64,          // 8 bytes
-    pub ever_deposited: bool,   // 1 byte
-    pub supply: u64,            // 8 bytes
-}
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapRouteCtx<'info>>,
+        amount_in: u64,
+        min_out_amount: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, CustomError::InvalidAmount);
 
-#[account]
-#[derive(InitSpace)]
-pub struct MintedPDA {
-    pub minted: u64, // 8 bytes
-}
+        // Every hop of the route is given by the AMM info of the pool,
+        // followed by the pool token accounts of the input and the output mint
+        let hops = ctx.remaining_accounts;
+        require!(
+            !hops.is_empty() && hops.len() % 3 == 0,
+            CustomError::InvalidRoute
+        );
 
-#[derive(Accounts)]
-pub struct InitializeCtx<'info> {
-    #[account(mut)]
-    pub initializer: Signer<'info>,
-    #[account(
-        init_if_needed, 
-        payer = initializer, 
-        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
-        bump,
-        space = 8 + AmmInfo::INIT_SPACE
-    )]
-    pub amm_info: Account<'info, AmmInfo>,
-    pub mint0: Account<'info, Mint>,
-    pub mint1: Account<'info, Mint>,
-    #[account(constraint = token_account0.mint == mint0.key() @ CustomError::InvalidMint)]
-    pub token_account0: Account<'info, TokenAccount>,
-    #[account(constraint = token_account1.mint == mint1.key() @ CustomError::InvalidMint)]
-    pub token_account1: Account<'info, TokenAccount>,
-    // Programs and other
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.senders_token_account_in.to_account_info(),
+                    to: hops[1].clone(),
+                    authority: ctx.accounts.sender.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
 
-#[derive(Accounts)]
-pub struct DepositCtx<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
-    pub mint0: Account<'info, Mint>,
-    pub mint1: Account<'info, Mint>,
-    #[account(
-        mut,
-        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
-        bump,
-    )]
-    pub amm_info: Box<Account<'info, AmmInfo>>, // Box is needed to avoid stack overflow (see: https://stackoverflow.com/questions/70747729/how-do-i-avoid-my-anchor-program-throwing-an-access-violation-in-stack-frame)
-    #[account(
-        init_if_needed,
-        payer = sender, 
-        seeds = ["minted".as_ref(), sender.key().as_ref()],
-        bump,
-        space = 8 + MintedPDA::INIT_SPACE
-    )]
-    pub minted_pda: Account<'info, MintedPDA>,
-    #[account(
-        mut, 
-        associated_token::mint = mint0,
-        associated_token::authority = sender
-    )]
-    pub senders_token_account0: Account<'info, TokenAccount>,
-    #[account(
-        mut, 
-        associated_token::mint = mint1,
-        associated_token::authority = sender
-    )]
-    pub senders_token_account1: Account<'info, TokenAccount>,
-    #[account(
-        mut, 
-        constraint = pdas_token_account0.mint == mint0.key() @ CustomError::InvalidMint,
-        constraint = pdas_token_account0.key() == amm_info.token_account0 @ CustomError::InvalidTokenAccount
-    )]
-    pub pdas_token_account0: Account<'info, TokenAccount>,
-    #[account(
-        mut, 
-        constraint = pdas_token_account1.mint == mint1.key() @ CustomError::InvalidMint,
-        constraint = pdas_token_account1.key() == amm_info.token_account1 @ CustomError::InvalidTokenAccount
-    )]
-    pub pdas_token_account1: Account<'info, TokenAccount>,
-    // Programs and other
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
+        // The output of every pool is transferred to the input token account
+        // of the next pool, so the intermediate amounts are always held by the
+        // pools, and only the output of the last one goes to the sender
+        let slot = Clock::get()?.slot;
+        let mut amount = amount_in;
 
-#[derive(Accounts)]
-pub struct RedeemOrSwapCtx<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
-    pub mint0: Account<'info, Mint>,
-    pub mint1: Account<'info, Mint>,
-    #[account(
-        mut,
+        for (i, hop) in hops.chunks(3).enumerate() {
+            let mut amm_info: Account<AmmInfo> = Account::try_from(&hop[0])?;
+            let (pool_token_account_in, pool_token_account_out) = (&hop[1], &hop[2]);
+
+            let is_mint0 = if pool_token_account_in.key() == amm_info.token_account0
+                && pool_token_account_out.key() == amm_info.token_account1
+            {
+                true
+            } else if pool_token_account_in.key() == amm_info.token_account1
+                && pool_token_account_out.key() == amm_info.token_account0
+            {
+                false
+            } else {
+                return err!(CustomError::InvalidTokenAccount);
+            };
+
+            amm_info.update_cumulative_prices(slot);
+
+            let (reserve_in, reserve_out) = if is_mint0 {
+                (amm_info.reserve0, amm_info.reserve1)
+            } else {
+                (amm_info.reserve1, amm_info.reserve0)
+            };
+
+            let amount_out = quote_swap(
+                &amm_info.curve,
+                amount,
+                reserve_in,
+                reserve_out,
+                amm_info.fee_bps,
+            )?;
+
+            let destination = match hops.get(3 * (i + 1) + 1) {
+                Some(next_pool_token_account_in) => next_pool_token_account_in.clone(),
+                None => ctx.accounts.senders_token_account_out.to_account_info(),
+            };
+
+            let amm_info_pda = amm_info.key();
+
+            let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+                "amm".as_bytes(),
+                amm_info.mint0.as_ref(),
+                amm_info.mint1.as_ref(),
+                &[amm_info.bump],
+            ]];
+
+            anchor_lang::solana_program::program::invoke_signed(
+                &spl_token::instruction::transfer(
+                    &ctx.accounts.token_program.key(),
+                    &pool_token_account_out.key(),
+                    &destination.key(),
+                    &amm_info_pda, //owner
+                    &[&amm_info_pda],
+                    amount_out,
+                )?,
+                &[
+                    pool_token_account_out.clone(),
+                    destination,
+                    amm_info.to_account_info().clone(),
+                    ctx.accounts.token_program.to_account_info().clone(),
+                ],
+                &amm_pda_signer_seeds,
+            )?;
+
+            let protocol_fee_in =
+                protocol_fee(amount, amm_info.fee_bps, amm_info.protocol_fee_share);
+
+            if is_mint0 {
+                amm_info.reserve0 = amm_info.reserve0 + amount - protocol_fee_in;
+                amm_info.reserve1 = amm_info.reserve1 - amount_out;
+                amm_info.protocol_fees0 += protocol_fee_in;
+            } else {
+                amm_info.reserve0 = amm_info.reserve0 - amount_out;
+                amm_info.reserve1 = amm_info.reserve1 + amount - protocol_fee_in;
+                amm_info.protocol_fees1 += protocol_fee_in;
+            }
+
+            // The pools of the route are not part of the context, so their
+            // data is written explicitly
+            amm_info.exit(ctx.program_id)?;
+
+            amount = amount_out;
+        }
+
+        msg!("Amount out: {}", amount);
+
+        if amount < min_out_amount {
+            return err!(CustomError::AmountLessThanMinOutAmount);
+        }
+
+        Ok(())
+    }
+
+    // Sends the output amounts to the borrower first, then calls the callback
+    // program, which must pay the pool back within the same transaction, and
+    // finally checks that the invariant of the curve holds, net of the fee on
+    // the amounts paid back
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwapCtx<'info>>,
+        amount0_out: u64,
+        amount1_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            amount0_out > 0 || amount1_out > 0,
+            CustomError::InvalidAmount
+        );
+        // The callback can not re-enter the AMM, which would see the reserves
+        // before the flash swap
+        require!(
+            ctx.accounts.callback_program.key() != *ctx.program_id,
+            CustomError::InvalidCallbackProgram
+        );
+
+        let amm_info = &mut ctx.accounts.amm_info;
+        require!(
+            amount0_out < amm_info.reserve0 && amount1_out < amm_info.reserve1,
+            CustomError::InsufficientLiquidity
+        );
+
+        amm_info.update_cumulative_prices(Clock::get()?.slot);
+
+        let amm_info_pda = amm_info.key();
+
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            "amm".as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
+
+        for (amount_out, source, destination) in [
+            (
+                amount0_out,
+                &ctx.accounts.pdas_token_account0,
+                &ctx.accounts.borrowers_token_account0,
+            ),
+            (
+                amount1_out,
+                &ctx.accounts.pdas_token_account1,
+                &ctx.accounts.borrowers_token_account1,
+            ),
+        ] {
+            if amount_out == 0 {
+                continue;
+            }
+
+            anchor_lang::solana_program::program::invoke_signed(
+                &spl_token::instruction::transfer(
+                    &ctx.accounts.token_program.key(),
+                    &source.key(),
+                    &destination.key(),
+                    &amm_info_pda, //owner
+                    &[&amm_info_pda],
+                    amount_out,
+                )?,
+                &[
+                    source.to_account_info().clone(),
+                    destination.to_account_info().clone(),
+                    amm_info.to_account_info().clone(),
+                    ctx.accounts.token_program.to_account_info().clone(),
+                ],
+                &amm_pda_signer_seeds,
+            )?;
+        }
+
+        // The callback gets the remaining accounts and the given data
+        let callback_accounts = ctx.remaining_accounts;
+        let mut account_infos = callback_accounts.to_vec();
+        account_infos.push(ctx.accounts.callback_program.to_account_info());
+
+        anchor_lang::solana_program::program::invoke(
+            &Instruction {
+                program_id: ctx.accounts.callback_program.key(),
+                accounts: callback_accounts
+                    .iter()
+                    .map(|account| AccountMeta {
+                        pubkey: account.key(),
+                        is_signer: account.is_signer,
+                        is_writable: account.is_writable,
+                    })
+                    .collect(),
+                data,
+            },
+            &account_infos,
+        )?;
+
+        ctx.accounts.pdas_token_account0.reload()?;
+        ctx.accounts.pdas_token_account1.reload()?;
+        let amm_info = &mut ctx.accounts.amm_info;
+
+        // The accrued protocol fees are held by the same token accounts, but
+        // they are not part of the balances of the pool
+        let balance0 = ctx.accounts.pdas_token_account0.amount - amm_info.protocol_fees0;
+        let balance1 = ctx.accounts.pdas_token_account1.amount - amm_info.protocol_fees1;
+
+        // The amounts paid back beyond the reserves left after sending the
+        // output amounts are charged the fee, rounded up
+        let amount0_in = balance0.saturating_sub(amm_info.reserve0 - amount0_out);
+        let amount1_in = balance1.saturating_sub(amm_info.reserve1 - amount1_out);
+
+        let balance0_adjusted = balance0 - fee_ceil(amount0_in, amm_info.fee_bps);
+        let balance1_adjusted = balance1 - fee_ceil(amount1_in, amm_info.fee_bps);
+
+        let curve = amm_info.curve;
+        let invariant_before = curve
+            .invariant(amm_info.reserve0, amm_info.reserve1)
+            .ok_or(QuoteError::MathOverflow)?;
+        let invariant_after = curve
+            .invariant(balance0_adjusted, balance1_adjusted)
+            .ok_or(QuoteError::MathOverflow)?;
+
+        require!(
+            invariant_after >= invariant_before,
+            CustomError::InvariantViolated
+        );
+
+        let protocol_fee0 = protocol_fee(amount0_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+        let protocol_fee1 = protocol_fee(amount1_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+
+        amm_info.reserve0 = balance0 - protocol_fee0;
+        amm_info.reserve1 = balance1 - protocol_fee1;
+        amm_info.protocol_fees0 += protocol_fee0;
+        amm_info.protocol_fees1 += protocol_fee1;
+
+        Ok(())
+    }
+
+    // The protocol fee switch: the share of the swap fee set aside for the
+    // protocol, in basis points of the fee (0 turns it off), and the owner of
+    // the token accounts it is collected to
+    pub fn set_protocol_fee(
+        ctx: Context<SetProtocolFeeCtx>,
+        protocol_fee_share: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            (protocol_fee_share as u64) <= FEE_DENOMINATOR,
+            CustomError::InvalidProtocolFeeShare
+        );
+
+        let amm_info = &mut ctx.accounts.amm_info;
+        amm_info.protocol_fee_share = protocol_fee_share;
+        amm_info.treasury = treasury;
+
+        Ok(())
+    }
+
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFeesCtx>) -> Result<()> {
+        let amm_info = &mut ctx.accounts.amm_info;
+        let amm_info_pda = amm_info.key();
+
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            "amm".as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
+
+        for (amount, source, destination) in [
+            (
+                amm_info.protocol_fees0,
+                &ctx.accounts.pdas_token_account0,
+                &ctx.accounts.treasury_token_account0,
+            ),
+            (
+                amm_info.protocol_fees1,
+                &ctx.accounts.pdas_token_account1,
+                &ctx.accounts.treasury_token_account1,
+            ),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+
+            anchor_lang::solana_program::program::invoke_signed(
+                &spl_token::instruction::transfer(
+                    &ctx.accounts.token_program.key(),
+                    &source.key(),
+                    &destination.key(),
+                    &amm_info_pda, //owner
+                    &[&amm_info_pda],
+                    amount,
+                )?,
+                &[
+                    source.to_account_info().clone(),
+                    destination.to_account_info().clone(),
+                    amm_info.to_account_info().clone(),
+                    ctx.accounts.token_program.to_account_info().clone(),
+                ],
+                &amm_pda_signer_seeds,
+            )?;
+        }
+
+        amm_info.protocol_fees0 = 0;
+        amm_info.protocol_fees1 = 0;
+
+        Ok(())
+    }
+
+    // Returns the cumulative prices at the current slot, without updating
+    // the pool. The time-weighted average price between two observations is
+    // (price_cumulative_2 - price_cumulative_1) / (slot_2 - slot_1), as a
+    // UQ64.64 fixed-point number
+    pub fn observe(ctx: Context<ObserveCtx>) -> Result<Observation> {
+        let slot = Clock::get()?.slot;
+        let (price0_cumulative, price1_cumulative) = ctx.accounts.amm_info.cumulative_prices(slot);
+
+        Ok(Observation {
+            slot,
+            price0_cumulative,
+            price1_cumulative,
+        })
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AmmInfo {
+    pub mint0: Pubkey,           // 32 bytes
+    pub mint1: Pubkey,           // 32 bytes
+    pub token_account0: Pubkey,  // 32 bytes
+    pub token_account1: Pubkey,  // 32 bytes
+    pub lp_mint: Pubkey,         // 32 bytes
+    pub reserve0: u64,           // 8 bytes
+    pub reserve1: u64,           // 8 bytes
+    pub supply: u64,             // 8 bytes
+    pub fee_bps: u16,            // 2 bytes
+    pub curve: CurveType,        // 9 bytes
+    pub admin: Pubkey,           // 32 bytes
+    pub treasury: Pubkey,        // 32 bytes
+    pub protocol_fee_share: u16, // 2 bytes
+    pub protocol_fees0: u64,     // 8 bytes
+    pub protocol_fees1: u64,     // 8 bytes
+    pub price0_cumulative: u128, // 16 bytes
+    pub price1_cumulative: u128, // 16 bytes
+    pub last_update_slot: u64,   // 8 bytes
+    pub bump: u8,                // 1 byte
+}
+
+impl AmmInfo {
+    // The cumulative prices at the given slot: the prices of each token in
+    // terms of the other, as UQ64.64 fixed-point numbers, are accumulated for
+    // every slot elapsed since the last update. Overflows are intended, since
+    // only the differences between two observations are meaningful
+    pub fn cumulative_prices(&self, slot: u64) -> (u128, u128) {
+        let elapsed = slot.saturating_sub(self.last_update_slot) as u128;
+        if elapsed == 0 || self.reserve0 == 0 || self.reserve1 == 0 {
+            return (self.price0_cumulative, self.price1_cumulative);
+        }
+
+        let price0 = ((self.reserve1 as u128) << 64) / self.reserve0 as u128;
+        let price1 = ((self.reserve0 as u128) << 64) / self.reserve1 as u128;
+
+        (
+            self.price0_cumulative
+                .wrapping_add(price0.wrapping_mul(elapsed)),
+            self.price1_cumulative
+                .wrapping_add(price1.wrapping_mul(elapsed)),
+        )
+    }
+
+    // Called before the reserves change, so that the prices are accumulated
+    // with the reserves they had during the elapsed slots
+    pub fn update_cumulative_prices(&mut self, slot: u64) {
+        (self.price0_cumulative, self.price1_cumulative) = self.cumulative_prices(slot);
+        self.last_update_slot = slot;
+    }
+}
+
+// Counts the pools, each listed by its own entry at the PDA
+// ["pool_entry", index], so that creating a pool never grows an account
+#[account]
+#[derive(InitSpace)]
+pub struct Registry {
+    pub bump: u8,        // 1 byte
+    pub pool_count: u64, // 8 bytes
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PoolEntry {
+    pub bump: u8,               // 1 byte
+    pub amm_info: Pubkey,       // 32 bytes
+    pub mint0: Pubkey,          // 32 bytes
+    pub mint1: Pubkey,          // 32 bytes
+    pub token_account0: Pubkey, // 32 bytes
+    pub token_account1: Pubkey, // 32 bytes
+}
+
+// The curve of a pool, chosen at initialization
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CurveType {
+    ConstantProduct,
+    StableSwap { amp: u64 },
+}
+
+impl Curve for CurveType {
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64> {
+        match *self {
+            CurveType::ConstantProduct => {
+                ConstantProduct.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+            CurveType::StableSwap { amp } => {
+                StableSwap { amp }.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+        }
+    }
+
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128> {
+        match *self {
+            CurveType::ConstantProduct => ConstantProduct.invariant(reserve0, reserve1),
+            CurveType::StableSwap { amp } => StableSwap { amp }.invariant(reserve0, reserve1),
+        }
+    }
+
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)> {
+        match *self {
+            CurveType::ConstantProduct => ConstantProduct.spot_price(reserve_in, reserve_out),
+            CurveType::StableSwap { amp } => StableSwap { amp }.spot_price(reserve_in, reserve_out),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Observation {
+    pub slot: u64,
+    pub price0_cumulative: u128,
+    pub price1_cumulative: u128,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistryCtx<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = ["registry".as_ref()],
+        bump,
+        space = 8 + Registry::INIT_SPACE
+    )]
+    pub registry: Account<'info, Registry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCtx<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        init_if_needed, 
+        payer = initializer, 
         seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
         bump,
+        space = 8 + AmmInfo::INIT_SPACE
     )]
     pub amm_info: Account<'info, AmmInfo>,
+    pub mint0: Account<'info, Mint>,
+    // The mints are in ascending order, so that every pair has a single pool
+    #[account(constraint = mint0.key() < mint1.key() @ CustomError::MintsNotOrdered)]
+    pub mint1: Account<'info, Mint>,
+    // Writable, since their owner is changed by the token program
+    #[account(mut, constraint = token_account0.mint == mint0.key() @ CustomError::InvalidMint)]
+    pub token_account0: Account<'info, TokenAccount>,
+    // Writable, since their owner is changed by the token program
+    #[account(mut, constraint = token_account1.mint == mint1.key() @ CustomError::InvalidMint)]
+    pub token_account1: Account<'info, TokenAccount>,
     #[account(
-        seeds = ["minted".as_ref(), sender.key().as_ref()],
+        init,
+        payer = initializer,
+        seeds = ["lp".as_ref(), amm_info.key().as_ref()],
         bump,
+        mint::decimals = mint0.decimals,
+        mint::authority = amm_info
     )]
-    pub minted_pda: Account<'info, MintedPDA>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["registry".as_ref()],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, Registry>,
+    #[account(
+        init,
+        payer = initializer,
+        seeds = ["pool_entry".as_ref(), registry.pool_count.to_le_bytes().as_ref()],
+        bump,
+        space = 8 + PoolEntry::INIT_SPACE
+    )]
+    pub pool_entry: Account<'info, PoolEntry>,
+    // Programs and other
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct DepositCtx<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub mint0: Account<'info, Mint>,
+    pub mint1: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
+        bump = amm_info.bump,
+    )]
+    pub amm_info: Box<Account<'info, AmmInfo>>, // Box is needed to avoid stack overflow (see: https://stackoverflow.com/questions/70747729/how-do-i-avoid-my-anchor-program-throwing-an-access-violation-in-stack-frame)
+    #[account(
+        mut,
+        address = amm_info.lp_mint @ CustomError::InvalidLpMint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        associated_token::mint = lp_mint,
+        associated_token::authority = sender
+    )]
+    pub senders_lp_token_account: Account<'info, TokenAccount>,
     #[account(
         mut, 
         associated_token::mint = mint0,
@@ -456,23 +1246,841 @@ pub struct RedeemOrSwapCtx<'info> {
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
+#[derive(Accounts)]
+pub struct RedeemCtx<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub mint0: Account<'info, Mint>,
+    pub mint1: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
+        bump = amm_info.bump,
+    )]
+    pub amm_info: Account<'info, AmmInfo>,
+    #[account(
+        mut,
+        address = amm_info.lp_mint @ CustomError::InvalidLpMint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = lp_mint,
+        associated_token::authority = sender
+    )]
+    pub senders_lp_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        associated_token::mint = mint0,
+        associated_token::authority = sender
+    )]
+    pub senders_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        associated_token::mint = mint1,
+        associated_token::authority = sender
+    )]
+    pub senders_token_account1: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        constraint = pdas_token_account0.mint == mint0.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account0.key() == amm_info.token_account0 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        constraint = pdas_token_account1.mint == mint1.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account1.key() == amm_info.token_account1 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account1: Account<'info, TokenAccount>,
+    // Programs and other
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SwapCtx<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub mint0: Account<'info, Mint>,
+    pub mint1: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
+        bump = amm_info.bump,
+    )]
+    pub amm_info: Account<'info, AmmInfo>,
+    #[account(
+        mut, 
+        associated_token::mint = mint0,
+        associated_token::authority = sender
+    )]
+    pub senders_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        associated_token::mint = mint1,
+        associated_token::authority = sender
+    )]
+    pub senders_token_account1: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        constraint = pdas_token_account0.mint == mint0.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account0.key() == amm_info.token_account0 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut, 
+        constraint = pdas_token_account1.mint == mint1.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account1.key() == amm_info.token_account1 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account1: Account<'info, TokenAccount>,
+    // Programs and other
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SwapRouteCtx<'info> {
+    pub sender: Signer<'info>,
+    #[account(mut)]
+    pub senders_token_account_in: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub senders_token_account_out: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FlashSwapCtx<'info> {
+    pub sender: Signer<'info>,
+    pub mint0: Account<'info, Mint>,
+    pub mint1: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
+        bump = amm_info.bump,
+    )]
+    pub amm_info: Account<'info, AmmInfo>,
+    #[account(
+        mut,
+        constraint = borrowers_token_account0.mint == mint0.key() @ CustomError::InvalidMint
+    )]
+    pub borrowers_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = borrowers_token_account1.mint == mint1.key() @ CustomError::InvalidMint
+    )]
+    pub borrowers_token_account1: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = pdas_token_account0.mint == mint0.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account0.key() == amm_info.token_account0 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = pdas_token_account1.mint == mint1.key() @ CustomError::InvalidMint,
+        constraint = pdas_token_account1.key() == amm_info.token_account1 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account1: Account<'info, TokenAccount>,
+    /// CHECK: the program of the borrower, called after sending the tokens
+    #[account(executable)]
+    pub callback_program: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolFeeCtx<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, has_one = admin @ CustomError::InvalidAdmin)]
+    pub amm_info: Account<'info, AmmInfo>,
+}
+
+#[derive(Accounts)]
+pub struct CollectProtocolFeesCtx<'info> {
+    pub admin: Signer<'info>,
+    pub mint0: Account<'info, Mint>,
+    pub mint1: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = ["amm".as_ref(), mint0.key().as_ref(), mint1.key().as_ref()],
+        bump = amm_info.bump,
+        has_one = admin @ CustomError::InvalidAdmin,
+    )]
+    pub amm_info: Account<'info, AmmInfo>,
+    #[account(
+        mut,
+        constraint = pdas_token_account0.key() == amm_info.token_account0 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = pdas_token_account1.key() == amm_info.token_account1 @ CustomError::InvalidTokenAccount
+    )]
+    pub pdas_token_account1: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_token_account0.mint == mint0.key() @ CustomError::InvalidMint,
+        constraint = treasury_token_account0.owner == amm_info.treasury @ CustomError::InvalidTreasury
+    )]
+    pub treasury_token_account0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_token_account1.mint == mint1.key() @ CustomError::InvalidMint,
+        constraint = treasury_token_account1.owner == amm_info.treasury @ CustomError::InvalidTreasury
+    )]
+    pub treasury_token_account1: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ObserveCtx<'info> {
+    pub amm_info: Account<'info, AmmInfo>,
+}
+
 #[error_code]
 pub enum CustomError {
     #[msg("Invalid amount")]
     InvalidAmount,
 
-    #[msg("Invalid amount for redeem, must be less or equal than the amount minted and also less than the supply")]
+    #[msg("Invalid amount for redeem, must be less or equal than the LP tokens of the sender and also less than the supply")]
     InvalidAmountForRedeem,
 
     #[msg("Invalid mint")]
     InvalidMint,
 
-    #[msg("Dep precondition failed")]
-    DepPreconditionFailed,
+    #[msg("The deposit is too small to mint any liquidity")]
+    ZeroLiquidity,
 
     #[msg("Invalid token account")]
     InvalidTokenAccount,
 
     #[msg("Amount less than the min out amount")]
     AmountLessThanMinOutAmount,
+
+    #[msg("The fee must be less than 10000 basis points")]
+    InvalidFee,
+
+    #[msg("Invalid LP mint")]
+    InvalidLpMint,
+
+    #[msg("The route must have at least one hop, of three accounts each")]
+    InvalidRoute,
+
+    #[msg("The output amounts must be less than the reserves")]
+    InsufficientLiquidity,
+
+    #[msg("The callback program can not be the AMM")]
+    InvalidCallbackProgram,
+
+    #[msg("The invariant of the curve does not hold after the flash swap")]
+    InvariantViolated,
+
+    #[msg("The mints of a pool must be in ascending order")]
+    MintsNotOrdered,
+
+    #[msg("The amounts are less than the minimum ones")]
+    SlippageExceeded,
+
+    #[msg("The deadline has expired")]
+    DeadlineExpired,
+
+    #[msg("The amplification coefficient must be between 1 and 10000")]
+    InvalidAmp,
+
+    #[msg("The mints of a StableSwap pool must have the same decimals")]
+    DecimalsMismatch,
+
+    #[msg("The curve math overflowed")]
+    MathOverflow,
+
+    #[msg("The initial liquidity must be greater than the minimum liquidity")]
+    InsufficientInitialLiquidity,
+
+    #[msg("Liquidity can not be less than the min liquidity")]
+    LiquidityLessThanMinLiquidity,
+
+    #[msg("Only the admin of the pool can do this")]
+    InvalidAdmin,
+
+    #[msg("The protocol fee share must be at most 10000 basis points")]
+    InvalidProtocolFeeShare,
+
+    #[msg("The token accounts must be owned by the treasury")]
+    InvalidTreasury,
+}
+
+impl From<QuoteError> for Error {
+    fn from(error: QuoteError) -> Self {
+        match error {
+            QuoteError::ZeroAmount => CustomError::InvalidAmount,
+            QuoteError::InsufficientInitialLiquidity => CustomError::InsufficientInitialLiquidity,
+            QuoteError::ZeroLiquidity => CustomError::ZeroLiquidity,
+            QuoteError::ExceedsSupply => CustomError::InvalidAmountForRedeem,
+            QuoteError::EmptyPool => CustomError::InsufficientLiquidity,
+            QuoteError::MathOverflow => CustomError::MathOverflow,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::{program_pack::Pack, system_instruction, system_program};
+    use anchor_lang::{InstructionData, ToAccountMetas};
+    use anchor_spl::associated_token::{
+        get_associated_token_address, spl_associated_token_account,
+    };
+    use solana_program_test::{
+        processor, BanksClient, BanksClientError, ProgramTest, ProgramTestContext,
+    };
+    use solana_sdk::{
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+
+    // The instructions borrow the accounts for as long as the accounts borrow
+    // their data, which the test processor can not express, so the accounts
+    // are leaked for the duration of the test
+    fn process(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        data: &[u8],
+    ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+        entry(program_id, Box::leak(Box::new(accounts.to_vec())), data)
+    }
+
+    // The callback of the flash swaps: pays back to the pool the amount in the
+    // instruction data, from the token account of the borrower
+    fn repay(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        data: &[u8],
+    ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+        let [owner, source, destination, token_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let amount = u64::from_le_bytes(data.try_into().unwrap());
+        anchor_lang::solana_program::program::invoke(
+            &spl_token::instruction::transfer(
+                token_program.key,
+                source.key,
+                destination.key,
+                owner.key,
+                &[],
+                amount,
+            )?,
+            accounts,
+        )
+    }
+
+    struct Pool {
+        amm_info: Pubkey,
+        mint0: Pubkey,
+        mint1: Pubkey,
+        token_account0: Pubkey,
+        token_account1: Pubkey,
+        lp_mint: Pubkey,
+    }
+
+    impl Pool {
+        fn token_account(&self, mint: &Pubkey) -> Pubkey {
+            if *mint == self.mint0 {
+                self.token_account0
+            } else {
+                self.token_account1
+            }
+        }
+    }
+
+    // The payer is the sender of every instruction, and owns an associated
+    // token account of every mint, with enough tokens for any test
+    struct Test {
+        context: ProgramTestContext,
+        banks_client: BanksClient,
+        payer: Keypair,
+        borrower_program_id: Pubkey,
+        registry: Pubkey,
+    }
+
+    impl Test {
+        async fn start() -> Self {
+            let borrower_program_id = Pubkey::new_unique();
+            let mut program_test =
+                ProgramTest::new("constant_product_amm", ID, processor!(process));
+            program_test.add_program("borrower", borrower_program_id, processor!(repay));
+            let context = program_test.start_with_context().await;
+            let (registry, _) = Pubkey::find_program_address(&[b"registry"], &ID);
+
+            let mut test = Self {
+                banks_client: context.banks_client.clone(),
+                payer: context.payer.insecure_clone(),
+                context,
+                borrower_program_id,
+                registry,
+            };
+
+            let initialize_registry = Instruction {
+                program_id: ID,
+                accounts: accounts::InitializeRegistryCtx {
+                    payer: test.payer.pubkey(),
+                    registry,
+                    system_program: system_program::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::InitializeRegistry {}.data(),
+            };
+            test.send(&[initialize_registry], &[]).await.unwrap();
+
+            test
+        }
+
+        async fn send(
+            &mut self,
+            instructions: &[Instruction],
+            signers: &[&Keypair],
+        ) -> std::result::Result<(), BanksClientError> {
+            let blockhash = self.banks_client.get_latest_blockhash().await?;
+            let mut all_signers = vec![&self.payer];
+            all_signers.extend_from_slice(signers);
+
+            let transaction = Transaction::new_signed_with_payer(
+                instructions,
+                Some(&self.payer.pubkey()),
+                &all_signers,
+                blockhash,
+            );
+            self.banks_client.process_transaction(transaction).await
+        }
+
+        async fn create_account(&mut self, len: usize, owner: &Pubkey) -> (Keypair, Instruction) {
+            let account = Keypair::new();
+            let rent = self.banks_client.get_rent().await.unwrap();
+            let instruction = system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(len),
+                len as u64,
+                owner,
+            );
+            (account, instruction)
+        }
+
+        fn associated_token_account(&self, mint: &Pubkey) -> Pubkey {
+            get_associated_token_address(&self.payer.pubkey(), mint)
+        }
+
+        async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+            let (mint, create_account) = self
+                .create_account(spl_token::state::Mint::LEN, &spl_token::id())
+                .await;
+            let initialize_mint = spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &self.payer.pubkey(),
+                None,
+                decimals,
+            )
+            .unwrap();
+            let create_associated_token_account =
+                spl_associated_token_account::instruction::create_associated_token_account(
+                    &self.payer.pubkey(),
+                    &self.payer.pubkey(),
+                    &mint.pubkey(),
+                    &spl_token::id(),
+                );
+            let mint_to = spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &self.associated_token_account(&mint.pubkey()),
+                &self.payer.pubkey(),
+                &[],
+                10u64.pow(15),
+            )
+            .unwrap();
+            self.send(
+                &[
+                    create_account,
+                    initialize_mint,
+                    create_associated_token_account,
+                    mint_to,
+                ],
+                &[&mint],
+            )
+            .await
+            .unwrap();
+
+            mint.pubkey()
+        }
+
+        // Owned by the payer, until handed over to a pool
+        async fn create_token_account(&mut self, mint: &Pubkey) -> Pubkey {
+            let (token_account, create_account) = self
+                .create_account(spl_token::state::Account::LEN, &spl_token::id())
+                .await;
+            let initialize_account = spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                mint,
+                &self.payer.pubkey(),
+            )
+            .unwrap();
+            self.send(&[create_account, initialize_account], &[&token_account])
+                .await
+                .unwrap();
+
+            token_account.pubkey()
+        }
+
+        async fn balance(&mut self, token_account: &Pubkey) -> u64 {
+            let account = self
+                .banks_client
+                .get_account(*token_account)
+                .await
+                .unwrap()
+                .unwrap();
+            spl_token::state::Account::unpack(&account.data)
+                .unwrap()
+                .amount
+        }
+
+        async fn balances(&mut self, pool: &Pool) -> (u64, u64) {
+            let (account0, account1) = (
+                self.associated_token_account(&pool.mint0),
+                self.associated_token_account(&pool.mint1),
+            );
+            (self.balance(&account0).await, self.balance(&account1).await)
+        }
+
+        async fn amm_info(&mut self, pool: &Pool) -> AmmInfo {
+            let account = self
+                .banks_client
+                .get_account(pool.amm_info)
+                .await
+                .unwrap()
+                .unwrap();
+            AmmInfo::try_deserialize(&mut &account.data[..]).unwrap()
+        }
+
+        // A constant-product pool of the given mints, in any order, with a
+        // first deposit of the given amounts
+        async fn create_pool(
+            &mut self,
+            (mint_a, amount_a): (Pubkey, u64),
+            (mint_b, amount_b): (Pubkey, u64),
+            fee_bps: u16,
+        ) -> Pool {
+            let ((mint0, amount0), (mint1, amount1)) = if mint_a < mint_b {
+                ((mint_a, amount_a), (mint_b, amount_b))
+            } else {
+                ((mint_b, amount_b), (mint_a, amount_a))
+            };
+
+            let (amm_info, _) =
+                Pubkey::find_program_address(&[b"amm", mint0.as_ref(), mint1.as_ref()], &ID);
+            let (lp_mint, _) = Pubkey::find_program_address(&[b"lp", amm_info.as_ref()], &ID);
+            let pool_count = {
+                let account = self
+                    .banks_client
+                    .get_account(self.registry)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                Registry::try_deserialize(&mut &account.data[..])
+                    .unwrap()
+                    .pool_count
+            };
+            let (pool_entry, _) =
+                Pubkey::find_program_address(&[b"pool_entry", &pool_count.to_le_bytes()], &ID);
+            let pool = Pool {
+                amm_info,
+                mint0,
+                mint1,
+                token_account0: self.create_token_account(&mint0).await,
+                token_account1: self.create_token_account(&mint1).await,
+                lp_mint,
+            };
+
+            let initialize = Instruction {
+                program_id: ID,
+                accounts: accounts::InitializeCtx {
+                    initializer: self.payer.pubkey(),
+                    amm_info,
+                    mint0,
+                    mint1,
+                    token_account0: pool.token_account0,
+                    token_account1: pool.token_account1,
+                    lp_mint,
+                    registry: self.registry,
+                    pool_entry,
+                    token_program: spl_token::id(),
+                    system_program: system_program::id(),
+                    associated_token_program: spl_associated_token_account::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::Initialize {
+                    fee_bps,
+                    curve: CurveType::ConstantProduct,
+                }
+                .data(),
+            };
+            self.send(&[initialize], &[]).await.unwrap();
+            self.deposit(&pool, amount0, amount1).await.unwrap();
+
+            pool
+        }
+
+        async fn deposit(
+            &mut self,
+            pool: &Pool,
+            amount0: u64,
+            amount1: u64,
+        ) -> std::result::Result<(), BanksClientError> {
+            let deposit = Instruction {
+                program_id: ID,
+                accounts: accounts::DepositCtx {
+                    sender: self.payer.pubkey(),
+                    mint0: pool.mint0,
+                    mint1: pool.mint1,
+                    amm_info: pool.amm_info,
+                    lp_mint: pool.lp_mint,
+                    senders_lp_token_account: self.associated_token_account(&pool.lp_mint),
+                    senders_token_account0: self.associated_token_account(&pool.mint0),
+                    senders_token_account1: self.associated_token_account(&pool.mint1),
+                    pdas_token_account0: pool.token_account0,
+                    pdas_token_account1: pool.token_account1,
+                    token_program: spl_token::id(),
+                    system_program: system_program::id(),
+                    associated_token_program: spl_associated_token_account::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::Deposit {
+                    amount0_desired: amount0,
+                    amount1_desired: amount1,
+                    amount0_min: 0,
+                    amount1_min: 0,
+                    deadline: None,
+                }
+                .data(),
+            };
+            self.send(&[deposit], &[]).await
+        }
+
+        async fn swap(&mut self, pool: &Pool, mint_in: Pubkey, amount_in: u64) {
+            let swap = Instruction {
+                program_id: ID,
+                accounts: accounts::SwapCtx {
+                    sender: self.payer.pubkey(),
+                    mint0: pool.mint0,
+                    mint1: pool.mint1,
+                    amm_info: pool.amm_info,
+                    senders_token_account0: self.associated_token_account(&pool.mint0),
+                    senders_token_account1: self.associated_token_account(&pool.mint1),
+                    pdas_token_account0: pool.token_account0,
+                    pdas_token_account1: pool.token_account1,
+                    token_program: spl_token::id(),
+                    system_program: system_program::id(),
+                    associated_token_program: spl_associated_token_account::id(),
+                }
+                .to_account_metas(None),
+                data: instruction::Swap {
+                    is_mint0: mint_in == pool.mint0,
+                    amount_in,
+                    min_out_amount: 0,
+                }
+                .data(),
+            };
+            self.send(&[swap], &[]).await.unwrap();
+        }
+
+        // The observation returned by observe, without changing the pool
+        async fn observe(&mut self, pool: &Pool) -> Observation {
+            let observe = Instruction {
+                program_id: ID,
+                accounts: accounts::ObserveCtx {
+                    amm_info: pool.amm_info,
+                }
+                .to_account_metas(None),
+                data: instruction::Observe {}.data(),
+            };
+            let blockhash = self.banks_client.get_latest_blockhash().await.unwrap();
+            let transaction = Transaction::new_signed_with_payer(
+                &[observe],
+                Some(&self.payer.pubkey()),
+                &[&self.payer],
+                blockhash,
+            );
+            let simulation = self
+                .banks_client
+                .simulate_transaction(transaction)
+                .await
+                .unwrap();
+            let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+            Observation::try_from_slice(&return_data.data).unwrap()
+        }
+
+        // Borrows the output amounts, and pays back the given amount of one of
+        // the mints of the pool
+        async fn flash_swap(
+            &mut self,
+            pool: &Pool,
+            (amount0_out, amount1_out): (u64, u64),
+            (mint_in, amount_in): (Pubkey, u64),
+        ) -> std::result::Result<(), BanksClientError> {
+            let mut accounts = accounts::FlashSwapCtx {
+                sender: self.payer.pubkey(),
+                mint0: pool.mint0,
+                mint1: pool.mint1,
+                amm_info: pool.amm_info,
+                borrowers_token_account0: self.associated_token_account(&pool.mint0),
+                borrowers_token_account1: self.associated_token_account(&pool.mint1),
+                pdas_token_account0: pool.token_account0,
+                pdas_token_account1: pool.token_account1,
+                callback_program: self.borrower_program_id,
+                token_program: spl_token::id(),
+            }
+            .to_account_metas(None);
+            // The accounts of the callback
+            accounts.extend([
+                AccountMeta::new(self.payer.pubkey(), true),
+                AccountMeta::new(self.associated_token_account(&mint_in), false),
+                AccountMeta::new(pool.token_account(&mint_in), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ]);
+            let flash_swap = Instruction {
+                program_id: ID,
+                accounts,
+                data: instruction::FlashSwap {
+                    amount0_out,
+                    amount1_out,
+                    data: amount_in.to_le_bytes().to_vec(),
+                }
+                .data(),
+            };
+            self.send(&[flash_swap], &[]).await
+        }
+    }
+
+    // A flash swap must pay back the borrowed amount plus the fee on the amount
+    // paid back, rounded up, which is left in the reserves
+    #[tokio::test]
+    async fn flash_swaps_must_pay_back_the_fee() {
+        let mut test = Test::start().await;
+        let mint_a = test.create_mint(6).await;
+        let mint_b = test.create_mint(6).await;
+        let pool = test
+            .create_pool((mint_a, 1_000_000_000), (mint_b, 1_000_000_000), 30)
+            .await;
+        let amount_out = 1_000_000;
+        // 1_003_010 - ceil(1_003_010 * 0.3%) = 1_000_000
+        let amount_in = 1_003_010;
+
+        let before = test.balances(&pool).await;
+        for amount_in in [0, amount_out, amount_in - 1] {
+            assert!(test
+                .flash_swap(&pool, (amount_out, 0), (pool.mint0, amount_in))
+                .await
+                .is_err());
+        }
+        assert_eq!(test.balances(&pool).await, before);
+
+        let amm_info = test.amm_info(&pool).await;
+        let k = amm_info.reserve0 as u128 * amm_info.reserve1 as u128;
+        test.flash_swap(&pool, (amount_out, 0), (pool.mint0, amount_in))
+            .await
+            .unwrap();
+        assert_eq!(
+            test.balances(&pool).await,
+            (before.0 - (amount_in - amount_out), before.1)
+        );
+
+        let amm_info = test.amm_info(&pool).await;
+        assert_eq!(
+            (amm_info.reserve0, amm_info.reserve1),
+            (1_000_000_000 + amount_in - amount_out, 1_000_000_000)
+        );
+        assert!(amm_info.reserve0 as u128 * amm_info.reserve1 as u128 >= k);
+
+        // Borrowing one token and paying back the other is a swap, which
+        // must keep k as well: (r0 + in - ceil(in * 0.3%)) * (r1 - out) >= r0 * r1
+        // with r0 = 1_000_003_010 and r1 = 1_000_000_000 from 1_004_018
+        let amount_in = 1_004_018;
+        assert!(test
+            .flash_swap(&pool, (0, amount_out), (pool.mint0, amount_in - 1))
+            .await
+            .is_err());
+        let amm_info = test.amm_info(&pool).await;
+        let k = amm_info.reserve0 as u128 * amm_info.reserve1 as u128;
+        test.flash_swap(&pool, (0, amount_out), (pool.mint0, amount_in))
+            .await
+            .unwrap();
+        let amm_info = test.amm_info(&pool).await;
+        assert!(amm_info.reserve0 as u128 * amm_info.reserve1 as u128 >= k);
+        assert_eq!(
+            (
+                test.balance(&pool.token_account0).await,
+                test.balance(&pool.token_account1).await
+            ),
+            (amm_info.reserve0, amm_info.reserve1)
+        );
+    }
+
+    // The prices of each token in terms of the other, as UQ64.64 fixed-point
+    // numbers, accumulated for every slot elapsed at the reserves they had
+    // during those slots
+    fn accumulate(price_cumulative: u128, reserve_in: u64, reserve_out: u64, slots: u64) -> u128 {
+        let price = ((reserve_out as u128) << 64) / reserve_in as u128;
+        price_cumulative.wrapping_add(price.wrapping_mul(slots as u128))
+    }
+
+    #[tokio::test]
+    async fn cumulative_prices_accumulate_the_reserves_for_every_slot() {
+        let mut test = Test::start().await;
+        let mint_a = test.create_mint(6).await;
+        let mint_b = test.create_mint(9).await;
+        let pool = test
+            .create_pool((mint_a, 1_000_000_000), (mint_b, 4_000_000_000_000), 30)
+            .await;
+
+        let mut last = test.amm_info(&pool).await;
+        for (slots, mint_in, amount_in) in [
+            (100, pool.mint0, 50_000_000),
+            (150, pool.mint1, 700_000_000_000),
+        ] {
+            let slot = last.last_update_slot + slots;
+            test.context.warp_to_slot(slot).unwrap();
+            test.swap(&pool, mint_in, amount_in).await;
+
+            // The swap accumulates the prices at the reserves before it
+            let amm_info = test.amm_info(&pool).await;
+            assert_eq!(amm_info.last_update_slot, slot);
+            assert_eq!(
+                (amm_info.price0_cumulative, amm_info.price1_cumulative),
+                (
+                    accumulate(last.price0_cumulative, last.reserve0, last.reserve1, slots),
+                    accumulate(last.price1_cumulative, last.reserve1, last.reserve0, slots)
+                )
+            );
+            last = amm_info;
+        }
+
+        // observe accumulates up to the current slot without updating the pool
+        let slot = last.last_update_slot + 40;
+        test.context.warp_to_slot(slot).unwrap();
+        let observation = test.observe(&pool).await;
+        assert_eq!(observation.slot, slot);
+        assert_eq!(
+            (observation.price0_cumulative, observation.price1_cumulative),
+            (
+                accumulate(last.price0_cumulative, last.reserve0, last.reserve1, 40),
+                accumulate(last.price1_cumulative, last.reserve1, last.reserve0, 40)
+            )
+        );
+        assert_eq!(
+            test.amm_info(&pool).await.last_update_slot,
+            last.last_update_slot
+        );
+    }
 }
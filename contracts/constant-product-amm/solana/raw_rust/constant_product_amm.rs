@@ -1,11 +1,14 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
@@ -14,16 +17,32 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+// The math of the pools, shared with the Anchor program and the clients
+#[path = "../amm_math.rs"]
+pub mod amm_math;
+use amm_math::*;
+
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 pub struct AmmInfo {
     pub mint0: Pubkey,
     pub mint1: Pubkey,
     pub token_account0: Pubkey,
     pub token_account1: Pubkey,
+    pub lp_mint: Pubkey,
     pub reserve0: u64,
     pub reserve1: u64,
-    pub ever_deposited: bool,
     pub supply: u64,
+    pub fee_bps: u16,
+    pub curve: CurveType,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub protocol_fee_share: u16,
+    pub protocol_fees0: u64,
+    pub protocol_fees1: u64,
+    pub price0_cumulative: u128,
+    pub price1_cumulative: u128,
+    pub last_update_slot: u64,
+    pub bump: u8,
 }
 
 impl AmmInfo {
@@ -32,16 +51,35 @@ impl AmmInfo {
         mint1: Pubkey,
         token_account0: Pubkey,
         token_account1: Pubkey,
+        lp_mint: Pubkey,
+        fee_bps: u16,
+        curve: CurveType,
+        admin: Pubkey,
+        last_update_slot: u64,
+        bump: u8,
     ) -> Self {
         Self {
             mint0,
             mint1,
             token_account0,
             token_account1,
+            lp_mint,
             reserve0: 0,
             reserve1: 0,
-            ever_deposited: false,
             supply: 0,
+            fee_bps,
+            curve,
+            // The protocol fee is off until the admin turns it on, and the
+            // treasury is the admin itself
+            admin,
+            treasury: admin,
+            protocol_fee_share: 0,
+            protocol_fees0: 0,
+            protocol_fees1: 0,
+            price0_cumulative: 0,
+            price1_cumulative: 0,
+            last_update_slot,
+            bump,
         }
     }
 
@@ -63,45 +101,216 @@ impl AmmInfo {
         Ok(())
     }
 
-    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 8 + 1 + 8;
+    // The direction of a swap given the pool token accounts of the input and
+    // of the output mint: true when the input is mint0
+    pub fn swap_direction(
+        &self,
+        pool_token_account_in: &Pubkey,
+        pool_token_account_out: &Pubkey,
+    ) -> Result<bool, ProgramError> {
+        if pool_token_account_in.eq(&self.token_account0)
+            && pool_token_account_out.eq(&self.token_account1)
+        {
+            return Ok(true);
+        }
+
+        if pool_token_account_in.eq(&self.token_account1)
+            && pool_token_account_out.eq(&self.token_account0)
+        {
+            return Ok(false);
+        }
+
+        msg!("Wrong token accounts for the hop");
+        Err(ProgramError::InvalidAccountData)
+    }
+
+    pub fn check_lp_mint(&self, lp_mint_public_key: &Pubkey) -> Result<(), ProgramError> {
+        if lp_mint_public_key.ne(&self.lp_mint) {
+            msg!("Wrong LP mint");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(())
+    }
+
+    // The cumulative prices at the given slot: the prices of each token in
+    // terms of the other, as UQ64.64 fixed-point numbers, are accumulated for
+    // every slot elapsed since the last update. Overflows are intended, since
+    // only the differences between two observations are meaningful
+    pub fn cumulative_prices(&self, slot: u64) -> (u128, u128) {
+        let elapsed = slot.saturating_sub(self.last_update_slot) as u128;
+        if elapsed == 0 || self.reserve0 == 0 || self.reserve1 == 0 {
+            return (self.price0_cumulative, self.price1_cumulative);
+        }
+
+        let price0 = ((self.reserve1 as u128) << 64) / self.reserve0 as u128;
+        let price1 = ((self.reserve0 as u128) << 64) / self.reserve1 as u128;
+
+        (
+            self.price0_cumulative
+                .wrapping_add(price0.wrapping_mul(elapsed)),
+            self.price1_cumulative
+                .wrapping_add(price1.wrapping_mul(elapsed)),
+        )
+    }
+
+    // Called before the reserves change, so that the prices are accumulated
+    // with the reserves they had during the elapsed slots
+    pub fn update_cumulative_prices(&mut self, slot: u64) {
+        (self.price0_cumulative, self.price1_cumulative) = self.cumulative_prices(slot);
+        self.last_update_slot = slot;
+    }
+
+    // The account is sized for the largest curve, so the data of a pool with
+    // a smaller one is followed by unused bytes, which are not read
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+
+    pub const LEN: usize =
+        32 + 32 + 32 + 32 + 32 + 8 + 8 + 8 + 2 + 9 + 32 + 32 + 2 + 8 + 8 + 16 + 16 + 8 + 1;
+}
+
+// Counts the pools, each listed by its own entry at the PDA
+// ["pool_entry", index], so that creating a pool never grows an account
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
+pub struct Registry {
+    pub bump: u8,
+    pub pool_count: u64,
+}
+
+impl Registry {
+    pub const LEN: usize = 1 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
+pub struct PoolEntry {
+    pub bump: u8,
+    pub amm_info: Pubkey,
+    pub mint0: Pubkey,
+    pub mint1: Pubkey,
+    pub token_account0: Pubkey,
+    pub token_account1: Pubkey,
+}
+
+impl PoolEntry {
+    pub const LEN: usize = 1 + 32 + 32 + 32 + 32 + 32;
+}
+
+// Returned by the observe instruction
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Observation {
+    pub slot: u64,
+    pub price0_cumulative: u128,
+    pub price1_cumulative: u128,
 }
 
 pub enum AmmInstruction {
-    Initialize,
+    Initialize {
+        fee_bps: u16,
+        curve: CurveType,
+    },
     Deposit {
-        amount0: u64,
-        amount1: u64,
+        amount0_desired: u64,
+        amount1_desired: u64,
+        amount0_min: u64,
+        amount1_min: u64,
+        deadline: Option<u64>,
     },
     Redeem {
         amount: u64,
+        amount0_min: u64,
+        amount1_min: u64,
+        deadline: Option<u64>,
     },
     Swap {
         is_mint0: bool,
         amount_in: u64,
         min_out_amount: u64,
     },
+    Observe,
+    SwapRoute {
+        amount_in: u64,
+        min_out_amount: u64,
+    },
+    FlashSwap {
+        amount0_out: u64,
+        amount1_out: u64,
+        data: Vec<u8>,
+    },
+    InitializeRegistry,
+    SetProtocolFee {
+        protocol_fee_share: u16,
+        treasury: Pubkey,
+    },
+    CollectProtocolFees,
+    ZapIn {
+        is_mint0: bool,
+        amount_in: u64,
+        min_liquidity: u64,
+        deadline: Option<u64>,
+    },
+    ZapOut {
+        is_mint0: bool,
+        amount: u64,
+        min_out_amount: u64,
+        deadline: Option<u64>,
+    },
 }
 
 impl AmmInstruction {
     pub fn from_instruction_data(instruction_data: &[u8]) -> Option<Self> {
         match instruction_data {
-            [0] => Some(Self::Initialize),
+            [0, tail @ ..] => Self::get_initialize_context(tail),
             [1, tail @ ..] => Self::get_deposit_context(tail),
             [2, tail @ ..] => Self::get_redeem_context(tail),
             [3, tail @ ..] => Self::get_swap_context(tail),
+            [4] => Some(Self::Observe),
+            [5, tail @ ..] => Self::get_swap_route_context(tail),
+            [6, tail @ ..] => Self::get_flash_swap_context(tail),
+            [7] => Some(Self::InitializeRegistry),
+            [8, tail @ ..] => Self::get_set_protocol_fee_context(tail),
+            [9] => Some(Self::CollectProtocolFees),
+            [10, tail @ ..] => Self::get_zap_in_context(tail),
+            [11, tail @ ..] => Self::get_zap_out_context(tail),
             _ => None,
         }
     }
 
+    // The curve is Borsh-serialized after the fee
+    fn get_initialize_context(instruction_data: &[u8]) -> Option<Self> {
+        let fee_bps = u16::from_le_bytes(instruction_data.get(0..2)?.try_into().unwrap());
+        let curve = CurveType::try_from_slice(&instruction_data[2..]).ok()?;
+        Some(Self::Initialize { fee_bps, curve })
+    }
+
+    // The optional deadline slot is Borsh-serialized after the amounts
     fn get_deposit_context(instruction_data: &[u8]) -> Option<Self> {
-        let amount0 = u64::from_le_bytes(instruction_data[0..8].try_into().unwrap());
-        let amount1 = u64::from_le_bytes(instruction_data[8..16].try_into().unwrap());
-        Some(Self::Deposit { amount0, amount1 })
+        let amount0_desired = u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let amount1_desired = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        let amount0_min = u64::from_le_bytes(instruction_data.get(16..24)?.try_into().unwrap());
+        let amount1_min = u64::from_le_bytes(instruction_data.get(24..32)?.try_into().unwrap());
+        let deadline = Option::<u64>::try_from_slice(instruction_data.get(32..)?).ok()?;
+        Some(Self::Deposit {
+            amount0_desired,
+            amount1_desired,
+            amount0_min,
+            amount1_min,
+            deadline,
+        })
     }
 
     fn get_redeem_context(instruction_data: &[u8]) -> Option<Self> {
-        let amount = u64::from_le_bytes(instruction_data[0..8].try_into().unwrap());
-        Some(Self::Redeem { amount })
+        let amount = u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let amount0_min = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        let amount1_min = u64::from_le_bytes(instruction_data.get(16..24)?.try_into().unwrap());
+        let deadline = Option::<u64>::try_from_slice(instruction_data.get(24..)?).ok()?;
+        Some(Self::Redeem {
+            amount,
+            amount0_min,
+            amount1_min,
+            deadline,
+        })
     }
 
     fn get_swap_context(instruction_data: &[u8]) -> Option<Self> {
@@ -114,12 +323,70 @@ impl AmmInstruction {
             min_out_amount,
         })
     }
+
+    fn get_swap_route_context(instruction_data: &[u8]) -> Option<Self> {
+        let amount_in = u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let min_out_amount = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        Some(Self::SwapRoute {
+            amount_in,
+            min_out_amount,
+        })
+    }
+
+    // The output amounts are followed by the instruction data of the callback
+    fn get_flash_swap_context(instruction_data: &[u8]) -> Option<Self> {
+        let amount0_out = u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let amount1_out = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        Some(Self::FlashSwap {
+            amount0_out,
+            amount1_out,
+            data: instruction_data.get(16..)?.to_vec(),
+        })
+    }
+
+    fn get_set_protocol_fee_context(instruction_data: &[u8]) -> Option<Self> {
+        let protocol_fee_share =
+            u16::from_le_bytes(instruction_data.get(0..2)?.try_into().unwrap());
+        let treasury = Pubkey::try_from(instruction_data.get(2..34)?).ok()?;
+        Some(Self::SetProtocolFee {
+            protocol_fee_share,
+            treasury,
+        })
+    }
+
+    // The direction is a u64, 0 meaning mint0, as in swap, followed by the
+    // amounts and the Borsh-serialized optional deadline slot
+    fn get_zap_in_context(instruction_data: &[u8]) -> Option<Self> {
+        let is_mint0 = 0 == u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let amount_in = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        let min_liquidity = u64::from_le_bytes(instruction_data.get(16..24)?.try_into().unwrap());
+        let deadline = Option::<u64>::try_from_slice(instruction_data.get(24..)?).ok()?;
+        Some(Self::ZapIn {
+            is_mint0,
+            amount_in,
+            min_liquidity,
+            deadline,
+        })
+    }
+
+    fn get_zap_out_context(instruction_data: &[u8]) -> Option<Self> {
+        let is_mint0 = 0 == u64::from_le_bytes(instruction_data.get(0..8)?.try_into().unwrap());
+        let amount = u64::from_le_bytes(instruction_data.get(8..16)?.try_into().unwrap());
+        let min_out_amount = u64::from_le_bytes(instruction_data.get(16..24)?.try_into().unwrap());
+        let deadline = Option::<u64>::try_from_slice(instruction_data.get(24..)?).ok()?;
+        Some(Self::ZapOut {
+            is_mint0,
+            amount,
+            min_out_amount,
+            deadline,
+        })
+    }
 }
 
 const SEED_FOR_AMM: &str = "amm";
-const SEED_FOR_MINTED: &str = "minted";
-const MINT_DECIMALS: u32 = 9;
-const MINTED_ACCOUNT_DATA_LEN: usize = 8;
+const SEED_FOR_LP_MINT: &str = "lp";
+const SEED_FOR_REGISTRY: &str = "registry";
+const SEED_FOR_POOL_ENTRY: &str = "pool_entry";
 
 pub fn process_instruction<'a>(
     program_id: &Pubkey,
@@ -130,20 +397,129 @@ pub fn process_instruction<'a>(
         .ok_or(ProgramError::InvalidInstructionData)?;
 
     match instruction {
-        AmmInstruction::Initialize => initialize(program_id, accounts),
-        AmmInstruction::Deposit { amount0, amount1 } => {
-            deposit(program_id, accounts, amount0, amount1)
+        AmmInstruction::Initialize { fee_bps, curve } => {
+            initialize(program_id, accounts, fee_bps, curve)
         }
-        AmmInstruction::Redeem { amount } => redeem(program_id, accounts, amount),
+        AmmInstruction::Deposit {
+            amount0_desired,
+            amount1_desired,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => deposit(
+            program_id,
+            accounts,
+            amount0_desired,
+            amount1_desired,
+            amount0_min,
+            amount1_min,
+            deadline,
+        ),
+        AmmInstruction::Redeem {
+            amount,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => redeem(
+            program_id,
+            accounts,
+            amount,
+            amount0_min,
+            amount1_min,
+            deadline,
+        ),
         AmmInstruction::Swap {
             is_mint0,
             amount_in,
             min_out_amount,
         } => swap(program_id, accounts, is_mint0, amount_in, min_out_amount),
+        AmmInstruction::Observe => observe(program_id, accounts),
+        AmmInstruction::SwapRoute {
+            amount_in,
+            min_out_amount,
+        } => swap_route(program_id, accounts, amount_in, min_out_amount),
+        AmmInstruction::FlashSwap {
+            amount0_out,
+            amount1_out,
+            data,
+        } => flash_swap(program_id, accounts, amount0_out, amount1_out, data),
+        AmmInstruction::InitializeRegistry => initialize_registry(program_id, accounts),
+        AmmInstruction::SetProtocolFee {
+            protocol_fee_share,
+            treasury,
+        } => set_protocol_fee(program_id, accounts, protocol_fee_share, treasury),
+        AmmInstruction::CollectProtocolFees => collect_protocol_fees(program_id, accounts),
+        AmmInstruction::ZapIn {
+            is_mint0,
+            amount_in,
+            min_liquidity,
+            deadline,
+        } => zap_in(
+            program_id,
+            accounts,
+            is_mint0,
+            amount_in,
+            min_liquidity,
+            deadline,
+        ),
+        AmmInstruction::ZapOut {
+            is_mint0,
+            amount,
+            min_out_amount,
+            deadline,
+        } => zap_out(
+            program_id,
+            accounts,
+            is_mint0,
+            amount,
+            min_out_amount,
+            deadline,
+        ),
+    }
+}
+
+fn initialize_registry<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let payer_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let registry_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let system_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !payer_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
     }
+
+    let registry_bump = find_pda(
+        program_id,
+        registry_account,
+        &[SEED_FOR_REGISTRY.as_bytes()],
+    )?;
+
+    let registry = Registry {
+        bump: registry_bump,
+        pool_count: 0,
+    };
+
+    create_pda_account(
+        payer_account,
+        registry_account,
+        system_program_account,
+        &[&[SEED_FOR_REGISTRY.as_bytes(), &[registry_bump]]],
+        Registry::LEN,
+        program_id,
+    )?;
+
+    registry.serialize(&mut &mut registry_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
 }
 
-fn initialize<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResult {
+fn initialize<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    fee_bps: u16,
+    curve: CurveType,
+) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
 
     let initializer_account: &AccountInfo = next_account_info(accounts_iter)?;
@@ -154,12 +530,42 @@ fn initialize<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> Progr
     let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
     let token_account_for_mint0: &AccountInfo = next_account_info(accounts_iter)?;
     let token_account_for_mint1: &AccountInfo = next_account_info(accounts_iter)?;
+    let lp_mint_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let registry_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let pool_entry_account: &AccountInfo = next_account_info(accounts_iter)?;
 
     if !initializer_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let amm_bump = check_pda(
+    if fee_bps as u64 >= FEE_DENOMINATOR {
+        msg!("The fee must be less than 10000 basis points");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // The mints are in ascending order, so that every pair has a single pool
+    if mint0_account.key >= mint1_account.key {
+        msg!("The mints of a pool must be in ascending order");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mint0 = spl_token::state::Mint::unpack(&mint0_account.data.borrow())?;
+    let mint1 = spl_token::state::Mint::unpack(&mint1_account.data.borrow())?;
+
+    if let CurveType::StableSwap { amp } = curve {
+        if !(1..=MAX_AMP).contains(&amp) {
+            msg!("The amplification coefficient must be between 1 and 10000");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // The StableSwap invariant compares the reserves unit by unit
+        if mint0.decimals != mint1.decimals {
+            msg!("The mints of a StableSwap pool must have the same decimals");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    let amm_bump = find_pda(
         program_id,
         amm_account,
         &[
@@ -173,7 +579,6 @@ fn initialize<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> Progr
         initializer_account,
         amm_account,
         system_program_account,
-        program_id,
         &[&[
             SEED_FOR_AMM.as_bytes(),
             mint0_account.key.as_ref(),
@@ -181,6 +586,38 @@ fn initialize<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> Progr
             &[amm_bump],
         ]],
         AmmInfo::LEN,
+        program_id,
+    )?;
+
+    // The LP mint of the pool, whose mint authority is the AMM PDA
+    let lp_mint_bump = find_pda(
+        program_id,
+        lp_mint_account,
+        &[SEED_FOR_LP_MINT.as_bytes(), amm_account.key.as_ref()],
+    )?;
+
+    create_pda_account(
+        initializer_account,
+        lp_mint_account,
+        system_program_account,
+        &[&[
+            SEED_FOR_LP_MINT.as_bytes(),
+            amm_account.key.as_ref(),
+            &[lp_mint_bump],
+        ]],
+        spl_token::state::Mint::LEN,
+        token_program_account.key,
+    )?;
+
+    invoke(
+        &spl_token::instruction::initialize_mint2(
+            token_program_account.key,
+            lp_mint_account.key,
+            amm_account.key,
+            None,
+            mint0.decimals,
+        )?,
+        &[lp_mint_account.clone(), token_program_account.clone()],
     )?;
 
     transfer_authority(
@@ -202,18 +639,65 @@ fn initialize<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> Progr
         *mint1_account.key,
         *token_account_for_mint0.key,
         *token_account_for_mint1.key,
+        *lp_mint_account.key,
+        fee_bps,
+        curve,
+        *initializer_account.key,
+        Clock::get()?.slot,
+        amm_bump,
     );
 
     amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
 
+    // List the pool in a new entry, at the next index of the registry
+    let mut registry: Registry = Registry::try_from_slice(*registry_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        registry_account,
+        &[SEED_FOR_REGISTRY.as_bytes(), &[registry.bump]],
+    )?;
+
+    let index = registry.pool_count.to_le_bytes();
+    let pool_entry_bump = find_pda(
+        program_id,
+        pool_entry_account,
+        &[SEED_FOR_POOL_ENTRY.as_bytes(), &index],
+    )?;
+
+    create_pda_account(
+        initializer_account,
+        pool_entry_account,
+        system_program_account,
+        &[&[SEED_FOR_POOL_ENTRY.as_bytes(), &index, &[pool_entry_bump]]],
+        PoolEntry::LEN,
+        program_id,
+    )?;
+
+    let pool_entry = PoolEntry {
+        bump: pool_entry_bump,
+        amm_info: *amm_account.key,
+        mint0: amm_info.mint0,
+        mint1: amm_info.mint1,
+        token_account0: amm_info.token_account0,
+        token_account1: amm_info.token_account1,
+    };
+    pool_entry.serialize(&mut &mut pool_entry_account.try_borrow_mut_data()?[..])?;
+
+    registry.pool_count += 1;
+    registry.serialize(&mut &mut registry_account.try_borrow_mut_data()?[..])?;
+
     Ok(())
 }
 
 fn deposit<'a>(
     program_id: &Pubkey,
     accounts: &'a [AccountInfo<'a>],
-    amount0: u64,
-    amount1: u64,
+    amount0_desired: u64,
+    amount1_desired: u64,
+    amount0_min: u64,
+    amount1_min: u64,
+    deadline: Option<u64>,
 ) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
 
@@ -224,14 +708,14 @@ fn deposit<'a>(
     let senders_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
     let senders_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
     let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
-    let minted_pda_account: &AccountInfo = next_account_info(accounts_iter)?;
-    let system_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let lp_mint_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_lp_token_account: &AccountInfo = next_account_info(accounts_iter)?;
 
     if !sender_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut amm_info: AmmInfo = AmmInfo::try_from_slice(*amm_account.data.borrow())?;
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
 
     check_pda(
         program_id,
@@ -240,10 +724,35 @@ fn deposit<'a>(
             SEED_FOR_AMM.as_bytes(),
             &amm_info.mint0.as_ref(),
             &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
         ],
     )?;
 
     amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+    amm_info.check_lp_mint(lp_mint_account.key)?;
+
+    let slot = Clock::get()?.slot;
+    check_deadline(deadline, slot)?;
+
+    amm_info.update_cumulative_prices(slot);
+
+    let DepositQuote {
+        amount0,
+        amount1,
+        liquidity,
+        locked_liquidity,
+    } = quote_deposit(
+        amount0_desired,
+        amount1_desired,
+        amm_info.reserve0,
+        amm_info.reserve1,
+        amm_info.supply,
+    )?;
+
+    if amount0 < amount0_min || amount1 < amount1_min {
+        msg!("The deposited amounts are less than the minimum ones");
+        return Err(ProgramError::InvalidArgument);
+    }
 
     transfer_tokens_from_user(
         token_program_account,
@@ -261,45 +770,50 @@ fn deposit<'a>(
         amount1,
     )?;
 
-    let to_mint = calculate_to_mint(&mut amm_info, amount0, amount1)?;
+    let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+        SEED_FOR_AMM.as_bytes(),
+        amm_info.mint0.as_ref(),
+        amm_info.mint1.as_ref(),
+        &[amm_info.bump],
+    ]];
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program_account.key,
+            lp_mint_account.key,
+            senders_lp_token_account.key,
+            amm_account.key,
+            &[],
+            liquidity,
+        )?,
+        &[
+            lp_mint_account.clone(),
+            senders_lp_token_account.clone(),
+            amm_account.clone(),
+            token_program_account.clone(),
+        ],
+        &amm_pda_signer_seeds,
+    )?;
 
-    amm_info.supply += to_mint;
+    // Only the program mints and burns LP tokens, so the supply is the one of
+    // the LP mint plus the locked minimum liquidity
+    amm_info.supply += liquidity + locked_liquidity;
     amm_info.reserve0 += amount0;
     amm_info.reserve1 += amount1;
 
     amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
 
-    let bump_seed_minted = check_pda(
-        program_id,
-        minted_pda_account,
-        &[SEED_FOR_MINTED.as_bytes(), sender_account.key.as_ref()],
-    )?;
-
-    if !account_exists(minted_pda_account) {
-        create_pda_account(
-            sender_account,
-            minted_pda_account,
-            system_program_account,
-            program_id,
-            &[&[
-                SEED_FOR_MINTED.as_bytes(),
-                sender_account.key.as_ref(),
-                &[bump_seed_minted],
-            ]],
-            MINTED_ACCOUNT_DATA_LEN,
-        )?;
-    }
-
-    let minted_amount =
-        to_mint + u64::from_le_bytes(minted_pda_account.data.borrow()[0..8].try_into().unwrap());
-    minted_pda_account
-        .try_borrow_mut_data()?
-        .copy_from_slice(&minted_amount.to_le_bytes());
-
     Ok(())
 }
 
-fn redeem<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], amount: u64) -> ProgramResult {
+fn redeem<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    amount: u64,
+    amount0_min: u64,
+    amount1_min: u64,
+    deadline: Option<u64>,
+) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
 
     let sender_account: &AccountInfo = next_account_info(accounts_iter)?;
@@ -309,53 +823,76 @@ fn redeem<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], amount: u64)
     let senders_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
     let senders_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
     let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
-    let minted_pda_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let lp_mint_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_lp_token_account: &AccountInfo = next_account_info(accounts_iter)?;
 
     if !sender_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut amm_info: AmmInfo = AmmInfo::try_from_slice(*amm_account.data.borrow())?;
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
 
-    let amm_bump = check_pda(
+    check_pda(
         program_id,
         amm_account,
         &[
             SEED_FOR_AMM.as_bytes(),
             &amm_info.mint0.as_ref(),
             &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
         ],
     )?;
 
-    check_pda(
-        program_id,
-        minted_pda_account,
-        &[SEED_FOR_MINTED.as_bytes(), sender_account.key.as_ref()],
-    )?;
-
     amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+    amm_info.check_lp_mint(lp_mint_account.key)?;
+
+    let slot = Clock::get()?.slot;
+    check_deadline(deadline, slot)?;
 
-    let mut minted_amount =
-        u64::from_le_bytes(minted_pda_account.data.borrow()[0..8].try_into().unwrap());
+    amm_info.update_cumulative_prices(slot);
 
-    if amount < minted_amount {
-        msg!("The minted amount can not be greater than the provided amount");
+    let senders_lp_tokens =
+        spl_token::state::Account::unpack(&senders_lp_token_account.data.borrow())?;
+
+    if amount > senders_lp_tokens.amount {
+        msg!("The redeemed amount can not be greater than the LP tokens of the sender");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    if amount >= amm_info.supply {
-        msg!("amount can not be greater or equal to the supply");
-        return Err(ProgramError::InvalidAccountData);
+    let (amount0, amount1) = quote_redeem(
+        amount,
+        amm_info.reserve0,
+        amm_info.reserve1,
+        amm_info.supply,
+    )?;
+
+    if amount0 < amount0_min || amount1 < amount1_min {
+        msg!("The redeemed amounts are less than the minimum ones");
+        return Err(ProgramError::InvalidArgument);
     }
 
-    let amount0: u64 = (amount * amm_info.reserve0) / amm_info.supply;
-    let amount1: u64 = (amount * amm_info.reserve1) / amm_info.supply;
+    invoke(
+        &spl_token::instruction::burn(
+            token_program_account.key,
+            senders_lp_token_account.key,
+            lp_mint_account.key,
+            sender_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            senders_lp_token_account.clone(),
+            lp_mint_account.clone(),
+            sender_account.clone(),
+            token_program_account.clone(),
+        ],
+    )?;
 
     let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
         SEED_FOR_AMM.as_bytes(),
         amm_info.mint0.as_ref(),
         amm_info.mint1.as_ref(),
-        &[amm_bump],
+        &[amm_info.bump],
     ]];
 
     transfer_tokens_from_pda(
@@ -382,21 +919,19 @@ fn redeem<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], amount: u64)
 
     amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
 
-    minted_amount -= amount;
-
-    minted_pda_account
-        .try_borrow_mut_data()?
-        .copy_from_slice(&minted_amount.to_le_bytes());
-
     Ok(())
 }
 
-fn swap<'a>(
+// Deposits a single token: the part of it which mints the most liquidity is
+// swapped through the pool, and the rest is deposited together with the output
+// of the swap. The accounts are the ones of the deposit
+fn zap_in<'a>(
     program_id: &Pubkey,
     accounts: &'a [AccountInfo<'a>],
     is_mint0: bool,
     amount_in: u64,
-    min_out_amount: u64,
+    min_liquidity: u64,
+    deadline: Option<u64>,
 ) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
 
@@ -407,29 +942,33 @@ fn swap<'a>(
     let senders_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
     let senders_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
     let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let lp_mint_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_lp_token_account: &AccountInfo = next_account_info(accounts_iter)?;
 
     if !sender_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    if amount_in <= 0 {
-        msg!("Amount in can not be less or equal to 0");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
-    let mut amm_info: AmmInfo = AmmInfo::try_from_slice(*amm_account.data.borrow())?;
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
 
-    let amm_bump = check_pda(
+    check_pda(
         program_id,
         amm_account,
         &[
             SEED_FOR_AMM.as_bytes(),
             &amm_info.mint0.as_ref(),
             &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
         ],
     )?;
 
     amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+    amm_info.check_lp_mint(lp_mint_account.key)?;
+
+    let slot = Clock::get()?.slot;
+    check_deadline(deadline, slot)?;
+
+    amm_info.update_cumulative_prices(slot);
 
     let (reserve_in, reserve_out) = if is_mint0 {
         (amm_info.reserve0, amm_info.reserve1)
@@ -437,64 +976,813 @@ fn swap<'a>(
         (amm_info.reserve1, amm_info.reserve0)
     };
 
-    let (source, destination) = if is_mint0 {
+    let ZapInQuote {
+        swap_in,
+        swap_out,
+        deposit,
+    } = quote_zap_in(
+        &amm_info.curve,
+        amount_in,
+        reserve_in,
+        reserve_out,
+        amm_info.supply,
+        amm_info.fee_bps,
+        amm_info.protocol_fee_share,
+    )?;
+
+    if deposit.liquidity < min_liquidity {
+        msg!("Liquidity can not be less than the min liquidity");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (senders_token_account_in, pdas_token_account_in) = if is_mint0 {
         (senders_token_account0, pdas_token_account0)
     } else {
         (senders_token_account1, pdas_token_account1)
     };
+    let (senders_token_account_out, pdas_token_account_out) = if is_mint0 {
+        (senders_token_account1, pdas_token_account1)
+    } else {
+        (senders_token_account0, pdas_token_account0)
+    };
 
+    // Only the swapped and the deposited amounts of the input token are
+    // transferred, the excess stays with the sender
     transfer_tokens_from_user(
         token_program_account,
         sender_account,
-        source,
-        destination,
-        amount_in,
+        senders_token_account_in,
+        pdas_token_account_in,
+        swap_in + deposit.amount0,
     )?;
 
-    let amount_out = amount_in * reserve_out / (reserve_in + amount_in);
-
-    msg!("Amount out: {}", amount_out);
-
-    if amount_out < min_out_amount {
-        msg!("Amount out can not be less than the min out amount");
-        return Err(ProgramError::InvalidArgument);
-    }
-
-    let (source, destination) = if is_mint0 {
-        (pdas_token_account1, senders_token_account1)
-    } else {
-        (pdas_token_account0, senders_token_account0)
-    };
-
     let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
         SEED_FOR_AMM.as_bytes(),
         amm_info.mint0.as_ref(),
         amm_info.mint1.as_ref(),
-        &[amm_bump],
+        &[amm_info.bump],
     ]];
 
-    transfer_tokens_from_pda(
-        token_program_account,
-        amm_account,
-        source,
-        destination,
-        &amm_pda_signer_seeds,
-        amount_out,
-    )?;
+    // The output of the swap which is not deposited goes to the sender
+    if swap_out > deposit.amount1 {
+        transfer_tokens_from_pda(
+            token_program_account,
+            amm_account,
+            pdas_token_account_out,
+            senders_token_account_out,
+            &amm_pda_signer_seeds,
+            swap_out - deposit.amount1,
+        )?;
+    }
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program_account.key,
+            lp_mint_account.key,
+            senders_lp_token_account.key,
+            amm_account.key,
+            &[],
+            deposit.liquidity,
+        )?,
+        &[
+            lp_mint_account.clone(),
+            senders_lp_token_account.clone(),
+            amm_account.clone(),
+            token_program_account.clone(),
+        ],
+        &amm_pda_signer_seeds,
+    )?;
+
+    let protocol_fee_in = protocol_fee(swap_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+    let reserve_in = reserve_in + swap_in - protocol_fee_in + deposit.amount0;
+    let reserve_out = reserve_out - swap_out + deposit.amount1;
+
+    if is_mint0 {
+        (amm_info.reserve0, amm_info.reserve1) = (reserve_in, reserve_out);
+        amm_info.protocol_fees0 += protocol_fee_in;
+    } else {
+        (amm_info.reserve0, amm_info.reserve1) = (reserve_out, reserve_in);
+        amm_info.protocol_fees1 += protocol_fee_in;
+    }
+    amm_info.supply += deposit.liquidity;
+
+    amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+// Redeems into a single token, mint0 if is_mint0: the redeemed amount of the
+// other token is swapped through the pool into the output one. The accounts
+// are the ones of the redeem
+fn zap_out<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    is_mint0: bool,
+    amount: u64,
+    min_out_amount: u64,
+    deadline: Option<u64>,
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let sender_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let lp_mint_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_lp_token_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !sender_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+    amm_info.check_lp_mint(lp_mint_account.key)?;
+
+    let slot = Clock::get()?.slot;
+    check_deadline(deadline, slot)?;
+
+    amm_info.update_cumulative_prices(slot);
+
+    let senders_lp_tokens =
+        spl_token::state::Account::unpack(&senders_lp_token_account.data.borrow())?;
+
+    if amount > senders_lp_tokens.amount {
+        msg!("The redeemed amount can not be greater than the LP tokens of the sender");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (reserve_out, reserve_other) = if is_mint0 {
+        (amm_info.reserve0, amm_info.reserve1)
+    } else {
+        (amm_info.reserve1, amm_info.reserve0)
+    };
+
+    let ZapOutQuote {
+        redeemed_out,
+        swap_in,
+        swap_out,
+        amount_out,
+    } = quote_zap_out(
+        &amm_info.curve,
+        amount,
+        reserve_out,
+        reserve_other,
+        amm_info.supply,
+        amm_info.fee_bps,
+    )?;
+
+    msg!("Amount out: {}", amount_out);
+
+    if amount_out < min_out_amount {
+        msg!("Amount out can not be less than the min out amount");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke(
+        &spl_token::instruction::burn(
+            token_program_account.key,
+            senders_lp_token_account.key,
+            lp_mint_account.key,
+            sender_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            senders_lp_token_account.clone(),
+            lp_mint_account.clone(),
+            sender_account.clone(),
+            token_program_account.clone(),
+        ],
+    )?;
+
+    let (pdas_token_account_out, senders_token_account_out) = if is_mint0 {
+        (pdas_token_account0, senders_token_account0)
+    } else {
+        (pdas_token_account1, senders_token_account1)
+    };
+
+    let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+        SEED_FOR_AMM.as_bytes(),
+        amm_info.mint0.as_ref(),
+        amm_info.mint1.as_ref(),
+        &[amm_info.bump],
+    ]];
+
+    // The redeemed amount of the other token never leaves the pool
+    transfer_tokens_from_pda(
+        token_program_account,
+        amm_account,
+        pdas_token_account_out,
+        senders_token_account_out,
+        &amm_pda_signer_seeds,
+        amount_out,
+    )?;
+
+    let protocol_fee_in = protocol_fee(swap_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+    let reserve_out = reserve_out - redeemed_out - swap_out;
+    let reserve_other = reserve_other - protocol_fee_in;
+
+    if is_mint0 {
+        (amm_info.reserve0, amm_info.reserve1) = (reserve_out, reserve_other);
+        amm_info.protocol_fees1 += protocol_fee_in;
+    } else {
+        (amm_info.reserve0, amm_info.reserve1) = (reserve_other, reserve_out);
+        amm_info.protocol_fees0 += protocol_fee_in;
+    }
+    amm_info.supply -= amount;
+
+    amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+fn swap<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    is_mint0: bool,
+    amount_in: u64,
+    min_out_amount: u64,
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let sender_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !sender_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if amount_in <= 0 {
+        msg!("Amount in can not be less or equal to 0");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+
+    amm_info.update_cumulative_prices(Clock::get()?.slot);
+
+    let (reserve_in, reserve_out) = if is_mint0 {
+        (amm_info.reserve0, amm_info.reserve1)
+    } else {
+        (amm_info.reserve1, amm_info.reserve0)
+    };
+
+    let (source, destination) = if is_mint0 {
+        (senders_token_account0, pdas_token_account0)
+    } else {
+        (senders_token_account1, pdas_token_account1)
+    };
+
+    transfer_tokens_from_user(
+        token_program_account,
+        sender_account,
+        source,
+        destination,
+        amount_in,
+    )?;
+
+    let amount_out = quote_swap(
+        &amm_info.curve,
+        amount_in,
+        reserve_in,
+        reserve_out,
+        amm_info.fee_bps,
+    )?;
+
+    msg!("Amount out: {}", amount_out);
+
+    if amount_out < min_out_amount {
+        msg!("Amount out can not be less than the min out amount");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (source, destination) = if is_mint0 {
+        (pdas_token_account1, senders_token_account1)
+    } else {
+        (pdas_token_account0, senders_token_account0)
+    };
+
+    let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+        SEED_FOR_AMM.as_bytes(),
+        amm_info.mint0.as_ref(),
+        amm_info.mint1.as_ref(),
+        &[amm_info.bump],
+    ]];
+
+    transfer_tokens_from_pda(
+        token_program_account,
+        amm_account,
+        source,
+        destination,
+        &amm_pda_signer_seeds,
+        amount_out,
+    )?;
+
+    // The protocol fee is part of the swap fee, and is held by the pool token
+    // account apart from the reserves
+    let protocol_fee_in = protocol_fee(amount_in, amm_info.fee_bps, amm_info.protocol_fee_share);
 
     if is_mint0 {
-        amm_info.reserve0 = amm_info.reserve0 + amount_in;
+        amm_info.reserve0 = amm_info.reserve0 + amount_in - protocol_fee_in;
         amm_info.reserve1 = amm_info.reserve1 - amount_out;
+        amm_info.protocol_fees0 += protocol_fee_in;
     } else {
         amm_info.reserve0 = amm_info.reserve0 - amount_out;
-        amm_info.reserve1 = amm_info.reserve1 + amount_in;
+        amm_info.reserve1 = amm_info.reserve1 + amount_in - protocol_fee_in;
+        amm_info.protocol_fees1 += protocol_fee_in;
+    }
+
+    amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+fn swap_route<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    amount_in: u64,
+    min_out_amount: u64,
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let sender_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account_in: &AccountInfo = next_account_info(accounts_iter)?;
+    let senders_token_account_out: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    // Every hop of the route is given by the AMM account of the pool,
+    // followed by the pool token accounts of the input and the output mint
+    let hops = accounts_iter.as_slice();
+
+    if !sender_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if amount_in <= 0 {
+        msg!("Amount in can not be less or equal to 0");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    if hops.is_empty() || hops.len() % 3 != 0 {
+        msg!("The route must have at least one hop, of three accounts each");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    transfer_tokens_from_user(
+        token_program_account,
+        sender_account,
+        senders_token_account_in,
+        &hops[1],
+        amount_in,
+    )?;
+
+    // The output of every pool is transferred to the input token account of
+    // the next pool, so the intermediate amounts are always held by the pools,
+    // and only the output of the last one goes to the sender
+    let slot = Clock::get()?.slot;
+    let mut amount = amount_in;
+
+    for (i, hop) in hops.chunks(3).enumerate() {
+        let (amm_account, pool_token_account_in, pool_token_account_out) =
+            (&hop[0], &hop[1], &hop[2]);
+
+        let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+        check_pda(
+            program_id,
+            amm_account,
+            &[
+                SEED_FOR_AMM.as_bytes(),
+                &amm_info.mint0.as_ref(),
+                &amm_info.mint1.as_ref(),
+                &[amm_info.bump],
+            ],
+        )?;
+
+        let is_mint0 =
+            amm_info.swap_direction(pool_token_account_in.key, pool_token_account_out.key)?;
+
+        amm_info.update_cumulative_prices(slot);
+
+        let (reserve_in, reserve_out) = if is_mint0 {
+            (amm_info.reserve0, amm_info.reserve1)
+        } else {
+            (amm_info.reserve1, amm_info.reserve0)
+        };
+
+        let amount_out = quote_swap(
+            &amm_info.curve,
+            amount,
+            reserve_in,
+            reserve_out,
+            amm_info.fee_bps,
+        )?;
+
+        let destination = match hops.get(3 * (i + 1) + 1) {
+            Some(next_pool_token_account_in) => next_pool_token_account_in,
+            None => senders_token_account_out,
+        };
+
+        let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+            SEED_FOR_AMM.as_bytes(),
+            amm_info.mint0.as_ref(),
+            amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ]];
+
+        transfer_tokens_from_pda(
+            token_program_account,
+            amm_account,
+            pool_token_account_out,
+            destination,
+            &amm_pda_signer_seeds,
+            amount_out,
+        )?;
+
+        let protocol_fee_in = protocol_fee(amount, amm_info.fee_bps, amm_info.protocol_fee_share);
+
+        if is_mint0 {
+            amm_info.reserve0 = amm_info.reserve0 + amount - protocol_fee_in;
+            amm_info.reserve1 = amm_info.reserve1 - amount_out;
+            amm_info.protocol_fees0 += protocol_fee_in;
+        } else {
+            amm_info.reserve0 = amm_info.reserve0 - amount_out;
+            amm_info.reserve1 = amm_info.reserve1 + amount - protocol_fee_in;
+            amm_info.protocol_fees1 += protocol_fee_in;
+        }
+
+        amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+        amount = amount_out;
+    }
+
+    msg!("Amount out: {}", amount);
+
+    if amount < min_out_amount {
+        msg!("Amount out can not be less than the min out amount");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+// Sends the output amounts to the borrower first, then calls the callback
+// program, which must pay the pool back within the same transaction, and
+// finally checks that the invariant of the curve holds, net of the fee on the
+// amounts paid back
+fn flash_swap<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    amount0_out: u64,
+    amount1_out: u64,
+    data: Vec<u8>,
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let sender_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let borrowers_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let borrowers_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let callback_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    // The remaining accounts are passed to the callback
+    let callback_accounts = accounts_iter.as_slice();
+
+    if !sender_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if amount0_out == 0 && amount1_out == 0 {
+        msg!("Amount out can not be 0 for both mints");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // The callback can not re-enter the AMM, which would see the reserves
+    // before the flash swap
+    if callback_program_account.key == program_id {
+        msg!("The callback program can not be the AMM");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+
+    if amount0_out >= amm_info.reserve0 || amount1_out >= amm_info.reserve1 {
+        msg!("Amount out can not be greater or equal to the reserve");
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    amm_info.update_cumulative_prices(Clock::get()?.slot);
+
+    let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+        SEED_FOR_AMM.as_bytes(),
+        amm_info.mint0.as_ref(),
+        amm_info.mint1.as_ref(),
+        &[amm_info.bump],
+    ]];
+
+    for (amount_out, source, destination) in [
+        (amount0_out, pdas_token_account0, borrowers_token_account0),
+        (amount1_out, pdas_token_account1, borrowers_token_account1),
+    ] {
+        if amount_out == 0 {
+            continue;
+        }
+
+        transfer_tokens_from_pda(
+            token_program_account,
+            amm_account,
+            source,
+            destination,
+            &amm_pda_signer_seeds,
+            amount_out,
+        )?;
+    }
+
+    let mut account_infos = callback_accounts.to_vec();
+    account_infos.push(callback_program_account.clone());
+
+    invoke(
+        &Instruction {
+            program_id: *callback_program_account.key,
+            accounts: callback_accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data,
+        },
+        &account_infos,
+    )?;
+
+    // The accrued protocol fees are held by the same token accounts, but they
+    // are not part of the balances of the pool
+    let balance0 = spl_token::state::Account::unpack(&pdas_token_account0.data.borrow())?.amount
+        - amm_info.protocol_fees0;
+    let balance1 = spl_token::state::Account::unpack(&pdas_token_account1.data.borrow())?.amount
+        - amm_info.protocol_fees1;
+
+    // The amounts paid back beyond the reserves left after sending the output
+    // amounts are charged the fee, rounded up
+    let amount0_in = balance0.saturating_sub(amm_info.reserve0 - amount0_out);
+    let amount1_in = balance1.saturating_sub(amm_info.reserve1 - amount1_out);
+
+    let balance0_adjusted = balance0 - fee_ceil(amount0_in, amm_info.fee_bps);
+    let balance1_adjusted = balance1 - fee_ceil(amount1_in, amm_info.fee_bps);
+
+    let invariant_before = amm_info
+        .curve
+        .invariant(amm_info.reserve0, amm_info.reserve1)
+        .ok_or(QuoteError::MathOverflow)?;
+    let invariant_after = amm_info
+        .curve
+        .invariant(balance0_adjusted, balance1_adjusted)
+        .ok_or(QuoteError::MathOverflow)?;
+
+    if invariant_after < invariant_before {
+        msg!("The invariant of the curve does not hold after the flash swap");
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let protocol_fee0 = protocol_fee(amount0_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+    let protocol_fee1 = protocol_fee(amount1_in, amm_info.fee_bps, amm_info.protocol_fee_share);
+
+    amm_info.reserve0 = balance0 - protocol_fee0;
+    amm_info.reserve1 = balance1 - protocol_fee1;
+    amm_info.protocol_fees0 += protocol_fee0;
+    amm_info.protocol_fees1 += protocol_fee1;
+
+    amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+// The protocol fee switch: the share of the swap fee set aside for the
+// protocol, in basis points of the fee (0 turns it off), and the owner of the
+// token accounts it is collected to
+fn set_protocol_fee<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    protocol_fee_share: u16,
+    treasury: Pubkey,
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let admin_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if protocol_fee_share as u64 > FEE_DENOMINATOR {
+        msg!("The protocol fee share must be at most 10000 basis points");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    if amm_info.admin != *admin_account.key {
+        msg!("Only the admin of the pool can set the protocol fee");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    amm_info.protocol_fee_share = protocol_fee_share;
+    amm_info.treasury = treasury;
+
+    amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+fn collect_protocol_fees<'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let admin_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let pdas_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let treasury_token_account0: &AccountInfo = next_account_info(accounts_iter)?;
+    let treasury_token_account1: &AccountInfo = next_account_info(accounts_iter)?;
+    let token_program_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
     }
 
+    let mut amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    amm_info.check_token_accounts(pdas_token_account0.key, pdas_token_account1.key)?;
+
+    if amm_info.admin != *admin_account.key {
+        msg!("Only the admin of the pool can collect the protocol fees");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let amm_pda_signer_seeds: &[&[&[u8]]] = &[&[
+        SEED_FOR_AMM.as_bytes(),
+        amm_info.mint0.as_ref(),
+        amm_info.mint1.as_ref(),
+        &[amm_info.bump],
+    ]];
+
+    for (amount, mint, source, destination) in [
+        (
+            amm_info.protocol_fees0,
+            amm_info.mint0,
+            pdas_token_account0,
+            treasury_token_account0,
+        ),
+        (
+            amm_info.protocol_fees1,
+            amm_info.mint1,
+            pdas_token_account1,
+            treasury_token_account1,
+        ),
+    ] {
+        let treasury_token_account = spl_token::state::Account::unpack(&destination.data.borrow())?;
+
+        if treasury_token_account.mint != mint || treasury_token_account.owner != amm_info.treasury
+        {
+            msg!("The token accounts must be owned by the treasury");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if amount == 0 {
+            continue;
+        }
+
+        transfer_tokens_from_pda(
+            token_program_account,
+            amm_account,
+            source,
+            destination,
+            &amm_pda_signer_seeds,
+            amount,
+        )?;
+    }
+
+    amm_info.protocol_fees0 = 0;
+    amm_info.protocol_fees1 = 0;
+
     amm_info.serialize(&mut &mut amm_account.try_borrow_mut_data()?[..])?;
 
     Ok(())
 }
 
+// Returns the cumulative prices at the current slot, without updating the
+// pool. The time-weighted average price between two observations is
+// (price_cumulative_2 - price_cumulative_1) / (slot_2 - slot_1), as a UQ64.64
+// fixed-point number
+fn observe<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResult {
+    let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+
+    let amm_account: &AccountInfo = next_account_info(accounts_iter)?;
+
+    let amm_info: AmmInfo = AmmInfo::from_account_data(&amm_account.data.borrow())?;
+
+    check_pda(
+        program_id,
+        amm_account,
+        &[
+            SEED_FOR_AMM.as_bytes(),
+            &amm_info.mint0.as_ref(),
+            &amm_info.mint1.as_ref(),
+            &[amm_info.bump],
+        ],
+    )?;
+
+    let slot = Clock::get()?.slot;
+    let (price0_cumulative, price1_cumulative) = amm_info.cumulative_prices(slot);
+
+    let observation = Observation {
+        slot,
+        price0_cumulative,
+        price1_cumulative,
+    };
+
+    set_return_data(&observation.try_to_vec()?);
+
+    Ok(())
+}
+
 pub fn transfer_authority<'a>(
     token_program: &'a AccountInfo<'a>,
     payer: &'a AccountInfo<'a>,
@@ -519,9 +1807,9 @@ pub fn create_pda_account<'a>(
     payer: &'a AccountInfo<'a>,
     new_account: &'a AccountInfo<'a>,
     system_program_account: &'a AccountInfo<'a>,
-    program_id: &Pubkey,
     signers_seeds: &[&[&[u8]]],
     data_len: usize,
+    owner: &Pubkey,
 ) -> ProgramResult {
     if new_account.lamports() != 0 {
         msg!("Trying to create an already existing account");
@@ -535,7 +1823,7 @@ pub fn create_pda_account<'a>(
         new_account.key,
         rent.minimum_balance(data_len),
         data_len as u64,
-        program_id,
+        owner,
     );
 
     let account_infos = [
@@ -560,7 +1848,7 @@ pub fn transfer_tokens_from_user<'a>(
         destination.key,
         authority.key,
         &[&authority.key],
-        amount * 10u64.pow(MINT_DECIMALS),
+        amount,
     )?;
 
     let account_infos = [
@@ -587,7 +1875,7 @@ pub fn transfer_tokens_from_pda<'a>(
         destination.key,
         &authority.key,
         &[&authority.key],
-        amount * 10u64.pow(MINT_DECIMALS),
+        amount,
     )?;
 
     let account_infos = [
@@ -600,7 +1888,9 @@ pub fn transfer_tokens_from_pda<'a>(
     invoke_signed(&instruction, &account_infos, signers_seeds)
 }
 
-pub fn check_pda<'a>(
+// Derives the canonical bump of a PDA, only when creating it: the bump is then
+// stored in the account, and later checked with `check_pda`
+pub fn find_pda<'a>(
     program_id: &Pubkey,
     account_to_check: &'a AccountInfo<'a>,
     seeds: &[&[u8]],
@@ -615,28 +1905,1140 @@ pub fn check_pda<'a>(
     return Ok(bump_seed);
 }
 
-pub fn calculate_to_mint(amm_info: &mut AmmInfo, x0: u64, x1: u64) -> Result<u64, ProgramError> {
-    let to_mint: u64;
+// Checks a PDA given its seeds, the last one being the bump stored in the account
+pub fn check_pda<'a>(
+    program_id: &Pubkey,
+    account_to_check: &'a AccountInfo<'a>,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let pub_key = Pubkey::create_program_address(&seeds, program_id)?;
 
-    if amm_info.ever_deposited {
-        if (amm_info.reserve0 * x1) != (amm_info.reserve1 * x0) {
-            msg!("Dep precondition");
-            return Err(ProgramError::InvalidInstructionData);
+    if pub_key != *account_to_check.key {
+        msg!("PDA doesen't match with the one provided");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(())
+}
+
+pub fn check_deadline(deadline: Option<u64>, slot: u64) -> ProgramResult {
+    if let Some(deadline) = deadline {
+        if slot > deadline {
+            msg!("The deadline has expired");
+            return Err(ProgramError::InvalidArgument);
         }
-        to_mint = (x0 * amm_info.supply) / amm_info.reserve0;
-    } else {
-        amm_info.ever_deposited = true;
-        to_mint = x0;
     }
 
-    if to_mint <= 0 {
-        msg!("Dep precondition");
-        return Err(ProgramError::InvalidInstructionData);
+    Ok(())
+}
+
+// The curve of a pool, chosen at initialization
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveType {
+    ConstantProduct,
+    StableSwap { amp: u64 },
+}
+
+impl Curve for CurveType {
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64> {
+        match *self {
+            CurveType::ConstantProduct => {
+                ConstantProduct.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+            CurveType::StableSwap { amp } => {
+                StableSwap { amp }.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+        }
     }
 
-    return Ok(to_mint);
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128> {
+        match *self {
+            CurveType::ConstantProduct => ConstantProduct.invariant(reserve0, reserve1),
+            CurveType::StableSwap { amp } => StableSwap { amp }.invariant(reserve0, reserve1),
+        }
+    }
+
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)> {
+        match *self {
+            CurveType::ConstantProduct => ConstantProduct.spot_price(reserve_in, reserve_out),
+            CurveType::StableSwap { amp } => StableSwap { amp }.spot_price(reserve_in, reserve_out),
+        }
+    }
+}
+
+impl From<QuoteError> for ProgramError {
+    fn from(error: QuoteError) -> Self {
+        match error {
+            QuoteError::ZeroAmount => {
+                msg!("The deposited amounts can not be 0");
+                ProgramError::InvalidInstructionData
+            }
+            QuoteError::InsufficientInitialLiquidity => {
+                msg!("The initial liquidity must be greater than the minimum liquidity");
+                ProgramError::InvalidInstructionData
+            }
+            QuoteError::ZeroLiquidity => {
+                msg!("The deposit is too small to mint any liquidity");
+                ProgramError::InvalidInstructionData
+            }
+            QuoteError::ExceedsSupply => {
+                msg!("amount can not be greater or equal to the supply");
+                ProgramError::InvalidAccountData
+            }
+            QuoteError::EmptyPool => {
+                msg!("The pool is empty");
+                ProgramError::InsufficientFunds
+            }
+            QuoteError::MathOverflow => {
+                msg!("The curve math overflowed");
+                ProgramError::ArithmeticOverflow
+            }
+        }
+    }
 }
 
-pub fn account_exists(account: &AccountInfo) -> bool {
-    account.lamports() != 0
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::system_program;
+    use solana_program_test::{
+        processor, BanksClient, BanksClientError, ProgramTest, ProgramTestContext,
+    };
+    use solana_sdk::{
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+    use std::collections::HashMap;
+
+    // The instructions borrow the accounts for as long as the accounts borrow
+    // their data, which the test processor can not express, so the accounts
+    // are leaked for the duration of the test
+    fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        process_instruction(program_id, Box::leak(Box::new(accounts.to_vec())), data)
+    }
+
+    // The callback of the flash swaps: pays back to the pool the amount in the
+    // instruction data, from the token account of the borrower
+    fn repay(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        let [owner, source, destination, token_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let amount = u64::from_le_bytes(data.try_into().unwrap());
+        invoke(
+            &spl_token::instruction::transfer(
+                token_program.key,
+                source.key,
+                destination.key,
+                owner.key,
+                &[],
+                amount,
+            )?,
+            accounts,
+        )
+    }
+
+    struct Pool {
+        amm: Pubkey,
+        mint0: Pubkey,
+        mint1: Pubkey,
+        token_account0: Pubkey,
+        token_account1: Pubkey,
+        lp_mint: Pubkey,
+        lp_token_account: Pubkey,
+    }
+
+    impl Pool {
+        fn token_account(&self, mint: &Pubkey) -> Pubkey {
+            if *mint == self.mint0 {
+                self.token_account0
+            } else {
+                self.token_account1
+            }
+        }
+
+        fn other_mint(&self, mint: &Pubkey) -> Pubkey {
+            if *mint == self.mint0 {
+                self.mint1
+            } else {
+                self.mint0
+            }
+        }
+    }
+
+    // The payer is the sender of every instruction, and owns a token account
+    // of every mint, with enough tokens for any test
+    struct Test {
+        context: ProgramTestContext,
+        banks_client: BanksClient,
+        payer: Keypair,
+        program_id: Pubkey,
+        borrower_program_id: Pubkey,
+        registry: Pubkey,
+        token_accounts: HashMap<Pubkey, Pubkey>,
+    }
+
+    impl Test {
+        async fn start() -> Self {
+            let program_id = Pubkey::new_unique();
+            let borrower_program_id = Pubkey::new_unique();
+            let mut program_test =
+                ProgramTest::new("constant_product_amm", program_id, processor!(process));
+            program_test.add_program("borrower", borrower_program_id, processor!(repay));
+            let context = program_test.start_with_context().await;
+            let (registry, _) =
+                Pubkey::find_program_address(&[SEED_FOR_REGISTRY.as_bytes()], &program_id);
+
+            let mut test = Self {
+                banks_client: context.banks_client.clone(),
+                payer: context.payer.insecure_clone(),
+                context,
+                program_id,
+                borrower_program_id,
+                registry,
+                token_accounts: HashMap::new(),
+            };
+
+            let initialize_registry = Instruction::new_with_bytes(
+                program_id,
+                &[7],
+                vec![
+                    AccountMeta::new(test.payer.pubkey(), true),
+                    AccountMeta::new(registry, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            );
+            test.send(&[initialize_registry], &[]).await.unwrap();
+
+            test
+        }
+
+        async fn send(
+            &mut self,
+            instructions: &[Instruction],
+            signers: &[&Keypair],
+        ) -> Result<(), BanksClientError> {
+            let blockhash = self.banks_client.get_latest_blockhash().await?;
+            let mut all_signers = vec![&self.payer];
+            all_signers.extend_from_slice(signers);
+
+            let transaction = Transaction::new_signed_with_payer(
+                instructions,
+                Some(&self.payer.pubkey()),
+                &all_signers,
+                blockhash,
+            );
+            self.banks_client.process_transaction(transaction).await
+        }
+
+        async fn create_account(&mut self, len: usize, owner: &Pubkey) -> (Keypair, Instruction) {
+            let account = Keypair::new();
+            let rent = self.banks_client.get_rent().await.unwrap();
+            let instruction = system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(len),
+                len as u64,
+                owner,
+            );
+            (account, instruction)
+        }
+
+        async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+            let (mint, create_account) = self
+                .create_account(spl_token::state::Mint::LEN, &spl_token::id())
+                .await;
+            let initialize_mint = spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &self.payer.pubkey(),
+                None,
+                decimals,
+            )
+            .unwrap();
+            self.send(&[create_account, initialize_mint], &[&mint])
+                .await
+                .unwrap();
+
+            let token_account = self.create_token_account(&mint.pubkey()).await;
+            let mint_to = spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &token_account,
+                &self.payer.pubkey(),
+                &[],
+                10u64.pow(15),
+            )
+            .unwrap();
+            self.send(&[mint_to], &[]).await.unwrap();
+            self.token_accounts.insert(mint.pubkey(), token_account);
+
+            mint.pubkey()
+        }
+
+        // Owned by the payer
+        async fn create_token_account(&mut self, mint: &Pubkey) -> Pubkey {
+            let (token_account, create_account) = self
+                .create_account(spl_token::state::Account::LEN, &spl_token::id())
+                .await;
+            let initialize_account = spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                mint,
+                &self.payer.pubkey(),
+            )
+            .unwrap();
+            self.send(&[create_account, initialize_account], &[&token_account])
+                .await
+                .unwrap();
+
+            token_account.pubkey()
+        }
+
+        async fn balance(&mut self, token_account: &Pubkey) -> u64 {
+            let account = self
+                .banks_client
+                .get_account(*token_account)
+                .await
+                .unwrap()
+                .unwrap();
+            spl_token::state::Account::unpack(&account.data)
+                .unwrap()
+                .amount
+        }
+
+        async fn amm_info(&mut self, pool: &Pool) -> AmmInfo {
+            let account = self
+                .banks_client
+                .get_account(pool.amm)
+                .await
+                .unwrap()
+                .unwrap();
+            AmmInfo::from_account_data(&account.data).unwrap()
+        }
+
+        async fn registry(&mut self) -> Registry {
+            let account = self
+                .banks_client
+                .get_account(self.registry)
+                .await
+                .unwrap()
+                .unwrap();
+            Registry::try_from_slice(&account.data).unwrap()
+        }
+
+        fn pool_entry(&self, index: u64) -> Pubkey {
+            let (pool_entry, _) = Pubkey::find_program_address(
+                &[SEED_FOR_POOL_ENTRY.as_bytes(), &index.to_le_bytes()],
+                &self.program_id,
+            );
+            pool_entry
+        }
+
+        // A pool of the given mints, in any order, with a first deposit of
+        // the given amounts
+        async fn create_pool(
+            &mut self,
+            (mint_a, amount_a): (Pubkey, u64),
+            (mint_b, amount_b): (Pubkey, u64),
+            fee_bps: u16,
+            curve: CurveType,
+        ) -> Pool {
+            let ((mint0, amount0), (mint1, amount1)) = if mint_a < mint_b {
+                ((mint_a, amount_a), (mint_b, amount_b))
+            } else {
+                ((mint_b, amount_b), (mint_a, amount_a))
+            };
+
+            let (amm, _) = Pubkey::find_program_address(
+                &[SEED_FOR_AMM.as_bytes(), mint0.as_ref(), mint1.as_ref()],
+                &self.program_id,
+            );
+            let (lp_mint, _) = Pubkey::find_program_address(
+                &[SEED_FOR_LP_MINT.as_bytes(), amm.as_ref()],
+                &self.program_id,
+            );
+            let token_account0 = self.create_token_account(&mint0).await;
+            let token_account1 = self.create_token_account(&mint1).await;
+            let pool_count = self.registry().await.pool_count;
+            let pool_entry = self.pool_entry(pool_count);
+
+            let mut data = vec![0];
+            data.extend_from_slice(&fee_bps.to_le_bytes());
+            data.extend_from_slice(&curve.try_to_vec().unwrap());
+            let initialize = Instruction::new_with_bytes(
+                self.program_id,
+                &data,
+                vec![
+                    AccountMeta::new(self.payer.pubkey(), true),
+                    AccountMeta::new(amm, false),
+                    AccountMeta::new_readonly(mint0, false),
+                    AccountMeta::new_readonly(mint1, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new(token_account0, false),
+                    AccountMeta::new(token_account1, false),
+                    AccountMeta::new(lp_mint, false),
+                    AccountMeta::new(self.registry, false),
+                    AccountMeta::new(pool_entry, false),
+                ],
+            );
+            self.send(&[initialize], &[]).await.unwrap();
+
+            let lp_token_account = self.create_token_account(&lp_mint).await;
+            let pool = Pool {
+                amm,
+                mint0,
+                mint1,
+                token_account0,
+                token_account1,
+                lp_mint,
+                lp_token_account,
+            };
+            self.deposit(&pool, amount0, amount1).await.unwrap();
+
+            pool
+        }
+
+        // The accounts of deposit, redeem and the zaps
+        fn liquidity_accounts(&self, pool: &Pool) -> Vec<AccountMeta> {
+            vec![
+                AccountMeta::new(self.payer.pubkey(), true),
+                AccountMeta::new(pool.amm, false),
+                AccountMeta::new(pool.token_account0, false),
+                AccountMeta::new(pool.token_account1, false),
+                AccountMeta::new(self.token_accounts[&pool.mint0], false),
+                AccountMeta::new(self.token_accounts[&pool.mint1], false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(pool.lp_mint, false),
+                AccountMeta::new(pool.lp_token_account, false),
+            ]
+        }
+
+        async fn deposit(
+            &mut self,
+            pool: &Pool,
+            amount0: u64,
+            amount1: u64,
+        ) -> Result<(), BanksClientError> {
+            let mut data = vec![1];
+            data.extend_from_slice(&amount0.to_le_bytes());
+            data.extend_from_slice(&amount1.to_le_bytes());
+            data.extend_from_slice(&[0; 16]);
+            data.extend_from_slice(&None::<u64>.try_to_vec().unwrap());
+            let deposit =
+                Instruction::new_with_bytes(self.program_id, &data, self.liquidity_accounts(pool));
+            self.send(&[deposit], &[]).await
+        }
+
+        // Redeem, zap_in and zap_out, whose amounts are followed by no deadline
+        async fn liquidity_instruction(
+            &mut self,
+            pool: &Pool,
+            tag: u8,
+            amounts: [u64; 3],
+        ) -> Result<(), BanksClientError> {
+            let mut data = vec![tag];
+            for amount in amounts {
+                data.extend_from_slice(&amount.to_le_bytes());
+            }
+            data.extend_from_slice(&None::<u64>.try_to_vec().unwrap());
+            let instruction =
+                Instruction::new_with_bytes(self.program_id, &data, self.liquidity_accounts(pool));
+            self.send(&[instruction], &[]).await
+        }
+
+        // The token balances of the payer for the mints of the pool, and the
+        // liquidity tokens
+        async fn balances(&mut self, pool: &Pool) -> (u64, u64, u64) {
+            let (account0, account1) = (
+                self.token_accounts[&pool.mint0],
+                self.token_accounts[&pool.mint1],
+            );
+            (
+                self.balance(&account0).await,
+                self.balance(&account1).await,
+                self.balance(&pool.lp_token_account).await,
+            )
+        }
+
+        // Returns the output amount
+        async fn swap(&mut self, pool: &Pool, mint_in: Pubkey, amount_in: u64) -> u64 {
+            let mint_out = pool.other_mint(&mint_in);
+            let account_out = self.token_accounts[&mint_out];
+            let balance_out = self.balance(&account_out).await;
+
+            let mut data = vec![3];
+            data.extend_from_slice(&u64::from(mint_in != pool.mint0).to_le_bytes());
+            data.extend_from_slice(&amount_in.to_le_bytes());
+            data.extend_from_slice(&0u64.to_le_bytes());
+            let swap = Instruction::new_with_bytes(
+                self.program_id,
+                &data,
+                vec![
+                    AccountMeta::new(self.payer.pubkey(), true),
+                    AccountMeta::new(pool.amm, false),
+                    AccountMeta::new(pool.token_account0, false),
+                    AccountMeta::new(pool.token_account1, false),
+                    AccountMeta::new(self.token_accounts[&pool.mint0], false),
+                    AccountMeta::new(self.token_accounts[&pool.mint1], false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                ],
+            );
+            self.send(&[swap], &[]).await.unwrap();
+
+            self.balance(&account_out).await - balance_out
+        }
+
+        // The treasury is the payer
+        async fn set_protocol_fee(&mut self, pool: &Pool, protocol_fee_share: u16) {
+            let mut data = vec![8];
+            data.extend_from_slice(&protocol_fee_share.to_le_bytes());
+            data.extend_from_slice(self.payer.pubkey().as_ref());
+            let set_protocol_fee = Instruction::new_with_bytes(
+                self.program_id,
+                &data,
+                vec![
+                    AccountMeta::new_readonly(self.payer.pubkey(), true),
+                    AccountMeta::new(pool.amm, false),
+                ],
+            );
+            self.send(&[set_protocol_fee], &[]).await.unwrap();
+        }
+
+        async fn swap_route(
+            &mut self,
+            mint_in: Pubkey,
+            route: &[&Pool],
+            amount_in: u64,
+            min_out_amount: u64,
+        ) -> Result<(), BanksClientError> {
+            let mut hops = vec![];
+            let mut mint = mint_in;
+            for pool in route {
+                hops.extend([
+                    AccountMeta::new(pool.amm, false),
+                    AccountMeta::new(pool.token_account(&mint), false),
+                    AccountMeta::new(pool.token_account(&pool.other_mint(&mint)), false),
+                ]);
+                mint = pool.other_mint(&mint);
+            }
+
+            let mut accounts = vec![
+                AccountMeta::new(self.payer.pubkey(), true),
+                AccountMeta::new(self.token_accounts[&mint_in], false),
+                AccountMeta::new(self.token_accounts[&mint], false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ];
+            accounts.extend(hops);
+
+            let mut data = vec![5];
+            data.extend_from_slice(&amount_in.to_le_bytes());
+            data.extend_from_slice(&min_out_amount.to_le_bytes());
+            let swap_route = Instruction::new_with_bytes(self.program_id, &data, accounts);
+            self.send(&[swap_route], &[]).await
+        }
+
+        // Borrows the output amounts, and pays back the given amount of one of
+        // the mints of the pool
+        async fn flash_swap(
+            &mut self,
+            pool: &Pool,
+            (amount0_out, amount1_out): (u64, u64),
+            (mint_in, amount_in): (Pubkey, u64),
+        ) -> Result<(), BanksClientError> {
+            let mut data = vec![6];
+            data.extend_from_slice(&amount0_out.to_le_bytes());
+            data.extend_from_slice(&amount1_out.to_le_bytes());
+            data.extend_from_slice(&amount_in.to_le_bytes());
+            let flash_swap = Instruction::new_with_bytes(
+                self.program_id,
+                &data,
+                vec![
+                    AccountMeta::new(self.payer.pubkey(), true),
+                    AccountMeta::new(pool.amm, false),
+                    AccountMeta::new(pool.token_account0, false),
+                    AccountMeta::new(pool.token_account1, false),
+                    AccountMeta::new(self.token_accounts[&pool.mint0], false),
+                    AccountMeta::new(self.token_accounts[&pool.mint1], false),
+                    AccountMeta::new_readonly(self.borrower_program_id, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    // The accounts of the callback
+                    AccountMeta::new(self.payer.pubkey(), true),
+                    AccountMeta::new(self.token_accounts[&mint_in], false),
+                    AccountMeta::new(pool.token_account(&mint_in), false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                ],
+            );
+            self.send(&[flash_swap], &[]).await
+        }
+
+        async fn slot(&mut self) -> u64 {
+            self.banks_client.get_sysvar::<Clock>().await.unwrap().slot
+        }
+
+        // The observation returned by observe, without changing the pool
+        async fn observe(&mut self, pool: &Pool) -> Observation {
+            let observe = Instruction::new_with_bytes(
+                self.program_id,
+                &[4],
+                vec![AccountMeta::new_readonly(pool.amm, false)],
+            );
+            let blockhash = self.banks_client.get_latest_blockhash().await.unwrap();
+            let transaction = Transaction::new_signed_with_payer(
+                &[observe],
+                Some(&self.payer.pubkey()),
+                &[&self.payer],
+                blockhash,
+            );
+            let simulation = self
+                .banks_client
+                .simulate_transaction(transaction)
+                .await
+                .unwrap();
+            let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+            Observation::try_from_slice(&return_data.data).unwrap()
+        }
+
+        // The output of a route, hop by hop, at the current reserves
+        async fn quote_route(&mut self, mint_in: Pubkey, route: &[&Pool], amount_in: u64) -> u64 {
+            let (mut mint, mut amount) = (mint_in, amount_in);
+            for pool in route {
+                let amm_info = self.amm_info(pool).await;
+                let (reserve_in, reserve_out) = if mint == pool.mint0 {
+                    (amm_info.reserve0, amm_info.reserve1)
+                } else {
+                    (amm_info.reserve1, amm_info.reserve0)
+                };
+                amount = quote_swap(
+                    &amm_info.curve,
+                    amount,
+                    reserve_in,
+                    reserve_out,
+                    amm_info.fee_bps,
+                )
+                .unwrap();
+                mint = pool.other_mint(&mint);
+            }
+
+            amount
+        }
+
+        // The reserves of a pool are always its token balances, net of the
+        // protocol fees
+        async fn check_reserves(&mut self, pool: &Pool) {
+            let amm_info = self.amm_info(pool).await;
+            assert_eq!(
+                self.balance(&pool.token_account0).await,
+                amm_info.reserve0 + amm_info.protocol_fees0
+            );
+            assert_eq!(
+                self.balance(&pool.token_account1).await,
+                amm_info.reserve1 + amm_info.protocol_fees1
+            );
+        }
+    }
+
+    // A route over the given number of hops, through pools of different fees
+    // and curves, compared with the swap math applied hop by hop
+    async fn check_route(hops: usize) {
+        let mut test = Test::start().await;
+        let mut mints = vec![];
+        for _ in 0..=hops {
+            mints.push(test.create_mint(6).await);
+        }
+
+        let curves = [
+            (30, CurveType::ConstantProduct),
+            (4, CurveType::StableSwap { amp: 100 }),
+            (100, CurveType::ConstantProduct),
+        ];
+        let mut pools = vec![];
+        for (i, (fee_bps, curve)) in curves.into_iter().take(hops).enumerate() {
+            let pool = test
+                .create_pool(
+                    (mints[i], 1_000_000_000),
+                    (mints[i + 1], 1_000_000_000 + i as u64 * 250_000_000),
+                    fee_bps,
+                    curve,
+                )
+                .await;
+            pools.push(pool);
+        }
+        let route: Vec<&Pool> = pools.iter().collect();
+
+        let (mint_in, mint_out) = (mints[0], mints[hops]);
+        let (account_in, account_out) = (
+            test.token_accounts[&mint_in],
+            test.token_accounts[&mint_out],
+        );
+        let balance_in = test.balance(&account_in).await;
+        let balance_out = test.balance(&account_out).await;
+        let mut balances = vec![];
+        for mint in &mints[1..hops] {
+            let account = test.token_accounts[mint];
+            balances.push(test.balance(&account).await);
+        }
+
+        let amount_in = 10_000_000;
+        let amount_out = test.quote_route(mint_in, &route, amount_in).await;
+        assert!(amount_out > 0);
+
+        // The route is atomic: it fails as a whole below the min out amount
+        assert!(test
+            .swap_route(mint_in, &route, amount_in, amount_out + 1)
+            .await
+            .is_err());
+        test.swap_route(mint_in, &route, amount_in, amount_out)
+            .await
+            .unwrap();
+
+        assert_eq!(test.balance(&account_in).await, balance_in - amount_in);
+        assert_eq!(test.balance(&account_out).await, balance_out + amount_out);
+        // The intermediate amounts never leave the pools
+        for (mint, balance) in mints[1..hops].iter().zip(balances) {
+            let account = test.token_accounts[mint];
+            assert_eq!(test.balance(&account).await, balance);
+        }
+        for pool in &pools {
+            test.check_reserves(pool).await;
+        }
+    }
+
+    // Every pool gets its own entry, at the next index of the registry, and
+    // there is a single pool for every pair of mints
+    #[tokio::test]
+    async fn pools_are_listed_in_the_registry() {
+        let mut test = Test::start().await;
+        let mints = [
+            test.create_mint(6).await,
+            test.create_mint(9).await,
+            test.create_mint(6).await,
+        ];
+        let pools = [
+            test.create_pool(
+                (mints[0], 1_000_000),
+                (mints[1], 1_000_000),
+                30,
+                CurveType::ConstantProduct,
+            )
+            .await,
+            test.create_pool(
+                (mints[1], 1_000_000),
+                (mints[2], 1_000_000),
+                30,
+                CurveType::ConstantProduct,
+            )
+            .await,
+        ];
+        assert_eq!(test.registry().await.pool_count, 2);
+
+        for (index, pool) in pools.iter().enumerate() {
+            let pool_entry = test.pool_entry(index as u64);
+            let account = test
+                .banks_client
+                .get_account(pool_entry)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(account.data.len(), PoolEntry::LEN);
+            let pool_entry = PoolEntry::try_from_slice(&account.data).unwrap();
+            assert_eq!(
+                (
+                    pool_entry.amm_info,
+                    pool_entry.mint0,
+                    pool_entry.mint1,
+                    pool_entry.token_account0,
+                    pool_entry.token_account1
+                ),
+                (
+                    pool.amm,
+                    pool.mint0,
+                    pool.mint1,
+                    pool.token_account0,
+                    pool.token_account1
+                )
+            );
+        }
+
+        // A second pool of the same pair, or an entry at another index, is
+        // rejected
+        let (mint0, mint1) = (pools[0].mint0, pools[0].mint1);
+        let token_account0 = test.create_token_account(&mint0).await;
+        let token_account1 = test.create_token_account(&mint1).await;
+        for index in [2, 3] {
+            let mut data = vec![0];
+            data.extend_from_slice(&30u16.to_le_bytes());
+            data.extend_from_slice(&CurveType::ConstantProduct.try_to_vec().unwrap());
+            let initialize = Instruction::new_with_bytes(
+                test.program_id,
+                &data,
+                vec![
+                    AccountMeta::new(test.payer.pubkey(), true),
+                    AccountMeta::new(pools[0].amm, false),
+                    AccountMeta::new_readonly(mint0, false),
+                    AccountMeta::new_readonly(mint1, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new(token_account0, false),
+                    AccountMeta::new(token_account1, false),
+                    AccountMeta::new(pools[0].lp_mint, false),
+                    AccountMeta::new(test.registry, false),
+                    AccountMeta::new(test.pool_entry(index), false),
+                ],
+            );
+            assert!(test.send(&[initialize], &[]).await.is_err());
+        }
+        assert_eq!(test.registry().await.pool_count, 2);
+    }
+
+    // Instruction data cut short is rejected rather than read out of bounds
+    #[test]
+    fn short_instruction_data_is_rejected() {
+        // swap_route, and flash_swap with no data for the callback
+        for tag in [5, 6] {
+            let mut data = vec![tag];
+            data.extend_from_slice(&1_000u64.to_le_bytes());
+            data.extend_from_slice(&900u64.to_le_bytes());
+            assert!(AmmInstruction::from_instruction_data(&data).is_some());
+            for len in 1..data.len() {
+                assert!(AmmInstruction::from_instruction_data(&data[..len]).is_none());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn swap_route_over_two_hops() {
+        check_route(2).await;
+    }
+
+    #[tokio::test]
+    async fn swap_route_over_three_hops() {
+        check_route(3).await;
+    }
+
+    // With the protocol fee off, whether it was never turned on or turned
+    // off again, swaps leave the whole input amount in the reserves, as
+    // before the protocol fee, and no protocol fee accrues
+    #[tokio::test]
+    async fn swaps_are_unchanged_with_the_protocol_fee_off() {
+        let mut test = Test::start().await;
+        let mut pools = vec![];
+        for turned_off in [false, true] {
+            let mint_a = test.create_mint(6).await;
+            let mint_b = test.create_mint(9).await;
+            let pool = test
+                .create_pool(
+                    (mint_a, 5_000_000_000),
+                    (mint_b, 2_000_000_000_000),
+                    30,
+                    CurveType::ConstantProduct,
+                )
+                .await;
+            if turned_off {
+                test.set_protocol_fee(&pool, 2_000).await;
+                test.set_protocol_fee(&pool, 0).await;
+            }
+            pools.push((pool, mint_a, mint_b));
+        }
+
+        let mut results = vec![];
+        for (pool, mint_a, mint_b) in &pools {
+            let (mut reserve_a, mut reserve_b) = (5_000_000_000, 2_000_000_000_000);
+            let mut amounts_out = vec![];
+            for (i, amount_in) in [1_000_000, 3_000_000_000, 7, 250_000_000]
+                .into_iter()
+                .enumerate()
+            {
+                let (mint_in, reserve_in, reserve_out) = if i % 2 == 0 {
+                    (*mint_a, &mut reserve_a, &mut reserve_b)
+                } else {
+                    (*mint_b, &mut reserve_b, &mut reserve_a)
+                };
+                let amount_out = test.swap(pool, mint_in, amount_in).await;
+                // The swap math without the protocol fee
+                assert_eq!(
+                    amount_out,
+                    (amount_in as u128 * 9_970 * *reserve_out as u128
+                        / (*reserve_in as u128 * 10_000 + amount_in as u128 * 9_970))
+                        as u64
+                );
+                *reserve_in += amount_in;
+                *reserve_out -= amount_out;
+                amounts_out.push(amount_out);
+            }
+
+            let amm_info = test.amm_info(pool).await;
+            let reserves = if pool.mint0 == *mint_a {
+                (amm_info.reserve0, amm_info.reserve1)
+            } else {
+                (amm_info.reserve1, amm_info.reserve0)
+            };
+            assert_eq!(reserves, (reserve_a, reserve_b));
+            assert_eq!((amm_info.protocol_fees0, amm_info.protocol_fees1), (0, 0));
+            test.check_reserves(pool).await;
+            results.push(amounts_out);
+        }
+
+        assert_eq!(results[0], results[1]);
+    }
+
+    // A flash swap must pay back the borrowed amount plus the fee on the amount
+    // paid back, rounded up, which is left in the reserves
+    #[tokio::test]
+    async fn flash_swaps_must_pay_back_the_fee() {
+        let mut test = Test::start().await;
+        let mint_a = test.create_mint(6).await;
+        let mint_b = test.create_mint(6).await;
+        let pool = test
+            .create_pool(
+                (mint_a, 1_000_000_000),
+                (mint_b, 1_000_000_000),
+                30,
+                CurveType::ConstantProduct,
+            )
+            .await;
+        let amount_out = 1_000_000;
+        // 1_003_010 - ceil(1_003_010 * 0.3%) = 1_000_000
+        let amount_in = 1_003_010;
+
+        let before = test.balances(&pool).await;
+        for amount_in in [0, amount_out, amount_in - 1] {
+            assert!(test
+                .flash_swap(&pool, (amount_out, 0), (pool.mint0, amount_in))
+                .await
+                .is_err());
+        }
+        assert_eq!(test.balances(&pool).await, before);
+
+        let amm_info = test.amm_info(&pool).await;
+        let k = amm_info.reserve0 as u128 * amm_info.reserve1 as u128;
+        test.flash_swap(&pool, (amount_out, 0), (pool.mint0, amount_in))
+            .await
+            .unwrap();
+        let after = test.balances(&pool).await;
+        assert_eq!(
+            after,
+            (before.0 - (amount_in - amount_out), before.1, before.2)
+        );
+
+        let amm_info = test.amm_info(&pool).await;
+        assert_eq!(
+            (amm_info.reserve0, amm_info.reserve1),
+            (1_000_000_000 + amount_in - amount_out, 1_000_000_000)
+        );
+        assert!(amm_info.reserve0 as u128 * amm_info.reserve1 as u128 >= k);
+        test.check_reserves(&pool).await;
+
+        // Borrowing one token and paying back the other is a swap, which
+        // must keep k as well: (r0 + in - ceil(in * 0.3%)) * (r1 - out) >= r0 * r1
+        // with r0 = 1_000_003_010 and r1 = 1_000_000_000 from 1_004_018
+        let amount_in = 1_004_018;
+        assert!(test
+            .flash_swap(&pool, (0, amount_out), (pool.mint0, amount_in - 1))
+            .await
+            .is_err());
+        let amm_info = test.amm_info(&pool).await;
+        let k = amm_info.reserve0 as u128 * amm_info.reserve1 as u128;
+        test.flash_swap(&pool, (0, amount_out), (pool.mint0, amount_in))
+            .await
+            .unwrap();
+        let amm_info = test.amm_info(&pool).await;
+        assert!(amm_info.reserve0 as u128 * amm_info.reserve1 as u128 >= k);
+        test.check_reserves(&pool).await;
+    }
+
+    // The prices of each token in terms of the other, as UQ64.64 fixed-point
+    // numbers, accumulated for every slot elapsed at the reserves they had
+    // during those slots
+    fn accumulate(price_cumulative: u128, reserve_in: u64, reserve_out: u64, slots: u64) -> u128 {
+        let price = ((reserve_out as u128) << 64) / reserve_in as u128;
+        price_cumulative.wrapping_add(price.wrapping_mul(slots as u128))
+    }
+
+    #[tokio::test]
+    async fn cumulative_prices_accumulate_the_reserves_for_every_slot() {
+        let mut test = Test::start().await;
+        let mint_a = test.create_mint(6).await;
+        let mint_b = test.create_mint(9).await;
+        let pool = test
+            .create_pool(
+                (mint_a, 1_000_000_000),
+                (mint_b, 4_000_000_000_000),
+                30,
+                CurveType::ConstantProduct,
+            )
+            .await;
+
+        let mut last = test.amm_info(&pool).await;
+        assert_eq!(last.last_update_slot, test.slot().await);
+        for (slots, mint_in, amount_in) in [
+            (100, pool.mint0, 50_000_000),
+            (150, pool.mint1, 700_000_000_000),
+        ] {
+            let slot = last.last_update_slot + slots;
+            test.context.warp_to_slot(slot).unwrap();
+            test.swap(&pool, mint_in, amount_in).await;
+
+            // The swap accumulates the prices at the reserves before it
+            let amm_info = test.amm_info(&pool).await;
+            assert_eq!(amm_info.last_update_slot, slot);
+            assert_eq!(
+                (amm_info.price0_cumulative, amm_info.price1_cumulative),
+                (
+                    accumulate(last.price0_cumulative, last.reserve0, last.reserve1, slots),
+                    accumulate(last.price1_cumulative, last.reserve1, last.reserve0, slots)
+                )
+            );
+            last = amm_info;
+        }
+
+        // observe accumulates up to the current slot without updating the pool
+        let slot = last.last_update_slot + 40;
+        test.context.warp_to_slot(slot).unwrap();
+        let observation = test.observe(&pool).await;
+        assert_eq!(observation.slot, slot);
+        assert_eq!(
+            (observation.price0_cumulative, observation.price1_cumulative),
+            (
+                accumulate(last.price0_cumulative, last.reserve0, last.reserve1, 40),
+                accumulate(last.price1_cumulative, last.reserve1, last.reserve0, 40)
+            )
+        );
+        assert_eq!(
+            test.amm_info(&pool).await.last_update_slot,
+            last.last_update_slot
+        );
+
+        // The time-weighted average price over the last slots is the price at
+        // the reserves, which did not change
+        assert_eq!(
+            (observation.price0_cumulative - last.price0_cumulative) / 40,
+            ((last.reserve1 as u128) << 64) / last.reserve0 as u128
+        );
+    }
+
+    // Every instruction transfers exactly the amounts quoted by the math the
+    // clients use, at the reserves before the instruction
+    #[tokio::test]
+    async fn instructions_transfer_the_quoted_amounts() {
+        let mut test = Test::start().await;
+        for curve in [
+            CurveType::ConstantProduct,
+            CurveType::StableSwap { amp: 50 },
+        ] {
+            let mint_a = test.create_mint(6).await;
+            let mint_b = test.create_mint(6).await;
+            let pool = test
+                .create_pool((mint_a, 3_000_000_000), (mint_b, 4_000_000_000), 25, curve)
+                .await;
+            test.set_protocol_fee(&pool, 1_500).await;
+
+            // Deposit
+            let amm_info = test.amm_info(&pool).await;
+            let quote = quote_deposit(
+                70_000_000,
+                70_000_000,
+                amm_info.reserve0,
+                amm_info.reserve1,
+                amm_info.supply,
+            )
+            .unwrap();
+            let before = test.balances(&pool).await;
+            test.deposit(&pool, 70_000_000, 70_000_000).await.unwrap();
+            let after = test.balances(&pool).await;
+            assert_eq!(
+                (before.0 - after.0, before.1 - after.1, after.2 - before.2),
+                (quote.amount0, quote.amount1, quote.liquidity)
+            );
+            test.check_reserves(&pool).await;
+
+            // Swap
+            let amm_info = test.amm_info(&pool).await;
+            let quote = quote_swap(
+                &amm_info.curve,
+                123_456_789,
+                amm_info.reserve1,
+                amm_info.reserve0,
+                amm_info.fee_bps,
+            )
+            .unwrap();
+            assert_eq!(test.swap(&pool, pool.mint1, 123_456_789).await, quote);
+            test.check_reserves(&pool).await;
+
+            // Redeem
+            let amm_info = test.amm_info(&pool).await;
+            let (amount0, amount1) = quote_redeem(
+                10_000_000,
+                amm_info.reserve0,
+                amm_info.reserve1,
+                amm_info.supply,
+            )
+            .unwrap();
+            let before = test.balances(&pool).await;
+            test.liquidity_instruction(&pool, 2, [10_000_000, 0, 0])
+                .await
+                .unwrap();
+            let after = test.balances(&pool).await;
+            assert_eq!(
+                (after.0 - before.0, after.1 - before.1, before.2 - after.2),
+                (amount0, amount1, 10_000_000)
+            );
+            test.check_reserves(&pool).await;
+
+            // Zap in of mint0
+            let amm_info = test.amm_info(&pool).await;
+            let quote = quote_zap_in(
+                &amm_info.curve,
+                50_000_000,
+                amm_info.reserve0,
+                amm_info.reserve1,
+                amm_info.supply,
+                amm_info.fee_bps,
+                amm_info.protocol_fee_share,
+            )
+            .unwrap();
+            let before = test.balances(&pool).await;
+            test.liquidity_instruction(&pool, 10, [0, 50_000_000, 0])
+                .await
+                .unwrap();
+            let after = test.balances(&pool).await;
+            assert_eq!(
+                (before.0 - after.0, after.1 - before.1, after.2 - before.2),
+                (
+                    quote.swap_in + quote.deposit.amount0,
+                    quote.swap_out - quote.deposit.amount1,
+                    quote.deposit.liquidity
+                )
+            );
+            test.check_reserves(&pool).await;
+
+            // Zap out to mint1
+            let amm_info = test.amm_info(&pool).await;
+            let quote = quote_zap_out(
+                &amm_info.curve,
+                20_000_000,
+                amm_info.reserve1,
+                amm_info.reserve0,
+                amm_info.supply,
+                amm_info.fee_bps,
+            )
+            .unwrap();
+            let before = test.balances(&pool).await;
+            test.liquidity_instruction(&pool, 11, [1, 20_000_000, 0])
+                .await
+                .unwrap();
+            let after = test.balances(&pool).await;
+            assert_eq!(
+                (after.0 - before.0, after.1 - before.1, before.2 - after.2),
+                (0, quote.amount_out, 20_000_000)
+            );
+            test.check_reserves(&pool).await;
+        }
+    }
 }
@@ -23,6 +23,7 @@ pub mod crowdfund {
         campaign_pda.campaign_owner = *ctx.accounts.campaign_owner.key;
         campaign_pda.end_donate_slot = end_donate_slot;
         campaign_pda.goal_in_lamports = goal_in_lamports;
+        campaign_pda.bump = ctx.bumps.campaign_pda;
         Ok(())
     }
 
@@ -42,6 +43,7 @@ pub mod crowdfund {
         );
 
         deposit_pda.total_donated += donated_lamports;
+        deposit_pda.bump = ctx.bumps.deposit_pda;
 
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
             &donor.key(),
@@ -142,7 +144,7 @@ pub struct InitializeCtx<'info> {
 pub struct DonateCtx<'info> {
     #[account(mut)]
     pub donor: Signer<'info>,
-    #[account(mut, seeds = [_campaign_name.as_ref()], bump )]
+    #[account(mut, seeds = [_campaign_name.as_ref()], bump = campaign_pda.bump )]
     pub campaign_pda: Account<'info, CampaignPDA>,
     #[account(
         init_if_needed,
@@ -160,7 +162,7 @@ pub struct DonateCtx<'info> {
 pub struct WithdrawCtx<'info> {
     #[account(mut)]
     pub campaign_owner: Signer<'info>,
-    #[account(mut, seeds = [_campaign_name.as_ref()], bump )]
+    #[account(mut, seeds = [_campaign_name.as_ref()], bump = campaign_pda.bump )]
     pub campaign_pda: Account<'info, CampaignPDA>,
 }
 
@@ -169,12 +171,12 @@ pub struct WithdrawCtx<'info> {
 pub struct ReclaimCtx<'info> {
     #[account(mut)]
     pub donor: Signer<'info>,
-    #[account(mut, seeds = [_campaign_name.as_ref()], bump )]
+    #[account(mut, seeds = [_campaign_name.as_ref()], bump = campaign_pda.bump )]
     pub campaign_pda: Account<'info, CampaignPDA>,
     #[account( 
         mut, 
         seeds = ["deposit".as_ref(), _campaign_name.as_ref(), donor.key().as_ref()],
-        bump,
+        bump = deposit_pda.bump,
     )]
     pub deposit_pda: Account<'info, DepositPDA>,
 }
@@ -187,12 +189,14 @@ pub struct CampaignPDA {
     pub campaign_owner: Pubkey, // 32 bytes
     pub end_donate_slot: u64,   // 8 bytes
     pub goal_in_lamports: u64,  // 8 bytes
+    pub bump: u8,               // 1 byte
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct DepositPDA {
     pub total_donated: u64, // 8 bytes
+    pub bump: u8,           // 1 byte
 }
 
 #[error_code]
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -13,14 +13,14 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct Campaign {
     pub receiver: Pubkey,
     pub end_donate_slot: u64,
     pub goal: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct DonationInfo {
     pub donor: Pubkey,
     pub reciever_campain: Pubkey,
@@ -20,6 +20,7 @@ pub mod escrow {
         escrow_info.buyer = *ctx.accounts.buyer.key;
         escrow_info.amount_in_lamports = amount_in_lamports;
         escrow_info.state = State::WaitDeposit;
+        escrow_info.bump = ctx.bumps.escrow_info;
 
         Ok(())
     }
@@ -111,6 +112,7 @@ pub struct EscrowInfo {
     pub buyer: Pubkey,           // 32 bytes
     pub amount_in_lamports: u64, // 8 bytes
     pub state: State,            // see: https://www.anchor-lang.com/docs/space
+    pub bump: u8,                // 1 byte
 }
 
 #[derive(Accounts)]
@@ -145,7 +147,7 @@ pub struct DepositCtx<'info> {
     #[account(
         mut,
         seeds = [escrow_name.as_ref(), seller.key().as_ref(), buyer.key().as_ref()],
-        bump,
+        bump = escrow_info.bump,
     )]
     pub escrow_info: Account<'info, EscrowInfo>,
     pub system_program: Program<'info, System>,
@@ -167,7 +169,7 @@ pub struct PayCtx<'info> {
     #[account(
         mut,
         seeds = [escrow_name.as_ref(), seller.key().as_ref(), buyer.key().as_ref()],
-        bump,
+        bump = escrow_info.bump,
     )]
     pub escrow_info: Account<'info, EscrowInfo>,
 }
@@ -188,7 +190,7 @@ pub struct RefundCtx<'info> {
     #[account(
         mut,
         seeds = [escrow_name.as_ref(), seller.key().as_ref(), buyer.key().as_ref()],
-        bump,
+        bump = escrow_info.bump,
     )]
     pub escrow_info: Account<'info, EscrowInfo>,
 }
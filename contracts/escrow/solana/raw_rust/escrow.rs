@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
@@ -36,14 +36,14 @@ pub fn process_instruction(
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug, PartialEq)]
 enum State {
     WaitDeposit = 0,
     WaitRecipient = 1,
     Closed = 2,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct EscrowInfo {
     pub seller: Pubkey,
     pub buyer: Pubkey,
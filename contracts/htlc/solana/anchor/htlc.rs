@@ -17,6 +17,7 @@ pub mod htlc {
         htlc_info.verifier = *ctx.accounts.verifier.key;
         htlc_info.hashed_secret = hashed_secret;
         htlc_info.reveal_timeout = Clock::get()?.slot + delay;
+        htlc_info.bump = ctx.bumps.htlc_info;
 
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.owner.key(),
@@ -74,6 +75,7 @@ pub struct HtlcPDA {
     pub hashed_secret: [u8; 32], // 32 bytes
     pub reveal_timeout: u64,     // 8 bytes
     pub amount: u64,             // 8 bytes
+    pub bump: u8,                // 1 byte
 }
 
 #[derive(Accounts)]
@@ -100,7 +102,7 @@ pub struct RevealCtx<'info> {
     #[account(
         mut, 
         seeds = [owner.key().as_ref(), verifier.key().as_ref()],
-        bump,
+        bump = htlc_info.bump,
         constraint = htlc_info.verifier == verifier.key() @ CustomError::InvalidVerifier,
         constraint = htlc_info.owner == owner.key() @ CustomError::InvalidOwner,
     )]
@@ -115,7 +117,7 @@ pub struct TimeoutCtx<'info> {
     #[account(
         mut, 
         seeds = [owner.key().as_ref(), verifier.key().as_ref()],
-        bump,
+        bump = htlc_info.bump,
         constraint = htlc_info.verifier == verifier.key() @ CustomError::InvalidVerifier,
         constraint = htlc_info.owner == owner.key() @ CustomError::InvalidOwner,
     )]
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -42,17 +42,18 @@ pub fn process_instruction(
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct HTLCInfo {
     pub owner: Pubkey,
     pub verifier: Pubkey,
     pub hashed_secret: [u8; 32],
     pub reveal_timeout: u64,
     pub amount: u64,
+    pub bump: u8,
 }
 
 impl HTLCInfo {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8;
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1;
 }
 
 fn initialize(
@@ -111,6 +112,7 @@ fn initialize(
         hashed_secret,
         reveal_timeout: Clock::get()?.slot + delay,
         amount,
+        bump,
     };
 
     invoke_signed(
@@ -144,16 +146,18 @@ fn reveal(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]
         return Err(ProgramError::IllegalOwner);
     }
 
-    let (expected_pda, _bump) =
-        Pubkey::find_program_address(&[owner.key.as_ref(), verifier.key.as_ref()], program_id);
+    let htlc_info: HTLCInfo = HTLCInfo::try_from_slice(*htlc_info_account_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[owner.key.as_ref(), verifier.key.as_ref(), &[htlc_info.bump]],
+        program_id,
+    )?;
 
     if expected_pda != *htlc_info_account_pda.key {
         msg!("Not the right PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let htlc_info: HTLCInfo = HTLCInfo::try_from_slice(*htlc_info_account_pda.data.borrow())?;
-
     if owner.key != &htlc_info.owner {
         msg!("The owner is not the owner of the HTLC");
         return Err(ProgramError::IllegalOwner);
@@ -189,14 +193,6 @@ fn timeout(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _bump) =
-        Pubkey::find_program_address(&[owner.key.as_ref(), verifier.key.as_ref()], program_id);
-
-    if expected_pda != *htlc_info_account_pda.key {
-        msg!("Not the right PDA");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
     if htlc_info_account_pda.owner.ne(&program_id) {
         msg!("The writing account isn't owned by program");
         return Err(ProgramError::IllegalOwner);
@@ -204,6 +200,16 @@ fn timeout(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
 
     let htlc_info: HTLCInfo = HTLCInfo::try_from_slice(*htlc_info_account_pda.data.borrow())?;
 
+    let expected_pda = Pubkey::create_program_address(
+        &[owner.key.as_ref(), verifier.key.as_ref(), &[htlc_info.bump]],
+        program_id,
+    )?;
+
+    if expected_pda != *htlc_info_account_pda.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
     if verifier.key != &htlc_info.verifier {
         msg!("The proposed verifier is not the verifier of the HTLC");
         return Err(ProgramError::InvalidAccountData);
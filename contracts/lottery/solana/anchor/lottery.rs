@@ -25,6 +25,7 @@ pub mod lottery {
             hashlock1,
             hashlock2,
             end_reveal,
+            ctx.bumps.lottery_info,
         )?;
 
         let player1 = ctx.accounts.player1.to_account_info();
@@ -120,6 +121,7 @@ pub struct LotteryInfo {
     #[max_len(30)]
     pub secret2: String,
     pub end_reveal: u64,
+    pub bump: u8,
 }
 
 impl LotteryInfo {
@@ -130,6 +132,7 @@ impl LotteryInfo {
         hashlock1: [u8; 32],
         hashlock2: [u8; 32],
         end_reveal: u64,
+        bump: u8,
     ) -> Result<()> {
         require!(hashlock1 != hashlock2, CustomError::TwoEqualHashes);
         require!(
@@ -142,6 +145,7 @@ impl LotteryInfo {
         self.hashlock1 = hashlock1;
         self.hashlock2 = hashlock2;
         self.end_reveal = end_reveal;
+        self.bump = bump;
         Ok(())
     }
 
@@ -242,7 +246,7 @@ pub struct RevealP1Ctx<'info> {
     #[account(
         mut,
         seeds = [player1.key().as_ref(), player2.key().as_ref()], 
-        bump,
+        bump = lottery_info.bump,
     )]
     pub lottery_info: Account<'info, LotteryInfo>,
 }
@@ -256,7 +260,7 @@ pub struct RevealP2Ctx<'info> {
     #[account(
         mut,
         seeds = [player1.key().as_ref(), player2.key().as_ref()], 
-        bump,
+        bump = lottery_info.bump,
     )]
     pub lottery_info: Account<'info, LotteryInfo>,
 }
@@ -269,7 +273,7 @@ pub struct RedeemIfP1NoRevealCtx<'info> {
     #[account(
         mut,
         seeds = [player1.key().as_ref(), player2.key().as_ref()], 
-        bump,
+        bump = lottery_info.bump,
     )]
     pub lottery_info: Account<'info, LotteryInfo>,
 }
@@ -282,7 +286,7 @@ pub struct RedeemIfP2NoRevealCtx<'info> {
     #[account(
         mut,
         seeds = [player1.key().as_ref(), player2.key().as_ref()], 
-        bump,
+        bump = lottery_info.bump,
     )]
     pub lottery_info: Account<'info, LotteryInfo>,
 }
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -18,7 +18,7 @@ entrypoint!(process_instruction);
 
 const DEADLINE_EXTENSION: u64 = 10;
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 pub struct LotteryInfo {
     pub state: u8, // 0 - Init, 1 - RevealP1, 2 - RevealP2
     pub player1: Pubkey,
@@ -28,10 +28,11 @@ pub struct LotteryInfo {
     pub hashlock2: [u8; 32],
     pub secret2_len: u64,
     pub end_reveal: u64,
+    pub bump: u8,
 }
 
 impl LotteryInfo {
-    pub const LEN: usize = 1 + 32 + 32 + 32 + 8 + 32 + 8 + 8;
+    pub const LEN: usize = 1 + 32 + 32 + 32 + 8 + 32 + 8 + 8 + 1;
 
     pub fn initialize(
         player1: Pubkey,
@@ -39,6 +40,7 @@ impl LotteryInfo {
         hashlock1: [u8; 32],
         hashlock2: [u8; 32],
         end_reveal: u64,
+        bump: u8,
     ) -> Result<Self, ProgramError> {
         if hashlock1 == hashlock2 {
             msg!("Provided two equal hashlocks");
@@ -59,6 +61,7 @@ impl LotteryInfo {
             hashlock2,
             secret2_len: 0,
             end_reveal,
+            bump,
         })
     }
 
@@ -290,6 +293,7 @@ fn join<'a>(
         hashlock1,
         hashlock2,
         deadline,
+        pda_bump,
     )?;
 
     lottery_info.serialize(&mut &mut lottery_info_account.try_borrow_mut_data()?[..])?;
@@ -330,19 +334,23 @@ fn reveal_p1<'a>(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
-        &[player1_account.key.as_ref(), player2_account.key.as_ref()],
+    let mut lottery_info: LotteryInfo =
+        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[
+            player1_account.key.as_ref(),
+            player2_account.key.as_ref(),
+            &[lottery_info.bump],
+        ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *lottery_info_account.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut lottery_info: LotteryInfo =
-        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
-
     lottery_info.reveal_p1(&secret)?;
 
     lottery_info.serialize(&mut &mut lottery_info_account.try_borrow_mut_data()?[..])?;
@@ -365,18 +373,23 @@ fn reveal_p2<'a>(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
-        &[player1_account.key.as_ref(), player2_account.key.as_ref()],
+    let mut lottery_info: LotteryInfo =
+        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[
+            player1_account.key.as_ref(),
+            player2_account.key.as_ref(),
+            &[lottery_info.bump],
+        ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *lottery_info_account.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut lottery_info: LotteryInfo =
-        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
     lottery_info.reveal_p2(&secret)?;
     lottery_info.serialize(&mut &mut lottery_info_account.try_borrow_mut_data()?[..])?;
 
@@ -406,18 +419,23 @@ fn redeem_if_p1_no_reveal<'a>(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
-        &[player1_account.key.as_ref(), player2_account.key.as_ref()],
+    let lottery_info: LotteryInfo =
+        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[
+            player1_account.key.as_ref(),
+            player2_account.key.as_ref(),
+            &[lottery_info.bump],
+        ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *lottery_info_account.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let lottery_info: LotteryInfo =
-        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
     lottery_info.check_redeem_if_p1_no_reveal()?;
 
     **player2_account.try_borrow_mut_lamports()? += **lottery_info_account.lamports.borrow();
@@ -440,18 +458,23 @@ fn redeem_if_p2_no_reveal<'a>(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) = Pubkey::find_program_address(
-        &[player1_account.key.as_ref(), player2_account.key.as_ref()],
+    let lottery_info: LotteryInfo =
+        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[
+            player1_account.key.as_ref(),
+            player2_account.key.as_ref(),
+            &[lottery_info.bump],
+        ],
         program_id,
-    );
+    )?;
 
     if expected_pda != *lottery_info_account.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let lottery_info: LotteryInfo =
-        LotteryInfo::try_from_slice(*lottery_info_account.data.borrow())?;
     lottery_info.check_redeem_if_p2_no_reveal()?;
 
     **player1_account.try_borrow_mut_lamports()? += **lottery_info_account.lamports.borrow();
@@ -26,6 +26,7 @@ pub mod payment_splitter {
         .unwrap();
 
         ps_info.current_lamports = lamports_to_transfer;
+        ps_info.bump = ctx.bumps.ps_info;
 
         let payees = ctx.remaining_accounts;
 
@@ -106,6 +107,7 @@ pub struct PaymentSplitterInfo {
     pub payees: Vec<Pubkey>,
     pub shares_amounts: Vec<u64>,
     pub released_amounts: Vec<u64>,
+    pub bump: u8,
 }
 
 impl PaymentSplitterInfo {
@@ -138,7 +140,7 @@ impl PaymentSplitterInfo {
     }
 
     pub const fn space(num_voters: u64) -> usize {
-        (8 + 8 + (4 + (num_voters * 32)) + (4 + (num_voters * 8)) + (4 + (num_voters * 8)) )
+        (8 + 8 + (4 + (num_voters * 32)) + (4 + (num_voters * 8)) + (4 + (num_voters * 8)) + 1)
             as usize
     }
 }
@@ -168,7 +170,7 @@ pub struct ReleaseCtx<'info> {
     #[account(
         mut,
         seeds = ["payment_splitter".as_ref(), initializer.key().as_ref()],
-        bump,
+        bump = ps_info.bump,
     )]
     pub ps_info: Account<'info, PaymentSplitterInfo>,
     pub system_program: Program<'info, System>,
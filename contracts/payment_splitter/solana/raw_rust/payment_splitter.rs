@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
@@ -18,14 +18,20 @@ entrypoint!(process_instruction);
 
 const PS_SEED: &str = "PS_SEED";
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 pub struct PaymentSplitterInfo {
     pub shares_map: BTreeMap<Pubkey, u64>,
     pub released_map: BTreeMap<Pubkey, u64>,
     pub current_lamports: u64,
+    pub bump: u8,
 }
 
 impl PaymentSplitterInfo {
+    // Both maps have one (32 bytes key, 8 bytes value) entry per payee
+    pub const fn size(payees: usize) -> usize {
+        (4 + payees * (32 + 8)) + (4 + payees * (32 + 8)) + 8 + 1
+    }
+
     pub fn check_validity(&mut self) {
         if self.shares_map.len() != self.released_map.len() {
             panic!("PaymentSplitter: shares_map and released_map are not the same size");
@@ -101,9 +107,18 @@ impl PSInstruction {
     }
 
     fn get_initialize_context(instruction_data: &[u8]) -> Option<Self> {
-        let mut ps_info = PaymentSplitterInfo::try_from_slice(&instruction_data).unwrap();
+        // The bump is not part of the instruction data, it is set when creating the PDA
+        let (shares_map, released_map, current_lamports) =
+            <(BTreeMap<Pubkey, u64>, BTreeMap<Pubkey, u64>, u64)>::try_from_slice(&instruction_data)
+                .unwrap();
+        let mut ps_info = PaymentSplitterInfo {
+            shares_map,
+            released_map,
+            current_lamports,
+            bump: 0,
+        };
         ps_info.check_validity();
-        let pda_size = instruction_data.len();
+        let pda_size = PaymentSplitterInfo::size(ps_info.shares_map.len());
         Some(Self::Initialize { ps_info, pda_size })
     }
 }
@@ -126,7 +141,7 @@ pub fn process_instruction<'a>(
 fn initialize<'a>(
     program_id: &Pubkey,
     accounts: &'a [AccountInfo<'a>],
-    ps_info: PaymentSplitterInfo,
+    mut ps_info: PaymentSplitterInfo,
     pda_size: usize,
 ) -> ProgramResult {
     let accounts_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
@@ -138,7 +153,7 @@ fn initialize<'a>(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let _ps_bump = check_pda(program_id, ps_state_account, &[PS_SEED.as_bytes()])?;
+    ps_info.bump = find_pda(program_id, ps_state_account, &[PS_SEED.as_bytes()])?;
 
     create_pda_account(
         initializer_account,
@@ -146,7 +161,7 @@ fn initialize<'a>(
         system_program_account,
         ps_info.current_lamports,
         program_id,
-        &[&[PS_SEED.as_bytes(), &[_ps_bump]]],
+        &[&[PS_SEED.as_bytes(), &[ps_info.bump]]],
         pda_size,
     )?;
 
@@ -164,10 +179,14 @@ fn release<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramR
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let _ps_bump = check_pda(program_id, ps_state_account, &[PS_SEED.as_bytes()])?;
-
     let mut ps_info = PaymentSplitterInfo::try_from_slice(*ps_state_account.data.borrow())?;
 
+    check_pda(
+        program_id,
+        ps_state_account,
+        &[PS_SEED.as_bytes(), &[ps_info.bump]],
+    )?;
+
     let payee_shares = ps_info.get_shares(payee_account.key);
     if payee_shares == 0 {
         msg!("Account has no shares");
@@ -196,7 +215,9 @@ fn release<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramR
     Ok(())
 }
 
-pub fn check_pda<'a>(
+// Derives the canonical bump of a PDA, only when creating it: the bump is then
+// stored in the account, and later checked with `check_pda`
+pub fn find_pda<'a>(
     program_id: &Pubkey,
     account_to_check: &'a AccountInfo<'a>,
     seeds: &[&[u8]],
@@ -211,6 +232,22 @@ pub fn check_pda<'a>(
     return Ok(bump_seed);
 }
 
+// Checks a PDA given its seeds, the last one being the bump stored in the account
+pub fn check_pda<'a>(
+    program_id: &Pubkey,
+    account_to_check: &'a AccountInfo<'a>,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let pub_key = Pubkey::create_program_address(&seeds, program_id)?;
+
+    if pub_key != *account_to_check.key {
+        msg!("PDA doesen't match with the one provided");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(())
+}
+
 pub fn create_pda_account<'a>(
     payer: &'a AccountInfo<'a>,
     new_account: &'a AccountInfo<'a>,
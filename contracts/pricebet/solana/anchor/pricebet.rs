@@ -9,8 +9,18 @@ use std::str::FromStr;
 
 declare_id!("J5hRaCiXinCxGG2kYJf6943YCGShKVQYC9N8PT3K4Tmz");
 
-const BTC_USDC_FEED: &str = "HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh2J"; // only for the devnet cluster
-const BTC_USDC_FEED_OWNER: &str = "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s"; // only for the devnet cluster
+// Pyth BTC/USD price feed and the oracle program owning it, which depend on
+// the cluster (see contracts/clusters.toml)
+#[cfg(all(feature = "devnet", feature = "mainnet"))]
+compile_error!("the devnet and mainnet features are mutually exclusive");
+#[cfg(feature = "mainnet")]
+const BTC_USDC_FEED: &str = "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU";
+#[cfg(not(feature = "mainnet"))]
+const BTC_USDC_FEED: &str = "HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh2J";
+#[cfg(feature = "mainnet")]
+const BTC_USDC_FEED_OWNER: &str = "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH";
+#[cfg(not(feature = "mainnet"))]
+const BTC_USDC_FEED_OWNER: &str = "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s";
 const STALENESS_THRESHOLD: u64 = 60; // staleness threshold in seconds
 
 #[program]
@@ -28,6 +38,7 @@ pub mod price_bet {
         bet_info.deadline = Clock::get()?.slot + delay;
         bet_info.wager = wager;
         bet_info.rate = rate;
+        bet_info.bump = ctx.bumps.bet_info;
 
         system_program::transfer(
             CpiContext::new(
@@ -135,6 +146,7 @@ pub struct OracleBetInfo {
     pub wager: u64,
     pub deadline: u64,
     pub rate: u64,
+    pub bump: u8,
 }
 
 #[derive(Accounts)]
@@ -162,7 +174,7 @@ pub struct JoinCtx<'info> {
         mut, 
         has_one = owner @ CustomError::InvalidParticipant, 
         seeds = [owner.key().as_ref()],  
-        bump,
+        bump = bet_info.bump,
     )]
     pub bet_info: Account<'info, OracleBetInfo>,
     pub system_program: Program<'info, System>,
@@ -178,7 +190,7 @@ pub struct WinCtx<'info> {
         has_one = owner @ CustomError::InvalidParticipant, 
         has_one = player @ CustomError::InvalidParticipant,
         seeds = [owner.key().as_ref()],  
-        bump,
+        bump = bet_info.bump,
     )]
     pub bet_info: Account<'info, OracleBetInfo>,
     /// CHECK
@@ -194,7 +206,7 @@ pub struct TimeoutCtx<'info> {
     #[account(
         mut,
         seeds = [owner.key().as_ref()], 
-        bump,
+        bump = bet_info.bump,
     )]
     pub bet_info: Account<'info, OracleBetInfo>,
     pub system_program: Program<'info, System>,
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
@@ -23,21 +23,32 @@ use std::str::FromStr;
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 pub struct OracleBetInfo {
     pub owner: Pubkey,  // 32 bytes
     pub player: Pubkey, // 32 bytes
     pub wager: u64,     // 8 bytes
     pub deadline: u64,  // 8 bytes
     pub rate: u64,      // 8 bytes
+    pub bump: u8,       // 1 byte
 }
 
 impl OracleBetInfo {
-    pub const LEN: usize = 32 + 32 + 8 + 8 + 8;
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 1;
 }
 
-const BTC_USDC_FEED: &str = "HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh2J"; // only for the devnet cluster
-const BTC_USDC_FEED_OWNER: &str = "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s"; // only for the devnet cluster
+// Pyth BTC/USD price feed and the oracle program owning it, which depend on
+// the cluster (see contracts/clusters.toml)
+#[cfg(all(feature = "devnet", feature = "mainnet"))]
+compile_error!("the devnet and mainnet features are mutually exclusive");
+#[cfg(feature = "mainnet")]
+const BTC_USDC_FEED: &str = "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU";
+#[cfg(not(feature = "mainnet"))]
+const BTC_USDC_FEED: &str = "HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh2J";
+#[cfg(feature = "mainnet")]
+const BTC_USDC_FEED_OWNER: &str = "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH";
+#[cfg(not(feature = "mainnet"))]
+const BTC_USDC_FEED_OWNER: &str = "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s";
 const STALENESS_THRESHOLD: u64 = 60; // staleness threshold in seconds
 
 pub enum OracleBetInstruction {
@@ -136,6 +147,7 @@ fn init<'a>(
         wager,
         deadline,
         rate,
+        bump: pda_bump,
     };
 
     oracle_bet_info.serialize(&mut &mut oracle_bet_pda.try_borrow_mut_data()?[..])?;
@@ -166,16 +178,18 @@ fn join<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResu
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) =
-        Pubkey::find_program_address(&[owner_account.key.as_ref()], program_id);
+    let mut oracle_bet_info = OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[owner_account.key.as_ref(), &[oracle_bet_info.bump]],
+        program_id,
+    )?;
 
     if expected_pda != *oracle_bet_pda.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut oracle_bet_info = OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
-
     if oracle_bet_info.player != Pubkey::default() {
         msg!("The player is already set");
         return Err(ProgramError::InvalidInstructionData);
@@ -213,17 +227,19 @@ fn win<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramResul
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) =
-        Pubkey::find_program_address(&[owner_account.key.as_ref()], program_id);
+    let oracle_bet_info: OracleBetInfo =
+        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[owner_account.key.as_ref(), &[oracle_bet_info.bump]],
+        program_id,
+    )?;
 
     if expected_pda != *oracle_bet_pda.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let oracle_bet_info: OracleBetInfo =
-        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
-
     if owner_account.key != &oracle_bet_info.owner || player_account.key != &oracle_bet_info.player
     {
         msg!("The participants are not the participants in the oracle_bet_info");
@@ -279,17 +295,19 @@ fn timeout<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>]) -> ProgramR
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) =
-        Pubkey::find_program_address(&[owner_account.key.as_ref()], program_id);
+    let oracle_bet_info: OracleBetInfo =
+        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[owner_account.key.as_ref(), &[oracle_bet_info.bump]],
+        program_id,
+    )?;
 
     if expected_pda != *oracle_bet_pda.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let oracle_bet_info: OracleBetInfo =
-        OracleBetInfo::try_from_slice(*oracle_bet_pda.data.borrow())?;
-
     if Clock::get()?.slot < oracle_bet_info.deadline {
         msg!("The timeout was not reached yet");
         return Err(ProgramError::InvalidInstructionData);
@@ -29,6 +29,7 @@ pub mod simple_transfer {
         balance_holder_pda.sender = ctx.accounts.sender.key();
         balance_holder_pda.recipient = ctx.accounts.recipient.key();
         balance_holder_pda.amount = amount_to_deposit;
+        balance_holder_pda.bump = ctx.bumps.balance_holder_pda;
 
         Ok(())
     }
@@ -84,7 +85,7 @@ pub struct WithdrawCtx<'info> {
     #[account(
         mut, 
         seeds = [recipient.key().as_ref(), sender.key().as_ref()],
-        bump,
+        bump = balance_holder_pda.bump,
         constraint = balance_holder_pda.recipient == recipient.key() @ CustomError::InvalidRecipient
     )]
     pub balance_holder_pda: Account<'info, BalanceHolderPDA>,
@@ -97,6 +98,7 @@ pub struct BalanceHolderPDA {
     pub sender: Pubkey,
     pub recipient: Pubkey,
     pub amount: u64,
+    pub bump: u8,
 }
 
 #[error_code]
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
@@ -40,15 +40,16 @@ pub fn process_instruction(
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct DonationDetails {
     pub sender: Pubkey,
     pub recipient: Pubkey,
     pub amount: u64,
+    pub bump: u8,
 }
 
 impl DonationDetails {
-    pub const LEN: usize = 32 + 32 + 8;
+    pub const LEN: usize = 32 + 32 + 8 + 1;
 }
 
 fn deposit(
@@ -113,6 +114,7 @@ fn deposit(
         sender: *sender.key,
         recipient: *recipient.key,
         amount,
+        bump: pda_bump,
     };
 
     if donation.sender != *sender.key {
@@ -139,17 +141,19 @@ fn withdraw(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (expected_pda, _pda_bump) =
-        Pubkey::find_program_address(&[sender.key.as_ref(), recipient.key.as_ref()], program_id);
+    let mut donation: DonationDetails =
+        DonationDetails::try_from_slice(*balance_holder_pda_account.data.borrow())?;
+
+    let expected_pda = Pubkey::create_program_address(
+        &[sender.key.as_ref(), recipient.key.as_ref(), &[donation.bump]],
+        program_id,
+    )?;
 
     if expected_pda != *balance_holder_pda_account.key {
         msg!("Invalid PDA");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut donation: DonationDetails =
-        DonationDetails::try_from_slice(*balance_holder_pda_account.data.borrow())?;
-
     if donation.recipient != *recipient.key {
         msg!("Only the recipient can withdraw");
         return Err(ProgramError::InvalidAccountData);
@@ -6,8 +6,10 @@ declare_id!("JDiaQThJ6C1erE6Cm47A22TXzVo976Bi5ggo77M9S9kX");
 pub mod simple_wallet {
     use super::*;
 
-    pub fn deposit(ctx: Context<DepositOrWithdrawCtx>, amount_to_deposit: u64) -> Result<()> {
+    pub fn deposit(ctx: Context<DepositCtx>, amount_to_deposit: u64) -> Result<()> {
         require!(amount_to_deposit > 0, CustomError::InvalidAmount);
+        ctx.accounts.user_wallet_pda.bump = ctx.bumps.user_wallet_pda;
+
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.owner.key(),
             &ctx.accounts.user_wallet_pda.key(),
@@ -44,6 +46,7 @@ pub mod simple_wallet {
         transaction_pda.receiver = *receiver.key;
         transaction_pda.amount_in_lamports = transaction_lamports_amount;
         transaction_pda.executed = false;
+        transaction_pda.bump = ctx.bumps.transaction_pda;
 
         emit!(SubmitTransaction {
             owner: *ctx.accounts.owner.key,
@@ -80,7 +83,7 @@ pub mod simple_wallet {
         Ok(())
     }
 
-    pub fn withdraw(ctx: Context<DepositOrWithdrawCtx>, amount_to_withdraw: u64) -> Result<()> {
+    pub fn withdraw(ctx: Context<WithdrawCtx>, amount_to_withdraw: u64) -> Result<()> {
         require!(amount_to_withdraw > 0, CustomError::InvalidAmount);
         let owner = &ctx.accounts.owner.to_account_info();
         let user_wallet_pda = &ctx.accounts.user_wallet_pda.to_account_info();
@@ -102,14 +105,17 @@ pub struct UserTransaction {
     pub receiver: Pubkey,
     pub amount_in_lamports: u64,
     pub executed: bool,
+    pub bump: u8,
 }
 
 #[account]
 #[derive(InitSpace)]
-pub struct UserWallet {}
+pub struct UserWallet {
+    pub bump: u8,
+}
 
 #[derive(Accounts)]
-pub struct DepositOrWithdrawCtx<'info> {
+pub struct DepositCtx<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     #[account(
@@ -123,6 +129,19 @@ pub struct DepositOrWithdrawCtx<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct WithdrawCtx<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = ["wallet".as_ref(), owner.key().as_ref()],
+        bump = user_wallet_pda.bump,
+    )]
+    pub user_wallet_pda: Account<'info, UserWallet>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(_transaction_seed: String)]
 pub struct CreateTransactionCtx<'info> {
@@ -131,7 +150,7 @@ pub struct CreateTransactionCtx<'info> {
     #[account(
         mut,
         seeds = ["wallet".as_ref(), owner.key().as_ref()],
-        bump,
+        bump = user_wallet_pda.bump,
     )]
     pub user_wallet_pda: Account<'info, UserWallet>,
     #[account(
@@ -155,14 +174,14 @@ pub struct ExecuteTransactionCtx<'info> {
     #[account(
         mut,
         seeds = ["wallet".as_ref(), owner.key().as_ref()],
-        bump,
+        bump = user_wallet_pda.bump,
     )]
     pub user_wallet_pda: Account<'info, UserWallet>,
     #[account(
         mut,
         close = owner,
         seeds = [_transaction_seed.as_ref(), user_wallet_pda.key().as_ref()],
-        bump,
+        bump = transaction_pda.bump,
     )]
     pub transaction_pda: Account<'info, UserTransaction>,
     #[account(
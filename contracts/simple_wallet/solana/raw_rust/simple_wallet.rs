@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
@@ -14,19 +14,22 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct UserTransaction {
     pub to: Pubkey,
     pub value: u64,
     pub executed: bool,
+    pub bump: u8,
 }
 
 impl UserTransaction {
-    pub const LEN: usize = 32 + 8 + 1;
+    pub const LEN: usize = 32 + 8 + 1 + 1;
 }
 
 const SEED_FOR_WALLET: &str = "wallet";
 const SEED_FOR_TRANSACTION: &str = "tx";
+// The wallet account holds the number of transactions, followed by its bump
+const WALLET_ACCOUNT_DATA_LEN: usize = 8 + 1;
 
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -75,23 +78,24 @@ fn deposit(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (wallet_pda, wallet_bump) = Pubkey::find_program_address(
-        &[SEED_FOR_WALLET.as_bytes(), owner_account.key.as_ref()],
-        program_id,
-    );
-
-    if wallet_pda != *wallet_account.key {
-        msg!("Not the sender's pda wallet");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
     let amount_to_deposit: u64 = instruction_data
         .iter()
         .rev()
         .fold(0, |acc, &x| (acc << 8) + x as u64);
 
     if wallet_account.lamports() == 0 {
-        let space = 8;
+        // The bump of the wallet is derived only when creating it
+        let (wallet_pda, wallet_bump) = Pubkey::find_program_address(
+            &[SEED_FOR_WALLET.as_bytes(), owner_account.key.as_ref()],
+            program_id,
+        );
+
+        if wallet_pda != *wallet_account.key {
+            msg!("Not the sender's pda wallet");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let space = WALLET_ACCOUNT_DATA_LEN;
         let rent = Rent::get()?;
         let rent_lamports = rent.minimum_balance(space);
         invoke_signed(
@@ -113,9 +117,12 @@ fn deposit(
                 &[wallet_bump],
             ]],
         )?;
+        wallet_account.try_borrow_mut_data()?[8] = wallet_bump;
         return Ok(());
     }
 
+    let wallet_bump = check_wallet(program_id, owner_account, wallet_account)?;
+
     invoke_signed(
         &system_instruction::transfer(owner_account.key, wallet_account.key, amount_to_deposit),
         &[owner_account.clone(), wallet_account.clone()],
@@ -153,15 +160,7 @@ fn create_transaction(
         }
     };
 
-    let (wallet_pda, _wallet_bump) = Pubkey::find_program_address(
-        &[SEED_FOR_WALLET.as_bytes(), owner_account.key.as_ref()],
-        program_id,
-    );
-
-    if wallet_pda != *wallet_account.key {
-        msg!("Not the sender's pda wallet");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    check_wallet(program_id, owner_account, wallet_account)?;
 
     let (transaction_pda, transaction_bump) = Pubkey::find_program_address(
         &[
@@ -198,21 +197,25 @@ fn create_transaction(
         ]],
     )?;
 
-    let mut new_transaction = UserTransaction::try_from_slice(&instruction_data)?;
+    // The instruction data holds the receiver, the value and the executed flag
+    let (to, value, _executed) = <(Pubkey, u64, bool)>::try_from_slice(&instruction_data)?;
 
-    if new_transaction.value <= 0 {
+    if value <= 0 {
         msg!("The amount to send should be greater than 0");
         return Err(ProgramError::InvalidInstructionData);
     }
 
-    new_transaction.executed = false;
+    let new_transaction = UserTransaction {
+        to,
+        value,
+        executed: false,
+        bump: transaction_bump,
+    };
     new_transaction.serialize(&mut &mut transaction_account.try_borrow_mut_data()?[..])?;
 
     // Update the number of transactions
     num_transactions += 1;
-    wallet_account
-        .try_borrow_mut_data()?
-        .copy_from_slice(&num_transactions.to_le_bytes());
+    wallet_account.try_borrow_mut_data()?[0..8].copy_from_slice(&num_transactions.to_le_bytes());
 
     Ok(())
 }
@@ -233,36 +236,29 @@ fn execute_transaction(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (wallet_pda, _wallet_bump) = Pubkey::find_program_address(
-        &[SEED_FOR_WALLET.as_bytes(), owner_account.key.as_ref()],
-        program_id,
-    );
-
-    if wallet_pda != *wallet_account.key {
-        msg!("Not the sender's pda wallet");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    check_wallet(program_id, owner_account, wallet_account)?;
 
     let transaction_id: u64 = instruction_data
         .iter()
         .rev()
         .fold(0, |acc, &x| (acc << 8) + x as u64);
 
-    let (transaction_pda, _transaction_bump) = Pubkey::find_program_address(
+    let mut transaction = UserTransaction::try_from_slice(*transaction_account.data.borrow())?;
+
+    let transaction_pda = Pubkey::create_program_address(
         &[
             format!("{}{}", SEED_FOR_TRANSACTION, transaction_id).as_bytes(),
             owner_account.key.as_ref(),
+            &[transaction.bump],
         ],
         program_id,
-    );
+    )?;
 
     if transaction_pda != *transaction_account.key {
         msg!("The provided transaction was not created by the sender");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    let mut transaction = UserTransaction::try_from_slice(*transaction_account.data.borrow())?;
-
     let rent_exemption = Rent::get()?.minimum_balance(wallet_account.data_len());
     if **wallet_account.lamports.borrow() < rent_exemption + transaction.value {
         msg!("Not enough lamports to send");
@@ -295,18 +291,41 @@ fn withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (wallet_pda, _wallet_bump) = Pubkey::find_program_address(
-        &[SEED_FOR_WALLET.as_bytes(), owner_account.key.as_ref()],
+    check_wallet(program_id, owner_account, wallet_account)?;
+
+    **owner_account.try_borrow_mut_lamports()? += **wallet_account.try_borrow_lamports()?;
+    **wallet_account.try_borrow_mut_lamports()? = 0;
+
+    Ok(())
+}
+
+// Checks the wallet PDA of the owner with the bump stored in it, and returns the bump
+fn check_wallet(
+    program_id: &Pubkey,
+    owner_account: &AccountInfo,
+    wallet_account: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let wallet_bump = match wallet_account.try_borrow_data()?.get(8) {
+        Some(bump) => *bump,
+        None => {
+            msg!("The wallet account is not initialized");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    };
+
+    let wallet_pda = Pubkey::create_program_address(
+        &[
+            SEED_FOR_WALLET.as_bytes(),
+            owner_account.key.as_ref(),
+            &[wallet_bump],
+        ],
         program_id,
-    );
+    )?;
 
     if wallet_pda != *wallet_account.key {
         msg!("Not the sender's pda wallet");
         return Err(ProgramError::InvalidAccountData);
     }
 
-    **owner_account.try_borrow_mut_lamports()? += **wallet_account.try_borrow_lamports()?;
-    **wallet_account.try_borrow_mut_lamports()? = 0;
-
-    Ok(())
+    Ok(wallet_bump)
 }
@@ -6,8 +6,10 @@ declare_id!("2ZE5N8rTU2S2GUuQGX8ZsBAraByUqD37hYP8pz1hYLLJ");
 pub mod storage {
     use super::*;
 
-    pub fn initialize(_ctx: Context<InitializeCtx>) -> Result<()> {
+    pub fn initialize(ctx: Context<InitializeCtx>) -> Result<()> {
         msg!("Initializing storage accounts");
+        ctx.accounts.string_storage_pda.bump = ctx.bumps.string_storage_pda;
+        ctx.accounts.bytes_storage_dpa.bump = ctx.bumps.bytes_storage_dpa;
         Ok(())
     }
 
@@ -26,11 +28,13 @@ pub mod storage {
 
 #[account]
 pub struct MemoryStringPDA {
+    pub bump: u8,
     pub my_string: String,
 }
 
 #[account]
 pub struct MemoryBytesPDA {
+    pub bump: u8,
     pub my_bytes: Vec<u8>,
 }
 
@@ -44,7 +48,7 @@ pub struct InitializeCtx<'info> {
         payer = user, 
         seeds = [b"storage_string", user.key.as_ref()],
         bump,
-        space = 8 + 4 // no additional space needed because we don't store anything yet
+        space = 8 + 1 + 4 // no additional space needed because we don't store anything yet
     )]
     pub string_storage_pda: Account<'info, MemoryStringPDA>,
     #[account(
@@ -52,7 +56,7 @@ pub struct InitializeCtx<'info> {
         payer = user, 
         seeds = [b"storage_bytes", user.key.as_ref()],
         bump,
-        space = 8 + 4  // no additional space needed because we don't store anything yet
+        space = 8 + 1 + 4  // no additional space needed because we don't store anything yet
     )]
     pub bytes_storage_dpa: Account<'info, MemoryBytesPDA>,
 }
@@ -66,8 +70,8 @@ pub struct StoreStringCtx<'info> {
     #[account(
         mut,
         seeds = [b"storage_string", user.key.as_ref()],
-        bump,
-        realloc = 8 + 4 + data_to_store.len(),
+        bump = string_storage_pda.bump,
+        realloc = 8 + 1 + 4 + data_to_store.len(),
         realloc::payer = user,
         realloc::zero = false,
     )]
@@ -83,8 +87,8 @@ pub struct StoreBytesCtx<'info> {
     #[account(
         mut,
         seeds = [b"storage_bytes", user.key.as_ref()],
-        bump,
-        realloc = 8 + 4 + data_to_store.len(),
+        bump = bytes_storage_dpa.bump,
+        realloc = 8 + 1 + 4 + data_to_store.len(),
         realloc::payer = user,
         realloc::zero = false,
     )]
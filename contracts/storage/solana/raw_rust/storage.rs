@@ -55,22 +55,23 @@ fn store_bytes(
 
     let bytes_pda_account: &AccountInfo = next_account_info(accounts_iter)?;
 
-    let (bytes_pda_pub_key, storage_bump) =
-        Pubkey::find_program_address(&[SEED_STORAGE_BYTES.as_bytes()], program_id);
-
-    if bytes_pda_pub_key != *bytes_pda_account.key {
-        msg!("Not the right PDA");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
     let system_program_account = next_account_info(accounts_iter)?;
     if system_program_account.key != &solana_program::system_program::id() {
         return Err(ProgramError::InvalidAccountData);
     }
 
-
     if bytes_pda_account.lamports() == 0 {
-        let space = bytes_to_store.len();
+        // The bump is derived only when creating the PDA, and is stored in the
+        // first byte of the account, before the data
+        let (bytes_pda_pub_key, storage_bump) =
+            Pubkey::find_program_address(&[SEED_STORAGE_BYTES.as_bytes()], program_id);
+
+        if bytes_pda_pub_key != *bytes_pda_account.key {
+            msg!("Not the right PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let space = 1 + bytes_to_store.len();
         let rent = Rent::get()?;
         let rent_lamports = rent.minimum_balance(space);
         invoke_signed(
@@ -89,13 +90,29 @@ fn store_bytes(
             &[&[SEED_STORAGE_BYTES.as_bytes(), &[storage_bump]]],
         )?;
 
-        bytes_pda_account.data.borrow_mut()[..].copy_from_slice(&bytes_to_store);
+        let mut bytes_pda_data = bytes_pda_account.data.borrow_mut();
+        bytes_pda_data[0] = storage_bump;
+        bytes_pda_data[1..].copy_from_slice(&bytes_to_store);
 
         return Ok(());
     }
 
+    let storage_bump = bytes_pda_account
+        .data
+        .borrow()
+        .get(0)
+        .copied()
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let bytes_pda_pub_key =
+        Pubkey::create_program_address(&[SEED_STORAGE_BYTES.as_bytes(), &[storage_bump]], program_id)?;
+
+    if bytes_pda_pub_key != *bytes_pda_account.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
     // Update the account size
-    let new_size = bytes_to_store.len();
+    let new_size = 1 + bytes_to_store.len();
     let rent = Rent::get()?;
     let new_minimum_balance = rent.minimum_balance(new_size);
 
@@ -113,7 +130,7 @@ fn store_bytes(
     bytes_pda_account.realloc(new_size, false)?;
 
     // Update the data
-    bytes_pda_account.data.borrow_mut()[..].copy_from_slice(&bytes_to_store);
+    bytes_pda_account.data.borrow_mut()[1..].copy_from_slice(&bytes_to_store);
 
     Ok(())
 }
@@ -132,21 +149,23 @@ fn store_string(
     
     let string_pda_account: &AccountInfo = next_account_info(accounts_iter)?;
 
-    let (string_pda_pub_key, storage_bump) =
-        Pubkey::find_program_address(&[SEED_STORAGE_STRING.as_bytes()], program_id);
-
-    if string_pda_pub_key != *string_pda_account.key {
-        msg!("Not the right PDA");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
     let system_program_account = next_account_info(accounts_iter)?;
     if system_program_account.key != &solana_program::system_program::id() {
         return Err(ProgramError::InvalidAccountData);
     }
 
     if string_pda_account.lamports() == 0 {
-        let space = string_to_store.as_bytes().len();
+        // The bump is derived only when creating the PDA, and is stored in the
+        // first byte of the account, before the data
+        let (string_pda_pub_key, storage_bump) =
+            Pubkey::find_program_address(&[SEED_STORAGE_STRING.as_bytes()], program_id);
+
+        if string_pda_pub_key != *string_pda_account.key {
+            msg!("Not the right PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let space = 1 + string_to_store.as_bytes().len();
         let rent = Rent::get()?;
         let rent_lamports = rent.minimum_balance(space);
         invoke_signed(
@@ -165,13 +184,29 @@ fn store_string(
             &[&[SEED_STORAGE_STRING.as_bytes(), &[storage_bump]]],
         )?;
 
-        string_pda_account.data.borrow_mut()[..].copy_from_slice(&string_to_store.as_bytes());
+        let mut string_pda_data = string_pda_account.data.borrow_mut();
+        string_pda_data[0] = storage_bump;
+        string_pda_data[1..].copy_from_slice(&string_to_store.as_bytes());
 
         return Ok(());
     }
 
+    let storage_bump = string_pda_account
+        .data
+        .borrow()
+        .get(0)
+        .copied()
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let string_pda_pub_key =
+        Pubkey::create_program_address(&[SEED_STORAGE_STRING.as_bytes(), &[storage_bump]], program_id)?;
+
+    if string_pda_pub_key != *string_pda_account.key {
+        msg!("Not the right PDA");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
     // Update the account size
-    let new_size =  string_to_store.as_bytes().len();
+    let new_size = 1 + string_to_store.as_bytes().len();
     let rent = Rent::get()?;
     let new_minimum_balance = rent.minimum_balance(new_size);
 
@@ -189,7 +224,7 @@ fn store_string(
     string_pda_account.realloc(new_size, false)?;
 
     // Update the data
-    string_pda_account.data.borrow_mut()[..].copy_from_slice(&string_to_store.as_bytes());
+    string_pda_account.data.borrow_mut()[1..].copy_from_slice(&string_to_store.as_bytes());
 
     Ok(())
 }
@@ -11,7 +11,9 @@ pub mod token_transfer {
 
     pub fn deposit(ctx: Context<DepositCtx>) -> Result<()> {
         msg!("Transferring the ATA to the holder_PDA");
-        let (atas_holder_pda, _nonce) =
+        // The bump of the holder PDA is derived once here, and stored in the
+        // deposit info for the withdrawals
+        let (atas_holder_pda, holder_bump) =
             Pubkey::find_program_address(&[b"atas_holder"], ctx.program_id);
         let token_program = &ctx.accounts.token_program;
 
@@ -30,6 +32,8 @@ pub mod token_transfer {
         let deposit_info = &mut ctx.accounts.deposit_info;
         deposit_info.recipient = *ctx.accounts.recipient.to_account_info().key;
         deposit_info.temp_ata = *ctx.accounts.temp_ata.to_account_info().key;
+        deposit_info.bump = ctx.bumps.deposit_info;
+        deposit_info.holder_bump = holder_bump;
 
         Ok(())
     }
@@ -43,8 +47,8 @@ pub mod token_transfer {
             CustomError::InvalidAmount
         );
 
-        let (atas_holder_pda, nonce) =
-            Pubkey::find_program_address(&[b"atas_holder"], ctx.program_id);
+        let atas_holder_pda = ctx.accounts.atas_holder_pda.key();
+        let nonce = ctx.accounts.deposit_info.holder_bump;
 
         // Transfer
         // Why using invoke_signed instead of invoke?
@@ -54,7 +58,7 @@ pub mod token_transfer {
         msg!("Transferring the tokens to the recipient");
         anchor_lang::solana_program::program::invoke_signed(
             &spl_token::instruction::transfer(
-                &anchor_spl::token::ID,
+                &ctx.accounts.token_program.key(),
                 &temp_ata.key(),
                 &ctx.accounts.recipient_ata.key(),
                 &atas_holder_pda, //owner
@@ -75,7 +79,7 @@ pub mod token_transfer {
             msg!("Closing the temp_ata account");
             anchor_lang::solana_program::program::invoke_signed(
                 &spl_token::instruction::close_account(
-                    &anchor_spl::token::ID,
+                    &ctx.accounts.token_program.key(),
                     &temp_ata.key(),
                     &ctx.accounts.sender.to_account_info().key,
                     &atas_holder_pda,
@@ -107,6 +111,8 @@ pub mod token_transfer {
 pub struct DepositInfo {
     pub temp_ata: Pubkey,  // 32 bytes
     pub recipient: Pubkey, // 32 bytes
+    pub bump: u8,          // 1 byte
+    pub holder_bump: u8,   // 1 byte
 }
 
 #[derive(Accounts)]
@@ -155,7 +161,7 @@ pub struct WithdrawCtx<'info> {
     #[account(
         mut, 
         seeds = [temp_ata.key().as_ref()],
-        bump,
+        bump = deposit_info.bump,
         constraint = deposit_info.recipient == recipient.key() @ CustomError::InvalidRecipient
     )]
     pub deposit_info: Account<'info, DepositInfo>,
@@ -163,7 +169,7 @@ pub struct WithdrawCtx<'info> {
     #[account(
         mut,
         seeds = [b"atas_holder"],
-        bump,
+        bump = deposit_info.holder_bump,
     )]
     pub atas_holder_pda: AccountInfo<'info>,
     // Programs and other
@@ -1,4 +1,4 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
@@ -13,12 +13,13 @@ use solana_program::{
 
 entrypoint!(process_instruction);
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Debug)]
 struct DepositInfo {
     pub sender: Pubkey,
     pub temp_token_account: Pubkey,
     pub reciever_token_account: Pubkey,
     pub amount: u64,
+    pub nonce: u8,
 }
 
 pub fn process_instruction(
@@ -86,20 +87,22 @@ fn deposit(
     // The reciever's token account to deposit to
     let reciever_token_account: &AccountInfo = next_account_info(accounts_iter)?;
 
+    // The PDA account that will own the temp token account. Its bump is derived
+    // once here, and stored in the deposit info for the withdrawals
+    let (pda, nonce) = Pubkey::find_program_address(&[b"TokenTransfer"], program_id);
+
     // Now we have all the information we need to build the DepositInfo struct instance
     let deposit_info: DepositInfo = DepositInfo {
         sender: *sender.key,
         temp_token_account: *temp_token_account.key,
         reciever_token_account: *reciever_token_account.key,
         amount: amount_to_deposit,
+        nonce,
     };
 
     // Serialize the DepositInfo struct instance and save it to the state account
     deposit_info.serialize(&mut &mut state_account.try_borrow_mut_data()?[..])?;
 
-    // The PDA account that will own the temp token account
-    let (pda, _nonce) = Pubkey::find_program_address(&[b"TokenTransfer"], program_id);
-
     // Call the Token program to transfer temp account ownership to the PDA
     let token_program: &AccountInfo = next_account_info(accounts_iter)?;
     invoke(
@@ -155,7 +158,12 @@ fn withdraw(
 
     // The PDA account that owns the temp token account
     let pda_account = next_account_info(accounts_iter)?;
-    let (pda, nonce) = Pubkey::find_program_address(&[b"TokenTransfer"], program_id);
+    let nonce = deposit_info.nonce;
+    let pda = Pubkey::create_program_address(&[b"TokenTransfer", &[nonce]], program_id)?;
+    if pda != *pda_account.key {
+        msg!("The PDA account doesn't match the one of the deposit");
+        return Err(ProgramError::InvalidAccountData);
+    }
 
     // Deserialize the amount that the recipient wants to withdraw
     let amount_to_withdraw: u64 = instruction_data
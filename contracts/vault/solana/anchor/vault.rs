@@ -22,6 +22,7 @@ pub mod vault {
         vault_info.request_time = 0;
         vault_info.amount = 0;
         vault_info.state = State::Idle;
+        vault_info.bump = ctx.bumps.vault_info;
 
         // Transfer lamports
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
@@ -112,6 +113,7 @@ pub struct VaultInfo {
     pub request_time: u64, // 8 bytes
     pub amount: u64,       // 8 bytes
     pub state: State,      // see: https://www.anchor-lang.com/docs/space
+    pub bump: u8,          // 1 byte
 }
 
 #[derive(Accounts)]
@@ -138,7 +140,7 @@ pub struct WithdrawCtx<'info> {
     #[account(
         mut,
         seeds = [owner.key().as_ref()],
-        bump,
+        bump = vault_info.bump,
         constraint = vault_info.owner == *owner.key @ CustomError::InvalidOwner,
     )]
     pub vault_info: Account<'info, VaultInfo>,
@@ -153,7 +155,7 @@ pub struct FinalizeCtx<'info> {
     #[account(
         mut,
         seeds = [owner.key().as_ref()],
-        bump,
+        bump = vault_info.bump,
         constraint = vault_info.owner == *owner.key @ CustomError::InvalidOwner,
     )]
     pub vault_info: Account<'info, VaultInfo>,
@@ -167,7 +169,7 @@ pub struct CancelCtx<'info> {
     #[account(
         mut,
         seeds = [owner.key().as_ref()],
-        bump,
+        bump = vault_info.bump,
         constraint = vault_info.owner == *owner.key @ CustomError::InvalidOwner,
         constraint = vault_info.recovery == *recovery.key @ CustomError::InvalidRecovery,
     )]
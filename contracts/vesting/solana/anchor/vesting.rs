@@ -24,6 +24,7 @@ pub mod vesting {
         vesting_info.start_slot = start_slot;
         vesting_info.duration = duration;
         vesting_info.released = 0;
+        vesting_info.bump = ctx.bumps.vesting_info;
 
         msg!("Transfering lamports to the vesting account");
         let transfer_instruction = anchor_lang::solana_program::system_instruction::transfer(
@@ -87,6 +88,7 @@ pub struct VestingInfo {
     pub beneficiary: Pubkey, // 32 bytes
     pub start_slot: u64,     // 8 bytes
     pub duration: u64,       // 8 bytes
+    pub bump: u8,            // 1 byte
 }
 
 #[derive(Accounts)]
@@ -114,7 +116,7 @@ pub struct ReleaseCtx<'info> {
     #[account(
         mut,
         seeds = [beneficiary.key().as_ref()],
-        bump,
+        bump = vesting_info.bump,
         constraint = vesting_info.beneficiary == *beneficiary.key @ CustomError::InvalidBeneficiary,
         constraint = vesting_info.funder == *funder.key @ CustomError::InvalidFunder,
     )]
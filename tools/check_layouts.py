@@ -0,0 +1,304 @@
+#!/usr/bin/env python3
+"""Check the account layouts of the Solana programs.
+
+For every use case, the script exports the Borsh schema of the state types
+of both flavors (the `#[account]` structs of the Anchor programs, and the
+types deriving `BorshSchema` in the raw programs, with the types they
+depend on), together with the maximum serialized size of each type (for
+the Anchor accounts, including the 8 bytes of the discriminator), and
+compares it with the golden snapshot in `tools/layouts/<usecase>.json`.
+Any layout change, even a reordering of the fields, makes the check fail:
+if the change is intended, run the script with `--update` and commit the
+new snapshot along with the program.
+
+The script also checks that the sizes hard-coded in the programs match the
+layouts:
+
+- every `LEN` constant of the raw programs equals the maximum serialized
+  size of its type;
+- every `8 + X::INIT_SPACE` of the Anchor programs refers to a type whose
+  size is bounded, i.e. whose strings and vectors all have a `max_len`.
+
+Usage: python3 tools/check_layouts.py [--update] [usecase ...]
+"""
+
+import json
+import re
+import sys
+
+import rust_source as rs
+
+LAYOUTS = rs.ROOT / "tools" / "layouts"
+DISCRIMINATOR = 8
+
+PRIMITIVES = {
+    "bool": 1,
+    "u8": 1,
+    "i8": 1,
+    "u16": 2,
+    "i16": 2,
+    "u32": 4,
+    "i32": 4,
+    "u64": 8,
+    "i64": 8,
+    "u128": 16,
+    "i128": 16,
+    "Pubkey": 32,
+}
+
+
+def split_top_level(text, sep=","):
+    """Split `text` on `sep`, ignoring separators nested in brackets."""
+    parts, depth, start = [], 0, 0
+    for i, c in enumerate(text):
+        if c in "<([{":
+            depth += 1
+        elif c in ">)]}":
+            depth -= 1
+        elif c == sep and depth == 0:
+            parts.append(text[start:i])
+            start = i + 1
+    parts.append(text[start:])
+    return [p.strip() for p in parts if p.strip()]
+
+
+def parse_fields(body):
+    """Fields of a struct body, as (name, type, max_len) triples."""
+    fields = []
+    max_len = None
+    for item in split_top_level(body):
+        for attr in re.findall(r"#\[([^\]]*)\]", item):
+            m = re.match(r"max_len\(\s*(\d+)", attr)
+            if m:
+                max_len = int(m.group(1))
+        item = re.sub(r"#\[[^\]]*\]", "", item).strip()
+        m = re.match(r"(?:pub(?:\([^)]*\))?\s+)?(\w+)\s*:\s*(.+)$", item, re.DOTALL)
+        if m:
+            fields.append((m.group(1), " ".join(m.group(2).split()), max_len))
+            max_len = None
+    return fields
+
+
+def parse_variants(body):
+    """Variants of an enum body, as (name, [field types]) pairs."""
+    variants = []
+    for item in split_top_level(body):
+        item = re.sub(r"#\[[^\]]*\]", "", item).strip()
+        m = re.match(r"(\w+)\s*(.*)$", item, re.DOTALL)
+        name, rest = m.group(1), m.group(2).strip()
+        if rest.startswith("("):
+            payload = [t for t in split_top_level(rest[1:-1])]
+        elif rest.startswith("{"):
+            payload = [t for _, t, _ in parse_fields(rest[1:-1])]
+        else:
+            payload = []
+        variants.append((name, payload))
+    return variants
+
+
+def type_items(text):
+    """Every struct and enum of the file, with its attributes."""
+    items = {}
+    pattern = r"((?:#\[[^\]]*\]\s*)*)(?:pub(?:\([^)]*\))?\s+)?(struct|enum)\s+(\w+)(?:<[^>]*>)?\s*\{"
+    for m in re.finditer(pattern, text):
+        body = rs.block_at(text, m.end() - 1)
+        items[m.group(3)] = {"attrs": m.group(1), "kind": m.group(2), "body": body}
+    return items
+
+
+def is_state(item, flavor):
+    if flavor == "anchor":
+        return "#[account]" in item["attrs"] and item["kind"] == "struct"
+    return "BorshSchema" in item["attrs"]
+
+
+class Layouts:
+    def __init__(self, text, flavor):
+        self.items = type_items(text)
+        self.flavor = flavor
+        self.definitions = {}
+        # Array lengths may be given by integer constants of the file
+        self.consts = {
+            m.group(1): int(m.group(2).replace("_", ""))
+            for m in re.finditer(r"const\s+(\w+)\s*:\s*usize\s*=\s*([\d_]+)\s*;", text)
+        }
+
+    def type_name(self, ty, max_len=None):
+        """Schema name of a field type, recording user-defined types."""
+        ty = ty.replace(" ", "")
+        m = re.fullmatch(r"\[(.+);(\w+)\]", ty)
+        if m:
+            length = self.consts.get(m.group(2), m.group(2))
+            return f"[{self.type_name(m.group(1))}; {length}]"
+        m = re.fullmatch(r"(Vec|Option|Box)<(.+)>", ty)
+        if m:
+            inner = self.type_name(m.group(2))
+            if m.group(1) == "Box":
+                return inner
+            name = f"{m.group(1)}<{inner}>"
+            return f"{name}[..{max_len}]" if max_len is not None and m.group(1) == "Vec" else name
+        m = re.fullmatch(r"(BTreeMap|HashMap)<(.+)>", ty)
+        if m:
+            key, value = split_top_level(m.group(2))
+            return f"{m.group(1)}<{self.type_name(key)}, {self.type_name(value)}>"
+        if ty == "String":
+            return f"String[..{max_len}]" if max_len is not None else "String"
+        if ty in self.items:
+            self.define(ty)
+        return ty
+
+    def define(self, name):
+        if name in self.definitions:
+            return
+        item = self.items[name]
+        self.definitions[name] = None
+        if item["kind"] == "struct":
+            fields = [[f, self.type_name(t, n)] for f, t, n in parse_fields(item["body"])]
+            self.definitions[name] = {"Struct": fields}
+        else:
+            variants = [
+                [v, [self.type_name(t) for t in payload]]
+                for v, payload in parse_variants(item["body"])
+            ]
+            self.definitions[name] = {"Enum": variants}
+
+    def size(self, name):
+        """Maximum serialized size of a schema type, None if unbounded."""
+        if name in PRIMITIVES:
+            return PRIMITIVES[name]
+        m = re.fullmatch(r"\[(.+); (\d+)\]", name)
+        if m:
+            inner = self.size(m.group(1))
+            return None if inner is None else inner * int(m.group(2))
+        m = re.fullmatch(r"String\[\.\.(\d+)\]", name)
+        if m:
+            return 4 + int(m.group(1))
+        m = re.fullmatch(r"Vec<(.+)>\[\.\.(\d+)\]", name)
+        if m:
+            inner = self.size(m.group(1))
+            return None if inner is None else 4 + inner * int(m.group(2))
+        m = re.fullmatch(r"Option<(.+)>", name)
+        if m:
+            inner = self.size(m.group(1))
+            return None if inner is None else 1 + inner
+        definition = self.definitions.get(name)
+        if definition is None:
+            return None
+        if "Struct" in definition:
+            sizes = [self.size(t) for _, t in definition["Struct"]]
+            return None if None in sizes else sum(sizes)
+        sizes = [[self.size(t) for t in payload] for _, payload in definition["Enum"]]
+        if any(None in s for s in sizes):
+            return None
+        return 1 + max((sum(s) for s in sizes), default=0)
+
+    def export(self):
+        roots = [n for n, item in self.items.items() if is_state(item, self.flavor)]
+        for name in roots:
+            self.define(name)
+        result = {}
+        for name in sorted(self.definitions):
+            entry = dict(self.definitions[name])
+            size = self.size(name)
+            if name in roots and self.flavor == "anchor" and size is not None:
+                size += DISCRIMINATOR
+            entry["max_size"] = size
+            result[name] = entry
+        return result
+
+
+def eval_sum(expr):
+    """Value of a constant expression made of integers, `+` and `*`."""
+    if not re.fullmatch(r"[\d\s+*()]+", expr):
+        return None
+    return eval(expr, {"__builtins__": {}})
+
+
+def check_raw_lens(text, layouts, where, errors):
+    for m in re.finditer(r"impl\s+(\w+)\s*\{", text):
+        body = rs.block_at(text, m.end() - 1)
+        c = re.search(r"const\s+LEN\s*:\s*usize\s*=\s*([^;]+);", body)
+        if c is None:
+            continue
+        name, value = m.group(1), eval_sum(c.group(1))
+        if name not in layouts:
+            errors.append(f"{where}: `{name}::LEN` refers to a type without a schema")
+            continue
+        size = layouts[name]["max_size"]
+        if value is None:
+            errors.append(f"{where}: `{name}::LEN` is not a constant sum")
+        elif size is None:
+            errors.append(f"{where}: `{name}::LEN` is {value}, but the size of `{name}` is unbounded")
+        elif value != size:
+            errors.append(f"{where}: `{name}::LEN` is {value}, but `{name}` serializes to {size} bytes")
+
+
+def check_init_space(text, layouts, where, errors):
+    for name in sorted(set(re.findall(r"space\s*=\s*8\s*\+\s*(\w+)::INIT_SPACE", text))):
+        if name not in layouts:
+            errors.append(f"{where}: `{name}::INIT_SPACE` refers to a type without a schema")
+        elif layouts[name]["max_size"] is None:
+            errors.append(f"{where}: `{name}` has unbounded fields, add a `#[max_len]`")
+
+
+def render(snapshot):
+    """JSON rendering of a snapshot, with one field or variant per line."""
+    text = json.dumps(snapshot, indent=2)
+    return re.sub(
+        r"\[\s*(\"[^\"]*\"),\s*(\"[^\"]*\"|\[[^\[\]]*\])\s*\]",
+        lambda m: "[" + m.group(1) + ", " + " ".join(m.group(2).split()) + "]",
+        text,
+    ) + "\n"
+
+
+def check_usecase(usecase, update):
+    errors = []
+    snapshot = {}
+    for flavor in rs.FLAVORS:
+        path = rs.source_file(usecase, flavor)
+        if path is None:
+            continue
+        text = rs.read_source(path)
+        where = f"{usecase}/{flavor}/{path.name}"
+        layouts = Layouts(text, flavor).export()
+        snapshot[flavor] = layouts
+        if flavor == "anchor":
+            check_init_space(text, layouts, where, errors)
+        else:
+            check_raw_lens(text, layouts, where, errors)
+
+    golden = LAYOUTS / f"{usecase}.json"
+    rendered = render(snapshot)
+    if update:
+        LAYOUTS.mkdir(exist_ok=True)
+        golden.write_text(rendered)
+    elif not golden.exists():
+        errors.append(f"{usecase}: no snapshot, run with --update to create it")
+    elif golden.read_text() != rendered:
+        old = json.loads(golden.read_text())
+        for flavor in sorted(set(old) | set(snapshot)):
+            before, after = old.get(flavor, {}), snapshot.get(flavor, {})
+            for name in sorted(set(before) | set(after)):
+                if before.get(name) != after.get(name):
+                    errors.append(f"{usecase}/{flavor}: layout of `{name}` changed")
+    return errors
+
+
+def main(argv):
+    update = "--update" in argv
+    argv = [a for a in argv if a != "--update"]
+    usecases = argv or sorted(
+        p.parent.parent.name for p in rs.CONTRACTS.glob("*/solana/*") if p.is_dir()
+    )
+    failed = False
+    for usecase in sorted(set(usecases)):
+        errors = check_usecase(usecase, update)
+        print(f"{usecase}: {'FAIL' if errors else 'ok'}")
+        for e in errors:
+            print(f"  error: {e}")
+        failed |= bool(errors)
+    return 1 if failed else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main(sys.argv[1:]))
@@ -0,0 +1,205 @@
+#!/usr/bin/env python3
+"""Check the Solana implementations against the use case specifications.
+
+Every use case with Solana implementations has a `spec.toml` next to its
+README, listing the actors, the states and the actions of the contract,
+together with the preconditions each action must enforce. For every flavor
+(`anchor`, `raw_rust`) this script checks that:
+
+- there is an instruction for every action of the specification, unless
+  the action is marked as `omitted` for Solana (e.g. plain lamport
+  transfers that need no instruction);
+- every precondition is enforced, i.e. the Anchor error it maps to is
+  actually raised, and the raw_rust message it maps to is actually logged.
+
+Dead declarations that make the programs drift from the specification
+(errors that are never raised, empty or unused account contexts,
+instructions that are not in the specification) are reported as warnings.
+
+With `--negative-tests`, the script prints instead the negative test cases
+derived from the preconditions, as JSON: one entry per action, flavor and
+precondition, with the instruction to call and the Anchor error or the
+raw_rust log line the failing call is expected to produce. The cases are
+not executed: setting up the state violating each precondition is left to
+whoever writes the tests of a program, and the checks above only make sure
+that the error or the message is raised or logged somewhere in the program.
+
+Usage: python3 tools/check_specs.py [--negative-tests] [usecase ...]
+"""
+
+import json
+import re
+import sys
+import tomllib
+
+import rust_source as rs
+
+
+class Report:
+    def __init__(self, usecase):
+        self.usecase = usecase
+        self.errors = []
+        self.warnings = []
+
+    def error(self, msg):
+        self.errors.append(msg)
+
+    def warn(self, msg):
+        self.warnings.append(msg)
+
+    def print(self):
+        status = "FAIL" if self.errors else "ok"
+        print(f"{self.usecase}: {status}")
+        for e in self.errors:
+            print(f"  error: {e}")
+        for w in self.warnings:
+            print(f"  warning: {w}")
+
+
+def check_spec_shape(spec, report):
+    actors = set(spec.get("actors", []))
+    states = set(spec.get("states", []))
+    if states and spec.get("initial") not in states:
+        report.error(f"initial state {spec.get('initial')!r} is not a state")
+
+    for name, action in spec.get("actions", {}).items():
+        for actor in action.get("actors", []):
+            if actor not in actors:
+                report.error(f"action {name}: unknown actor {actor!r}")
+        for state in action.get("from", []) + [action.get("to")]:
+            if states and state is not None and state not in states:
+                report.error(f"action {name}: unknown state {state!r}")
+        for pre in action.get("preconditions", []):
+            if "id" not in pre or "description" not in pre:
+                report.error(f"action {name}: preconditions need an id and a description")
+        solana = action.get("solana", {})
+        if "omitted" in solana and set(solana) & set(rs.FLAVORS):
+            report.error(f"action {name}: an omitted action cannot name an instruction")
+
+
+def instruction_name(action_name, action, flavor):
+    return action.get("solana", {}).get(flavor, action_name)
+
+
+def check_flavor(spec, flavor, report):
+    path = rs.source_file(report.usecase, flavor)
+    if path is None:
+        return
+    text = rs.read_source(path)
+    where = f"{flavor}/{path.name}"
+
+    declared = rs.instructions(text, flavor)
+    expected = set()
+    for name, action in spec.get("actions", {}).items():
+        if "omitted" in action.get("solana", {}):
+            continue
+        instruction = instruction_name(name, action, flavor)
+        expected.add(instruction)
+        if instruction not in declared:
+            report.error(f"{where}: no instruction for action {name!r} (expected `{instruction}`)")
+
+    for instruction in declared:
+        if instruction not in expected:
+            report.warn(f"{where}: instruction `{instruction}` is not in the specification")
+
+    if flavor == "anchor":
+        check_anchor_preconditions(spec, text, where, report)
+        check_anchor_dead_code(text, where, report)
+    else:
+        check_raw_preconditions(spec, text, where, report)
+
+
+def check_anchor_preconditions(spec, text, where, report):
+    _, variants = rs.anchor_error_variants(text)
+    raised = rs.anchor_raised_errors(text)
+    for name, action in spec.get("actions", {}).items():
+        for pre in action.get("preconditions", []):
+            error = pre.get("anchor")
+            if error is None:
+                continue
+            if error not in variants:
+                report.error(f"{where}: {name}.{pre['id']}: error `{error}` is not declared")
+            elif error not in raised:
+                report.error(f"{where}: {name}.{pre['id']}: error `{error}` is never raised")
+
+
+def check_raw_preconditions(spec, text, where, report):
+    messages = rs.raw_messages(text)
+    for name, action in spec.get("actions", {}).items():
+        for pre in action.get("preconditions", []):
+            message = pre.get("raw_rust")
+            if message is not None and message not in messages:
+                report.error(f"{where}: {name}.{pre['id']}: message {message!r} is never logged")
+
+
+def check_anchor_dead_code(text, where, report):
+    _, variants = rs.anchor_error_variants(text)
+    raised = rs.anchor_raised_errors(text)
+    for variant in variants:
+        if variant not in raised:
+            report.warn(f"{where}: error `{variant}` is declared but never raised")
+
+    for name, body in rs.accounts_structs(text):
+        if not body.strip():
+            report.warn(f"{where}: account context `{name}` is empty")
+        elif not re.search(rf"Context<(?:[^<>]*,\s*)?{name}\b", text):
+            report.warn(f"{where}: account context `{name}` is not used by any instruction")
+
+
+def load_spec(usecase):
+    with open(rs.CONTRACTS / usecase / "spec.toml", "rb") as f:
+        return tomllib.load(f)
+
+
+def negative_tests(usecase, spec):
+    tests = []
+    for name, action in spec.get("actions", {}).items():
+        if "omitted" in action.get("solana", {}):
+            continue
+        for flavor in rs.FLAVORS:
+            if rs.source_file(usecase, flavor) is None:
+                continue
+            for pre in action.get("preconditions", []):
+                expected = pre.get(flavor)
+                if expected is None:
+                    continue
+                tests.append(
+                    {
+                        "usecase": usecase,
+                        "flavor": flavor,
+                        "action": name,
+                        "instruction": instruction_name(name, action, flavor),
+                        "precondition": pre["id"],
+                        "expect": {"error" if flavor == "anchor" else "log": expected},
+                    }
+                )
+    return tests
+
+
+def check_usecase(usecase):
+    report = Report(usecase)
+    spec = load_spec(usecase)
+    check_spec_shape(spec, report)
+    for flavor in rs.FLAVORS:
+        check_flavor(spec, flavor, report)
+    return report
+
+
+def main(argv):
+    emit_tests = "--negative-tests" in argv
+    argv = [a for a in argv if a != "--negative-tests"]
+    usecases = argv or sorted(p.parent.name for p in rs.CONTRACTS.glob("*/spec.toml"))
+    if emit_tests:
+        tests = [t for u in usecases for t in negative_tests(u, load_spec(u))]
+        print(json.dumps(tests, indent=2))
+        return 0
+    failed = False
+    for usecase in usecases:
+        report = check_usecase(usecase)
+        report.print()
+        failed |= bool(report.errors)
+    return 1 if failed else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main(sys.argv[1:]))
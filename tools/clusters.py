@@ -0,0 +1,183 @@
+#!/usr/bin/env python3
+"""Keep the cluster-dependent constants of the programs in sync with
+`contracts/clusters.toml`.
+
+The addresses that differ between localnet, devnet and mainnet (the program
+IDs of the Anchor programs, the oracle accounts of pricebet, the SPL
+programs) are listed per cluster in `contracts/clusters.toml`. The programs
+select a cluster at compile time with the `devnet` or the `mainnet` feature,
+localnet being the default when no cluster feature is enabled, so every
+constant taken from the profiles is written as one `#[cfg(...)]` item per
+distinct value:
+
+    #[cfg(feature = "mainnet")]
+    const BTC_USDC_FEED: &str = "GVXR...";
+    #[cfg(not(feature = "mainnet"))]
+    const BTC_USDC_FEED: &str = "HovQ...";
+
+A constant with the same value on every cluster stays a plain item. Files
+with cfg-gated constants also get a `compile_error!` rejecting more than one
+cluster feature.
+
+The script fails if a constant of a program differs from the profiles; with
+`--update` it rewrites the constants instead.
+
+Usage: python3 tools/clusters.py [--update]
+"""
+
+import itertools
+import re
+import sys
+import tomllib
+
+import rust_source as rs
+
+PROFILES = rs.CONTRACTS / "clusters.toml"
+CLUSTERS = ("localnet", "devnet", "mainnet")
+DEFAULT = "localnet"
+SECTIONS = ("programs", "pricebet", "spl")
+
+BASE58 = re.compile(r"[1-9A-HJ-NP-Za-km-z]{32,44}")
+CFG = r"#\[cfg\([^\n]*\)\]\n"
+GUARD = re.compile(r"#\[cfg\(all\([^\n]*\)\)\]\ncompile_error!\([^\n]*\);\n")
+
+
+def features():
+    return [c for c in CLUSTERS if c != DEFAULT]
+
+
+def managed_items(profiles):
+    """(file, key, regex of the item, template of the item) for every
+    constant taken from the profiles."""
+    items = []
+    for usecase in profiles[DEFAULT]["programs"]:
+        items.append(
+            (
+                rs.source_file(usecase, "anchor"),
+                f"programs.{usecase}",
+                r'declare_id!\("\w*"\);',
+                'declare_id!("{}");',
+            )
+        )
+    for flavor in rs.FLAVORS:
+        for name in ("BTC_USDC_FEED", "BTC_USDC_FEED_OWNER"):
+            items.append(
+                (
+                    rs.source_file("pricebet", flavor),
+                    f"pricebet.{name.lower()}",
+                    rf'const {name}: &str = "\w*";',
+                    f'const {name}: &str = "{{}}";',
+                )
+            )
+    return items
+
+
+def lookup(profile, key):
+    section, name = key.split(".")
+    return profile[section][name]
+
+
+def cfg(clusters):
+    """The cfg predicate selecting exactly the given clusters."""
+
+    def any_of(names):
+        preds = [f'feature = "{n}"' for n in names]
+        return preds[0] if len(preds) == 1 else f"any({', '.join(preds)})"
+
+    if DEFAULT in clusters:
+        return f"not({any_of([f for f in features() if f not in clusters])})"
+    return any_of(clusters)
+
+
+def render(template, values):
+    """The source of a constant, given its value on every cluster."""
+    distinct = list(dict.fromkeys(values[c] for c in CLUSTERS))
+    if len(distinct) == 1:
+        return template.format(distinct[0]) + "\n"
+    lines = []
+    # The value of the default cluster goes last, as the fallback
+    for value in sorted(distinct, key=lambda v: values[DEFAULT] == v):
+        clusters = [c for c in CLUSTERS if values[c] == value]
+        lines.append(f"#[cfg({cfg(clusters)})]")
+        lines.append(template.format(value))
+    return "\n".join(lines) + "\n"
+
+
+def guard():
+    lines = []
+    for a, b in itertools.combinations(features(), 2):
+        lines.append(f'#[cfg(all(feature = "{a}", feature = "{b}"))]')
+        lines.append(f'compile_error!("the {a} and {b} features are mutually exclusive");')
+    return "\n".join(lines) + "\n" if lines else ""
+
+
+def check_profiles(profiles):
+    errors = []
+    for cluster in CLUSTERS:
+        if cluster not in profiles:
+            errors.append(f"missing cluster {cluster}")
+            continue
+        for section in SECTIONS:
+            for name, value in profiles[cluster].get(section, {}).items():
+                if not BASE58.fullmatch(value):
+                    errors.append(f"{cluster}.{section}.{name}: not an address")
+    for cluster in CLUSTERS[1:]:
+        for section in SECTIONS:
+            if profiles.get(cluster, {}).get(section, {}).keys() != profiles[DEFAULT].get(
+                section, {}
+            ).keys():
+                errors.append(f"{cluster}.{section}: keys differ from {DEFAULT}.{section}")
+    return errors
+
+
+def sync(profiles, update):
+    errors = []
+    by_file = {}
+    for path, key, regex, template in managed_items(profiles):
+        by_file.setdefault(path, []).append((key, regex, template))
+
+    for path, items in by_file.items():
+        text = path.read_text()
+        new = GUARD.sub("", text)
+        first_cfg = None
+        for key, regex, template in items:
+            values = {c: lookup(profiles[c], key) for c in CLUSTERS}
+            pattern = re.compile(rf"(?:(?:{CFG})?{regex}[^\n]*\n)+")
+            matches = list(pattern.finditer(new))
+            if len(matches) != 1:
+                errors.append(f"{path.relative_to(rs.ROOT)}: cannot find the item of {key}")
+                continue
+            m = matches[0]
+            source = render(template, values)
+            new = new[: m.start()] + source + new[m.end() :]
+            if source.startswith("#[cfg(") and (first_cfg is None or m.start() < first_cfg):
+                first_cfg = m.start()
+        if first_cfg is not None:
+            new = new[:first_cfg] + guard() + new[first_cfg:]
+        if new != text:
+            if update:
+                path.write_text(new)
+                print(f"updated {path.relative_to(rs.ROOT)}")
+            else:
+                errors.append(
+                    f"{path.relative_to(rs.ROOT)}: constants differ from {PROFILES.name}"
+                )
+    return errors
+
+
+def main(argv):
+    update = "--update" in argv
+    with open(PROFILES, "rb") as f:
+        profiles = tomllib.load(f)
+    errors = check_profiles(profiles)
+    if not errors:
+        errors = sync(profiles, update)
+    for e in errors:
+        print(f"error: {e}")
+    if not errors:
+        print("ok")
+    return 1 if errors else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main(sys.argv[1:]))
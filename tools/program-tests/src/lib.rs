@@ -0,0 +1,6 @@
+//! `program-tests`: the tests of the raw Rust Solana programs.
+//!
+//! The programs have no manifest of their own, so every program is compiled
+//! as an integration test of this crate (see `tests/`), which includes its
+//! source file with `#[path]` and runs the `#[cfg(test)]` module at its end
+//! with `solana-program-test`, processing the instructions natively.
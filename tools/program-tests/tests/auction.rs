@@ -0,0 +1,2 @@
+#[path = "../../../contracts/auction/solana/raw_rust/auction.rs"]
+mod program;
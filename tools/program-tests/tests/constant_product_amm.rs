@@ -0,0 +1,2 @@
+#[path = "../../../contracts/constant-product-amm/solana/raw_rust/constant_product_amm.rs"]
+mod program;
@@ -0,0 +1,53 @@
+//! Cluster profiles: the addresses that depend on the cluster, shared with
+//! the programs (see `contracts/clusters.toml`).
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+const PROFILES: &str = include_str!("../../../contracts/clusters.toml");
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    Mainnet,
+}
+
+impl Cluster {
+    fn name(self) -> &'static str {
+        match self {
+            Cluster::Localnet => "localnet",
+            Cluster::Devnet => "devnet",
+            Cluster::Mainnet => "mainnet",
+        }
+    }
+}
+
+pub struct Profile {
+    cluster: Cluster,
+    table: toml::Table,
+}
+
+impl Profile {
+    pub fn load(cluster: Cluster) -> Result<Self> {
+        let mut profiles: toml::Table = PROFILES.parse()?;
+        match profiles.remove(cluster.name()) {
+            Some(toml::Value::Table(table)) => Ok(Profile { cluster, table }),
+            _ => Err(anyhow!("no profile for the {} cluster", cluster.name())),
+        }
+    }
+
+    /// The address at `key` in the profile, e.g. `programs.bet` or `spl.token`.
+    pub fn address(&self, key: &str) -> Result<Pubkey> {
+        let (section, name) = key.split_once('.').unwrap_or((key, ""));
+        let value = self
+            .table
+            .get(section)
+            .and_then(|s| s.get(name))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("no {} in the {} profile", key, self.cluster.name()))?;
+        Pubkey::from_str(value).with_context(|| format!("invalid address for {}", key))
+    }
+}
@@ -0,0 +1,131 @@
+//! `rosetta-sol`: offline builder and signer for the transactions of the
+//! Solana programs of this repository.
+//!
+//! Every instruction of every program, in both the Anchor and the raw Rust
+//! flavor, has its own subcommand:
+//!
+//! ```text
+//! rosetta-sol --blockhash <HASH> <usecase> <anchor|raw-rust> <instruction> [ARGS]
+//! ```
+//!
+//! Signers are given as keypair files, the other accounts as public keys;
+//! PDAs are derived from the seeds used by the programs. The transaction is
+//! signed with the given recent blockhash, without contacting any cluster,
+//! and printed in base64 (the wire format accepted by `sendTransaction`).
+//! Program IDs and other cluster-dependent addresses are taken from the
+//! profile of `--cluster`, the same the programs are built with.
+
+mod cluster;
+mod programs;
+mod tx;
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
+use std::path::PathBuf;
+
+use cluster::{Cluster, Profile};
+use programs::*;
+
+#[derive(Parser)]
+#[command(name = "rosetta-sol", version, about)]
+struct Cli {
+    /// Recent blockhash the transaction is signed with
+    #[arg(long, global = true)]
+    blockhash: Option<Hash>,
+
+    /// Keypair file of the fee payer (default: the first signer)
+    #[arg(long, global = true)]
+    fee_payer: Option<PathBuf>,
+
+    /// Cluster whose profile gives the program IDs and the other
+    /// cluster-dependent addresses
+    #[arg(long, global = true, value_enum, default_value_t = Cluster::Localnet)]
+    cluster: Cluster,
+
+    /// Address of the program (required for the raw Rust programs, which
+    /// have no declared ID)
+    #[arg(long, global = true)]
+    program_id: Option<Pubkey>,
+
+    #[command(subcommand)]
+    usecase: UseCase,
+}
+
+#[derive(Subcommand)]
+enum UseCase {
+    #[command(subcommand)]
+    Auction(auction::Command),
+    #[command(subcommand)]
+    Bet(bet::Command),
+    #[command(subcommand, name = "constant-product-amm")]
+    ConstantProductAmm(constant_product_amm::Command),
+    #[command(subcommand)]
+    Crowdfund(crowdfund::Command),
+    #[command(subcommand)]
+    Escrow(escrow::Command),
+    #[command(subcommand)]
+    Htlc(htlc::Command),
+    #[command(subcommand)]
+    Lottery(lottery::Command),
+    #[command(subcommand, name = "payment_splitter")]
+    PaymentSplitter(payment_splitter::Command),
+    #[command(subcommand)]
+    Pricebet(pricebet::Command),
+    #[command(subcommand, name = "simple_transfer")]
+    SimpleTransfer(simple_transfer::Command),
+    #[command(subcommand, name = "simple_wallet")]
+    SimpleWallet(simple_wallet::Command),
+    #[command(subcommand)]
+    Storage(storage::Command),
+    #[command(subcommand, name = "token_transfer")]
+    TokenTransfer(token_transfer::Command),
+    #[command(subcommand)]
+    Vault(vault::Command),
+    #[command(subcommand)]
+    Vesting(vesting::Command),
+}
+
+impl UseCase {
+    fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            UseCase::Auction(c) => c.build(b),
+            UseCase::Bet(c) => c.build(b),
+            UseCase::ConstantProductAmm(c) => c.build(b),
+            UseCase::Crowdfund(c) => c.build(b),
+            UseCase::Escrow(c) => c.build(b),
+            UseCase::Htlc(c) => c.build(b),
+            UseCase::Lottery(c) => c.build(b),
+            UseCase::PaymentSplitter(c) => c.build(b),
+            UseCase::Pricebet(c) => c.build(b),
+            UseCase::SimpleTransfer(c) => c.build(b),
+            UseCase::SimpleWallet(c) => c.build(b),
+            UseCase::Storage(c) => c.build(b),
+            UseCase::TokenTransfer(c) => c.build(b),
+            UseCase::Vault(c) => c.build(b),
+            UseCase::Vesting(c) => c.build(b),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Quotes are computed offline, without building a transaction
+    if let UseCase::ConstantProductAmm(constant_product_amm::Command::Quote(quote)) = &cli.usecase {
+        println!("{}", quote.run()?);
+        return Ok(());
+    }
+
+    let blockhash = cli
+        .blockhash
+        .ok_or_else(|| anyhow::anyhow!("--blockhash is required"))?;
+
+    let profile = Profile::load(cli.cluster)?;
+    let mut builder = Builder::new(cli.program_id, profile);
+    cli.usecase.build(&mut builder)?;
+
+    let encoded = tx::sign(builder, cli.fee_payer.as_deref(), blockhash)?;
+    println!("{}", encoded);
+    Ok(())
+}
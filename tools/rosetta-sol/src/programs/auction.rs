@@ -0,0 +1,713 @@
+use anyhow::Result;
+use borsh::BorshSerialize;
+use clap::Subcommand;
+use solana_sdk::{keccak, pubkey::Pubkey};
+use std::path::PathBuf;
+
+use super::*;
+
+/// The mode of an auction, as serialized by the programs.
+#[derive(BorshSerialize)]
+enum AuctionMode {
+    English,
+    Dutch { floor_price: u64 },
+    Sealed { reveal_slots: u64, penalty_bps: u16 },
+}
+
+fn auction_mode(
+    floor_price: Option<u64>,
+    reveal_slots: Option<u64>,
+    penalty_bps: u16,
+) -> AuctionMode {
+    match (floor_price, reveal_slots) {
+        (Some(floor_price), _) => AuctionMode::Dutch { floor_price },
+        (None, Some(reveal_slots)) => AuctionMode::Sealed {
+            reveal_slots,
+            penalty_bps,
+        },
+        (None, None) => AuctionMode::English,
+    }
+}
+
+/// The reserve price of an auction, as serialized by the programs.
+#[derive(BorshSerialize)]
+enum ReservePrice {
+    None,
+    Visible { price: u64 },
+    Hidden { commitment: [u8; 32] },
+}
+
+fn reserve(reserve_price: Option<u64>, reserve_salt: &Option<String>) -> ReservePrice {
+    match (reserve_price, reserve_salt) {
+        (Some(price), Some(salt)) => ReservePrice::Hidden {
+            commitment: commitment(price, salt),
+        },
+        (Some(price), None) => ReservePrice::Visible { price },
+        (None, _) => ReservePrice::None,
+    }
+}
+
+/// The commitment to a hidden reserve price, as checked by the programs.
+fn commitment(amount: u64, salt: &str) -> [u8; 32] {
+    keccak::hashv(&[&amount.to_le_bytes(), salt.as_bytes()]).to_bytes()
+}
+
+/// The commitment to a sealed bid, bound to its bidder and its auction, as
+/// checked by the programs.
+fn bid_commitment(bidder: &Pubkey, auction: &Pubkey, amount: u64, salt: &str) -> [u8; 32] {
+    keccak::hashv(&[
+        bidder.as_ref(),
+        auction.as_ref(),
+        &amount.to_le_bytes(),
+        salt.as_bytes(),
+    ])
+    .to_bytes()
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The seller starts its auction `auction_id` of `auctioned_object`,
+    /// escrowing the auctioned tokens from its associated token account
+    Start {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        auctioned_object: String,
+        /// The next id among the auctions of the seller, from 0
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        duration_slots: u64,
+        #[arg(long)]
+        starting_bid: u64,
+        /// Mint of the auctioned tokens
+        #[arg(long)]
+        mint: Pubkey,
+        /// Auctioned amount, in base units (1 for an NFT)
+        #[arg(long, default_value_t = 1)]
+        amount: u64,
+        /// A bid less than this many slots before the deadline extends it
+        #[arg(long, default_value_t = 0)]
+        extension_window: u64,
+        /// Slots added to the deadline by a bid in the extension window
+        #[arg(long, default_value_t = 0)]
+        extension_amount: u64,
+        /// Maximum total extension of the deadline, in slots
+        #[arg(long, default_value_t = 0)]
+        max_extension: u64,
+        /// Run a Dutch auction, whose price decays from the starting bid to
+        /// this floor price
+        #[arg(long, conflicts_with = "reveal_slots")]
+        floor_price: Option<u64>,
+        /// Run a sealed-bid auction, whose bids are revealed during this many
+        /// slots after the deadline
+        #[arg(long)]
+        reveal_slots: Option<u64>,
+        /// Share of the deposit, in basis points, that bidders who do not
+        /// reveal their sealed bid lose to the seller
+        #[arg(long, default_value_t = 0, requires = "reveal_slots")]
+        penalty_bps: u16,
+        /// Below this price the auctioned tokens go back to the seller
+        #[arg(long)]
+        reserve_price: Option<u64>,
+        /// Hide the reserve price behind a commitment with this salt, to
+        /// reveal at the end
+        #[arg(long, requires = "reserve_price")]
+        reserve_salt: Option<String>,
+        /// A bid of at least this price wins right away, in an English
+        /// auction
+        #[arg(long)]
+        buy_now_price: Option<u64>,
+    },
+    /// A bidder outbids the current highest bidder, whose bid becomes
+    /// withdrawable
+    Bid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the deadline, the seller takes the highest bid, and the highest
+    /// bidder the auctioned tokens. Anyone can end the auction after the end
+    /// deadline, when a hidden reserve price counts as not met
+    End {
+        /// The seller, or anyone after the end deadline
+        #[arg(long)]
+        caller: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        mint: Pubkey,
+        /// The seller, if nobody bid or the reserve price is not met
+        #[arg(long)]
+        highest_bidder: Pubkey,
+        /// The hidden reserve price to reveal
+        #[arg(long, default_value_t = 0)]
+        reserve_price: u64,
+        #[arg(long, default_value = "")]
+        reserve_salt: String,
+    },
+    /// The seller cancels the auction, as long as nobody bid, and takes back
+    /// the auctioned tokens
+    Cancel {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        mint: Pubkey,
+    },
+    /// An outbid bidder withdraws its deposits
+    WithdrawRefund {
+        #[arg(long)]
+        bidder: PathBuf,
+        /// Receives the penalty of sealed bids that were not revealed
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+    },
+    /// A bidder commits to a sealed bid of `amount`, depositing `deposit`
+    CommitBid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        salt: String,
+        /// At least the bid, not to reveal it
+        #[arg(long)]
+        deposit: u64,
+    },
+    /// A bidder reveals its sealed bid after the deadline
+    RevealBid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        salt: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The seller starts its auction `auction_id` of `auctioned_object`,
+    /// handing over the token account holding the auctioned tokens
+    Start {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        auctioned_object: String,
+        /// The next id among the auctions of the seller, from 0
+        #[arg(long)]
+        auction_id: u64,
+        /// Slot of the deadline
+        #[arg(long)]
+        end_slot: u64,
+        #[arg(long)]
+        starting_bid: u64,
+        /// Token account of the seller holding the auctioned tokens
+        #[arg(long)]
+        escrow_token_account: Pubkey,
+        /// A bid less than this many slots before the deadline extends it
+        #[arg(long, default_value_t = 0)]
+        extension_window: u64,
+        /// Slots added to the deadline by a bid in the extension window
+        #[arg(long, default_value_t = 0)]
+        extension_amount: u64,
+        /// Maximum total extension of the deadline, in slots
+        #[arg(long, default_value_t = 0)]
+        max_extension: u64,
+        /// Run a Dutch auction, whose price decays from the starting bid to
+        /// this floor price
+        #[arg(long, conflicts_with = "reveal_slots")]
+        floor_price: Option<u64>,
+        /// Run a sealed-bid auction, whose bids are revealed during this many
+        /// slots after the deadline
+        #[arg(long)]
+        reveal_slots: Option<u64>,
+        /// Share of the deposit, in basis points, that bidders who do not
+        /// reveal their sealed bid lose to the seller
+        #[arg(long, default_value_t = 0, requires = "reveal_slots")]
+        penalty_bps: u16,
+        /// Below this price the auctioned tokens go back to the seller
+        #[arg(long)]
+        reserve_price: Option<u64>,
+        /// Hide the reserve price behind a commitment with this salt, to
+        /// reveal at the end
+        #[arg(long, requires = "reserve_price")]
+        reserve_salt: Option<String>,
+        /// A bid of at least this price wins right away, in an English
+        /// auction
+        #[arg(long)]
+        buy_now_price: Option<u64>,
+    },
+    /// A bidder outbids the current highest bidder, whose bid becomes
+    /// withdrawable
+    Bid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the deadline, the seller takes the highest bid, and the highest
+    /// bidder the auctioned tokens, in its associated token account. Anyone
+    /// can end the auction after the end deadline, when a hidden reserve
+    /// price counts as not met
+    End {
+        /// The seller, or anyone after the end deadline
+        #[arg(long)]
+        caller: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        escrow_token_account: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+        /// The seller, if nobody bid or the reserve price is not met
+        #[arg(long)]
+        highest_bidder: Pubkey,
+        /// The hidden reserve price to reveal
+        #[arg(long, default_value_t = 0)]
+        reserve_price: u64,
+        #[arg(long, default_value = "")]
+        reserve_salt: String,
+    },
+    /// The seller cancels the auction, as long as nobody bid, and takes back
+    /// the auctioned tokens
+    Cancel {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        escrow_token_account: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+    },
+    /// An outbid bidder withdraws its deposits
+    WithdrawRefund {
+        #[arg(long)]
+        bidder: PathBuf,
+        /// Receives the penalty of sealed bids that were not revealed
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+    },
+    /// A bidder commits to a sealed bid of `amount`, depositing `deposit`
+    CommitBid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        salt: String,
+        /// At least the bid, not to reveal it
+        #[arg(long)]
+        deposit: u64,
+    },
+    /// A bidder reveals its sealed bid after the deadline
+    RevealBid {
+        #[arg(long)]
+        bidder: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        auction_id: u64,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        salt: String,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("auction")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn auction_pda(seller: &Pubkey, auction_id: u64, program_id: &Pubkey) -> Pubkey {
+    pda(
+        &[b"auction", seller.as_ref(), &auction_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+fn seller_index_pda(seller: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"seller_index", seller.as_ref()], program_id)
+}
+
+fn pending_return_pda(auction: &Pubkey, bidder: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(
+        &[b"pending_return", auction.as_ref(), bidder.as_ref()],
+        program_id,
+    )
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Start {
+            seller,
+            auctioned_object,
+            auction_id,
+            duration_slots,
+            starting_bid,
+            mint,
+            amount,
+            extension_window,
+            extension_amount,
+            max_extension,
+            floor_price,
+            reveal_slots,
+            penalty_bps,
+            reserve_price,
+            reserve_salt,
+            buy_now_price,
+        } => {
+            let seller = b.signer(seller)?;
+            let auction = auction_pda(&seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(seller),
+                writable(seller_index_pda(&seller, &program_id)),
+                writable(auction),
+                readonly(*mint),
+                writable(b.associated_token_address(&seller, mint)?),
+                writable(b.associated_token_address(&auction, mint)?),
+                readonly(b.token_program()?),
+                system_program(),
+                readonly(b.associated_token_program()?),
+            ];
+            let args = (
+                auction_id,
+                auctioned_object,
+                duration_slots,
+                starting_bid,
+                amount,
+                extension_window,
+                extension_amount,
+                max_extension,
+                auction_mode(*floor_price, *reveal_slots, *penalty_bps),
+                reserve(*reserve_price, reserve_salt),
+                buy_now_price,
+            );
+            b.push(program_id, accounts, anchor_data("start", args));
+        }
+        Anchor::Bid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("bid", (amount,)));
+        }
+        Anchor::End {
+            caller,
+            seller,
+            auction_id,
+            mint,
+            highest_bidder,
+            reserve_price,
+            reserve_salt,
+        } => {
+            let caller = b.signer(caller)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(caller),
+                writable(*seller),
+                writable(auction),
+                writable(seller_index_pda(seller, &program_id)),
+                readonly(*mint),
+                writable(b.associated_token_address(&auction, mint)?),
+                readonly(*highest_bidder),
+                writable(b.associated_token_address(highest_bidder, mint)?),
+                readonly(b.token_program()?),
+                system_program(),
+                readonly(b.associated_token_program()?),
+            ];
+            let args = (reserve_price, reserve_salt);
+            b.push(program_id, accounts, anchor_data("end", args));
+        }
+        Anchor::Cancel {
+            seller,
+            auction_id,
+            mint,
+        } => {
+            let seller = b.signer(seller)?;
+            let auction = auction_pda(&seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(seller),
+                writable(auction),
+                writable(seller_index_pda(&seller, &program_id)),
+                readonly(*mint),
+                writable(b.associated_token_address(&auction, mint)?),
+                writable(b.associated_token_address(&seller, mint)?),
+                readonly(b.token_program()?),
+            ];
+            b.push(program_id, accounts, anchor_data("cancel", ()));
+        }
+        Anchor::WithdrawRefund {
+            bidder,
+            seller,
+            auction_id,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                writable(*seller),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw_refund", ()));
+        }
+        Anchor::CommitBid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+            salt,
+            deposit,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                system_program(),
+            ];
+            let args = (bid_commitment(&bidder, &auction, *amount, salt), deposit);
+            b.push(program_id, accounts, anchor_data("commit_bid", args));
+        }
+        Anchor::RevealBid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+            salt,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                readonly_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+            ];
+            let args = (amount, salt);
+            b.push(program_id, accounts, anchor_data("reveal_bid", args));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Start {
+            seller,
+            auctioned_object,
+            auction_id,
+            end_slot,
+            starting_bid,
+            escrow_token_account,
+            extension_window,
+            extension_amount,
+            max_extension,
+            floor_price,
+            reveal_slots,
+            penalty_bps,
+            reserve_price,
+            reserve_salt,
+            buy_now_price,
+        } => {
+            let seller = b.signer(seller)?;
+            let accounts = vec![
+                writable_signer(seller),
+                writable(auction_pda(&seller, *auction_id, &program_id)),
+                system_program(),
+                writable(*escrow_token_account),
+                readonly(b.token_program()?),
+                writable(seller_index_pda(&seller, &program_id)),
+            ];
+            let data = raw_data(
+                0,
+                &[
+                    &end_slot.to_le_bytes(),
+                    &starting_bid.to_le_bytes(),
+                    &extension_window.to_le_bytes(),
+                    &extension_amount.to_le_bytes(),
+                    &max_extension.to_le_bytes(),
+                    &auction_id.to_le_bytes(),
+                    &borsh::to_vec(&auction_mode(*floor_price, *reveal_slots, *penalty_bps))?,
+                    &borsh::to_vec(&reserve(*reserve_price, reserve_salt))?,
+                    &borsh::to_vec(buy_now_price)?,
+                    auctioned_object.as_bytes(),
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Bid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[&amount.to_le_bytes()]));
+        }
+        RawRust::End {
+            caller,
+            seller,
+            auction_id,
+            escrow_token_account,
+            mint,
+            highest_bidder,
+            reserve_price,
+            reserve_salt,
+        } => {
+            let caller = b.signer(caller)?;
+            // The raw program expects the token account of the winner to exist
+            let winners_token_account =
+                b.create_associated_token_account(&caller, highest_bidder, mint)?;
+            let accounts = vec![
+                writable_signer(caller),
+                writable(*seller),
+                writable(auction_pda(seller, *auction_id, &program_id)),
+                writable(*escrow_token_account),
+                writable(winners_token_account),
+                readonly(b.token_program()?),
+                writable(seller_index_pda(seller, &program_id)),
+            ];
+            let data = raw_data(2, &[&reserve_price.to_le_bytes(), reserve_salt.as_bytes()]);
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Cancel {
+            seller,
+            auction_id,
+            escrow_token_account,
+            mint,
+        } => {
+            let seller = b.signer(seller)?;
+            let accounts = vec![
+                writable_signer(seller),
+                writable(auction_pda(&seller, *auction_id, &program_id)),
+                writable(*escrow_token_account),
+                writable(b.associated_token_address(&seller, mint)?),
+                readonly(b.token_program()?),
+                writable(seller_index_pda(&seller, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(6, &[]));
+        }
+        RawRust::WithdrawRefund {
+            bidder,
+            seller,
+            auction_id,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                writable(*seller),
+            ];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+        RawRust::CommitBid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+            salt,
+            deposit,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                writable_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+                system_program(),
+            ];
+            let commitment = bid_commitment(&bidder, &auction, *amount, salt);
+            let data = raw_data(4, &[&deposit.to_le_bytes(), &commitment]);
+            b.push(program_id, accounts, data);
+        }
+        RawRust::RevealBid {
+            bidder,
+            seller,
+            auction_id,
+            amount,
+            salt,
+        } => {
+            let bidder = b.signer(bidder)?;
+            let auction = auction_pda(seller, *auction_id, &program_id);
+            let accounts = vec![
+                readonly_signer(bidder),
+                writable(auction),
+                writable(pending_return_pda(&auction, &bidder, &program_id)),
+            ];
+            let data = raw_data(5, &[&amount.to_le_bytes(), salt.as_bytes()]);
+            b.push(program_id, accounts, data);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// Both participants join the bet, depositing the wager
+    Join {
+        #[arg(long)]
+        participant1: PathBuf,
+        #[arg(long)]
+        participant2: PathBuf,
+        #[arg(long)]
+        oracle: Pubkey,
+        /// Number of slots before the deadline
+        #[arg(long)]
+        delay: u64,
+        /// Wager of each participant, in lamports
+        #[arg(long)]
+        wager: u64,
+    },
+    /// The oracle sends the pot to the winner
+    Win {
+        #[arg(long)]
+        oracle: PathBuf,
+        #[arg(long)]
+        winner: Pubkey,
+        #[arg(long)]
+        participant1: Pubkey,
+        #[arg(long)]
+        participant2: Pubkey,
+    },
+    /// After the deadline, the participants get their wager back
+    Timeout {
+        /// Signer of the raw Rust instruction (the Anchor instruction has
+        /// no signer other than the fee payer)
+        #[arg(long)]
+        actor: Option<PathBuf>,
+        #[arg(long)]
+        participant1: Pubkey,
+        #[arg(long)]
+        participant2: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("bet")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn bet_pda(participant1: &Pubkey, participant2: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[participant1.as_ref(), participant2.as_ref()], program_id)
+}
+
+fn anchor(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Join {
+            participant1,
+            participant2,
+            oracle,
+            delay,
+            wager,
+        } => {
+            let participant1 = b.signer(participant1)?;
+            let participant2 = b.signer(participant2)?;
+            let accounts = vec![
+                writable_signer(participant1),
+                writable_signer(participant2),
+                readonly(*oracle),
+                writable(bet_pda(&participant1, &participant2, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("join", (delay, wager)));
+        }
+        Ix::Win {
+            oracle,
+            winner,
+            participant1,
+            participant2,
+        } => {
+            let oracle = b.signer(oracle)?;
+            let accounts = vec![
+                writable_signer(oracle),
+                writable(*winner),
+                writable(bet_pda(participant1, participant2, &program_id)),
+                readonly(*participant1),
+                readonly(*participant2),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("win", ()));
+        }
+        Ix::Timeout {
+            actor,
+            participant1,
+            participant2,
+        } => {
+            if let Some(actor) = actor {
+                b.signer(actor)?;
+            }
+            let accounts = vec![
+                writable(*participant1),
+                writable(*participant2),
+                writable(bet_pda(participant1, participant2, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("timeout", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Join {
+            participant1,
+            participant2,
+            oracle,
+            delay,
+            wager,
+        } => {
+            let participant1 = b.signer(participant1)?;
+            let participant2 = b.signer(participant2)?;
+            let accounts = vec![
+                writable_signer(participant1),
+                writable_signer(participant2),
+                readonly(*oracle),
+                writable(bet_pda(&participant1, &participant2, &program_id)),
+                system_program(),
+            ];
+            let data = raw_data(0, &[&delay.to_le_bytes(), &wager.to_le_bytes()]);
+            b.push(program_id, accounts, data);
+        }
+        Ix::Win {
+            oracle,
+            winner,
+            participant1,
+            participant2,
+        } => {
+            let oracle = b.signer(oracle)?;
+            let accounts = vec![
+                readonly_signer(oracle),
+                writable(*winner),
+                readonly(*participant1),
+                readonly(*participant2),
+                writable(bet_pda(participant1, participant2, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[]));
+        }
+        Ix::Timeout {
+            actor,
+            participant1,
+            participant2,
+        } => {
+            let actor = match actor {
+                Some(actor) => b.signer(actor)?,
+                None => anyhow::bail!("--actor is required for the raw Rust program"),
+            };
+            let accounts = vec![
+                readonly_signer(actor),
+                writable(*participant1),
+                writable(*participant2),
+                writable(bet_pda(participant1, participant2, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+    }
+    Ok(())
+}
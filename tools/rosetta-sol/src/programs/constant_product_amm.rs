@@ -0,0 +1,1156 @@
+use anyhow::{anyhow, Result};
+use borsh::BorshSerialize;
+use clap::{Args, Subcommand, ValueEnum};
+use solana_sdk::pubkey::Pubkey;
+use std::{path::PathBuf, str::FromStr};
+
+use super::*;
+
+// The math of the pools, the same the programs are built with
+#[allow(dead_code)]
+#[path = "../../../../contracts/constant-product-amm/solana/amm_math.rs"]
+mod amm_math;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+    /// Compute offline what an instruction would do on a pool, with the same
+    /// math and rounding as the programs
+    #[command(subcommand)]
+    Quote(Quote),
+}
+
+/// The pool is identified by its pair of mints, in ascending order. The token
+/// accounts of the sender are its associated token accounts.
+#[derive(Args)]
+pub struct Pool {
+    #[arg(long)]
+    mint0: Pubkey,
+    #[arg(long)]
+    mint1: Pubkey,
+    /// Token account of the pool for mint0
+    #[arg(long)]
+    pool_token_account0: Pubkey,
+    /// Token account of the pool for mint1
+    #[arg(long)]
+    pool_token_account1: Pubkey,
+}
+
+/// The curve of a pool, chosen at initialization.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Curve {
+    ConstantProduct,
+    StableSwap,
+}
+
+/// The curve as serialized by the programs.
+#[derive(BorshSerialize)]
+enum CurveType {
+    ConstantProduct,
+    StableSwap { amp: u64 },
+}
+
+// The same dispatch as in the programs
+impl amm_math::Curve for CurveType {
+    fn amount_out(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_bps: u16,
+    ) -> Option<u64> {
+        match *self {
+            CurveType::ConstantProduct => {
+                amm_math::ConstantProduct.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+            CurveType::StableSwap { amp } => {
+                amm_math::StableSwap { amp }.amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+            }
+        }
+    }
+
+    fn invariant(&self, reserve0: u64, reserve1: u64) -> Option<u128> {
+        match *self {
+            CurveType::ConstantProduct => amm_math::ConstantProduct.invariant(reserve0, reserve1),
+            CurveType::StableSwap { amp } => {
+                amm_math::StableSwap { amp }.invariant(reserve0, reserve1)
+            }
+        }
+    }
+
+    fn spot_price(&self, reserve_in: u64, reserve_out: u64) -> Option<(u128, u128)> {
+        match *self {
+            CurveType::ConstantProduct => {
+                amm_math::ConstantProduct.spot_price(reserve_in, reserve_out)
+            }
+            CurveType::StableSwap { amp } => {
+                amm_math::StableSwap { amp }.spot_price(reserve_in, reserve_out)
+            }
+        }
+    }
+}
+
+fn curve_type(curve: Curve, amp: Option<u64>) -> Result<CurveType> {
+    match (curve, amp) {
+        (Curve::ConstantProduct, None) => Ok(CurveType::ConstantProduct),
+        (Curve::StableSwap, Some(amp)) => Ok(CurveType::StableSwap { amp }),
+        (Curve::ConstantProduct, Some(_)) => Err(anyhow!("--amp only applies to stable-swap")),
+        (Curve::StableSwap, None) => Err(anyhow!("stable-swap requires --amp")),
+    }
+}
+
+/// A hop of a route, given as
+/// `<MINT0>:<MINT1>:<POOL_TOKEN_ACCOUNT_IN>:<POOL_TOKEN_ACCOUNT_OUT>`, where the
+/// pool token accounts are the ones of the input and of the output mint.
+#[derive(Clone)]
+pub struct Hop {
+    mint0: Pubkey,
+    mint1: Pubkey,
+    pool_token_account_in: Pubkey,
+    pool_token_account_out: Pubkey,
+}
+
+impl FromStr for Hop {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let keys = s
+            .split(':')
+            .map(Pubkey::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        match keys[..] {
+            [mint0, mint1, pool_token_account_in, pool_token_account_out] => Ok(Hop {
+                mint0,
+                mint1,
+                pool_token_account_in,
+                pool_token_account_out,
+            }),
+            _ => Err(anyhow!("expected four public keys separated by ':'")),
+        }
+    }
+}
+
+/// An account passed to the callback of a flash swap, given as
+/// `<PUBKEY>[:<FLAGS>]`, where the flags are `w` for writable and `s` for
+/// signer (e.g. `<PUBKEY>:ws`).
+#[derive(Clone)]
+pub struct CallbackAccount(AccountMeta);
+
+impl FromStr for CallbackAccount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (pubkey, flags) = s.split_once(':').unwrap_or((s, ""));
+        if let Some(flag) = flags.chars().find(|c| !"ws".contains(*c)) {
+            return Err(anyhow!("unknown account flag '{}'", flag));
+        }
+        Ok(CallbackAccount(AccountMeta {
+            pubkey: Pubkey::from_str(pubkey)?,
+            is_signer: flags.contains('s'),
+            is_writable: flags.contains('w'),
+        }))
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// Create the registry of the pools, once for the program
+    InitializeRegistry {
+        #[arg(long)]
+        payer: PathBuf,
+    },
+    /// Create the pool, handing over its two token accounts to the program
+    Initialize {
+        #[arg(long)]
+        initializer: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Swap fee, in basis points of the input amount
+        #[arg(long)]
+        fee_bps: u16,
+        #[arg(long, value_enum, default_value_t = Curve::ConstantProduct)]
+        curve: Curve,
+        /// Amplification coefficient of a stable-swap pool, from 1 to 10000
+        #[arg(long)]
+        amp: Option<u64>,
+        /// Number of pools in the registry, which is the index of the entry
+        /// listing the new pool
+        #[arg(long)]
+        pool_count: u64,
+    },
+    /// Deposit liquidity in the pool
+    Deposit {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Maximum amount of mint0, in base units
+        #[arg(long)]
+        amount0_desired: u64,
+        /// Maximum amount of mint1, in base units
+        #[arg(long)]
+        amount1_desired: u64,
+        /// Minimum amount of mint0 deposited, in base units
+        #[arg(long, default_value_t = 0)]
+        amount0_min: u64,
+        /// Minimum amount of mint1 deposited, in base units
+        #[arg(long, default_value_t = 0)]
+        amount1_min: u64,
+        /// Last slot at which the deposit is accepted
+        #[arg(long)]
+        deadline: Option<u64>,
+    },
+    /// Redeem liquidity from the pool
+    Redeem {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Amount of LP tokens, in base units
+        #[arg(long)]
+        amount: u64,
+        /// Minimum amount of mint0 received, in base units
+        #[arg(long, default_value_t = 0)]
+        amount0_min: u64,
+        /// Minimum amount of mint1 received, in base units
+        #[arg(long, default_value_t = 0)]
+        amount1_min: u64,
+        /// Last slot at which the redeem is accepted
+        #[arg(long)]
+        deadline: Option<u64>,
+    },
+    /// Deposit liquidity with tokens of a single mint, part of which are
+    /// swapped for tokens of the other
+    ZapIn {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Deposit tokens of mint0 (otherwise, of mint1)
+        #[arg(long)]
+        is_mint0: bool,
+        /// Input amount, in base units of the input mint
+        #[arg(long)]
+        amount_in: u64,
+        /// Minimum amount of LP tokens minted, in base units
+        #[arg(long, default_value_t = 0)]
+        min_liquidity: u64,
+        /// Last slot at which the deposit is accepted
+        #[arg(long)]
+        deadline: Option<u64>,
+    },
+    /// Redeem liquidity into tokens of a single mint, swapping the redeemed
+    /// tokens of the other
+    ZapOut {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Receive tokens of mint0 (otherwise, of mint1)
+        #[arg(long)]
+        is_mint0: bool,
+        /// Amount of LP tokens, in base units
+        #[arg(long)]
+        amount: u64,
+        /// Minimum output amount, in base units of the output mint
+        #[arg(long)]
+        min_out_amount: u64,
+        /// Last slot at which the redeem is accepted
+        #[arg(long)]
+        deadline: Option<u64>,
+    },
+    /// Swap tokens of one mint for tokens of the other
+    Swap {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Swap tokens of mint0 for tokens of mint1 (otherwise, the converse)
+        #[arg(long)]
+        is_mint0: bool,
+        /// Input amount, in base units of the input mint
+        #[arg(long)]
+        amount_in: u64,
+        /// Minimum output amount, in base units of the output mint
+        #[arg(long)]
+        min_out_amount: u64,
+    },
+    /// Swap along a route of pools, in a single instruction
+    SwapRoute {
+        #[arg(long)]
+        sender: PathBuf,
+        #[arg(long)]
+        mint_in: Pubkey,
+        #[arg(long)]
+        mint_out: Pubkey,
+        /// A hop of the route, in order (repeated for every hop)
+        #[arg(long = "hop", required = true)]
+        hops: Vec<Hop>,
+        /// Input amount, in base units of the input mint
+        #[arg(long)]
+        amount_in: u64,
+        /// Minimum output amount of the whole route, in base units of the
+        /// output mint
+        #[arg(long)]
+        min_out_amount: u64,
+    },
+    /// Borrow tokens from the pool, which the callback program must pay back
+    /// plus the fee. The tokens are sent to the token accounts of the sender
+    FlashSwap {
+        #[arg(long)]
+        sender: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        /// Amount of mint0 sent to the sender, in base units
+        #[arg(long)]
+        amount0_out: u64,
+        /// Amount of mint1 sent to the sender, in base units
+        #[arg(long)]
+        amount1_out: u64,
+        /// Program called after sending the tokens
+        #[arg(long)]
+        callback_program: Pubkey,
+        /// Instruction data of the callback, in hex
+        #[arg(long, default_value = "")]
+        callback_data: String,
+        /// An account of the callback, in order (repeated for every account)
+        #[arg(long = "callback-account")]
+        callback_accounts: Vec<CallbackAccount>,
+    },
+    /// Read the cumulative prices of the pool, returned by the program, e.g.
+    /// when simulating the transaction (the fee payer is given with
+    /// `--fee-payer`)
+    Observe {
+        #[arg(long)]
+        mint0: Pubkey,
+        #[arg(long)]
+        mint1: Pubkey,
+    },
+    /// Set the share of the swap fee going to the protocol, and its treasury
+    /// (admin only)
+    SetProtocolFee {
+        #[arg(long)]
+        admin: PathBuf,
+        #[arg(long)]
+        mint0: Pubkey,
+        #[arg(long)]
+        mint1: Pubkey,
+        /// Share of the swap fee, in basis points of the fee (0 turns it off)
+        #[arg(long)]
+        protocol_fee_share: u16,
+        /// Owner of the token accounts the protocol fees are collected to
+        #[arg(long)]
+        treasury: Pubkey,
+    },
+    /// Send the accrued protocol fees to the associated token accounts of the
+    /// treasury (admin only)
+    CollectProtocolFees {
+        #[arg(long)]
+        admin: PathBuf,
+        #[command(flatten)]
+        pool: Pool,
+        #[arg(long)]
+        treasury: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("constant-product-amm")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+            Command::Quote(_) => Err(anyhow!("a quote does not build a transaction")),
+        }
+    }
+}
+
+/// The state of the pool, as stored in its AMM info account.
+#[derive(Args)]
+pub struct PoolState {
+    #[arg(long)]
+    reserve0: u64,
+    #[arg(long)]
+    reserve1: u64,
+    /// Supply of liquidity tokens, including the locked ones
+    #[arg(long, default_value_t = 0)]
+    supply: u64,
+    #[arg(long, default_value_t = 0)]
+    fee_bps: u16,
+    #[arg(long, value_enum, default_value_t = Curve::ConstantProduct)]
+    curve: Curve,
+    #[arg(long)]
+    amp: Option<u64>,
+    /// Share of the swap fee going to the protocol, in basis points of the fee
+    #[arg(long, default_value_t = 0)]
+    protocol_fee_share: u16,
+}
+
+impl PoolState {
+    // The reserves of the input (or output) mint and of the other one
+    fn reserves(&self, is_mint0: bool) -> (u64, u64) {
+        if is_mint0 {
+            (self.reserve0, self.reserve1)
+        } else {
+            (self.reserve1, self.reserve0)
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Quote {
+    /// Output amount and price impact of a swap
+    Swap {
+        #[command(flatten)]
+        state: PoolState,
+        /// Swap mint0 for mint1 (otherwise mint1 for mint0)
+        #[arg(long)]
+        is_mint0: bool,
+        #[arg(long)]
+        amount_in: u64,
+    },
+    /// Amounts taken and liquidity minted by a deposit
+    Deposit {
+        #[command(flatten)]
+        state: PoolState,
+        #[arg(long)]
+        amount0_desired: u64,
+        #[arg(long)]
+        amount1_desired: u64,
+    },
+    /// Amounts returned by a redeem
+    Redeem {
+        #[command(flatten)]
+        state: PoolState,
+        /// Amount of LP tokens, in base units
+        #[arg(long)]
+        amount: u64,
+    },
+    /// Amounts swapped and deposited, and liquidity minted, by a zap in
+    ZapIn {
+        #[command(flatten)]
+        state: PoolState,
+        /// Deposit mint0 (otherwise mint1)
+        #[arg(long)]
+        is_mint0: bool,
+        #[arg(long)]
+        amount_in: u64,
+    },
+    /// Amounts redeemed and swapped by a zap out
+    ZapOut {
+        #[command(flatten)]
+        state: PoolState,
+        /// Receive mint0 (otherwise mint1)
+        #[arg(long)]
+        is_mint0: bool,
+        /// Amount of LP tokens, in base units
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+impl Quote {
+    /// The quote, one `name: value` line per amount.
+    pub fn run(&self) -> Result<String> {
+        match self {
+            Quote::Swap {
+                state,
+                is_mint0,
+                amount_in,
+            } => swap_quote(
+                &curve_type(state.curve, state.amp)?,
+                state,
+                *is_mint0,
+                *amount_in,
+            ),
+            Quote::Deposit {
+                state,
+                amount0_desired,
+                amount1_desired,
+            } => {
+                let quote = amm_math::quote_deposit(
+                    *amount0_desired,
+                    *amount1_desired,
+                    state.reserve0,
+                    state.reserve1,
+                    state.supply,
+                )
+                .map_err(quote_error)?;
+                Ok(format!(
+                    "amount0: {}\namount1: {}\nliquidity: {}\nlocked_liquidity: {}",
+                    quote.amount0, quote.amount1, quote.liquidity, quote.locked_liquidity
+                ))
+            }
+            Quote::Redeem { state, amount } => {
+                let (amount0, amount1) =
+                    amm_math::quote_redeem(*amount, state.reserve0, state.reserve1, state.supply)
+                        .map_err(quote_error)?;
+                Ok(format!("amount0: {}\namount1: {}", amount0, amount1))
+            }
+            Quote::ZapIn {
+                state,
+                is_mint0,
+                amount_in,
+            } => {
+                let (reserve_in, reserve_out) = state.reserves(*is_mint0);
+                let quote = amm_math::quote_zap_in(
+                    &curve_type(state.curve, state.amp)?,
+                    *amount_in,
+                    reserve_in,
+                    reserve_out,
+                    state.supply,
+                    state.fee_bps,
+                    state.protocol_fee_share,
+                )
+                .map_err(quote_error)?;
+                Ok(format!(
+                    "swap_in: {}\nswap_out: {}\ndeposit_in: {}\ndeposit_out: {}\nliquidity: {}",
+                    quote.swap_in,
+                    quote.swap_out,
+                    quote.deposit.amount0,
+                    quote.deposit.amount1,
+                    quote.deposit.liquidity
+                ))
+            }
+            Quote::ZapOut {
+                state,
+                is_mint0,
+                amount,
+            } => {
+                let (reserve_out, reserve_other) = state.reserves(*is_mint0);
+                let quote = amm_math::quote_zap_out(
+                    &curve_type(state.curve, state.amp)?,
+                    *amount,
+                    reserve_out,
+                    reserve_other,
+                    state.supply,
+                    state.fee_bps,
+                )
+                .map_err(quote_error)?;
+                Ok(format!(
+                    "redeemed_out: {}\nswap_in: {}\nswap_out: {}\namount_out: {}",
+                    quote.redeemed_out, quote.swap_in, quote.swap_out, quote.amount_out
+                ))
+            }
+        }
+    }
+}
+
+fn swap_quote(
+    curve: &impl amm_math::Curve,
+    state: &PoolState,
+    is_mint0: bool,
+    amount_in: u64,
+) -> Result<String> {
+    let (reserve_in, reserve_out) = state.reserves(is_mint0);
+    let amount_out = amm_math::quote_swap(curve, amount_in, reserve_in, reserve_out, state.fee_bps)
+        .map_err(quote_error)?;
+    let price_impact_bps =
+        amm_math::price_impact_bps(curve, amount_in, reserve_in, reserve_out, state.fee_bps)
+            .map_err(quote_error)?;
+    Ok(format!(
+        "amount_out: {}\nprice_impact_bps: {}",
+        amount_out, price_impact_bps
+    ))
+}
+
+fn quote_error(error: amm_math::QuoteError) -> anyhow::Error {
+    anyhow!("{:?}", error)
+}
+
+fn amm_pda(pool: &Pool, program_id: &Pubkey) -> Pubkey {
+    pool_pda(&pool.mint0, &pool.mint1, program_id)
+}
+
+fn pool_pda(mint0: &Pubkey, mint1: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"amm", mint0.as_ref(), mint1.as_ref()], program_id)
+}
+
+fn registry_pda(program_id: &Pubkey) -> Pubkey {
+    pda(&[b"registry"], program_id)
+}
+
+fn pool_entry_pda(index: u64, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"pool_entry", &index.to_le_bytes()], program_id)
+}
+
+// The programs only create pools with the mints in ascending order
+fn check_mint_order(pool: &Pool) -> Result<()> {
+    if pool.mint0 >= pool.mint1 {
+        return Err(anyhow!(
+            "the mints must be in ascending order, i.e. --mint0 {} --mint1 {}",
+            pool.mint1,
+            pool.mint0
+        ));
+    }
+    Ok(())
+}
+
+fn lp_mint_pda(pool: &Pool, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"lp", amm_pda(pool, program_id).as_ref()], program_id)
+}
+
+// Accounts of the Anchor deposit and redeem instructions, and of swap without
+// the LP accounts
+fn anchor_accounts(
+    b: &Builder,
+    sender: Pubkey,
+    pool: &Pool,
+    program_id: &Pubkey,
+    with_lp: bool,
+) -> Result<Vec<AccountMeta>> {
+    let mut accounts = vec![
+        writable_signer(sender),
+        readonly(pool.mint0),
+        readonly(pool.mint1),
+        writable(amm_pda(pool, program_id)),
+    ];
+    if with_lp {
+        let lp_mint = lp_mint_pda(pool, program_id);
+        accounts.push(writable(lp_mint));
+        accounts.push(writable(b.associated_token_address(&sender, &lp_mint)?));
+    }
+    accounts.extend([
+        writable(b.associated_token_address(&sender, &pool.mint0)?),
+        writable(b.associated_token_address(&sender, &pool.mint1)?),
+        writable(pool.pool_token_account0),
+        writable(pool.pool_token_account1),
+        readonly(b.token_program()?),
+        system_program(),
+        readonly(b.associated_token_program()?),
+    ]);
+    Ok(accounts)
+}
+
+fn anchor(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::InitializeRegistry { payer } => {
+            let payer = b.signer(payer)?;
+            let accounts = vec![
+                writable_signer(payer),
+                writable(registry_pda(&program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("initialize_registry", ()));
+        }
+        Ix::Initialize {
+            initializer,
+            pool,
+            fee_bps,
+            curve,
+            amp,
+            pool_count,
+        } => {
+            check_mint_order(pool)?;
+            let curve = curve_type(*curve, *amp)?;
+            let initializer = b.signer(initializer)?;
+            let accounts = vec![
+                writable_signer(initializer),
+                writable(amm_pda(pool, &program_id)),
+                readonly(pool.mint0),
+                readonly(pool.mint1),
+                // Writable, since their owner is changed by the token program
+                writable(pool.pool_token_account0),
+                writable(pool.pool_token_account1),
+                writable(lp_mint_pda(pool, &program_id)),
+                writable(registry_pda(&program_id)),
+                writable(pool_entry_pda(*pool_count, &program_id)),
+                readonly(b.token_program()?),
+                system_program(),
+                readonly(b.associated_token_program()?),
+            ];
+            let data = anchor_data("initialize", (fee_bps, curve));
+            b.push(program_id, accounts, data);
+        }
+        Ix::Deposit {
+            sender,
+            pool,
+            amount0_desired,
+            amount1_desired,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = anchor_accounts(b, sender, pool, &program_id, true)?;
+            let args = (
+                amount0_desired,
+                amount1_desired,
+                amount0_min,
+                amount1_min,
+                deadline,
+            );
+            b.push(program_id, accounts, anchor_data("deposit", args));
+        }
+        Ix::Redeem {
+            sender,
+            pool,
+            amount,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = anchor_accounts(b, sender, pool, &program_id, true)?;
+            let args = (amount, amount0_min, amount1_min, deadline);
+            b.push(program_id, accounts, anchor_data("redeem", args));
+        }
+        Ix::ZapIn {
+            sender,
+            pool,
+            is_mint0,
+            amount_in,
+            min_liquidity,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = anchor_accounts(b, sender, pool, &program_id, true)?;
+            let args = (is_mint0, amount_in, min_liquidity, deadline);
+            b.push(program_id, accounts, anchor_data("zap_in", args));
+        }
+        Ix::ZapOut {
+            sender,
+            pool,
+            is_mint0,
+            amount,
+            min_out_amount,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = anchor_accounts(b, sender, pool, &program_id, true)?;
+            let args = (is_mint0, amount, min_out_amount, deadline);
+            b.push(program_id, accounts, anchor_data("zap_out", args));
+        }
+        Ix::Swap {
+            sender,
+            pool,
+            is_mint0,
+            amount_in,
+            min_out_amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = anchor_accounts(b, sender, pool, &program_id, false)?;
+            let data = anchor_data("swap", (is_mint0, amount_in, min_out_amount));
+            b.push(program_id, accounts, data);
+        }
+        Ix::SwapRoute {
+            sender,
+            mint_in,
+            mint_out,
+            hops,
+            amount_in,
+            min_out_amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = route_accounts(b, sender, mint_in, mint_out, hops, &program_id)?;
+            let data = anchor_data("swap_route", (amount_in, min_out_amount));
+            b.push(program_id, accounts, data);
+        }
+        Ix::FlashSwap {
+            sender,
+            pool,
+            amount0_out,
+            amount1_out,
+            callback_program,
+            callback_data,
+            callback_accounts,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = flash_swap_accounts(
+                b,
+                sender,
+                pool,
+                callback_program,
+                callback_accounts,
+                &program_id,
+                true,
+            )?;
+            let callback_data = hex::decode(callback_data)?;
+            let data = anchor_data("flash_swap", (amount0_out, amount1_out, callback_data));
+            b.push(program_id, accounts, data);
+        }
+        Ix::Observe { mint0, mint1 } => {
+            let accounts = vec![readonly(pool_pda(mint0, mint1, &program_id))];
+            b.push(program_id, accounts, anchor_data("observe", ()));
+        }
+        Ix::SetProtocolFee {
+            admin,
+            mint0,
+            mint1,
+            protocol_fee_share,
+            treasury,
+        } => {
+            let admin = b.signer(admin)?;
+            let accounts = vec![
+                readonly_signer(admin),
+                writable(pool_pda(mint0, mint1, &program_id)),
+            ];
+            let data = anchor_data("set_protocol_fee", (protocol_fee_share, treasury));
+            b.push(program_id, accounts, data);
+        }
+        Ix::CollectProtocolFees {
+            admin,
+            pool,
+            treasury,
+        } => {
+            let admin = b.signer(admin)?;
+            let mut accounts = vec![
+                readonly_signer(admin),
+                readonly(pool.mint0),
+                readonly(pool.mint1),
+                writable(amm_pda(pool, &program_id)),
+            ];
+            accounts.extend(collect_accounts(b, pool, treasury)?);
+            let data = anchor_data("collect_protocol_fees", ());
+            b.push(program_id, accounts, data);
+        }
+    }
+    Ok(())
+}
+
+// Accounts of the swap_route instruction, the same in both flavors: the hops
+// are passed after the accounts of the sender
+fn route_accounts(
+    b: &Builder,
+    sender: Pubkey,
+    mint_in: &Pubkey,
+    mint_out: &Pubkey,
+    hops: &[Hop],
+    program_id: &Pubkey,
+) -> Result<Vec<AccountMeta>> {
+    let mut accounts = vec![
+        readonly_signer(sender),
+        writable(b.associated_token_address(&sender, mint_in)?),
+        writable(b.associated_token_address(&sender, mint_out)?),
+        readonly(b.token_program()?),
+    ];
+    for hop in hops {
+        accounts.push(writable(pool_pda(&hop.mint0, &hop.mint1, program_id)));
+        accounts.push(writable(hop.pool_token_account_in));
+        accounts.push(writable(hop.pool_token_account_out));
+    }
+    Ok(accounts)
+}
+
+// The accounts of the flash swap in the two flavors, which are in a different
+// order, followed by the accounts of the callback
+fn flash_swap_accounts(
+    b: &Builder,
+    sender: Pubkey,
+    pool: &Pool,
+    callback_program: &Pubkey,
+    callback_accounts: &[CallbackAccount],
+    program_id: &Pubkey,
+    anchor: bool,
+) -> Result<Vec<AccountMeta>> {
+    let borrowers_token_accounts = [
+        writable(b.associated_token_address(&sender, &pool.mint0)?),
+        writable(b.associated_token_address(&sender, &pool.mint1)?),
+    ];
+    let pool_token_accounts = [
+        writable(pool.pool_token_account0),
+        writable(pool.pool_token_account1),
+    ];
+    let mut accounts = vec![readonly_signer(sender)];
+    if anchor {
+        accounts.extend([readonly(pool.mint0), readonly(pool.mint1)]);
+        accounts.push(writable(amm_pda(pool, program_id)));
+        accounts.extend(borrowers_token_accounts);
+        accounts.extend(pool_token_accounts);
+    } else {
+        accounts.push(writable(amm_pda(pool, program_id)));
+        accounts.extend(pool_token_accounts);
+        accounts.extend(borrowers_token_accounts);
+    }
+    accounts.push(readonly(*callback_program));
+    accounts.push(readonly(b.token_program()?));
+    accounts.extend(callback_accounts.iter().map(|account| account.0.clone()));
+    Ok(accounts)
+}
+
+// The pool and treasury token accounts of collect_protocol_fees, after the
+// AMM account in both flavors
+fn collect_accounts(b: &Builder, pool: &Pool, treasury: &Pubkey) -> Result<Vec<AccountMeta>> {
+    Ok(vec![
+        writable(pool.pool_token_account0),
+        writable(pool.pool_token_account1),
+        writable(b.associated_token_address(treasury, &pool.mint0)?),
+        writable(b.associated_token_address(treasury, &pool.mint1)?),
+        readonly(b.token_program()?),
+    ])
+}
+
+// Accounts shared by the raw deposit, redeem and swap instructions
+fn raw_accounts(
+    b: &Builder,
+    sender: Pubkey,
+    pool: &Pool,
+    program_id: &Pubkey,
+) -> Result<Vec<AccountMeta>> {
+    Ok(vec![
+        readonly_signer(sender),
+        writable(amm_pda(pool, program_id)),
+        writable(pool.pool_token_account0),
+        writable(pool.pool_token_account1),
+        writable(b.associated_token_address(&sender, &pool.mint0)?),
+        writable(b.associated_token_address(&sender, &pool.mint1)?),
+        readonly(b.token_program()?),
+    ])
+}
+
+// The raw program reads the direction of a swap or a zap as a u64, 0 meaning
+// mint0
+fn direction(is_mint0: bool) -> u64 {
+    if is_mint0 {
+        0
+    } else {
+        1
+    }
+}
+
+fn raw_rust(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::InitializeRegistry { payer } => {
+            let payer = b.signer(payer)?;
+            let accounts = vec![
+                writable_signer(payer),
+                writable(registry_pda(&program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(7, &[]));
+        }
+        Ix::Initialize {
+            initializer,
+            pool,
+            fee_bps,
+            curve,
+            amp,
+            pool_count,
+        } => {
+            check_mint_order(pool)?;
+            let curve = curve_type(*curve, *amp)?;
+            let initializer = b.signer(initializer)?;
+            let accounts = vec![
+                writable_signer(initializer),
+                writable(amm_pda(pool, &program_id)),
+                readonly(pool.mint0),
+                readonly(pool.mint1),
+                system_program(),
+                readonly(b.token_program()?),
+                writable(pool.pool_token_account0),
+                writable(pool.pool_token_account1),
+                writable(lp_mint_pda(pool, &program_id)),
+                writable(registry_pda(&program_id)),
+                writable(pool_entry_pda(*pool_count, &program_id)),
+            ];
+            // The curve is Borsh encoded, as in the Anchor program
+            let data = raw_data(0, &[&fee_bps.to_le_bytes(), &borsh::to_vec(&curve)?]);
+            b.push(program_id, accounts, data);
+        }
+        Ix::Deposit {
+            sender,
+            pool,
+            amount0_desired,
+            amount1_desired,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            // The raw program mints the LP tokens to an existing token account
+            let lp_mint = lp_mint_pda(pool, &program_id);
+            let lp_token_account = b.create_associated_token_account(&sender, &sender, &lp_mint)?;
+            let mut accounts = raw_accounts(b, sender, pool, &program_id)?;
+            accounts.push(writable(lp_mint));
+            accounts.push(writable(lp_token_account));
+            // The deadline is Borsh encoded, as in the Anchor program
+            let data = raw_data(
+                1,
+                &[
+                    &amount0_desired.to_le_bytes(),
+                    &amount1_desired.to_le_bytes(),
+                    &amount0_min.to_le_bytes(),
+                    &amount1_min.to_le_bytes(),
+                    &borsh::to_vec(&deadline)?,
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::Redeem {
+            sender,
+            pool,
+            amount,
+            amount0_min,
+            amount1_min,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let lp_mint = lp_mint_pda(pool, &program_id);
+            let mut accounts = raw_accounts(b, sender, pool, &program_id)?;
+            accounts.push(writable(lp_mint));
+            accounts.push(writable(b.associated_token_address(&sender, &lp_mint)?));
+            let data = raw_data(
+                2,
+                &[
+                    &amount.to_le_bytes(),
+                    &amount0_min.to_le_bytes(),
+                    &amount1_min.to_le_bytes(),
+                    &borsh::to_vec(&deadline)?,
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::ZapIn {
+            sender,
+            pool,
+            is_mint0,
+            amount_in,
+            min_liquidity,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let lp_mint = lp_mint_pda(pool, &program_id);
+            let lp_token_account = b.create_associated_token_account(&sender, &sender, &lp_mint)?;
+            let mut accounts = raw_accounts(b, sender, pool, &program_id)?;
+            accounts.push(writable(lp_mint));
+            accounts.push(writable(lp_token_account));
+            let data = raw_data(
+                10,
+                &[
+                    &direction(*is_mint0).to_le_bytes(),
+                    &amount_in.to_le_bytes(),
+                    &min_liquidity.to_le_bytes(),
+                    &borsh::to_vec(&deadline)?,
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::ZapOut {
+            sender,
+            pool,
+            is_mint0,
+            amount,
+            min_out_amount,
+            deadline,
+        } => {
+            let sender = b.signer(sender)?;
+            let lp_mint = lp_mint_pda(pool, &program_id);
+            let mut accounts = raw_accounts(b, sender, pool, &program_id)?;
+            accounts.push(writable(lp_mint));
+            accounts.push(writable(b.associated_token_address(&sender, &lp_mint)?));
+            let data = raw_data(
+                11,
+                &[
+                    &direction(*is_mint0).to_le_bytes(),
+                    &amount.to_le_bytes(),
+                    &min_out_amount.to_le_bytes(),
+                    &borsh::to_vec(&deadline)?,
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::Swap {
+            sender,
+            pool,
+            is_mint0,
+            amount_in,
+            min_out_amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = raw_accounts(b, sender, pool, &program_id)?;
+            let data = raw_data(
+                3,
+                &[
+                    &direction(*is_mint0).to_le_bytes(),
+                    &amount_in.to_le_bytes(),
+                    &min_out_amount.to_le_bytes(),
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::SwapRoute {
+            sender,
+            mint_in,
+            mint_out,
+            hops,
+            amount_in,
+            min_out_amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = route_accounts(b, sender, mint_in, mint_out, hops, &program_id)?;
+            let data = raw_data(
+                5,
+                &[&amount_in.to_le_bytes(), &min_out_amount.to_le_bytes()],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::FlashSwap {
+            sender,
+            pool,
+            amount0_out,
+            amount1_out,
+            callback_program,
+            callback_data,
+            callback_accounts,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = flash_swap_accounts(
+                b,
+                sender,
+                pool,
+                callback_program,
+                callback_accounts,
+                &program_id,
+                false,
+            )?;
+            // The callback data follows the output amounts
+            let data = raw_data(
+                6,
+                &[
+                    &amount0_out.to_le_bytes(),
+                    &amount1_out.to_le_bytes(),
+                    &hex::decode(callback_data)?,
+                ],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::Observe { mint0, mint1 } => {
+            let accounts = vec![readonly(pool_pda(mint0, mint1, &program_id))];
+            b.push(program_id, accounts, raw_data(4, &[]));
+        }
+        Ix::SetProtocolFee {
+            admin,
+            mint0,
+            mint1,
+            protocol_fee_share,
+            treasury,
+        } => {
+            let admin = b.signer(admin)?;
+            let accounts = vec![
+                readonly_signer(admin),
+                writable(pool_pda(mint0, mint1, &program_id)),
+            ];
+            let data = raw_data(8, &[&protocol_fee_share.to_le_bytes(), treasury.as_ref()]);
+            b.push(program_id, accounts, data);
+        }
+        Ix::CollectProtocolFees {
+            admin,
+            pool,
+            treasury,
+        } => {
+            let admin = b.signer(admin)?;
+            let mut accounts = vec![readonly_signer(admin), writable(amm_pda(pool, &program_id))];
+            accounts.extend(collect_accounts(b, pool, treasury)?);
+            b.push(program_id, accounts, raw_data(9, &[]));
+        }
+    }
+    Ok(())
+}
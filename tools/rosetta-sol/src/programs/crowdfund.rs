@@ -0,0 +1,240 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+// Sizes of the state accounts created by the client for the raw program
+const RAW_CAMPAIGN_LEN: usize = 32 + 8 + 8;
+const RAW_DONATION_LEN: usize = 32 + 32 + 8;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// Create a campaign named `campaign_name`
+    Initialize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        campaign_name: String,
+        #[arg(long)]
+        end_donate_slot: u64,
+        #[arg(long)]
+        goal: u64,
+    },
+    /// Donate to the campaign
+    Donate {
+        #[arg(long)]
+        donor: PathBuf,
+        #[arg(long)]
+        campaign_name: String,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the deadline, the owner withdraws the donations
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        campaign_name: String,
+    },
+    /// After the deadline, a donor reclaims the donations
+    Reclaim {
+        #[arg(long)]
+        donor: PathBuf,
+        #[arg(long)]
+        campaign_name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// Create the campaign account and initialize it
+    CreateCampaign {
+        #[arg(long)]
+        owner: PathBuf,
+        /// Keypair of the new campaign account
+        #[arg(long)]
+        campaign: PathBuf,
+        #[arg(long)]
+        end_donate_slot: u64,
+        #[arg(long)]
+        goal: u64,
+    },
+    /// Create a donation account holding the amount, and donate it
+    Donate {
+        #[arg(long)]
+        donor: PathBuf,
+        #[arg(long)]
+        campaign: Pubkey,
+        /// Keypair of the new donation account
+        #[arg(long)]
+        donation: PathBuf,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the deadline, the owner withdraws the donations
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        campaign: Pubkey,
+    },
+    /// After the deadline, a donor reclaims the donation
+    Reclaim {
+        #[arg(long)]
+        donor: PathBuf,
+        #[arg(long)]
+        campaign: Pubkey,
+        #[arg(long)]
+        donation: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("crowdfund")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn campaign_pda(campaign_name: &str, program_id: &Pubkey) -> Pubkey {
+    pda(&[campaign_name.as_ref()], program_id)
+}
+
+fn deposit_pda(campaign_name: &str, donor: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(
+        &[b"deposit", campaign_name.as_ref(), donor.as_ref()],
+        program_id,
+    )
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize {
+            owner,
+            campaign_name,
+            end_donate_slot,
+            goal,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(campaign_pda(campaign_name, &program_id)),
+                system_program(),
+            ];
+            let data = anchor_data("initialize", (campaign_name, end_donate_slot, goal));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::Donate {
+            donor,
+            campaign_name,
+            amount,
+        } => {
+            let donor = b.signer(donor)?;
+            let accounts = vec![
+                writable_signer(donor),
+                writable(campaign_pda(campaign_name, &program_id)),
+                writable(deposit_pda(campaign_name, &donor, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("donate", (campaign_name, amount)));
+        }
+        Anchor::Withdraw {
+            owner,
+            campaign_name,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(campaign_pda(campaign_name, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw", (campaign_name,)));
+        }
+        Anchor::Reclaim {
+            donor,
+            campaign_name,
+        } => {
+            let donor = b.signer(donor)?;
+            let accounts = vec![
+                writable_signer(donor),
+                writable(campaign_pda(campaign_name, &program_id)),
+                writable(deposit_pda(campaign_name, &donor, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("reclaim", (campaign_name,)));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::CreateCampaign {
+            owner,
+            campaign,
+            end_donate_slot,
+            goal,
+        } => {
+            let owner = b.signer(owner)?;
+            let campaign = b.create_account(&owner, campaign, RAW_CAMPAIGN_LEN, 0, &program_id)?;
+            let accounts = vec![readonly_signer(owner), writable(campaign)];
+            let data = raw_data(
+                0,
+                &[owner.as_ref(), &end_donate_slot.to_le_bytes(), &goal.to_le_bytes()],
+            );
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Donate {
+            donor,
+            campaign,
+            donation,
+            amount,
+        } => {
+            let donor = b.signer(donor)?;
+            let donation =
+                b.create_account(&donor, donation, RAW_DONATION_LEN, *amount, &program_id)?;
+            let accounts = vec![
+                readonly_signer(donor),
+                writable(*campaign),
+                writable(donation),
+            ];
+            let data = raw_data(1, &[donor.as_ref(), campaign.as_ref(), &amount.to_le_bytes()]);
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Withdraw { owner, campaign } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![writable_signer(owner), writable(*campaign)];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+        RawRust::Reclaim {
+            donor,
+            campaign,
+            donation,
+        } => {
+            let donor = b.signer(donor)?;
+            let accounts = vec![
+                writable_signer(donor),
+                writable(*campaign),
+                writable(*donation),
+            ];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+    }
+    Ok(())
+}
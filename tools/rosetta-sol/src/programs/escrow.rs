@@ -0,0 +1,232 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+// Size of the state account created by the client for the raw program
+const RAW_ESCROW_LEN: usize = 32 + 32 + 8 + 1;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The seller creates the escrow named `escrow_name`
+    Initialize {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        buyer: Pubkey,
+        #[arg(long)]
+        escrow_name: String,
+        /// Required payment, in lamports
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The buyer deposits the required payment
+    Deposit {
+        #[arg(long)]
+        buyer: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        escrow_name: String,
+    },
+    /// The buyer pays the seller
+    Pay {
+        #[arg(long)]
+        buyer: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        escrow_name: String,
+    },
+    /// The seller refunds the buyer
+    Refund {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        buyer: Pubkey,
+        #[arg(long)]
+        escrow_name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The seller creates the state account and initializes the escrow
+    Initialize {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        buyer: Pubkey,
+        /// Keypair of the new state account
+        #[arg(long)]
+        state: PathBuf,
+        /// Required payment, in lamports
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The buyer transfers the required payment to the state account
+    Deposit {
+        #[arg(long)]
+        buyer: PathBuf,
+        #[arg(long)]
+        state: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The buyer pays the seller
+    Pay {
+        #[arg(long)]
+        buyer: PathBuf,
+        #[arg(long)]
+        seller: Pubkey,
+        #[arg(long)]
+        state: Pubkey,
+    },
+    /// The seller refunds the buyer
+    Refund {
+        #[arg(long)]
+        seller: PathBuf,
+        #[arg(long)]
+        buyer: Pubkey,
+        #[arg(long)]
+        state: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("escrow")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn escrow_pda(escrow_name: &str, seller: &Pubkey, buyer: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(
+        &[escrow_name.as_ref(), seller.as_ref(), buyer.as_ref()],
+        program_id,
+    )
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize {
+            seller,
+            buyer,
+            escrow_name,
+            amount,
+        } => {
+            let seller = b.signer(seller)?;
+            let accounts = vec![
+                writable_signer(seller),
+                readonly(*buyer),
+                system_program(),
+                writable(escrow_pda(escrow_name, &seller, buyer, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("initialize", (amount, escrow_name)));
+        }
+        Anchor::Deposit {
+            buyer,
+            seller,
+            escrow_name,
+        } => {
+            let buyer = b.signer(buyer)?;
+            let accounts = vec![
+                writable_signer(buyer),
+                readonly(*seller),
+                writable(escrow_pda(escrow_name, seller, &buyer, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("deposit", (escrow_name,)));
+        }
+        Anchor::Pay {
+            buyer,
+            seller,
+            escrow_name,
+        } => {
+            let buyer = b.signer(buyer)?;
+            let accounts = vec![
+                writable_signer(buyer),
+                writable(*seller),
+                writable(escrow_pda(escrow_name, seller, &buyer, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("pay", (escrow_name,)));
+        }
+        Anchor::Refund {
+            seller,
+            buyer,
+            escrow_name,
+        } => {
+            let seller = b.signer(seller)?;
+            let accounts = vec![
+                writable_signer(seller),
+                writable(*buyer),
+                writable(escrow_pda(escrow_name, &seller, buyer, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("refund", (escrow_name,)));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Initialize {
+            seller,
+            buyer,
+            state,
+            amount,
+        } => {
+            let seller = b.signer(seller)?;
+            let state = b.create_account(&seller, state, RAW_ESCROW_LEN, 0, &program_id)?;
+            let accounts = vec![readonly_signer(seller), readonly(*buyer), writable(state)];
+            b.push(program_id, accounts, raw_data(0, &[&amount.to_le_bytes()]));
+        }
+        RawRust::Deposit {
+            buyer,
+            state,
+            amount,
+        } => {
+            let buyer = b.signer(buyer)?;
+            b.transfer(&buyer, state, *amount);
+            let accounts = vec![readonly_signer(buyer), writable(*state)];
+            b.push(program_id, accounts, raw_data(1, &[]));
+        }
+        RawRust::Pay {
+            buyer,
+            seller,
+            state,
+        } => {
+            let buyer = b.signer(buyer)?;
+            let accounts = vec![readonly_signer(buyer), writable(*seller), writable(*state)];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+        RawRust::Refund {
+            seller,
+            buyer,
+            state,
+        } => {
+            let seller = b.signer(seller)?;
+            let accounts = vec![writable_signer(seller), writable(*buyer), writable(*state)];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+    }
+    Ok(())
+}
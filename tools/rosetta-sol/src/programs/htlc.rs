@@ -0,0 +1,199 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::{keccak, pubkey::Pubkey};
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The owner commits to the keccak hash of the secret and deposits the amount
+    Initialize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        verifier: Pubkey,
+        #[arg(long)]
+        secret: String,
+        /// Number of slots before the deadline
+        #[arg(long)]
+        delay: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner reveals the secret and gets the deposit back
+    Reveal {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        verifier: Pubkey,
+        #[arg(long)]
+        secret: String,
+    },
+    /// After the deadline, the verifier gets the deposit
+    Timeout {
+        #[arg(long)]
+        verifier: PathBuf,
+        #[arg(long)]
+        owner: Pubkey,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The owner commits to the keccak hash of the secret and deposits the amount
+    Initialize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        verifier: Pubkey,
+        #[arg(long)]
+        secret: String,
+        /// Number of slots before the deadline
+        #[arg(long)]
+        delay: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner reveals the secret and gets the deposit back
+    Reveal {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        verifier: Pubkey,
+        #[arg(long)]
+        secret: String,
+    },
+    /// After the deadline, the verifier gets the deposit (the raw program
+    /// requires the owner's signature)
+    Timeout {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        verifier: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("htlc")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn htlc_pda(owner: &Pubkey, verifier: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[owner.as_ref(), verifier.as_ref()], program_id)
+}
+
+fn hashed_secret(secret: &str) -> [u8; 32] {
+    keccak::hash(secret.as_bytes()).to_bytes()
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize {
+            owner,
+            verifier,
+            secret,
+            delay,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                readonly(*verifier),
+                system_program(),
+                writable(htlc_pda(&owner, verifier, &program_id)),
+            ];
+            let data = anchor_data("initialize", (hashed_secret(secret), delay, amount));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::Reveal {
+            owner,
+            verifier,
+            secret,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                readonly(*verifier),
+                writable(htlc_pda(&owner, verifier, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("reveal", (secret,)));
+        }
+        Anchor::Timeout { verifier, owner } => {
+            let verifier = b.signer(verifier)?;
+            let accounts = vec![
+                writable_signer(verifier),
+                readonly(*owner),
+                writable(htlc_pda(owner, &verifier, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("timeout", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Initialize {
+            owner,
+            verifier,
+            secret,
+            delay,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                readonly(*verifier),
+                writable(htlc_pda(&owner, verifier, &program_id)),
+                system_program(),
+            ];
+            let data = raw_data(
+                0,
+                &[&hashed_secret(secret), &delay.to_le_bytes(), &amount.to_le_bytes()],
+            );
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Reveal {
+            owner,
+            verifier,
+            secret,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(htlc_pda(&owner, verifier, &program_id)),
+                readonly(*verifier),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[secret.as_bytes()]));
+        }
+        RawRust::Timeout { owner, verifier } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable(htlc_pda(&owner, verifier, &program_id)),
+                readonly_signer(owner),
+                writable(*verifier),
+            ];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+    }
+    Ok(())
+}
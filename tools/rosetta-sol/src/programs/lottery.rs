@@ -0,0 +1,186 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// Both players join the lottery, committing to their secrets
+    Join {
+        #[arg(long)]
+        player1: PathBuf,
+        #[arg(long)]
+        player2: PathBuf,
+        /// Keccak hash of the secret of player1, in hex
+        #[arg(long, value_parser = parse_hash32)]
+        hashlock1: [u8; 32],
+        /// Keccak hash of the secret of player2, in hex
+        #[arg(long, value_parser = parse_hash32)]
+        hashlock2: [u8; 32],
+        /// Number of slots before the deadline to reveal
+        #[arg(long)]
+        delay: u64,
+        /// Bet of each player, in lamports
+        #[arg(long)]
+        amount: u64,
+    },
+    /// Player1 reveals the secret
+    RevealP1 {
+        #[arg(long)]
+        player1: PathBuf,
+        #[arg(long)]
+        player2: Pubkey,
+        #[arg(long)]
+        secret: String,
+    },
+    /// Player2 reveals the secret, and the winner is paid
+    RevealP2 {
+        #[arg(long)]
+        player1: Pubkey,
+        #[arg(long)]
+        player2: PathBuf,
+        #[arg(long)]
+        secret: String,
+    },
+    /// Player2 redeems the pot if player1 did not reveal
+    RedeemIfP1NoReveal {
+        #[arg(long)]
+        player1: Pubkey,
+        #[arg(long)]
+        player2: PathBuf,
+    },
+    /// Player1 redeems the pot if player2 did not reveal
+    RedeemIfP2NoReveal {
+        #[arg(long)]
+        player1: PathBuf,
+        #[arg(long)]
+        player2: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("lottery")?;
+                build(ix, b, program_id, Flavor::Anchor)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                build(ix, b, program_id, Flavor::RawRust)
+            }
+        }
+    }
+}
+
+// Both programs take the same accounts, in the same order
+enum Flavor {
+    Anchor,
+    RawRust,
+}
+
+fn lottery_pda(player1: &Pubkey, player2: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[player1.as_ref(), player2.as_ref()], program_id)
+}
+
+fn build(ix: &Ix, b: &mut Builder, program_id: Pubkey, flavor: Flavor) -> Result<()> {
+    let (accounts, data) = match ix {
+        Ix::Join {
+            player1,
+            player2,
+            hashlock1,
+            hashlock2,
+            delay,
+            amount,
+        } => {
+            let player1 = b.signer(player1)?;
+            let player2 = b.signer(player2)?;
+            let accounts = vec![
+                writable_signer(player1),
+                writable_signer(player2),
+                writable(lottery_pda(&player1, &player2, &program_id)),
+                system_program(),
+            ];
+            let data = match flavor {
+                Flavor::Anchor => anchor_data("join", (hashlock1, hashlock2, delay, amount)),
+                Flavor::RawRust => raw_data(
+                    0,
+                    &[hashlock1, hashlock2, &delay.to_le_bytes(), &amount.to_le_bytes()],
+                ),
+            };
+            (accounts, data)
+        }
+        Ix::RevealP1 {
+            player1,
+            player2,
+            secret,
+        } => {
+            let player1 = b.signer(player1)?;
+            let accounts = vec![
+                writable_signer(player1),
+                readonly(*player2),
+                writable(lottery_pda(&player1, player2, &program_id)),
+            ];
+            let data = match flavor {
+                Flavor::Anchor => anchor_data("reveal_p1", (secret,)),
+                Flavor::RawRust => raw_data(1, &[secret.as_bytes()]),
+            };
+            (accounts, data)
+        }
+        Ix::RevealP2 {
+            player1,
+            player2,
+            secret,
+        } => {
+            let player2 = b.signer(player2)?;
+            let accounts = vec![
+                writable(*player1),
+                writable_signer(player2),
+                writable(lottery_pda(player1, &player2, &program_id)),
+            ];
+            let data = match flavor {
+                Flavor::Anchor => anchor_data("reveal_p2", (secret,)),
+                Flavor::RawRust => raw_data(2, &[secret.as_bytes()]),
+            };
+            (accounts, data)
+        }
+        Ix::RedeemIfP1NoReveal { player1, player2 } => {
+            let player2 = b.signer(player2)?;
+            let accounts = vec![
+                readonly(*player1),
+                writable_signer(player2),
+                writable(lottery_pda(player1, &player2, &program_id)),
+            ];
+            let data = match flavor {
+                Flavor::Anchor => anchor_data("redeem_if_p1_no_reveal", ()),
+                Flavor::RawRust => raw_data(3, &[]),
+            };
+            (accounts, data)
+        }
+        Ix::RedeemIfP2NoReveal { player1, player2 } => {
+            let player1 = b.signer(player1)?;
+            let accounts = vec![
+                writable_signer(player1),
+                readonly(*player2),
+                writable(lottery_pda(&player1, player2, &program_id)),
+            ];
+            let data = match flavor {
+                Flavor::Anchor => anchor_data("redeem_if_p2_no_reveal", ()),
+                Flavor::RawRust => raw_data(4, &[]),
+            };
+            (accounts, data)
+        }
+    };
+    b.push(program_id, accounts, data);
+    Ok(())
+}
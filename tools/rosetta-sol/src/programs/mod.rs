@@ -0,0 +1,212 @@
+//! One module per use case. Each module exposes a `Command` with an
+//! `anchor` and a `raw-rust` subcommand, which in turn have a subcommand for
+//! every instruction of the program, with the same accounts, seeds and
+//! instruction data as the program expects.
+
+pub mod auction;
+pub mod bet;
+pub mod constant_product_amm;
+pub mod crowdfund;
+pub mod escrow;
+pub mod htlc;
+pub mod lottery;
+pub mod payment_splitter;
+pub mod pricebet;
+pub mod simple_transfer;
+pub mod simple_wallet;
+pub mod storage;
+pub mod token_transfer;
+pub mod vault;
+pub mod vesting;
+
+use anyhow::{anyhow, Result};
+use borsh::BorshSerialize;
+use solana_sdk::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{read_keypair_file, Keypair, Signer},
+};
+use solana_system_interface::instruction as system_instruction;
+use std::path::Path;
+
+use crate::cluster::Profile;
+
+/// Collects the instructions of a transaction and the keypairs signing it.
+pub struct Builder {
+    program_id: Option<Pubkey>,
+    profile: Profile,
+    instructions: Vec<Instruction>,
+    signers: Vec<Keypair>,
+}
+
+impl Builder {
+    pub fn new(program_id: Option<Pubkey>, profile: Profile) -> Self {
+        Builder {
+            program_id,
+            profile,
+            instructions: vec![],
+            signers: vec![],
+        }
+    }
+
+    /// The program address given with `--program-id`, or the declared ID of
+    /// the Anchor program on the cluster.
+    pub fn anchor_program(&self, usecase: &str) -> Result<Pubkey> {
+        match self.program_id {
+            Some(program_id) => Ok(program_id),
+            None => self.address(&format!("programs.{}", usecase)),
+        }
+    }
+
+    /// The program address given with `--program-id`.
+    pub fn raw_program(&self) -> Result<Pubkey> {
+        self.program_id
+            .ok_or_else(|| anyhow!("--program-id is required for the raw Rust programs"))
+    }
+
+    /// A cluster-dependent address, from the profile of the cluster.
+    pub fn address(&self, key: &str) -> Result<Pubkey> {
+        self.profile.address(key)
+    }
+
+    pub fn token_program(&self) -> Result<Pubkey> {
+        self.address("spl.token")
+    }
+
+    pub fn associated_token_program(&self) -> Result<Pubkey> {
+        self.address("spl.associated_token")
+    }
+
+    /// The associated token account of `wallet` for `mint`.
+    pub fn associated_token_address(&self, wallet: &Pubkey, mint: &Pubkey) -> Result<Pubkey> {
+        let token_program = self.token_program()?;
+        Ok(pda(
+            &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+            &self.associated_token_program()?,
+        ))
+    }
+
+    /// Create the associated token account of `wallet` for `mint`, unless it
+    /// already exists, for the raw programs which expect it to exist.
+    pub fn create_associated_token_account(
+        &mut self,
+        payer: &Pubkey,
+        wallet: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<Pubkey> {
+        let token_account = self.associated_token_address(wallet, mint)?;
+        let accounts = vec![
+            writable_signer(*payer),
+            writable(token_account),
+            readonly(*wallet),
+            readonly(*mint),
+            system_program(),
+            readonly(self.token_program()?),
+        ];
+        // Instruction 1 of the associated token program is CreateIdempotent
+        self.push(self.associated_token_program()?, accounts, vec![1]);
+        Ok(token_account)
+    }
+
+    /// Load a keypair file as a signer of the transaction.
+    pub fn signer(&mut self, path: &Path) -> Result<Pubkey> {
+        let keypair = read_keypair_file(path)
+            .map_err(|e| anyhow!("cannot read keypair {}: {}", path.display(), e))?;
+        let pubkey = keypair.pubkey();
+        if !self.signers.iter().any(|s| s.pubkey() == pubkey) {
+            self.signers.push(keypair);
+        }
+        Ok(pubkey)
+    }
+
+    /// Create a new account owned by `owner`, funded with the rent-exempt
+    /// minimum for `space` bytes plus `extra_lamports`. Used by the raw
+    /// programs whose state accounts are created by the client.
+    pub fn create_account(
+        &mut self,
+        payer: &Pubkey,
+        new_account: &Path,
+        space: usize,
+        extra_lamports: u64,
+        owner: &Pubkey,
+    ) -> Result<Pubkey> {
+        let new_account = self.signer(new_account)?;
+        let lamports = Rent::default().minimum_balance(space) + extra_lamports;
+        self.instructions.push(system_instruction::create_account(
+            payer,
+            &new_account,
+            lamports,
+            space as u64,
+            owner,
+        ));
+        Ok(new_account)
+    }
+
+    /// Transfer lamports with the system program, for the raw programs which
+    /// expect the client to fund an account before calling them.
+    pub fn transfer(&mut self, from: &Pubkey, to: &Pubkey, lamports: u64) {
+        self.instructions
+            .push(system_instruction::transfer(from, to, lamports));
+    }
+
+    pub fn push(&mut self, program_id: Pubkey, accounts: Vec<AccountMeta>, data: Vec<u8>) {
+        self.instructions
+            .push(Instruction::new_with_bytes(program_id, &data, accounts));
+    }
+
+    pub fn finish(self) -> (Vec<Instruction>, Vec<Keypair>) {
+        (self.instructions, self.signers)
+    }
+}
+
+pub fn pda(seeds: &[&[u8]], program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+/// Instruction data of an Anchor instruction: the 8 bytes discriminator of
+/// `global:<name>`, followed by the Borsh-serialized arguments.
+pub fn anchor_data<T: BorshSerialize>(name: &str, args: T) -> Vec<u8> {
+    let mut data = hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+    args.serialize(&mut data).unwrap();
+    data
+}
+
+/// Instruction data of a raw program: the instruction tag followed by the
+/// given payload.
+pub fn raw_data(tag: u8, payload: &[&[u8]]) -> Vec<u8> {
+    let mut data = vec![tag];
+    for part in payload {
+        data.extend_from_slice(part);
+    }
+    data
+}
+
+/// Parse a 32 bytes hash given in hex, e.g. the hashlocks of the lottery.
+pub fn parse_hash32(s: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s)?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("expected 32 bytes in hex, got {} characters", s.len()))
+}
+
+pub fn writable(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new(pubkey, false)
+}
+
+pub fn readonly(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new_readonly(pubkey, false)
+}
+
+pub fn writable_signer(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new(pubkey, true)
+}
+
+pub fn readonly_signer(pubkey: Pubkey) -> AccountMeta {
+    AccountMeta::new_readonly(pubkey, true)
+}
+
+pub fn system_program() -> AccountMeta {
+    readonly(solana_sdk::system_program::id())
+}
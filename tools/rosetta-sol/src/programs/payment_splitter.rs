@@ -0,0 +1,128 @@
+use anyhow::{ensure, Result};
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use super::*;
+
+// Seed of the state PDA of the raw program
+const RAW_PS_SEED: &[u8] = b"PS_SEED";
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// Deposit the lamports to split among the payees
+    Initialize {
+        #[arg(long)]
+        initializer: PathBuf,
+        #[arg(long)]
+        lamports: u64,
+        /// Payee, repeated once per payee
+        #[arg(long = "payee", required = true)]
+        payees: Vec<Pubkey>,
+        /// Shares of the payee given at the same position
+        #[arg(long = "shares", required = true)]
+        shares: Vec<u64>,
+    },
+    /// A payee releases the lamports due
+    Release {
+        #[arg(long)]
+        payee: PathBuf,
+        #[arg(long)]
+        initializer: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("payment_splitter")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn anchor_ps_pda(initializer: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"payment_splitter", initializer.as_ref()], program_id)
+}
+
+fn anchor(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Initialize {
+            initializer,
+            lamports,
+            payees,
+            shares,
+        } => {
+            let initializer = b.signer(initializer)?;
+            let mut accounts = vec![
+                writable_signer(initializer),
+                writable(anchor_ps_pda(&initializer, &program_id)),
+                system_program(),
+            ];
+            // The payees are passed as remaining accounts
+            accounts.extend(payees.iter().map(|payee| readonly(*payee)));
+            b.push(program_id, accounts, anchor_data("initialize", (lamports, shares)));
+        }
+        Ix::Release { payee, initializer } => {
+            let payee = b.signer(payee)?;
+            let accounts = vec![
+                writable_signer(payee),
+                writable(*initializer),
+                writable(anchor_ps_pda(initializer, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("release", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    let ps_pda = pda(&[RAW_PS_SEED], &program_id);
+    match ix {
+        Ix::Initialize {
+            initializer,
+            lamports,
+            payees,
+            shares,
+        } => {
+            ensure!(
+                payees.len() == shares.len(),
+                "expected one --shares for each --payee"
+            );
+            let initializer = b.signer(initializer)?;
+            let accounts = vec![
+                writable_signer(initializer),
+                writable(ps_pda),
+                system_program(),
+            ];
+            // The instruction data is the serialized PaymentSplitterInfo
+            let shares_map: BTreeMap<Pubkey, u64> =
+                payees.iter().copied().zip(shares.iter().copied()).collect();
+            let released_map: BTreeMap<Pubkey, u64> =
+                payees.iter().map(|payee| (*payee, 0)).collect();
+            let ps_info = borsh::to_vec(&(shares_map, released_map, lamports))?;
+            b.push(program_id, accounts, raw_data(0, &[&ps_info]));
+        }
+        Ix::Release { payee, .. } => {
+            let payee = b.signer(payee)?;
+            let accounts = vec![writable_signer(payee), writable(ps_pda)];
+            b.push(program_id, accounts, raw_data(1, &[]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// The owner creates the bet and deposits the wager
+    Init {
+        #[arg(long)]
+        owner: PathBuf,
+        /// Number of slots before the deadline
+        #[arg(long)]
+        delay: u64,
+        #[arg(long)]
+        wager: u64,
+        /// Exchange rate the player bets the price will exceed
+        #[arg(long)]
+        rate: u64,
+    },
+    /// A player joins the bet, depositing the same wager
+    Join {
+        #[arg(long)]
+        player: PathBuf,
+        #[arg(long)]
+        owner: Pubkey,
+    },
+    /// The player wins if the price read from the oracle exceeds the rate
+    Win {
+        #[arg(long)]
+        player: PathBuf,
+        #[arg(long)]
+        owner: Pubkey,
+        /// Price feed (default: the one accepted by the programs on the cluster)
+        #[arg(long)]
+        price_feed: Option<Pubkey>,
+    },
+    /// After the deadline, the owner takes the pot
+    Timeout {
+        #[arg(long)]
+        owner: PathBuf,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("pricebet")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn bet_pda(owner: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[owner.as_ref()], program_id)
+}
+
+fn price_feed_or_default(b: &Builder, price_feed: &Option<Pubkey>) -> Result<Pubkey> {
+    match price_feed {
+        Some(price_feed) => Ok(*price_feed),
+        None => b.address("pricebet.btc_usdc_feed"),
+    }
+}
+
+fn anchor(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Init {
+            owner,
+            delay,
+            wager,
+            rate,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(bet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("init", (delay, wager, rate)));
+        }
+        Ix::Join { player, owner } => {
+            let player = b.signer(player)?;
+            let accounts = vec![
+                writable_signer(player),
+                writable(*owner),
+                writable(bet_pda(owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("join", ()));
+        }
+        Ix::Win {
+            player,
+            owner,
+            price_feed,
+        } => {
+            let player = b.signer(player)?;
+            let price_feed = price_feed_or_default(b, price_feed)?;
+            let accounts = vec![
+                writable_signer(player),
+                readonly(*owner),
+                writable(bet_pda(owner, &program_id)),
+                readonly(price_feed),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("win", ()));
+        }
+        Ix::Timeout { owner } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(bet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("timeout", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Init {
+            owner,
+            delay,
+            wager,
+            rate,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(bet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            let data = raw_data(
+                0,
+                &[&delay.to_le_bytes(), &wager.to_le_bytes(), &rate.to_le_bytes()],
+            );
+            b.push(program_id, accounts, data);
+        }
+        Ix::Join { player, owner } => {
+            let player = b.signer(player)?;
+            let accounts = vec![
+                readonly(*owner),
+                writable_signer(player),
+                writable(bet_pda(owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[]));
+        }
+        Ix::Win {
+            player,
+            owner,
+            price_feed,
+        } => {
+            let player = b.signer(player)?;
+            let price_feed = price_feed_or_default(b, price_feed)?;
+            let accounts = vec![
+                readonly(*owner),
+                writable_signer(player),
+                readonly(price_feed),
+                writable(bet_pda(owner, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+        Ix::Timeout { owner } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(bet_pda(&owner, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+    }
+    Ok(())
+}
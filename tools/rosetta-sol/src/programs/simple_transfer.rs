@@ -0,0 +1,118 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Ix),
+    #[command(subcommand)]
+    RawRust(Ix),
+}
+
+#[derive(Subcommand)]
+pub enum Ix {
+    /// The sender deposits lamports for the recipient
+    Deposit {
+        #[arg(long)]
+        sender: PathBuf,
+        #[arg(long)]
+        recipient: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The recipient withdraws part of the deposit
+    Withdraw {
+        #[arg(long)]
+        recipient: PathBuf,
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("simple_transfer")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn anchor(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Deposit {
+            sender,
+            recipient,
+            amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = vec![
+                writable(pda(&[recipient.as_ref(), sender.as_ref()], &program_id)),
+                writable_signer(sender),
+                readonly(*recipient),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("deposit", (amount,)));
+        }
+        Ix::Withdraw {
+            recipient,
+            sender,
+            amount,
+        } => {
+            let recipient = b.signer(recipient)?;
+            let accounts = vec![
+                writable_signer(recipient),
+                writable(*sender),
+                writable(pda(&[recipient.as_ref(), sender.as_ref()], &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw", (amount,)));
+        }
+    }
+    Ok(())
+}
+
+// Unlike the Anchor program, the raw program seeds the PDA with the sender first
+fn raw_rust(ix: &Ix, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Ix::Deposit {
+            sender,
+            recipient,
+            amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = vec![
+                writable_signer(sender),
+                readonly(*recipient),
+                writable(pda(&[sender.as_ref(), recipient.as_ref()], &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(0, &[&amount.to_le_bytes()]));
+        }
+        Ix::Withdraw {
+            recipient,
+            sender,
+            amount,
+        } => {
+            let recipient = b.signer(recipient)?;
+            let accounts = vec![
+                writable(*sender),
+                writable_signer(recipient),
+                writable(pda(&[sender.as_ref(), recipient.as_ref()], &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[&amount.to_le_bytes()]));
+        }
+    }
+    Ok(())
+}
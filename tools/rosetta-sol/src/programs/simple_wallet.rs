@@ -0,0 +1,228 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The owner deposits lamports in the wallet
+    Deposit {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner creates a transaction identified by `transaction_seed`
+    CreateTransaction {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        transaction_seed: String,
+        #[arg(long)]
+        receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner executes a transaction
+    ExecuteTransaction {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        transaction_seed: String,
+        #[arg(long)]
+        receiver: Pubkey,
+    },
+    /// The owner withdraws lamports from the wallet
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The owner deposits lamports in the wallet
+    Deposit {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner creates a transaction
+    CreateTransaction {
+        #[arg(long)]
+        owner: PathBuf,
+        /// Number of transactions created so far, as counted by the wallet
+        #[arg(long)]
+        transaction_id: u64,
+        #[arg(long)]
+        receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The owner executes a transaction
+    ExecuteTransaction {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        transaction_id: u64,
+        #[arg(long)]
+        receiver: Pubkey,
+    },
+    /// The owner withdraws all the lamports of the wallet
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("simple_wallet")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn wallet_pda(owner: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[b"wallet", owner.as_ref()], program_id)
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Deposit { owner, amount } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("deposit", (amount,)));
+        }
+        Anchor::CreateTransaction {
+            owner,
+            transaction_seed,
+            receiver,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let wallet = wallet_pda(&owner, &program_id);
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet),
+                writable(pda(&[transaction_seed.as_ref(), wallet.as_ref()], &program_id)),
+                writable(*receiver),
+                system_program(),
+            ];
+            let data = anchor_data("create_transaction", (transaction_seed, amount));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::ExecuteTransaction {
+            owner,
+            transaction_seed,
+            receiver,
+        } => {
+            let owner = b.signer(owner)?;
+            let wallet = wallet_pda(&owner, &program_id);
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet),
+                writable(pda(&[transaction_seed.as_ref(), wallet.as_ref()], &program_id)),
+                writable(*receiver),
+                system_program(),
+            ];
+            let data = anchor_data("execute_transaction", (transaction_seed,));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::Withdraw { owner, amount } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw", (amount,)));
+        }
+    }
+    Ok(())
+}
+
+fn raw_transaction_pda(transaction_id: u64, owner: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(
+        &[format!("tx{}", transaction_id).as_bytes(), owner.as_ref()],
+        program_id,
+    )
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Deposit { owner, amount } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(0, &[&amount.to_le_bytes()]));
+        }
+        RawRust::CreateTransaction {
+            owner,
+            transaction_id,
+            receiver,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+                writable(raw_transaction_pda(*transaction_id, &owner, &program_id)),
+                system_program(),
+            ];
+            // The instruction data is the serialized UserTransaction
+            let data = raw_data(1, &[receiver.as_ref(), &amount.to_le_bytes(), &[0]]);
+            b.push(program_id, accounts, data);
+        }
+        RawRust::ExecuteTransaction {
+            owner,
+            transaction_id,
+            receiver,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+                writable(raw_transaction_pda(*transaction_id, &owner, &program_id)),
+                writable(*receiver),
+            ];
+            b.push(program_id, accounts, raw_data(2, &[&transaction_id.to_le_bytes()]));
+        }
+        RawRust::Withdraw { owner } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(wallet_pda(&owner, &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+    }
+    Ok(())
+}
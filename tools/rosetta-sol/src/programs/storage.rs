@@ -0,0 +1,133 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// Create the string and the bytes storage of the user
+    Initialize {
+        #[arg(long)]
+        user: PathBuf,
+    },
+    /// Store a string
+    StoreString {
+        #[arg(long)]
+        user: PathBuf,
+        #[arg(long)]
+        data: String,
+    },
+    /// Store a sequence of bytes, given in hex
+    StoreBytes {
+        #[arg(long)]
+        user: PathBuf,
+        #[arg(long, value_parser = parse_hex)]
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// Store a sequence of bytes, given in hex
+    StoreBytes {
+        #[arg(long)]
+        sender: PathBuf,
+        #[arg(long, value_parser = parse_hex)]
+        data: Vec<u8>,
+    },
+    /// Store a string
+    StoreString {
+        #[arg(long)]
+        sender: PathBuf,
+        #[arg(long)]
+        data: String,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("storage")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    Ok(hex::decode(s)?)
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize { user } => {
+            let user = b.signer(user)?;
+            let accounts = vec![
+                writable_signer(user),
+                system_program(),
+                writable(pda(&[b"storage_string", user.as_ref()], &program_id)),
+                writable(pda(&[b"storage_bytes", user.as_ref()], &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("initialize", ()));
+        }
+        Anchor::StoreString { user, data } => {
+            let user = b.signer(user)?;
+            let accounts = vec![
+                writable_signer(user),
+                system_program(),
+                writable(pda(&[b"storage_string", user.as_ref()], &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("store_string", (data,)));
+        }
+        Anchor::StoreBytes { user, data } => {
+            let user = b.signer(user)?;
+            let accounts = vec![
+                writable_signer(user),
+                system_program(),
+                writable(pda(&[b"storage_bytes", user.as_ref()], &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("store_bytes", (data,)));
+        }
+    }
+    Ok(())
+}
+
+// The raw program keeps a single storage per kind, not one per user
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::StoreBytes { sender, data } => {
+            let sender = b.signer(sender)?;
+            let accounts = vec![
+                writable_signer(sender),
+                writable(pda(&[b"storage_bytes"], &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(0, &[data.as_slice()]));
+        }
+        RawRust::StoreString { sender, data } => {
+            let sender = b.signer(sender)?;
+            let accounts = vec![
+                writable_signer(sender),
+                writable(pda(&[b"storage_string"], &program_id)),
+                system_program(),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[data.as_bytes()]));
+        }
+    }
+    Ok(())
+}
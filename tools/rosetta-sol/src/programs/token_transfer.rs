@@ -0,0 +1,189 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::{pubkey::Pubkey, sysvar};
+use std::path::PathBuf;
+
+use super::*;
+
+// Size of the state account created by the client for the raw program
+const RAW_DEPOSIT_LEN: usize = 32 + 32 + 32 + 8 + 1;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The sender hands the temporary token account over to the program
+    Deposit {
+        #[arg(long)]
+        sender: PathBuf,
+        #[arg(long)]
+        recipient: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+        /// Token account of the sender holding the tokens to transfer
+        #[arg(long)]
+        temp_ata: Pubkey,
+    },
+    /// The recipient withdraws tokens to the associated token account
+    Withdraw {
+        #[arg(long)]
+        recipient: PathBuf,
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+        #[arg(long)]
+        temp_ata: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The sender creates the state account and hands the temporary token
+    /// account over to the program
+    Deposit {
+        #[arg(long)]
+        sender: PathBuf,
+        /// Token account of the sender holding the tokens to transfer
+        #[arg(long)]
+        temp_token_account: Pubkey,
+        /// Keypair of the new state account
+        #[arg(long)]
+        state: PathBuf,
+        #[arg(long)]
+        recipient_token_account: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The recipient withdraws tokens
+    Withdraw {
+        #[arg(long)]
+        recipient: PathBuf,
+        #[arg(long)]
+        sender: Pubkey,
+        #[arg(long)]
+        recipient_token_account: Pubkey,
+        #[arg(long)]
+        temp_token_account: Pubkey,
+        #[arg(long)]
+        state: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("token_transfer")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Deposit {
+            sender,
+            recipient,
+            mint,
+            temp_ata,
+        } => {
+            let sender = b.signer(sender)?;
+            let accounts = vec![
+                writable_signer(sender),
+                readonly(*recipient),
+                readonly(*mint),
+                // Writable, since its owner is changed by the token program
+                writable(*temp_ata),
+                writable(pda(&[temp_ata.as_ref()], &program_id)),
+                readonly(b.token_program()?),
+                system_program(),
+                readonly(b.associated_token_program()?),
+            ];
+            b.push(program_id, accounts, anchor_data("deposit", ()));
+        }
+        Anchor::Withdraw {
+            recipient,
+            sender,
+            mint,
+            temp_ata,
+            amount,
+        } => {
+            let recipient = b.signer(recipient)?;
+            let accounts = vec![
+                readonly(*mint),
+                writable_signer(recipient),
+                writable(*sender),
+                writable(b.associated_token_address(&recipient, mint)?),
+                writable(*temp_ata),
+                writable(pda(&[temp_ata.as_ref()], &program_id)),
+                writable(pda(&[b"atas_holder"], &program_id)),
+                readonly(b.token_program()?),
+                system_program(),
+                readonly(b.associated_token_program()?),
+                readonly(sysvar::rent::id()),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw", (amount,)));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Deposit {
+            sender,
+            temp_token_account,
+            state,
+            recipient_token_account,
+            amount,
+        } => {
+            let sender = b.signer(sender)?;
+            let state = b.create_account(&sender, state, RAW_DEPOSIT_LEN, 0, &program_id)?;
+            let accounts = vec![
+                readonly_signer(sender),
+                writable(*temp_token_account),
+                writable(state),
+                readonly(*recipient_token_account),
+                readonly(b.token_program()?),
+            ];
+            b.push(program_id, accounts, raw_data(0, &[&amount.to_le_bytes()]));
+        }
+        RawRust::Withdraw {
+            recipient,
+            sender,
+            recipient_token_account,
+            temp_token_account,
+            state,
+            amount,
+        } => {
+            let recipient = b.signer(recipient)?;
+            let accounts = vec![
+                readonly_signer(recipient),
+                writable(*sender),
+                writable(*recipient_token_account),
+                writable(*temp_token_account),
+                writable(*state),
+                readonly(b.token_program()?),
+                readonly(pda(&[b"TokenTransfer"], &program_id)),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[&amount.to_le_bytes()]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+// Size of the state account created by the client for the raw program
+const RAW_VAULT_LEN: usize = 32 + 32 + 32 + 8 + 8 + 8 + 1;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The owner creates the vault and deposits the initial amount
+    Initialize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        recovery: Pubkey,
+        /// Number of slots between a withdrawal request and its finalization
+        #[arg(long)]
+        wait_time: u64,
+        #[arg(long)]
+        initial_amount: u64,
+    },
+    /// The owner requests a withdrawal to the receiver
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the wait time, the owner finalizes the withdrawal
+    Finalize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        receiver: Pubkey,
+    },
+    /// The recovery key cancels the withdrawal request
+    Cancel {
+        #[arg(long)]
+        recovery: PathBuf,
+        #[arg(long)]
+        owner: Pubkey,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The owner creates the state account, holding the initial amount, and
+    /// initializes the vault
+    Initialize {
+        #[arg(long)]
+        owner: PathBuf,
+        /// Keypair of the new state account
+        #[arg(long)]
+        state: PathBuf,
+        #[arg(long)]
+        recovery: Pubkey,
+        /// Number of slots between a withdrawal request and its finalization
+        #[arg(long)]
+        wait_time: u64,
+        #[arg(long)]
+        initial_amount: u64,
+    },
+    /// The owner requests a withdrawal to the receiver
+    Withdraw {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        state: Pubkey,
+        #[arg(long)]
+        receiver: Pubkey,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// After the wait time, the owner finalizes the withdrawal
+    Finalize {
+        #[arg(long)]
+        owner: PathBuf,
+        #[arg(long)]
+        state: Pubkey,
+        #[arg(long)]
+        receiver: Pubkey,
+    },
+    /// The recovery key cancels the withdrawal request
+    Cancel {
+        #[arg(long)]
+        recovery: PathBuf,
+        #[arg(long)]
+        state: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("vault")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn vault_pda(owner: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[owner.as_ref()], program_id)
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize {
+            owner,
+            recovery,
+            wait_time,
+            initial_amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                readonly(*recovery),
+                writable(vault_pda(&owner, &program_id)),
+                system_program(),
+            ];
+            let data = anchor_data("initialize", (wait_time, initial_amount));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::Withdraw {
+            owner,
+            receiver,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                readonly(*receiver),
+                writable(vault_pda(&owner, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("withdraw", (amount,)));
+        }
+        Anchor::Finalize { owner, receiver } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![
+                writable_signer(owner),
+                writable(*receiver),
+                writable(vault_pda(&owner, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("finalize", ()));
+        }
+        Anchor::Cancel { recovery, owner } => {
+            let recovery = b.signer(recovery)?;
+            let accounts = vec![
+                writable_signer(recovery),
+                readonly(*owner),
+                writable(vault_pda(owner, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("cancel", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Initialize {
+            owner,
+            state,
+            recovery,
+            wait_time,
+            initial_amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let state =
+                b.create_account(&owner, state, RAW_VAULT_LEN, *initial_amount, &program_id)?;
+            let accounts = vec![readonly_signer(owner), writable(state), readonly(*recovery)];
+            b.push(program_id, accounts, raw_data(0, &[&wait_time.to_le_bytes()]));
+        }
+        RawRust::Withdraw {
+            owner,
+            state,
+            receiver,
+            amount,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![readonly_signer(owner), writable(*state), readonly(*receiver)];
+            b.push(program_id, accounts, raw_data(1, &[&amount.to_le_bytes()]));
+        }
+        RawRust::Finalize {
+            owner,
+            state,
+            receiver,
+        } => {
+            let owner = b.signer(owner)?;
+            let accounts = vec![readonly_signer(owner), writable(*state), writable(*receiver)];
+            b.push(program_id, accounts, raw_data(2, &[]));
+        }
+        RawRust::Cancel { recovery, state } => {
+            let recovery = b.signer(recovery)?;
+            let accounts = vec![readonly_signer(recovery), writable(*state)];
+            b.push(program_id, accounts, raw_data(3, &[]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use clap::Subcommand;
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+use super::*;
+
+// Size of the state account created by the client for the raw program
+const RAW_VESTING_LEN: usize = 8 + 32 + 32 + 8 + 8;
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Anchor(Anchor),
+    #[command(subcommand)]
+    RawRust(RawRust),
+}
+
+#[derive(Subcommand)]
+pub enum Anchor {
+    /// The funder locks the lamports to vest to the beneficiary
+    Initialize {
+        #[arg(long)]
+        funder: PathBuf,
+        #[arg(long)]
+        beneficiary: Pubkey,
+        #[arg(long)]
+        start_slot: u64,
+        /// Duration of the vesting, in slots
+        #[arg(long)]
+        duration: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The beneficiary releases the vested lamports
+    Release {
+        #[arg(long)]
+        beneficiary: PathBuf,
+        #[arg(long)]
+        funder: Pubkey,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RawRust {
+    /// The funder creates the vesting account, holding the lamports to vest,
+    /// and initializes it
+    Initialize {
+        #[arg(long)]
+        funder: PathBuf,
+        #[arg(long)]
+        beneficiary: Pubkey,
+        /// Keypair of the new vesting account
+        #[arg(long)]
+        vesting: PathBuf,
+        #[arg(long)]
+        start_slot: u64,
+        /// Duration of the vesting, in slots
+        #[arg(long)]
+        duration: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// The beneficiary releases the vested lamports
+    Release {
+        #[arg(long)]
+        beneficiary: PathBuf,
+        #[arg(long)]
+        vesting: Pubkey,
+        #[arg(long)]
+        funder: Pubkey,
+    },
+}
+
+impl Command {
+    pub fn build(&self, b: &mut Builder) -> Result<()> {
+        match self {
+            Command::Anchor(ix) => {
+                let program_id = b.anchor_program("vesting")?;
+                anchor(ix, b, program_id)
+            }
+            Command::RawRust(ix) => {
+                let program_id = b.raw_program()?;
+                raw_rust(ix, b, program_id)
+            }
+        }
+    }
+}
+
+fn vesting_pda(beneficiary: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    pda(&[beneficiary.as_ref()], program_id)
+}
+
+fn anchor(ix: &Anchor, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        Anchor::Initialize {
+            funder,
+            beneficiary,
+            start_slot,
+            duration,
+            amount,
+        } => {
+            let funder = b.signer(funder)?;
+            let accounts = vec![
+                writable_signer(funder),
+                readonly(*beneficiary),
+                writable(vesting_pda(beneficiary, &program_id)),
+                system_program(),
+            ];
+            let data = anchor_data("initialize", (start_slot, duration, amount));
+            b.push(program_id, accounts, data);
+        }
+        Anchor::Release {
+            beneficiary,
+            funder,
+        } => {
+            let beneficiary = b.signer(beneficiary)?;
+            let accounts = vec![
+                writable_signer(beneficiary),
+                writable(*funder),
+                writable(vesting_pda(&beneficiary, &program_id)),
+            ];
+            b.push(program_id, accounts, anchor_data("release", ()));
+        }
+    }
+    Ok(())
+}
+
+fn raw_rust(ix: &RawRust, b: &mut Builder, program_id: Pubkey) -> Result<()> {
+    match ix {
+        RawRust::Initialize {
+            funder,
+            beneficiary,
+            vesting,
+            start_slot,
+            duration,
+            amount,
+        } => {
+            let funder = b.signer(funder)?;
+            let vesting =
+                b.create_account(&funder, vesting, RAW_VESTING_LEN, *amount, &program_id)?;
+            let accounts = vec![
+                readonly_signer(funder),
+                readonly(*beneficiary),
+                writable(vesting),
+            ];
+            let data = raw_data(0, &[&start_slot.to_le_bytes(), &duration.to_le_bytes()]);
+            b.push(program_id, accounts, data);
+        }
+        RawRust::Release {
+            beneficiary,
+            vesting,
+            funder,
+        } => {
+            let beneficiary = b.signer(beneficiary)?;
+            let accounts = vec![
+                writable_signer(beneficiary),
+                writable(*vesting),
+                writable(*funder),
+            ];
+            b.push(program_id, accounts, raw_data(1, &[]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_sdk::{
+    hash::Hash,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use std::path::Path;
+
+use crate::programs::Builder;
+
+/// Sign the instructions collected by `builder` and return the serialized
+/// transaction, base64-encoded.
+pub fn sign(builder: Builder, fee_payer: Option<&Path>, blockhash: Hash) -> Result<String> {
+    let (instructions, mut signers) = builder.finish();
+
+    let payer = match fee_payer {
+        Some(path) => {
+            let payer = read_keypair_file(path)
+                .map_err(|e| anyhow!("cannot read keypair {}: {}", path.display(), e))?;
+            signers.retain(|s| s.pubkey() != payer.pubkey());
+            signers.insert(0, payer);
+            signers[0].pubkey()
+        }
+        None => signers
+            .first()
+            .map(|s| s.pubkey())
+            .ok_or_else(|| anyhow!("the instruction has no signer, use --fee-payer"))?,
+    };
+
+    let signers: Vec<&Keypair> = signers.iter().collect();
+    let transaction =
+        Transaction::new_signed_with_payer(&instructions, Some(&payer), &signers, blockhash);
+
+    Ok(STANDARD.encode(bincode::serialize(&transaction)?))
+}
@@ -0,0 +1,140 @@
+"""Lightweight helpers to inspect the Solana programs' Rust sources.
+
+The programs in this repository are single-file snapshots without a Cargo
+manifest, so the tools in this directory work on the source text directly.
+"""
+
+import re
+from pathlib import Path
+
+ROOT = Path(__file__).resolve().parent.parent
+CONTRACTS = ROOT / "contracts"
+FLAVORS = ("anchor", "raw_rust")
+
+
+def strip_comments(text):
+    """Blank out comments, keeping offsets and string literals intact."""
+    out = []
+    i, n = 0, len(text)
+    while i < n:
+        c = text[i]
+        if c == '"':
+            j = i + 1
+            while j < n and text[j] != '"':
+                j += 2 if text[j] == "\\" else 1
+            out.append(text[i : j + 1])
+            i = j + 1
+        elif text.startswith("//", i):
+            j = text.find("\n", i)
+            j = n if j == -1 else j
+            out.append(" " * (j - i))
+            i = j
+        elif text.startswith("/*", i):
+            j = text.find("*/", i + 2)
+            j = n if j == -1 else j + 2
+            out.append(re.sub(r"[^\n]", " ", text[i:j]))
+            i = j
+        else:
+            out.append(c)
+            i += 1
+    return "".join(out)
+
+
+def block_at(text, open_idx):
+    """Return the text between the brace at `open_idx` and its match."""
+    depth = 0
+    i = open_idx
+    in_str = False
+    while i < len(text):
+        c = text[i]
+        if in_str:
+            if c == "\\":
+                i += 1
+            elif c == '"':
+                in_str = False
+        elif c == '"':
+            in_str = True
+        elif c == "{":
+            depth += 1
+        elif c == "}":
+            depth -= 1
+            if depth == 0:
+                return text[open_idx + 1 : i]
+        i += 1
+    raise ValueError("unbalanced braces")
+
+
+def find_block(text, header_regex):
+    """Return the body of the first item whose header matches `header_regex`."""
+    m = re.search(header_regex, text)
+    if m is None:
+        return None
+    return block_at(text, text.index("{", m.end() - 1))
+
+
+def source_file(usecase, flavor):
+    """Return the single `.rs` file of a use case flavor, if any."""
+    files = sorted((CONTRACTS / usecase / "solana" / flavor).glob("*.rs"))
+    return files[0] if files else None
+
+
+def read_source(path):
+    return strip_comments(path.read_text())
+
+
+def anchor_instructions(text):
+    """Names of the handlers declared in the `#[program]` module."""
+    body = find_block(text, r"#\[program\]\s*pub\s+mod\s+\w+\s*\{")
+    if body is None:
+        return []
+    return re.findall(r"pub\s+fn\s+(\w+)\s*(?:<[^>]*>)?\s*\(", body)
+
+
+def raw_instructions(text):
+    """Names of the handlers dispatched by `process_instruction`."""
+    body = find_block(text, r"fn\s+process_instruction\b[^{]*\{")
+    if body is None:
+        return []
+    return re.findall(r"\b(\w+)\s*\(\s*program_id\b", body)
+
+
+def instructions(text, flavor):
+    if flavor == "anchor":
+        return anchor_instructions(text)
+    return raw_instructions(text)
+
+
+def anchor_error_variants(text):
+    """Variants of the `#[error_code]` enum, with the enum name."""
+    m = re.search(r"#\[error_code\]\s*pub\s+enum\s+(\w+)\s*\{", text)
+    if m is None:
+        return None, []
+    body = block_at(text, m.end() - 1)
+    return m.group(1), re.findall(r"^\s*(\w+)\s*,", body, re.MULTILINE)
+
+
+def anchor_raised_errors(text):
+    """Error variants referenced outside the `#[error_code]` enum."""
+    enum, variants = anchor_error_variants(text)
+    if enum is None:
+        return set()
+    m = re.search(r"#\[error_code\]\s*pub\s+enum\s+\w+\s*\{", text)
+    body = block_at(text, m.end() - 1)
+    outside = text[: m.start()] + text[m.end() + len(body) :]
+    return {v for v in variants if re.search(rf"\b{enum}::{v}\b", outside)}
+
+
+def raw_messages(text):
+    """Literal messages logged with `msg!` before returning an error."""
+    return set(re.findall(r'msg!\(\s*"([^"]*)"\s*\)', text))
+
+
+def accounts_structs(text):
+    """Name and body of every `#[derive(Accounts)]` struct."""
+    result = []
+    for m in re.finditer(
+        r"#\[derive\(Accounts\)\]\s*(?:#\[[^\]]*\]\s*)*pub\s+struct\s+(\w+)(?:<[^>]*>)?\s*\{",
+        text,
+    ):
+        result.append((m.group(1), block_at(text, m.end() - 1)))
+    return result